@@ -0,0 +1,69 @@
+//! Benchmarks of the interpreter stages on the programs in `scenes/bench`: tokenizing, parsing
+//! and evaluating them in a fresh `EvalSession::benchmark`. The long running `int-sum.lisp` is
+//! left to `lispers run --benchmark`.
+//!
+//! Run with `cargo bench`, or `cargo bench -- <filter>` for a single stage or program.
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use lispers::prelude::*;
+use lispers_core::parser::tokenizer::tokenize;
+
+/// The benchmarked programs, by name.
+const PROGRAMS: [(&str, &str); 3] = [
+    ("fib", include_str!("../scenes/bench/fib.lisp")),
+    ("lists", include_str!("../scenes/bench/lists.lisp")),
+    ("scene", include_str!("../scenes/bench/scene.lisp")),
+];
+
+/// Parse all expressions of a program, which must be free of syntax errors.
+fn parse(source: &str) -> Vec<Expression> {
+    ExpressionStream::from_char_stream(source.chars())
+        .collect::<Result<_, _>>()
+        .expect("benchmark programs parse")
+}
+
+/// Evaluate parsed expressions in order, failing on the first error.
+fn evaluate(session: &EvalSession, program: Vec<Expression>) -> Expression {
+    program.into_iter().fold(Expression::Nil, |_, e| {
+        eval(session.environment(), e).expect("benchmark programs evaluate")
+    })
+}
+
+fn bench_tokenize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    for (name, source) in PROGRAMS {
+        group.bench_function(name, |b| {
+            b.iter(|| tokenize(black_box(source).chars()).count())
+        });
+    }
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in PROGRAMS {
+        group.bench_function(name, |b| b.iter(|| parse(black_box(source))));
+    }
+    group.finish();
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    group.sample_size(10);
+    for (name, source) in PROGRAMS {
+        let program = parse(source);
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || (EvalSession::benchmark(), program.clone()),
+                |(session, program)| evaluate(&session, program),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_tokenize, bench_parse, bench_eval);
+criterion_main!(benches);
@@ -0,0 +1,137 @@
+//! Embedding the interpreter in a Rust program: a todo list implemented in Rust, registered as
+//! foreign data and driven by a lisp script.
+//!
+//! Run with `cargo run --example embedding`.
+
+use std::fmt::Display;
+
+use lispers::prelude::*;
+
+/// A todo item.
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+struct Todo {
+    title: String,
+    done: bool,
+}
+
+/// The foreign type. Lisp values are copied, so functions on the list return an updated list
+/// instead of modifying it in place.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+struct TodoList {
+    todos: Vec<Todo>,
+}
+
+impl Display for TodoList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TodoList(")?;
+        for (i, todo) in self.todos.iter().enumerate() {
+            let mark = if todo.done { "x" } else { " " };
+            write!(
+                f,
+                "{}[{}] {}",
+                if i > 0 { ", " } else { "" },
+                mark,
+                todo.title
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Create an empty list: `(todo-list)`
+#[native_lisp_function(eval)]
+fn todo_list() -> ForeignDataWrapper<TodoList> {
+    ForeignDataWrapper::new(TodoList::default())
+}
+
+/// Add an open item: `(todo-add list "title")`
+#[native_lisp_function(eval)]
+fn todo_add(mut list: ForeignDataWrapper<TodoList>, title: String) -> ForeignDataWrapper<TodoList> {
+    list.todos.push(Todo { title, done: false });
+    list
+}
+
+/// Mark the item at an index as done: `(todo-done list 0)`
+#[native_lisp_function(eval)]
+fn todo_done(
+    mut list: ForeignDataWrapper<TodoList>,
+    index: i64,
+) -> Result<ForeignDataWrapper<TodoList>, EvalError> {
+    let len = list.todos.len();
+    let todo = usize::try_from(index)
+        .ok()
+        .and_then(|i| list.todos.get_mut(i))
+        .ok_or(EvalError::IndexOutOfRange(index, len))?;
+    todo.done = true;
+    Ok(list)
+}
+
+/// Get the titles of the open items: `(todo-open list)`
+#[native_lisp_function(eval)]
+fn todo_open(list: ForeignDataWrapper<TodoList>) -> Vec<Expression> {
+    list.todos
+        .iter()
+        .filter(|todo| !todo.done)
+        .map(|todo| todo.title.clone().into())
+        .collect()
+}
+
+/// Get the number of items of a list, or of characters of a string: `(todo-count x)`
+#[native_lisp_function(eval)]
+fn todo_count_list(list: ForeignDataWrapper<TodoList>) -> i64 {
+    list.todos.len() as i64
+}
+
+#[native_lisp_function(eval)]
+fn todo_count_string(s: String) -> i64 {
+    s.chars().count() as i64
+}
+
+native_lisp_function_proxy!(
+    fname = todo_count,
+    eval,
+    dispatch = todo_count_list,
+    dispatch = todo_count_string
+);
+
+/// Adds the todo list functions to an environment layer, like the `mk_*` functions of the
+/// builtin libraries.
+fn mk_todo(layer: &mut EnvironmentLayer) {
+    layer.set("todo-list".to_string(), Expression::Function(todo_list));
+    layer.set("todo-add".to_string(), Expression::Function(todo_add));
+    layer.set("todo-done".to_string(), Expression::Function(todo_done));
+    layer.set("todo-open".to_string(), Expression::Function(todo_open));
+    layer.set("todo-count".to_string(), Expression::Function(todo_count));
+}
+
+const SCRIPT: &str = r#"
+(define todos
+  (reduce (lambda (list title) (todo-add list title))
+          (todo-list)
+          '("write docs" "fix bug" "release")))
+(set 'todos (todo-done todos 1))
+(println todos)
+(println (todo-open todos))
+(todo-count todos)
+"#;
+
+fn main() -> Result<(), LispError> {
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_todo(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let values = eval_source(&env, SCRIPT)?;
+    assert_eq!(values.last(), Some(&Expression::Integer(3)));
+
+    // Values are passed back to Rust by converting them into the foreign type
+    let todos: ForeignDataWrapper<TodoList> = env.get("todos").unwrap().try_into()?;
+    assert!(todos.todos[1].done);
+
+    // Errors of native functions carry the position of the failing expression
+    match eval_source(&env, "(todo-done todos 5)") {
+        Err(e) => println!("{}", e),
+        Ok(_) => unreachable!("index 5 is out of range"),
+    }
+    Ok(())
+}
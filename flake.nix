@@ -90,6 +90,10 @@
         });
         packages.default = self'.packages.lispers;
         apps = {
+          lispers = {
+            type = "app";
+            program = "${self'.packages.lispers}/bin/lispers";
+          };
           lisp_demo = {
             type = "app";
             program = "${self'.packages.lispers}/bin/lisp_demo";
@@ -110,7 +114,7 @@
             type = "app";
             program = "${self'.packages.lispers}/bin/rt_interp";
           };
-          default = self'.apps.rt_lisp_demo;
+          default = self'.apps.lispers;
         };
 
         devShells.default = pkgs.mkShell {
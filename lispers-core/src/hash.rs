@@ -0,0 +1,54 @@
+//! A hash function for hashes saved to files.
+
+use std::fmt;
+use std::hash::Hasher;
+
+/// The 64-bit FNV-1a hash. Unlike `DefaultHasher`, it gives the same hash with every build, so
+/// hashes can be saved, e.g. to check whether a cache file belongs to a source. As `Hash`
+/// implementations may change between releases too, hash bytes with `Hasher::write` or text
+/// with `fmt::Write`.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Write for Fnv1a {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_fnv1a() {
+    use std::fmt::Write as _;
+
+    let hash = |bytes: &[u8]| {
+        let mut hasher = Fnv1a::default();
+        hasher.write(bytes);
+        hasher.finish()
+    };
+    assert_eq!(hash(b""), 0xcbf29ce484222325);
+    assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+
+    let mut hasher = Fnv1a::default();
+    write!(hasher, "{:?}", ("foo", 1)).unwrap();
+    assert_eq!(hasher.finish(), hash(b"(\"foo\", 1)"));
+}
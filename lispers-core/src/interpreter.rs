@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use crate::lisp::eval::EvalError;
+use crate::lisp::{eval_source, Environment, Expression, InterpreterConfig, LispError};
+
+/// The signature of native functions, which get the calling environment and their arguments
+/// unevaluated.
+pub type NativeFunction = fn(&Environment, Expression) -> Result<Expression, EvalError>;
+
+/// An interpreter with the prelude and file I/O, for embedding lisp in a host application
+/// without assembling an `Environment` by hand.
+///
+/// ```
+/// use lispers_core::{EvalError, Expression, Interpreter};
+///
+/// fn double(env: &lispers_core::Environment, args: Expression) -> Result<Expression, EvalError> {
+///     let [x]: [Expression; 1] = args.try_into()?;
+///     let x: i64 = lispers_core::eval(env, x)?.try_into()?;
+///     Ok(Expression::Integer(2 * x))
+/// }
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.define_fn("double", double);
+/// interpreter.set_global("x", 20);
+/// assert_eq!(interpreter.eval_str("(+ (double x) 2)"), Ok(Expression::Integer(42)));
+/// ```
+#[derive(Debug)]
+pub struct Interpreter {
+    environment: Environment<'static>,
+}
+
+impl Interpreter {
+    /// Create an interpreter with the prelude and file I/O.
+    pub fn new() -> Self {
+        Environment::builder()
+            .with_prelude()
+            .with_math()
+            .with_io(true)
+            .build()
+            .into()
+    }
+
+    /// Create an interpreter with the given evaluation settings.
+    pub fn with_config(config: InterpreterConfig) -> Self {
+        let mut interpreter = Interpreter::new();
+        interpreter.environment.set_config(config);
+        interpreter
+    }
+
+    /// Get the global environment, e.g. to call `eval` directly or to attach an observer.
+    pub fn environment(&self) -> &Environment<'static> {
+        &self.environment
+    }
+
+    /// Evaluate all expressions of `source` in order, returning the value of the last one, or
+    /// nil if there is none. Nothing is evaluated if the source does not parse.
+    pub fn eval_str(&self, source: &str) -> Result<Expression, LispError> {
+        Ok(eval_source(&self.environment, source)?
+            .pop()
+            .unwrap_or(Expression::Nil))
+    }
+
+    /// Evaluate a lisp file like `eval_str`. `FILE` is bound to its path while evaluating, so
+    /// files included by it are resolved next to it.
+    pub fn eval_file(&self, path: impl AsRef<Path>) -> Result<Expression, LispError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| EvalError::with_context(path.display(), e))?;
+        let mut env = self.environment.mk_inner();
+        env.set("FILE".to_string(), path.display().to_string().into());
+        Ok(eval_source(&env, &source)?.pop().unwrap_or(Expression::Nil))
+    }
+
+    /// Bind a native function to a global symbol, like the builtins. It replaces a builtin of
+    /// the same name.
+    pub fn define_fn(&mut self, name: &str, f: NativeFunction) {
+        self.environment
+            .set(name.to_string(), Expression::Function(f));
+    }
+
+    /// Bind a closure as a native function to a global symbol, so it can capture state of the
+    /// host. See `Environment::register_closure`.
+    pub fn define_closure<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&Environment, Expression) -> Result<Expression, EvalError> + Send + Sync + 'static,
+    {
+        self.environment.register_closure(name, f);
+    }
+
+    /// Get the value bound to a global symbol.
+    pub fn get_global(&self, name: &str) -> Option<Expression> {
+        self.environment.get(name)
+    }
+
+    /// Bind a value to a global symbol, like `(set 'name value)` does.
+    pub fn set_global(&self, name: &str, value: impl Into<Expression>) {
+        self.environment.shared_set(name.to_string(), value.into());
+    }
+}
+
+impl From<Environment<'static>> for Interpreter {
+    /// Use an environment with other builtins, e.g. one built with `Environment::builder`.
+    fn from(environment: Environment<'static>) -> Self {
+        Interpreter { environment }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_interpreter() {
+    let dir = std::env::temp_dir().join(format!("lispers-test-interpreter-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("main.lisp"),
+        "(include \"lib.lisp\") (lib-inc answer)",
+    )
+    .unwrap();
+    std::fs::write(dir.join("lib.lisp"), "(defun lib-inc (x) (+ x 1))").unwrap();
+
+    let interpreter = Interpreter::new();
+    interpreter.set_global("answer", 41);
+    assert_eq!(
+        interpreter.eval_file(dir.join("main.lisp")),
+        Ok(Expression::Integer(42))
+    );
+    // Globals set by evaluated code and by the host are the same bindings
+    assert_eq!(
+        interpreter.eval_str("(set 'answer 1) (lib-inc answer)"),
+        Ok(Expression::Integer(2))
+    );
+    assert_eq!(
+        interpreter.get_global("answer"),
+        Some(Expression::Integer(1))
+    );
+    assert_eq!(interpreter.get_global("FILE"), None);
+    assert_eq!(interpreter.eval_str(""), Ok(Expression::Nil));
+    assert!(matches!(
+        interpreter.eval_str("(+ 1"),
+        Err(LispError::Parser(_))
+    ));
+    assert!(matches!(
+        interpreter.eval_file(dir.join("missing.lisp")),
+        Err(LispError::Eval(EvalError::RuntimeError(_, Some(_))))
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -1,2 +1,28 @@
+//! The lispers interpreter: a parser and evaluator for a small lisp, to be embedded in Rust
+//! programs.
+//!
+//! The items re-exported here are the intended stable surface for 0.x releases: evaluating
+//! source code with an `Interpreter` or in an `Environment`, converting between `Expression`s
+//! and Rust values, and adding native functions with `EnvironmentLayer::set`. Breaking changes
+//! to them only happen with a minor version bump. The error enums, `Token` and configuration
+//! structs are `#[non_exhaustive]`, so new variants and settings are not breaking. Everything
+//! else in the `lisp` and `parser` modules, like the builtin implementations and the tokenizer,
+//! is public for reuse but may change with any release.
+
+pub mod hash;
+mod interpreter;
 pub mod lisp;
 pub mod parser;
+
+pub use interpreter::{Interpreter, NativeFunction};
+
+pub use lisp::builder::EnvironmentBuilder;
+pub use lisp::environment::EnvironmentLayer;
+pub use lisp::eval::EvalError;
+pub use lisp::expression::{Coerce, ForeignData, ForeignDataWrapper, NativeClosure};
+pub use lisp::prelude::mk_prelude;
+pub use lisp::profile::{EvalObserver, Profiler};
+pub use lisp::record::LispRecord;
+pub use lisp::{eval, eval_source, Environment, Expression, InterpreterConfig, LispError, Warning};
+pub use parser::token::Position;
+pub use parser::{ExpressionStream, ParserError};
@@ -0,0 +1,140 @@
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::environment::InterpreterConfig;
+use super::io::mk_io;
+use super::math::mk_math;
+use super::prelude::mk_loading;
+use super::prelude::mk_prelude;
+
+#[derive(Clone, Debug, Default)]
+/// Builds an `Environment` with a selection of the builtin groups, e.g. without file access for
+/// untrusted scripts:
+///
+/// ```
+/// use lispers_core::lisp::{eval_source, Environment};
+///
+/// let env = Environment::builder().with_prelude().with_math().with_io(false).build();
+/// assert!(eval_source(&env, "(cos 0)").is_ok());
+/// assert!(eval_source(&env, "(read-file \"/etc/passwd\")").is_err());
+/// ```
+///
+/// Groups of other crates are added with `with`, taking their `mk_*` function.
+pub struct EnvironmentBuilder {
+    prelude: bool,
+    math: bool,
+    io: bool,
+    groups: Vec<fn(&mut EnvironmentLayer)>,
+    config: InterpreterConfig,
+}
+
+/// Unbind the symbols a group of builtins binds.
+fn remove_group(layer: &mut EnvironmentLayer, group: fn(&mut EnvironmentLayer)) {
+    let mut builtins = EnvironmentLayer::new();
+    group(&mut builtins);
+    for name in builtins.names() {
+        layer.remove(name);
+    }
+}
+
+impl EnvironmentBuilder {
+    /// Start with no builtins at all.
+    pub fn new() -> Self {
+        EnvironmentBuilder::default()
+    }
+
+    /// Add the prelude: the special forms, arithmetic, lists, strings, maps and the standard
+    /// library. Its builtins accessing files are only added together with `with_io(true)`.
+    pub fn with_prelude(mut self) -> Self {
+        self.prelude = true;
+        self
+    }
+
+    /// Add the math functions and constants, like `sin` and `pi`.
+    pub fn with_math(mut self) -> Self {
+        self.math = true;
+        self
+    }
+
+    /// Choose whether scripts may access the file system, with the file I/O builtins and the
+    /// loading builtins of the prelude like `include`. Without, `deny_file_access` of the
+    /// configuration is set, so builtins of other groups writing files, like `render`, fail.
+    pub fn with_io(mut self, io: bool) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Add a group of builtins with its `mk_*` function. Groups are added in order, after the
+    /// builtin groups of this crate.
+    pub fn with(mut self, group: fn(&mut EnvironmentLayer)) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    /// Use the given evaluation settings.
+    pub fn with_config(mut self, config: InterpreterConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Build the `Environment` with the selected builtins.
+    pub fn build(self) -> Environment<'static> {
+        let mut layer = EnvironmentLayer::new();
+        if self.prelude {
+            mk_prelude(&mut layer);
+            if !self.io {
+                remove_group(&mut layer, mk_loading);
+            }
+            if !self.math {
+                remove_group(&mut layer, mk_math);
+            }
+        } else if self.math {
+            mk_math(&mut layer);
+        }
+        if self.io {
+            mk_io(&mut layer);
+        }
+        for group in self.groups {
+            group(&mut layer);
+        }
+
+        let mut env = Environment::from_layer(layer);
+        env.set_config(
+            self.config
+                .with_deny_file_access(self.config.deny_file_access || !self.io),
+        );
+        env
+    }
+}
+
+#[test]
+fn test_builder() {
+    use super::eval_source;
+    use super::expression::Expression;
+
+    let bound = |env: &Environment, name: &str| env.get(name).is_some();
+
+    let env = Environment::builder().build();
+    assert!(!bound(&env, "+"));
+
+    let env = Environment::builder().with_prelude().build();
+    assert!(env.config().deny_file_access);
+    assert!(bound(&env, "+") && bound(&env, "range"));
+    assert!(!bound(&env, "sin") && !bound(&env, "include") && !bound(&env, "read-file"));
+
+    let env = Environment::builder()
+        .with_math()
+        .with_io(true)
+        .with(|layer| layer.set("answer".to_string(), Expression::Integer(42)))
+        .with_config(InterpreterConfig::default().with_distinct_false(true))
+        .build();
+    assert!(bound(&env, "sin") && bound(&env, "read-file") && bound(&env, "answer"));
+    assert!(!bound(&env, "+") && !bound(&env, "include"));
+    assert!(env.config().distinct_false && !env.config().deny_file_access);
+
+    let env = Environment::builder().with_prelude().with_io(true).build();
+    assert!(bound(&env, "include") && bound(&env, "read-file"));
+    assert_eq!(
+        eval_source(&env, "(sum (range 0 4))"),
+        Ok(vec![Expression::Integer(6)])
+    );
+}
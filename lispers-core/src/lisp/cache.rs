@@ -0,0 +1,191 @@
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::eval::EvalError;
+use super::expression::Expression;
+use crate::hash::Fnv1a;
+use crate::parser::{ExpressionStream, ParserError};
+
+/// Version of the cache format, cache files of other versions are ignored.
+const CACHE_VERSION: u32 = 2;
+
+/// Contents of a `.lispc` cache file.
+#[derive(Serialize, Deserialize)]
+struct ExpressionCache {
+    version: u32,
+    /// Hash of the source the expressions were parsed from
+    source_hash: u64,
+    expressions: Vec<Expression>,
+}
+
+/// The start of serialized expressions, a magic number and the format version. Bytes of other
+/// versions are rejected.
+const BYTES_HEADER: &[u8; 8] = b"LISPB\0\0\x01";
+
+/// The file extension of compiled lisp programs, see `write_compiled`.
+pub const COMPILED_EXTENSION: &str = "lispb";
+
+/// Serialize a value behind `BYTES_HEADER`.
+fn to_bytes<T: Serialize>(value: &T) -> Result<Vec<u8>, EvalError> {
+    let mut bytes = BYTES_HEADER.to_vec();
+    bincode::serialize_into(&mut bytes, value).map_err(EvalError::from_error)?;
+    Ok(bytes)
+}
+
+/// Deserialize a value serialized with `to_bytes`.
+fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, EvalError> {
+    let body = bytes.strip_prefix(BYTES_HEADER).ok_or_else(|| {
+        EvalError::RuntimeError(
+            "Not a serialized expression of this lispers version".to_string(),
+            None,
+        )
+    })?;
+    bincode::deserialize(body)
+        .map_err(|e| EvalError::with_context("Invalid serialized expression", e))
+}
+
+impl Expression {
+    /// Serialize the expression into a compact binary form, which `from_bytes` reads back
+    /// without tokenizing and parsing. Native functions and foreign data cannot be serialized.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EvalError> {
+        to_bytes(self)
+    }
+
+    /// Deserialize an expression serialized with `to_bytes`. Invalid input fails, also if it
+    /// nests deeper than `expression::MAX_NESTING`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Expression, EvalError> {
+        from_bytes(bytes)
+    }
+}
+
+/// Write the expressions of a program to a compiled file, which `read_compiled` loads without
+/// parsing the source again. Source positions are not kept.
+pub fn write_compiled(path: &Path, expressions: Vec<Expression>) -> Result<(), EvalError> {
+    let bytes = to_bytes(&expressions)?;
+    std::fs::write(path, bytes).map_err(|e| EvalError::with_context(path.display(), e))
+}
+
+/// Read the expressions of a compiled file written by `write_compiled`.
+pub fn read_compiled(path: &Path) -> Result<Vec<Expression>, EvalError> {
+    let bytes = std::fs::read(path).map_err(|e| EvalError::with_context(path.display(), e))?;
+    from_bytes(&bytes).map_err(|e| EvalError::with_context(path.display(), e))
+}
+
+/// Get the path of the cache file of a lisp source file.
+pub fn cache_path(path: &Path) -> PathBuf {
+    path.with_extension("lispc")
+}
+
+/// Hash a source with 64-bit FNV-1a, which unlike `DefaultHasher` gives the same hash with
+/// every build, so cache files stay valid across Rust releases.
+fn source_hash(source: &str) -> u64 {
+    let mut hash = Fnv1a::default();
+    hash.write(source.as_bytes());
+    hash.finish()
+}
+
+/// Read the expressions of a cache file, if it exists and matches `source_hash`.
+fn read_cache(path: &Path, source_hash: u64) -> Option<Vec<Expression>> {
+    let bytes = std::fs::read(path).ok()?;
+    let cache: ExpressionCache = bincode::deserialize(&bytes).ok()?;
+    (cache.version == CACHE_VERSION && cache.source_hash == source_hash)
+        .then_some(cache.expressions)
+}
+
+/// Parse the lisp file at `path`, using the expressions of its cache file (see `cache_path`)
+/// if the source did not change since the cache was written. Otherwise the cache is
+/// (re)written, failing to do so is not an error.
+pub fn parse_cached(path: &Path) -> Result<Vec<Expression>, EvalError> {
+    let source =
+        std::fs::read_to_string(path).map_err(|e| EvalError::with_context(path.display(), e))?;
+    let source_hash = source_hash(&source);
+    let cache_file = cache_path(path);
+
+    if let Some(expressions) = read_cache(&cache_file, source_hash) {
+        return Ok(expressions);
+    }
+
+    let expressions = ExpressionStream::from_char_stream(source.chars())
+        .collect::<Result<Vec<_>, ParserError>>()?;
+
+    let cache = ExpressionCache {
+        version: CACHE_VERSION,
+        source_hash,
+        expressions,
+    };
+    if let Ok(bytes) = bincode::serialize(&cache) {
+        let _ = std::fs::write(&cache_file, bytes);
+    }
+
+    Ok(cache.expressions)
+}
+
+#[test]
+fn test_parse_cached() {
+    let dir = std::env::temp_dir().join(format!("lispers-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("scene.lisp");
+
+    std::fs::write(&file, "(set 'a 1) (list \"b\" 2.5 'c 99999999999999999999)").unwrap();
+    let parsed = parse_cached(&file).unwrap();
+    assert!(cache_path(&file).exists());
+    assert_eq!(parse_cached(&file).unwrap(), parsed);
+    assert_eq!(
+        read_cache(
+            &cache_path(&file),
+            source_hash(&std::fs::read_to_string(&file).unwrap())
+        ),
+        Some(parsed)
+    );
+
+    // The hash must not depend on the build
+    assert_eq!(source_hash(""), 0xcbf29ce484222325);
+    assert_eq!(source_hash("a"), 0xaf63dc4c8601ec8c);
+
+    // A changed source invalidates the cache
+    std::fs::write(&file, "(set 'a 2)").unwrap();
+    assert_eq!(parse_cached(&file).unwrap().len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_compiled() {
+    let expressions: Vec<Expression> = ExpressionStream::from_char_stream(
+        "(defun f (x) (* x 2.5)) (f 99999999999999999999) '(a . \"b\")".chars(),
+    )
+    .collect::<Result<_, _>>()
+    .unwrap();
+    let bytes = expressions[0].to_bytes().unwrap();
+    assert_eq!(Expression::from_bytes(&bytes), Ok(expressions[0].clone()));
+    assert!(Expression::from_bytes(&bytes[1..]).is_err());
+    assert!(Expression::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Expression::Function(|_, e| Ok(e)).to_bytes().is_err());
+
+    let file = std::env::temp_dir().join(format!(
+        "lispers-compiled-{}.{}",
+        std::process::id(),
+        COMPILED_EXTENSION
+    ));
+    write_compiled(&file, expressions.clone()).unwrap();
+    assert_eq!(read_compiled(&file), Ok(expressions));
+
+    // Large programs and long lists do not overflow the stack
+    let source = "(set 'a 1) ".repeat(20000) + &format!("'({})", "1 ".repeat(1000));
+    let expressions: Vec<Expression> = ExpressionStream::from_char_stream(source.chars())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    write_compiled(&file, expressions.clone()).unwrap();
+    assert_eq!(read_compiled(&file), Ok(expressions));
+
+    // Untrusted input cannot nest without bounds
+    let quote = 2u32
+        .to_le_bytes()
+        .repeat(super::expression::MAX_NESTING + 1);
+    let nil = 11u32.to_le_bytes();
+    assert!(Expression::from_bytes(&[BYTES_HEADER.as_slice(), &quote, &nil].concat()).is_err());
+    std::fs::remove_file(&file).unwrap();
+    assert!(read_compiled(&file).is_err());
+}
@@ -0,0 +1,296 @@
+//! A readable serde representation of expressions for self-describing formats like JSON. The
+//! derived `Serialize` and `Deserialize` of `Expression` mirror its variants, which suits
+//! compact formats like the bincode of `.lispc` caches. This representation is what a person
+//! would write by hand instead:
+//!
+//! - `nil`, `true` and `false` are `null`, `true` and `false`
+//! - Integers, Floats and Strings are numbers and strings, BigInts are `{"bigint": "digits"}`
+//! - Proper lists are arrays, other cells are `{"cons": [car, cdr]}`
+//! - Symbols are `{"symbol": "name"}` and quotes are `{"quote": expr}`
+//! - Maps are `{"map": [[key, value], ...]}` in insertion order
+//! - Lambdas are `{"lambda": {"args": ["name", ...], "body": [expr, ...]}}`
+//!
+//! Native functions and foreign data cannot be serialized. Use the `Canonical` wrapper, or
+//! `#[serde(with = "lispers_core::lisp::canonical")]` on an `Expression` field of a host type.
+
+use std::fmt;
+use std::sync::Arc;
+
+use num_bigint::BigInt;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::expression::{Expression, MapKey};
+
+/// An expression (de)serialized in the canonical representation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Canonical(pub Expression);
+
+/// Serialize an expression in the canonical representation, for `#[serde(with)]`.
+pub fn serialize<S: Serializer>(expr: &Expression, serializer: S) -> Result<S::Ok, S::Error> {
+    CanonicalRef(expr).serialize(serializer)
+}
+
+/// Deserialize an expression from the canonical representation, for `#[serde(with)]`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Expression, D::Error> {
+    Canonical::deserialize(deserializer).map(|c| c.0)
+}
+
+/// Serializes a borrowed expression, so nested expressions are not copied.
+struct CanonicalRef<'a>(&'a Expression);
+
+/// A single entry object, e.g. `{"symbol": "name"}`.
+fn tagged<S: Serializer, T: Serialize>(
+    serializer: S,
+    tag: &str,
+    value: T,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(tag, &value)?;
+    map.end()
+}
+
+impl Serialize for CanonicalRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Expression::Nil => serializer.serialize_unit(),
+            Expression::True => serializer.serialize_bool(true),
+            Expression::False => serializer.serialize_bool(false),
+            Expression::Integer(i) => serializer.serialize_i64(*i),
+            Expression::Float(f) => serializer.serialize_f64(*f),
+            Expression::BigInt(i) => tagged(serializer, "bigint", i.to_string()),
+            Expression::String(s) => serializer.serialize_str(s),
+            Expression::Symbol(s) => tagged(serializer, "symbol", s),
+            Expression::Quote(e) => tagged(serializer, "quote", CanonicalRef(e)),
+            Expression::Cell(car, cdr) => match self.0.list_iter().collect::<Result<Vec<_>, _>>() {
+                Ok(elements) => {
+                    let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                    for e in elements {
+                        seq.serialize_element(&CanonicalRef(e))?;
+                    }
+                    seq.end()
+                }
+                Err(_) => tagged(serializer, "cons", [CanonicalRef(car), CanonicalRef(cdr)]),
+            },
+            Expression::Map(m) => tagged(
+                serializer,
+                "map",
+                m.iter()
+                    .map(|(k, v)| (Canonical(k.clone().into()), CanonicalRef(v)))
+                    .collect::<Vec<_>>(),
+            ),
+            Expression::AnonymousFunction {
+                argument_symbols,
+                body,
+            } => tagged(
+                serializer,
+                "lambda",
+                LambdaRef {
+                    args: argument_symbols,
+                    body: body.iter().map(CanonicalRef).collect(),
+                },
+            ),
+            Expression::Function(_)
+            | Expression::NativeClosure(_)
+            | Expression::ForeignExpression(_) => Err(ser::Error::custom(format!(
+                "{} cannot be serialized",
+                self.0
+            ))),
+        }
+    }
+}
+
+impl Serialize for Canonical {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CanonicalRef(&self.0).serialize(serializer)
+    }
+}
+
+/// The contents of a serialized lambda.
+#[derive(Deserialize)]
+struct Lambda {
+    args: Vec<String>,
+    body: Vec<Canonical>,
+}
+
+/// The contents of a lambda to serialize, borrowed like `CanonicalRef`.
+#[derive(Serialize)]
+struct LambdaRef<'a> {
+    args: &'a [String],
+    body: Vec<CanonicalRef<'a>>,
+}
+
+struct CanonicalVisitor;
+
+impl<'de> Visitor<'de> for CanonicalVisitor {
+    type Value = Canonical;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a canonical lisp expression")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Canonical, E> {
+        Ok(Canonical(Expression::Nil))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Canonical, E> {
+        Ok(Canonical(Expression::Nil))
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Canonical, E> {
+        Ok(Canonical(if b {
+            Expression::True
+        } else {
+            Expression::False
+        }))
+    }
+
+    fn visit_i64<E: de::Error>(self, i: i64) -> Result<Canonical, E> {
+        Ok(Canonical(Expression::Integer(i)))
+    }
+
+    fn visit_u64<E: de::Error>(self, i: u64) -> Result<Canonical, E> {
+        Ok(Canonical(BigInt::from(i).into()))
+    }
+
+    fn visit_f64<E: de::Error>(self, f: f64) -> Result<Canonical, E> {
+        Ok(Canonical(Expression::Float(f)))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Canonical, E> {
+        Ok(Canonical(Expression::String(s.to_string())))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Canonical, A::Error> {
+        let mut elements = Vec::new();
+        while let Some(Canonical(e)) = seq.next_element()? {
+            elements.push(e);
+        }
+        Ok(Canonical(elements.into()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Canonical, A::Error> {
+        let tag: String = map
+            .next_key()?
+            .ok_or_else(|| de::Error::custom("expected a tagged expression"))?;
+        let expr = match tag.as_str() {
+            "symbol" => Expression::Symbol(map.next_value()?),
+            "quote" => Expression::Quote(Arc::new(map.next_value::<Canonical>()?.0)),
+            "bigint" => {
+                let digits: String = map.next_value()?;
+                digits
+                    .parse::<BigInt>()
+                    .map_err(|_| de::Error::custom(format!("invalid bigint {:?}", digits)))?
+                    .into()
+            }
+            "cons" => {
+                let [Canonical(car), Canonical(cdr)]: [Canonical; 2] = map.next_value()?;
+                Expression::Cell(Arc::new(car), Arc::new(cdr))
+            }
+            "map" => {
+                let entries: Vec<(Canonical, Canonical)> = map.next_value()?;
+                Expression::Map(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| Ok((MapKey::try_from(k.0).map_err(de::Error::custom)?, v.0)))
+                        .collect::<Result<_, A::Error>>()?,
+                )
+            }
+            "lambda" => {
+                let Lambda { args, body } = map.next_value()?;
+                Expression::AnonymousFunction {
+                    argument_symbols: args,
+                    body: body.into_iter().map(|c| c.0).collect(),
+                }
+            }
+            tag => return Err(de::Error::unknown_variant(tag, TAGS)),
+        };
+        if map.next_key::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::custom(format!(
+                "expected a single entry in a tagged expression {:?}",
+                tag
+            )));
+        }
+        Ok(Canonical(expr))
+    }
+}
+
+/// The tags of expressions serialized as single entry maps.
+const TAGS: &[&str] = &["symbol", "quote", "bigint", "cons", "map", "lambda"];
+
+impl<'de> Deserialize<'de> for Canonical {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Canonical, D::Error> {
+        deserializer.deserialize_any(CanonicalVisitor)
+    }
+}
+
+#[test]
+fn test_canonical_json() {
+    use crate::parser::ExpressionStream;
+
+    let parse = |source: &str| -> Expression {
+        ExpressionStream::from_char_stream(source.chars())
+            .next()
+            .unwrap()
+            .unwrap()
+    };
+    let json = |source: &str| serde_json::to_string(&Canonical(parse(source))).unwrap();
+    let roundtrip = |source: &str| {
+        let expr = parse(source);
+        let json = serde_json::to_string(&Canonical(expr.clone())).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Canonical>(&json).unwrap().0,
+            expr,
+            "{}",
+            json
+        );
+    };
+
+    assert_eq!(
+        json("(set 'xs (list 1 2.5 \"three\" nil))"),
+        r#"[{"symbol":"set"},{"quote":{"symbol":"xs"}},[{"symbol":"list"},1,2.5,"three",null]]"#
+    );
+    assert_eq!(json("'(1 . 2)"), r#"{"quote":{"cons":[1,2]}}"#);
+    assert_eq!(
+        json("99999999999999999999"),
+        r#"{"bigint":"99999999999999999999"}"#
+    );
+    for source in [
+        "(defun f (x) (* x 2.0))",
+        "'(a (b . c) \"d\" 99999999999999999999 -7)",
+        "(quote (1 . (2 . 3)))",
+    ] {
+        roundtrip(source);
+    }
+    let lambda = Expression::AnonymousFunction {
+        argument_symbols: vec!["x".to_string()],
+        body: vec![parse("(+ x 1)")].into(),
+    };
+    let map = Expression::Map(
+        [(MapKey::Symbol("a".to_string()), lambda)]
+            .into_iter()
+            .collect(),
+    );
+    let map_json = r#"{"map":[[{"symbol":"a"},{"lambda":{"args":["x"],"body":[[{"symbol":"+"},{"symbol":"x"},1]]}}]]}"#;
+    assert_eq!(
+        serde_json::to_string(&Canonical(map.clone())).unwrap(),
+        map_json
+    );
+    assert_eq!(serde_json::from_str::<Canonical>(map_json).unwrap().0, map);
+    assert_eq!(
+        serde_json::from_str::<Canonical>("[true, false, null, {\"symbol\": \"t\"}]")
+            .unwrap()
+            .0,
+        vec![
+            Expression::True,
+            Expression::False,
+            Expression::Nil,
+            Expression::Symbol("t".to_string())
+        ]
+        .into()
+    );
+    assert!(serde_json::from_str::<Canonical>(r#"{"symbol":"a","quote":1}"#).is_err());
+    assert!(serde_json::from_str::<Canonical>(r#"{"vector":[1]}"#).is_err());
+    assert!(serde_json::to_string(&Canonical(Expression::Function(|_, e| Ok(e)))).is_err());
+}
@@ -1,29 +1,282 @@
-use super::{expression::Expression, prelude::mk_prelude};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use super::{
+    builder::EnvironmentBuilder,
+    eval::{EvalError, Warning, MAX_BACKTRACE},
+    expression::{Expression, MapKey, NativeClosure},
+    modules::IMPORTS_SYMBOL,
+    output::OutputSink,
+    prelude::mk_prelude,
+    profile::EvalObserver,
+    random::Rng,
+    trace::TraceState,
+};
+use indexmap::IndexMap;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, RwLock,
+    },
+    time::{Duration, Instant},
+};
 
-#[derive(PartialEq, Clone, Debug)]
+/// How often the timeout is checked, in function calls.
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Debug)]
+/// The state of the evaluation running on a thread in the environments sharing an `EvalId`:
+/// the progress checked against the limits of the `InterpreterConfig` and the backtrace.
+struct Evaluation {
+    /// The `EvalId` of the environments.
+    id: usize,
+    /// The number of nested function calls.
+    depth: usize,
+    /// The number of function calls since the top level evaluation started.
+    steps: u64,
+    /// When the top level evaluation started.
+    started: Option<Instant>,
+    /// The calls an error is propagating through, with the keys of the called lists.
+    backtrace: Vec<(String, usize)>,
+}
+
+thread_local! {
+    /// The evaluations of the current thread. Threads evaluating in the same environment at once
+    /// do not add to each other's limits or backtraces, and calls take no lock.
+    static EVALUATIONS: RefCell<Vec<Evaluation>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Identifies the evaluations of an environment and its inner environments on each thread. The
+/// state of the evaluations on the dropping thread is removed with the last environment.
+#[derive(Debug)]
+struct EvalId(usize);
+
+impl EvalId {
+    fn new() -> Arc<EvalId> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Arc::new(EvalId(NEXT.fetch_add(1, Ordering::Relaxed)))
+    }
+}
+
+impl Drop for EvalId {
+    fn drop(&mut self) {
+        let _ = EVALUATIONS.try_with(|evaluations| {
+            evaluations.borrow_mut().retain(|e| e.id != self.0);
+        });
+    }
+}
+
+/// Leaves a function call entered with `Environment::enter_call` when dropped.
+pub(crate) struct CallGuard<'a>(&'a Environment<'a>);
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        self.0.with_evaluation(|evaluation| evaluation.depth -= 1);
+    }
+}
+
+/// The default maximum number of nested function calls, see `InterpreterConfig::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// The default maximum size of exact integers computed by `expt`, about 315,000 decimal digits,
+/// see `InterpreterConfig::max_integer_bits`.
+pub const DEFAULT_MAX_INTEGER_BITS: u64 = 1 << 20;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+#[non_exhaustive]
+/// Settings controlling the evaluation semantics of an `Environment`.
+pub struct InterpreterConfig {
+    /// When set, only `true` counts as true in conditions. Otherwise every value except `nil` and
+    /// `false` does.
+    pub strict_truthiness: bool,
+    /// Scheme-like booleans: `false` evaluates to itself instead of `nil`, predicates return it
+    /// instead of `nil` and `nil` (the empty list) counts as true.
+    pub distinct_false: bool,
+    /// Allow negative indices counting from the end, like `-1` for the last element.
+    pub negative_indices: bool,
+    /// The maximum number of nested function calls, `DEFAULT_MAX_DEPTH` by default. The stack
+    /// grows as needed, so without a maximum deep recursion is only bounded by memory.
+    pub max_depth: Option<usize>,
+    /// The maximum number of function calls while evaluating a top level expression.
+    pub max_steps: Option<u64>,
+    /// The maximum time evaluating a top level expression may take.
+    pub timeout: Option<Duration>,
+    /// The maximum number of bits of an exact integer power, `DEFAULT_MAX_INTEGER_BITS` by
+    /// default, so `(expt 10 100000000)` fails instead of computing for minutes.
+    pub max_integer_bits: Option<u64>,
+    /// Deny builtins access to the file system, see `check_file_access`. Set for environments
+    /// built with `with_io(false)`, which leave out the file builtins of this crate.
+    pub deny_file_access: bool,
+}
+
+impl Default for InterpreterConfig {
+    fn default() -> Self {
+        InterpreterConfig {
+            strict_truthiness: false,
+            distinct_false: false,
+            negative_indices: false,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_steps: None,
+            timeout: None,
+            max_integer_bits: Some(DEFAULT_MAX_INTEGER_BITS),
+            deny_file_access: false,
+        }
+    }
+}
+
+impl InterpreterConfig {
+    /// Set `strict_truthiness`.
+    pub fn with_strict_truthiness(self, strict_truthiness: bool) -> Self {
+        InterpreterConfig {
+            strict_truthiness,
+            ..self
+        }
+    }
+
+    /// Set `distinct_false`.
+    pub fn with_distinct_false(self, distinct_false: bool) -> Self {
+        InterpreterConfig {
+            distinct_false,
+            ..self
+        }
+    }
+
+    /// Set `negative_indices`.
+    pub fn with_negative_indices(self, negative_indices: bool) -> Self {
+        InterpreterConfig {
+            negative_indices,
+            ..self
+        }
+    }
+
+    /// Set `max_depth`.
+    pub fn with_max_depth(self, max_depth: Option<usize>) -> Self {
+        InterpreterConfig { max_depth, ..self }
+    }
+
+    /// Set `max_steps`.
+    pub fn with_max_steps(self, max_steps: Option<u64>) -> Self {
+        InterpreterConfig { max_steps, ..self }
+    }
+
+    /// Set `timeout`.
+    pub fn with_timeout(self, timeout: Option<Duration>) -> Self {
+        InterpreterConfig { timeout, ..self }
+    }
+
+    /// Set `max_integer_bits`.
+    pub fn with_max_integer_bits(self, max_integer_bits: Option<u64>) -> Self {
+        InterpreterConfig {
+            max_integer_bits,
+            ..self
+        }
+    }
+
+    /// Set `deny_file_access`.
+    pub fn with_deny_file_access(self, deny_file_access: bool) -> Self {
+        InterpreterConfig {
+            deny_file_access,
+            ..self
+        }
+    }
+
+    /// Check whether a builtin may access the file at `path`. Builtins of other crates touching
+    /// the file system, like the raytracer writing images, call this before doing so.
+    pub fn check_file_access(&self, path: &Path) -> Result<(), EvalError> {
+        if self.deny_file_access {
+            return Err(EvalError::RuntimeError(
+                format!("File access to {} is not allowed", path.display()),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Get the lisp value of a boolean, which is `nil` or `false` for `false` depending on
+    /// `distinct_false`.
+    pub fn boolean(&self, value: bool) -> Expression {
+        Expression::from_bool(value, self.distinct_false)
+    }
+
+    /// Resolve an element index into a collection of length `len`.
+    pub fn index(&self, index: i64, len: usize) -> Result<usize, EvalError> {
+        self.resolve_index(index, len, len)
+    }
+
+    /// Resolve a slice bound into a collection of length `len`, which unlike an element index
+    /// may also point right after the last element.
+    pub fn bound(&self, index: i64, len: usize) -> Result<usize, EvalError> {
+        self.resolve_index(index, len, len + 1)
+    }
+
+    fn resolve_index(&self, index: i64, len: usize, limit: usize) -> Result<usize, EvalError> {
+        let resolved = match index {
+            i if i < 0 && self.negative_indices => len as i64 + i,
+            i => i,
+        };
+        if resolved < 0 || resolved as usize >= limit {
+            return Err(EvalError::IndexOutOfRange(index, len));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+#[derive(Debug)]
 /// A Environment is a stack of `EnvironmentLayer`s. Each `EnvironmentLayer` is a mapping from
 /// variable names to their values.
+///
+/// Values own their contents and lambdas do not capture their environment, while inner
+/// environments only borrow their outer one. So no reference cycles can form and every value is
+/// freed as soon as it is unbound, without a garbage collector. Values which capture an
+/// environment have to keep this property, e.g. by holding it weakly.
+///
+/// Environments are `Send` and `Sync`, so scripts can be evaluated on worker threads, also in
+/// the same environment at once. Each thread has its own call depth, step count, timeout and
+/// backtrace, while the bindings, output, warnings, random number generator and interrupt flag
+/// are shared.
 pub struct Environment<'a> {
-    /// The current mapping.
-    layer: EnvironmentLayer,
+    /// The current mapping. Inner environments only hold a shared reference, so the layer is
+    /// mutable through `update`.
+    layer: RwLock<EnvironmentLayer>,
     /// The outer _fallback_ mapping.
     outer: Option<&'a Environment<'a>>,
     /// A shared layer taking precendence over the outer layer, but not the current layer.
-    shared: Rc<RefCell<EnvironmentLayer>>,
+    shared: Arc<RwLock<EnvironmentLayer>>,
+    /// Evaluation settings, inherited by inner environments.
+    config: InterpreterConfig,
+    /// Whether the shared layer is a fork of the outer shared layer (see `fork_isolated`).
+    isolated: bool,
+    /// The random number generator, shared with inner environments.
+    rng: Arc<Mutex<Rng>>,
+    /// The collected warnings, shared with inner environments.
+    warnings: Arc<Mutex<Vec<Warning>>>,
+    /// Where printed output goes, shared with inner environments.
+    output: Arc<Mutex<OutputSink>>,
+    /// Identifies the evaluations on each thread, shared with inner environments.
+    eval_id: Arc<EvalId>,
+    /// Set to abort the evaluation, shared with inner environments. See `interrupt_flag`.
+    interrupt: Arc<AtomicBool>,
+    /// The observer of function calls, shared with inner environments.
+    observer: Arc<RwLock<Option<Arc<dyn EvalObserver>>>>,
+    /// The traced calls, shared with inner environments.
+    trace: Arc<Mutex<TraceState>>,
+    /// Whether an observer is attached or calls are traced, so calls only take the locks of
+    /// `observer` and `trace` if needed. Shared with inner environments.
+    instrumented: Arc<AtomicBool>,
 }
 
 #[derive(PartialEq, Clone, Debug)]
 /// A concrete EnvironmentLayer, containing a mapping from symbol names to Expressions.
+/// Symbols are kept in insertion order.
 pub struct EnvironmentLayer {
-    symbols: HashMap<String, Expression>,
+    symbols: IndexMap<String, Expression>,
 }
 
 impl EnvironmentLayer {
     /// Construct an empty `EnvironmentLayer`.
     pub fn new() -> Self {
         EnvironmentLayer {
-            symbols: HashMap::new(),
+            symbols: IndexMap::new(),
         }
     }
 
@@ -36,79 +289,187 @@ impl EnvironmentLayer {
     pub fn get(&self, key: &str) -> Option<Expression> {
         self.symbols.get(key).cloned()
     }
+
+    /// Check whether a symbol is bound in the `EnvironmentLayer`.
+    pub fn contains(&self, key: &str) -> bool {
+        self.symbols.contains_key(key)
+    }
+
+    /// Unbind a symbol in the `EnvironmentLayer`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<Expression> {
+        self.symbols.shift_remove(key)
+    }
+
+    /// Get the bound symbols, in insertion order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(String::as_str)
+    }
+}
+
+impl Default for EnvironmentLayer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl From<HashMap<String, Expression>> for EnvironmentLayer {
     fn from(map: HashMap<String, Expression>) -> Self {
+        EnvironmentLayer {
+            symbols: map.into_iter().collect(),
+        }
+    }
+}
+
+impl From<IndexMap<String, Expression>> for EnvironmentLayer {
+    fn from(map: IndexMap<String, Expression>) -> Self {
         EnvironmentLayer { symbols: map }
     }
 }
 
 impl<'a> Environment<'a> {
+    /// Start building an `Environment` from selected groups of builtins.
+    pub fn builder() -> EnvironmentBuilder {
+        EnvironmentBuilder::new()
+    }
+
     /// Construct an empty `Environment`.
     pub fn new() -> Self {
         Environment {
-            layer: EnvironmentLayer::new(),
+            layer: RwLock::new(EnvironmentLayer::new()),
             outer: None,
-            shared: Rc::new(RefCell::new(EnvironmentLayer::new())),
+            shared: Arc::new(RwLock::new(EnvironmentLayer::new())),
+            config: InterpreterConfig::default(),
+            isolated: false,
+            rng: Arc::new(Mutex::new(Rng::default())),
+            warnings: Arc::new(Mutex::new(Vec::new())),
+            output: Arc::new(Mutex::new(OutputSink::default())),
+            eval_id: EvalId::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            observer: Arc::new(RwLock::new(None)),
+            trace: Arc::new(Mutex::new(TraceState::default())),
+            instrumented: Arc::new(AtomicBool::new(false)),
         }
     }
 
     /// Construct an `Environment` from a `EnvironmentLayer` with no outer `Environment`.
     pub fn from_layer(layer: EnvironmentLayer) -> Self {
         Environment {
-            layer,
+            layer: RwLock::new(layer),
             outer: None,
-            shared: Rc::new(RefCell::new(EnvironmentLayer::new())),
+            shared: Arc::new(RwLock::new(EnvironmentLayer::new())),
+            config: InterpreterConfig::default(),
+            isolated: false,
+            rng: Arc::new(Mutex::new(Rng::default())),
+            warnings: Arc::new(Mutex::new(Vec::new())),
+            output: Arc::new(Mutex::new(OutputSink::default())),
+            eval_id: EvalId::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            observer: Arc::new(RwLock::new(None)),
+            trace: Arc::new(Mutex::new(TraceState::default())),
+            instrumented: Arc::new(AtomicBool::new(false)),
         }
     }
 
     /// Construct a new `Environment` with `self` as the outer `Environment`.
     pub fn mk_inner(&'a self) -> Environment<'a> {
         Environment {
-            layer: EnvironmentLayer::new(),
+            layer: RwLock::new(EnvironmentLayer::new()),
             outer: Some(self),
             shared: self.shared.clone(),
+            config: self.config,
+            isolated: self.isolated,
+            rng: self.rng.clone(),
+            warnings: self.warnings.clone(),
+            output: self.output.clone(),
+            eval_id: self.eval_id.clone(),
+            interrupt: self.interrupt.clone(),
+            observer: self.observer.clone(),
+            trace: self.trace.clone(),
+            instrumented: self.instrumented.clone(),
         }
     }
 
     /// Construct a new `Environment` with `self` as the outer `Environment` and `layer` as the
     pub fn overlay(&'a self, layer: EnvironmentLayer) -> Environment<'a> {
         Environment {
-            layer,
-            outer: Some(&self),
+            layer: RwLock::new(layer),
+            outer: Some(self),
             shared: self.shared.clone(),
+            config: self.config,
+            isolated: self.isolated,
+            rng: self.rng.clone(),
+            warnings: self.warnings.clone(),
+            output: self.output.clone(),
+            eval_id: self.eval_id.clone(),
+            interrupt: self.interrupt.clone(),
+            observer: self.observer.clone(),
+            trace: self.trace.clone(),
+            instrumented: self.instrumented.clone(),
+        }
+    }
+
+    /// Construct a new `Environment` with `self` as the outer `Environment` and a shared layer of
+    /// its own. The new shared layer starts out as a copy-on-write view of the shared layer of
+    /// `self`: lookups fall back to it, but values set with `shared_set` do not persist in `self`.
+    pub fn fork_isolated(&'a self) -> Environment<'a> {
+        Environment {
+            layer: RwLock::new(EnvironmentLayer::new()),
+            outer: Some(self),
+            shared: Arc::new(RwLock::new(EnvironmentLayer::new())),
+            config: self.config,
+            isolated: true,
+            rng: self.rng.clone(),
+            warnings: self.warnings.clone(),
+            output: self.output.clone(),
+            eval_id: self.eval_id.clone(),
+            interrupt: self.interrupt.clone(),
+            observer: self.observer.clone(),
+            trace: self.trace.clone(),
+            instrumented: self.instrumented.clone(),
         }
     }
 
     /// Set a value in the shared layer.
-    ///
-    /// Panics:
-    /// - if the shared layer cannot be borrowed mutably.
     pub fn shared_set(&self, key: String, value: Expression) {
-        match self.shared.try_borrow_mut() {
-            Ok(mut shared) => shared.set(key, value),
-            Err(e) => panic!("Cannot borrow shared layer mutably. ({})", e),
-        }
+        self.shared.write().unwrap().set(key, value);
     }
 
     /// Get a value from the shared layer.
     pub fn shared_get(&self, key: &str) -> Option<Expression> {
-        self.shared.borrow().get(key)
+        // Environments are walked in loops, as recursion runs as deep as the lisp calls
+        let mut env = self;
+        loop {
+            if let Some(e) = env.shared.read().unwrap().get(key) {
+                return Some(e);
+            }
+            if !env.isolated {
+                return None;
+            }
+            env = env.outer?;
+        }
     }
 
     /// Get a value from the `Environment`, without looking at the shared layer.
     pub fn layer_get(&self, key: &str) -> Option<Expression> {
-        if let Some(e) = self.layer.get(key) {
-            Some(e)
-        } else {
-            self.outer?.layer_get(key).clone()
+        let mut env = self;
+        loop {
+            if let Some(e) = env.layer.read().unwrap().get(key) {
+                return Some(e);
+            }
+            env = env.outer?;
         }
     }
 
-    /// Get a value from the `Environment`.
+    /// Get a value from the `Environment`. Symbols which are not bound are resolved as module
+    /// members: `module/name` as `name` of the module bound to `module`, and unqualified names
+    /// in the modules imported with `import`.
     pub fn get(&self, key: &str) -> Option<Expression> {
-        if let Some(e) = self.layer.get(key) {
+        self.lookup(key).or_else(|| self.module_get(key))
+    }
+
+    /// Get a value from the `Environment`, without resolving module members.
+    fn lookup(&self, key: &str) -> Option<Expression> {
+        if let Some(e) = self.layer.read().unwrap().get(key) {
             Some(e)
         } else if let Some(e) = self.shared_get(key) {
             Some(e)
@@ -117,9 +478,357 @@ impl<'a> Environment<'a> {
         }
     }
 
+    /// Resolve a qualified symbol, or an unqualified one in the imported modules.
+    fn module_get(&self, key: &str) -> Option<Expression> {
+        match key.split_once('/') {
+            Some((module, name)) if !module.is_empty() && !name.is_empty() => {
+                self.module_member(module, name)
+            }
+            _ => {
+                let imports: Vec<Expression> = self.lookup(IMPORTS_SYMBOL)?.try_into().ok()?;
+                imports.iter().find_map(|module| match module {
+                    Expression::Symbol(module) => self.module_member(module, key),
+                    _ => None,
+                })
+            }
+        }
+    }
+
+    /// Get a member of the module, i.e. symbol keyed map, bound to `module`.
+    fn module_member(&self, module: &str, name: &str) -> Option<Expression> {
+        match self.lookup(module)? {
+            Expression::Map(members) => members.get(&MapKey::Symbol(name.to_string())).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Get the values set in the shared layer, in the order they were first set.
+    pub(crate) fn shared_bindings(&self) -> Vec<(String, Expression)> {
+        self.shared
+            .read()
+            .unwrap()
+            .symbols
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    /// Iterate over the global bindings, i.e. those of the outermost layer and the shared layer,
+    /// in the order they were first set. Symbols bound in both are listed once, with the value
+    /// they resolve to at the top level.
+    pub fn iter_globals(&self) -> impl Iterator<Item = (String, Expression)> {
+        let mut root = self;
+        while let Some(outer) = root.outer {
+            root = outer;
+        }
+        let mut globals = root.layer.read().unwrap().symbols.clone();
+        let mut env = Some(self);
+        while let Some(current) = env {
+            for (k, v) in current.shared_bindings() {
+                globals.entry(k).or_insert(v);
+            }
+            env = current.outer.filter(|_| current.isolated);
+        }
+        globals.into_iter()
+    }
+
     /// Set a value in the current `EnvironmentLayer`.
     pub fn set(&mut self, key: String, value: Expression) {
-        self.layer.set(key, value);
+        self.layer.get_mut().unwrap().set(key, value);
+    }
+
+    /// Bind a closure as a native function in the current `EnvironmentLayer`, so builtins can
+    /// capture state of the host. The closure gets its arguments unevaluated, like the
+    /// functions of `Expression::Function`.
+    pub fn register_closure<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&Environment, Expression) -> Result<Expression, EvalError> + Send + Sync + 'static,
+    {
+        self.set(name.to_string(), NativeClosure::new(f).into());
+    }
+
+    /// Update the innermost existing binding of a symbol, searching in the same order as `get`.
+    /// Bindings outside of an environment made with `fork_isolated` are not changed, the symbol
+    /// is bound in the shared layer of the fork instead. Returns `false` if the symbol is not
+    /// bound.
+    pub fn update(&self, key: &str, value: Expression) -> bool {
+        if self.layer.read().unwrap().contains(key) {
+            self.layer.write().unwrap().set(key.to_string(), value);
+            true
+        } else if self.shared_get(key).is_some() {
+            self.shared_set(key.to_string(), value);
+            true
+        } else {
+            self.outer_update(key, value)
+        }
+    }
+
+    /// Update the innermost existing binding of a symbol in the layers of the outer
+    /// environments, stopping at the boundary of an isolated fork.
+    fn outer_update(&self, key: &str, value: Expression) -> bool {
+        let mut env = self;
+        while let Some(outer) = env.outer {
+            if env.isolated && !Arc::ptr_eq(&env.shared, &outer.shared) {
+                let bound = outer.layer_get(key).is_some();
+                if bound {
+                    env.shared_set(key.to_string(), value);
+                }
+                return bound;
+            }
+            if outer.layer.read().unwrap().contains(key) {
+                outer.layer.write().unwrap().set(key.to_string(), value);
+                return true;
+            }
+            env = outer;
+        }
+        false
+    }
+
+    /// Get the random number generator, which is locked until the guard is dropped.
+    pub fn rng(&self) -> MutexGuard<'_, Rng> {
+        self.rng.lock().unwrap()
+    }
+
+    /// Report a warning, which does not abort the evaluation. Warnings are collected until the
+    /// host takes them with `take_warnings`.
+    pub fn warn(&self, warning: Warning) {
+        self.warnings.lock().unwrap().push(warning);
+    }
+
+    /// Take all warnings reported since the last call.
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings.lock().unwrap())
+    }
+
+    /// Record a call an error propagates through, innermost first, with the key of the called
+    /// list (see `Spans::key`). Frames beyond `MAX_BACKTRACE` are dropped.
+    pub(crate) fn push_frame(&self, frame: String, list: usize) {
+        self.with_evaluation(|evaluation| {
+            if evaluation.backtrace.len() < MAX_BACKTRACE {
+                evaluation.backtrace.push((frame, list));
+            }
+        });
+    }
+
+    /// Run `f` with the state of the evaluation on the current thread.
+    fn with_evaluation<T>(&self, f: impl FnOnce(&mut Evaluation) -> T) -> T {
+        EVALUATIONS.with_borrow_mut(|evaluations| {
+            let id = self.eval_id.0;
+            let index = match evaluations.iter().position(|e| e.id == id) {
+                Some(index) => index,
+                None => {
+                    evaluations.push(Evaluation {
+                        id,
+                        depth: 0,
+                        steps: 0,
+                        started: None,
+                        backtrace: Vec::new(),
+                    });
+                    evaluations.len() - 1
+                }
+            };
+            f(&mut evaluations[index])
+        })
+    }
+
+    /// Enter a function call, checking the interrupt flag and the limits of the configuration. A
+    /// call at the top level starts a new evaluation on the current thread, resetting the steps,
+    /// the timeout, the interrupt flag and the backtrace left by a failed evaluation whose error
+    /// was used without `take_backtrace`. The call is left when the returned guard is dropped.
+    pub(crate) fn enter_call(&self) -> Result<CallGuard<'_>, EvalError> {
+        let error = self.with_evaluation(|evaluation| {
+            if evaluation.depth == 0 {
+                evaluation.steps = 0;
+                evaluation.started = self.config.timeout.map(|_| Instant::now());
+                evaluation.backtrace.clear();
+                self.interrupt.store(false, Ordering::Relaxed);
+            }
+            evaluation.depth += 1;
+            evaluation.steps += 1;
+            self.check_limits(evaluation)
+        });
+
+        let guard = CallGuard(self);
+        match error {
+            Some(e) => Err(e),
+            None => Ok(guard),
+        }
+    }
+
+    /// Check an evaluation against the interrupt flag and the limits of the configuration.
+    fn check_limits(&self, evaluation: &Evaluation) -> Option<EvalError> {
+        match self.config {
+            _ if self.interrupt.load(Ordering::Relaxed) => Some(EvalError::Interrupted),
+            InterpreterConfig {
+                max_depth: Some(max),
+                ..
+            } if evaluation.depth > max => Some(EvalError::LimitExceeded(format!(
+                "Maximum recursion depth of {} exceeded",
+                max
+            ))),
+            InterpreterConfig {
+                max_steps: Some(max),
+                ..
+            } if evaluation.steps > max => Some(EvalError::LimitExceeded(format!(
+                "Maximum of {} steps exceeded",
+                max
+            ))),
+            InterpreterConfig {
+                timeout: Some(timeout),
+                ..
+            } if evaluation.steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL)
+                && evaluation.started.is_some_and(|s| s.elapsed() > timeout) =>
+            {
+                Some(EvalError::LimitExceeded(format!(
+                    "Timeout of {:?} exceeded",
+                    timeout
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the flag interrupting the evaluation. Setting it, e.g. from a signal handler or
+    /// another thread, makes the running evaluations fail with `EvalError::Interrupted` at the
+    /// next function call. The flag is cleared when the next top level evaluation starts.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Attach an observer receiving the function calls of evaluations in this environment and
+    /// the environments sharing its state, or detach it with `None`. Returns the observer
+    /// attached before.
+    pub fn set_observer(
+        &self,
+        observer: Option<Arc<dyn EvalObserver>>,
+    ) -> Option<Arc<dyn EvalObserver>> {
+        let previous = std::mem::replace(&mut *self.observer.write().unwrap(), observer);
+        self.update_instrumented();
+        previous
+    }
+
+    /// Get the attached observer of function calls.
+    pub(crate) fn observer(&self) -> Option<Arc<dyn EvalObserver>> {
+        if !self.instrumented.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.observer.read().unwrap().clone()
+    }
+
+    /// Update the `instrumented` flag after attaching an observer or changing the traced calls.
+    fn update_instrumented(&self) {
+        let observed = self.observer.read().unwrap().is_some();
+        let trace = self.trace.lock().unwrap();
+        let traced = !trace.functions.is_empty() || trace.step_depth.is_some();
+        self.instrumented
+            .store(observed || traced, Ordering::Relaxed);
+    }
+
+    /// Print the calls of the function bound to `name` with their arguments and values to the
+    /// output, until `untrace` is called.
+    pub fn trace(&self, name: &str) {
+        self.trace
+            .lock()
+            .unwrap()
+            .functions
+            .insert(name.to_string());
+        self.update_instrumented();
+    }
+
+    /// Stop printing the calls of the function bound to `name`. Returns whether it was traced.
+    pub fn untrace(&self, name: &str) -> bool {
+        let traced = self.trace.lock().unwrap().functions.remove(name);
+        self.update_instrumented();
+        traced
+    }
+
+    /// Get the names of the traced functions, sorted.
+    pub fn traced_functions(&self) -> Vec<String> {
+        self.trace
+            .lock()
+            .unwrap()
+            .functions
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Print all calls nested at most `depth` deep like the calls of traced functions, where the
+    /// top level call has depth 1. With `None` only traced functions are printed. Returns the
+    /// previous depth.
+    pub fn set_step_depth(&self, depth: Option<usize>) -> Option<usize> {
+        let previous = std::mem::replace(&mut self.trace.lock().unwrap().step_depth, depth);
+        self.update_instrumented();
+        previous
+    }
+
+    /// Check whether the call of a function through `head` is printed.
+    pub(crate) fn traces(&self, head: &Expression) -> bool {
+        if !self.instrumented.load(Ordering::Relaxed) {
+            return false;
+        }
+        let trace = self.trace.lock().unwrap();
+        match trace.step_depth {
+            Some(max) if self.with_evaluation(|evaluation| evaluation.depth) <= max => true,
+            _ => matches!(head, Expression::Symbol(name) if trace.functions.contains(name)),
+        }
+    }
+
+    /// Enter a printed call, returning the number of printed calls it is nested in.
+    pub(crate) fn trace_enter(&self) -> usize {
+        let mut trace = self.trace.lock().unwrap();
+        trace.indent += 1;
+        trace.indent - 1
+    }
+
+    /// Leave a printed call.
+    pub(crate) fn trace_leave(&self) {
+        let mut trace = self.trace.lock().unwrap();
+        trace.indent = trace.indent.saturating_sub(1);
+    }
+
+    /// Take the frames recorded on the current thread since the last call, or since the last
+    /// error was handled.
+    pub fn take_backtrace(&self) -> Vec<String> {
+        self.take_frames()
+            .into_iter()
+            .map(|(frame, _)| frame)
+            .collect()
+    }
+
+    /// Like `take_backtrace`, with the keys of the called lists.
+    pub(crate) fn take_frames(&self) -> Vec<(String, usize)> {
+        self.with_evaluation(|evaluation| std::mem::take(&mut evaluation.backtrace))
+    }
+
+    /// Write printed output, like that of `print`, to the innermost capture, the writer set with
+    /// `set_output` or standard output.
+    pub fn write_output(&self, s: &str) -> Result<(), EvalError> {
+        Ok(self.output.lock().unwrap().write(s)?)
+    }
+
+    /// Send printed output which is not captured to `writer` instead of standard output.
+    pub fn set_output(&self, writer: Box<dyn std::io::Write + Send>) {
+        self.output.lock().unwrap().set_writer(writer);
+    }
+
+    /// Call `f` and capture the output printed meanwhile. Returns the result of `f` and the
+    /// output.
+    pub fn capture_output<T>(&self, f: impl FnOnce() -> T) -> (T, String) {
+        self.output.lock().unwrap().begin_capture();
+        let result = f();
+        let output = self.output.lock().unwrap().end_capture();
+        (result, output)
+    }
+
+    /// Get the evaluation settings.
+    pub fn config(&self) -> &InterpreterConfig {
+        &self.config
+    }
+
+    /// Replace the evaluation settings. Inner environments created afterwards inherit them.
+    pub fn set_config(&mut self, config: InterpreterConfig) {
+        self.config = config;
     }
 }
 
@@ -129,9 +838,19 @@ impl Default for Environment<'_> {
         let mut d = EnvironmentLayer::new();
         mk_prelude(&mut d);
         Environment {
-            layer: d,
+            layer: RwLock::new(d),
             outer: None,
-            shared: Rc::new(RefCell::new(EnvironmentLayer::new())),
+            shared: Arc::new(RwLock::new(EnvironmentLayer::new())),
+            config: InterpreterConfig::default(),
+            isolated: false,
+            rng: Arc::new(Mutex::new(Rng::default())),
+            warnings: Arc::new(Mutex::new(Vec::new())),
+            output: Arc::new(Mutex::new(OutputSink::default())),
+            eval_id: EvalId::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            observer: Arc::new(RwLock::new(None)),
+            trace: Arc::new(Mutex::new(TraceState::default())),
+            instrumented: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -149,3 +868,195 @@ fn test_environment() {
     assert_eq!(env.get("b"), Some(Expression::Integer(2)));
     assert_eq!(env.get("c"), None);
 }
+
+#[test]
+fn test_environment_update() {
+    let mut env = Environment::new();
+    env.set("a".to_string(), Expression::Integer(1));
+    env.shared_set("b".to_string(), Expression::Integer(2));
+    let inner = env.mk_inner();
+    let mut innermost = inner.mk_inner();
+    innermost.set("c".to_string(), Expression::Integer(3));
+
+    assert!(innermost.update("a", Expression::Integer(10)));
+    assert!(innermost.update("b", Expression::Integer(20)));
+    assert!(innermost.update("c", Expression::Integer(30)));
+    assert!(!innermost.update("d", Expression::Integer(40)));
+    assert_eq!(innermost.get("c"), Some(Expression::Integer(30)));
+    assert_eq!(innermost.get("d"), None);
+    assert_eq!(env.get("a"), Some(Expression::Integer(10)));
+    assert_eq!(env.get("b"), Some(Expression::Integer(20)));
+}
+
+#[test]
+fn test_iter_globals() {
+    let mut env = Environment::new();
+    env.set("a".to_string(), Expression::Integer(1));
+    env.shared_set("b".to_string(), Expression::Integer(2));
+    env.shared_set("a".to_string(), Expression::Integer(3));
+    let mut inner = env.mk_inner();
+    inner.set("c".to_string(), Expression::Integer(4));
+    let sandbox = inner.fork_isolated();
+    sandbox.shared_set("d".to_string(), Expression::Integer(5));
+    sandbox.shared_set("b".to_string(), Expression::Integer(6));
+
+    let globals = |env: &Environment| env.iter_globals().collect::<Vec<_>>();
+    assert_eq!(
+        globals(&inner),
+        vec![
+            ("a".to_string(), Expression::Integer(1)),
+            ("b".to_string(), Expression::Integer(2)),
+        ]
+    );
+    assert_eq!(
+        globals(&sandbox),
+        vec![
+            ("a".to_string(), Expression::Integer(1)),
+            ("d".to_string(), Expression::Integer(5)),
+            ("b".to_string(), Expression::Integer(6)),
+        ]
+    );
+}
+
+#[test]
+fn test_no_leaks() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    eval_source(
+        &env,
+        "(define count (lambda (n) (if (= n 0) count (count (- n 1)))))
+         (set 'self (count 10))
+         (set 'table (make-map (list (cons 'f self))))
+         (sandbox (set 'inner count) (inner 3))
+         (catch (throw 'error) (lambda (e) e))
+         (gc)",
+    )
+    .unwrap();
+
+    // All inner environments are dropped, leaving no references to the shared state
+    assert_eq!(Arc::strong_count(&env.shared), 1);
+    assert_eq!(Arc::strong_count(&env.rng), 1);
+    assert_eq!(Arc::strong_count(&env.warnings), 1);
+    assert_eq!(Arc::strong_count(&env.output), 1);
+    assert_eq!(Arc::strong_count(&env.eval_id), 1);
+    assert_eq!(Arc::strong_count(&env.interrupt), 1);
+}
+
+#[test]
+fn test_threads() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    eval_source(
+        &env,
+        "(defun fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))",
+    )
+    .unwrap();
+
+    // Evaluate in the same environment on several threads at once
+    let values: Vec<Expression> = std::thread::scope(|s| {
+        let workers: Vec<_> = (10..14)
+            .map(|n| {
+                let env = &env;
+                s.spawn(move || eval_source(env, &format!("(fib {})", n)).unwrap().remove(0))
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).collect()
+    });
+    assert_eq!(values, [55, 89, 144, 233].map(Expression::Integer).to_vec());
+
+    // Move an environment to a worker thread
+    let worker = std::thread::spawn(move || eval_source(&env, "(fib 15)").unwrap().remove(0));
+    assert_eq!(worker.join().unwrap(), Expression::Integer(610));
+}
+
+#[test]
+fn test_concurrent_evaluations() {
+    use super::{eval_source, LispError};
+    use std::sync::Barrier;
+
+    let mut env = Environment::default();
+    env.set_config(InterpreterConfig::default().with_max_steps(Some(2000)));
+    let barrier = Arc::new(Barrier::new(2));
+    env.shared_set(
+        "wait".to_string(),
+        Expression::NativeClosure(NativeClosure::new(move |_, _| {
+            barrier.wait();
+            Ok(Expression::Nil)
+        })),
+    );
+    eval_source(
+        &env,
+        "(defun count (n) (if (= n 0) 0 (count (- n 1))))
+         (defun fail-a () (car 1))
+         (defun fail-b () (list (cdr 1)))",
+    )
+    .unwrap();
+
+    // Both threads are in the middle of an evaluation at once
+    let run = |program: &'static str| {
+        let env = &env;
+        move || match eval_source(env, program) {
+            Ok(mut values) => Ok(values.pop().unwrap()),
+            Err(LispError::Eval(e)) => Err((e.root().clone(), e.backtrace().to_vec())),
+            Err(e) => panic!("Unexpected error {:?}", e),
+        }
+    };
+    let (a, b) = std::thread::scope(|s| {
+        let a = s.spawn(run(
+            "(progn (count 200) (wait) (count 200) (wait) (fail-a))",
+        ));
+        let b = s.spawn(run(
+            "(progn (count 200) (wait) (count 200) (wait) (fail-b))",
+        ));
+        (a.join().unwrap(), b.join().unwrap())
+    });
+
+    // Each thread only counts its own steps against the limit and records its own calls
+    assert!(
+        matches!(a, Err((EvalError::TypeError(_), ref calls)) if calls == &["car", "fail-a", "progn"])
+    );
+    assert!(
+        matches!(b, Err((EvalError::TypeError(_), ref calls)) if calls == &["cdr", "list", "fail-b", "progn"])
+    );
+    let (a, b) = std::thread::scope(|s| {
+        let a = s.spawn(run("(progn (wait) (count 1000))"));
+        let b = s.spawn(run("(progn (wait) (count 10))"));
+        (a.join().unwrap(), b.join().unwrap())
+    });
+    assert!(matches!(a, Err((EvalError::LimitExceeded(_), _))));
+    assert_eq!(b, Ok(Expression::Integer(0)));
+}
+
+#[test]
+fn test_native_closure() {
+    use super::eval::eval;
+    use super::eval_source;
+    use std::sync::atomic::AtomicI64;
+
+    let total = Arc::new(AtomicI64::new(0));
+    let mut env = Environment::default();
+    let counter = total.clone();
+    env.register_closure("add-to-total", move |env, args| {
+        let [x]: [Expression; 1] = args.try_into()?;
+        let x: i64 = eval(env, x)?.try_into()?;
+        Ok(Expression::Integer(
+            counter.fetch_add(x, Ordering::Relaxed) + x,
+        ))
+    });
+
+    assert_eq!(
+        eval_source(
+            &env,
+            "(add-to-total 2) (map add-to-total '(3 4)) (eq? add-to-total add-to-total)"
+        ),
+        Ok(vec![
+            Expression::Integer(2),
+            vec![Expression::Integer(5), Expression::Integer(9)].into(),
+            Expression::True
+        ])
+    );
+    assert_eq!(total.load(Ordering::Relaxed), 9);
+    assert_eq!(env.get("add-to-total").unwrap().to_string(), "<function>");
+}
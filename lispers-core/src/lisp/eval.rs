@@ -1,12 +1,20 @@
 use std::fmt::Display;
+use std::sync::Arc;
 
+use crate::parser::token::Position;
+use crate::parser::ExpressionStream;
 use crate::parser::ParserError;
+use crate::parser::Spans;
 
 use super::environment::Environment;
 use super::environment::EnvironmentLayer;
 use super::expression::Expression;
+use super::printer::truncate;
+use super::profile::observe_call;
+use super::trace::trace_call;
 
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 /// All possible evaluation errors
 pub enum EvalError {
     SymbolNotBound(String),
@@ -15,8 +23,185 @@ pub enum EvalError {
     ArgumentError(String),
     TypeError(String),
     NotASymbol(Expression),
-    RuntimeError(String),
+    /// A failure of a builtin, with the error causing it if there is one.
+    RuntimeError(String, Option<ErrorSource>),
+    /// An index and the length of the indexed collection.
+    IndexOutOfRange(i64, usize),
     ParserError(ParserError),
+    /// A value thrown by lisp code using `throw`.
+    LispThrow(Expression),
+    /// A failure raised by lisp code using `error` or `assert`, carrying a lisp value like the
+    /// message.
+    Raised(Expression),
+    /// A limit of the `InterpreterConfig`, like the maximum recursion depth, was exceeded.
+    LimitExceeded(String),
+    /// The evaluation was interrupted with the flag of `Environment::interrupt_flag`.
+    Interrupted,
+    /// An error raised while evaluating the list starting at the given source position, which is
+    /// the innermost failing call with a known position (see `locate_error`).
+    Located(Position, Box<EvalError>),
+    /// An error with the calls it propagated through, innermost first.
+    Traced(Vec<String>, Box<EvalError>),
+}
+
+/// The maximum number of calls recorded for the backtrace of an error.
+pub const MAX_BACKTRACE: usize = 64;
+
+/// The stack space a function call needs at least, more than any builtin uses before evaluating
+/// its arguments. With less left, the call continues on a new stack segment.
+pub(crate) const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// The size of the stack segments allocated for deeply nested calls.
+pub(crate) const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// An error of native code causing a `RuntimeError`. Sources are shared, so errors stay cheap
+/// to clone, and compare equal if their messages do.
+#[derive(Debug, Clone)]
+pub struct ErrorSource(pub Arc<dyn std::error::Error + Send + Sync>);
+
+impl PartialEq for ErrorSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl Display for ErrorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl EvalError {
+    /// Create a `RuntimeError` caused by `source`, using its message.
+    pub fn from_error<E: std::error::Error + Send + Sync + 'static>(source: E) -> EvalError {
+        EvalError::RuntimeError(source.to_string(), Some(ErrorSource(Arc::new(source))))
+    }
+
+    /// Create a `RuntimeError` caused by `source`, prefixing its message with some context like
+    /// the path of a file: `"{context}: {source}"`.
+    pub fn with_context<E: std::error::Error + Send + Sync + 'static>(
+        context: impl Display,
+        source: E,
+    ) -> EvalError {
+        EvalError::RuntimeError(
+            format!("{}: {}", context, source),
+            Some(ErrorSource(Arc::new(source))),
+        )
+    }
+
+    /// Attach a source position to the error. Errors that are already located keep their
+    /// innermost position.
+    pub fn at(self, position: Position) -> EvalError {
+        match self {
+            EvalError::Located(_, _) => self,
+            EvalError::Traced(t, e) => EvalError::Traced(t, Box::new(e.at(position))),
+            e => EvalError::Located(position, Box::new(e)),
+        }
+    }
+
+    /// Attach the calls the error propagated through, as taken from
+    /// `Environment::take_backtrace`. Errors without calls are left as they are.
+    pub fn traced(self, backtrace: Vec<String>) -> EvalError {
+        match self {
+            e if backtrace.is_empty() => e,
+            EvalError::Traced(mut t, e) => {
+                t.extend(backtrace);
+                EvalError::Traced(t, e)
+            }
+            e => EvalError::Traced(backtrace, Box::new(e)),
+        }
+    }
+
+    /// Get the calls the error propagated through, innermost first.
+    pub fn backtrace(&self) -> &[String] {
+        match self {
+            EvalError::Traced(t, _) => t,
+            EvalError::Located(_, e) => e.backtrace(),
+            _ => &[],
+        }
+    }
+
+    /// Get the error without its source position and backtrace.
+    pub fn root(&self) -> &EvalError {
+        match self {
+            EvalError::Located(_, e) | EvalError::Traced(_, e) => e.root(),
+            e => e,
+        }
+    }
+
+    /// Whether the error aborts the whole evaluation, like an exceeded limit or an interrupt.
+    /// Such errors are not handled by `catch`, so scripts cannot ignore them.
+    pub fn is_abort(&self) -> bool {
+        matches!(
+            self.root(),
+            EvalError::LimitExceeded(_) | EvalError::Interrupted
+        )
+    }
+
+    /// Get the kind of the error as named in lisp conditions, like `type-error`. Located and
+    /// traced errors have the kind of the wrapped error, thrown and raised values have none.
+    pub fn kind(&self) -> Option<&'static str> {
+        let kind = match self {
+            EvalError::LispThrow(_) | EvalError::Raised(_) => return None,
+            EvalError::Located(_, e) | EvalError::Traced(_, e) => return e.kind(),
+            EvalError::SymbolNotBound(_) => "symbol-not-bound",
+            EvalError::NotAFunction(_) => "not-a-function",
+            EvalError::NotANumber(_) => "not-a-number",
+            EvalError::ArgumentError(_) => "argument-error",
+            EvalError::TypeError(_) => "type-error",
+            EvalError::NotASymbol(_) => "not-a-symbol",
+            EvalError::RuntimeError(_, _) => "runtime-error",
+            EvalError::IndexOutOfRange(_, _) => "index-out-of-range",
+            EvalError::ParserError(_) => "parser-error",
+            EvalError::LimitExceeded(_) => "limit-exceeded",
+            EvalError::Interrupted => "interrupted",
+        };
+        Some(kind)
+    }
+
+    /// Convert the error to a lisp condition object, as passed to `catch` handlers.
+    /// Thrown and raised values are returned as is, all other errors as a `(kind . "message")`
+    /// pair. The message leaves out the source position and the backtrace.
+    pub fn into_condition(self) -> Expression {
+        match self.root() {
+            EvalError::LispThrow(e) | EvalError::Raised(e) => e.clone(),
+            e => Expression::Cell(
+                Arc::new(Expression::Symbol(e.kind().unwrap_or_default().to_string())),
+                Arc::new(Expression::String(e.to_string())),
+            ),
+        }
+    }
+}
+
+/// Conversions which cannot fail, like of an `Expression` into itself, e.g. in a pair.
+impl From<std::convert::Infallible> for EvalError {
+    fn from(value: std::convert::Infallible) -> Self {
+        match value {}
+    }
+}
+
+impl From<std::io::Error> for EvalError {
+    fn from(value: std::io::Error) -> Self {
+        EvalError::from_error(value)
+    }
+}
+
+impl From<std::num::ParseIntError> for EvalError {
+    fn from(value: std::num::ParseIntError) -> Self {
+        EvalError::from_error(value)
+    }
+}
+
+impl From<std::num::ParseFloatError> for EvalError {
+    fn from(value: std::num::ParseFloatError) -> Self {
+        EvalError::from_error(value)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for EvalError {
+    fn from(value: std::string::FromUtf8Error) -> Self {
+        EvalError::from_error(value)
+    }
 }
 
 impl From<ParserError> for EvalError {
@@ -34,8 +219,122 @@ impl Display for EvalError {
             EvalError::ArgumentError(s) => write!(f, "Argument error: {}", s),
             EvalError::TypeError(s) => write!(f, "Type error: {}", s),
             EvalError::NotASymbol(e) => write!(f, "Expression {} is not a symbol", e),
-            EvalError::RuntimeError(s) => write!(f, "Runtime error: {}", s),
+            EvalError::RuntimeError(s, _) => write!(f, "Runtime error: {}", s),
+            EvalError::IndexOutOfRange(i, len) => {
+                write!(f, "Index {} is out of range for length {}", i, len)
+            }
             EvalError::ParserError(s) => write!(f, "Parser error: {}", s),
+            EvalError::LispThrow(e) => write!(f, "Uncaught throw: {}", e),
+            EvalError::Raised(Expression::String(s)) => write!(f, "Runtime error: {}", s),
+            EvalError::Raised(e) => write!(f, "Runtime error: {}", e),
+            EvalError::LimitExceeded(s) => write!(f, "Limit exceeded: {}", s),
+            EvalError::Interrupted => write!(f, "Interrupted"),
+            EvalError::Located(p, e) => write!(f, "{} (at {})", e, p),
+            EvalError::Traced(t, e) => {
+                write!(f, "{}", e)?;
+                for frame in t {
+                    write!(f, "\n  in {}", frame)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {
+    /// The native error causing a `RuntimeError` or the error of the parser. Source positions
+    /// and backtraces are part of the message, so located and traced errors have the source of
+    /// the wrapped error.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EvalError::RuntimeError(_, Some(source)) => Some(source.0.as_ref()),
+            EvalError::ParserError(e) => Some(e),
+            EvalError::Located(_, e) | EvalError::Traced(_, e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+/// A problem found during evaluation, which unlike an `EvalError` does not abort it. Warnings are
+/// collected by the `Environment` (see `Environment::warn`).
+pub struct Warning {
+    pub message: String,
+    /// The source position of the top level expression which raised the warning, if known.
+    pub position: Option<Position>,
+}
+
+impl Warning {
+    /// Create a warning without a source position.
+    pub fn new(message: String) -> Self {
+        Warning {
+            message,
+            position: None,
+        }
+    }
+
+    /// Attach a source position to the warning, unless it already has one.
+    pub fn at(self, position: Position) -> Warning {
+        Warning {
+            position: self.position.or(Some(position)),
+            ..self
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.position {
+            Some(p) => write!(f, "{} (at {})", self.message, p),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+/// Errors of evaluating lisp source code, which can fail while parsing or evaluating.
+pub enum LispError {
+    Parser(ParserError),
+    Eval(EvalError),
+}
+
+impl From<ParserError> for LispError {
+    fn from(value: ParserError) -> Self {
+        LispError::Parser(value)
+    }
+}
+
+impl From<EvalError> for LispError {
+    fn from(value: EvalError) -> Self {
+        LispError::Eval(value)
+    }
+}
+
+impl From<LispError> for EvalError {
+    fn from(value: LispError) -> Self {
+        match value {
+            LispError::Parser(e) => EvalError::ParserError(e),
+            LispError::Eval(e) => e,
+        }
+    }
+}
+
+impl Display for LispError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LispError::Parser(e) => write!(f, "Parser error: {}", e),
+            LispError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LispError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LispError::Parser(e) => Some(e),
+            LispError::Eval(e) => e.source(),
         }
     }
 }
@@ -59,17 +358,13 @@ impl Iterator for CellIterator {
         if let Some(expr) = self.expr.take() {
             match expr {
                 Expression::Cell(head, tail) => {
-                    self.expr = Some(*tail);
-                    return Some(Ok(*head));
-                }
-                Expression::Nil => {
-                    return None;
-                }
-                _ => {
-                    return Some(Err(EvalError::TypeError(
-                        "Expected a cell or nil".to_string(),
-                    )));
+                    self.expr = Some(Arc::unwrap_or_clone(tail));
+                    Some(Ok(Arc::unwrap_or_clone(head)))
                 }
+                Expression::Nil => None,
+                _ => Some(Err(EvalError::TypeError(
+                    "Expected a cell or nil".to_string(),
+                ))),
             }
         } else {
             None
@@ -77,13 +372,25 @@ impl Iterator for CellIterator {
     }
 }
 
-/// Dispatch an anonymous function call. Evaluates `body` in `env`, binding `args` to `argument_symbols`
-fn dispatch_anonymous_function(
+/// Evaluate body expressions in order, returning the value of the last one (or nil).
+pub fn eval_body(
     env: &Environment,
-    argument_symbols: Vec<String>,
-    body: Expression,
-    args: Expression,
+    body: impl IntoIterator<Item = Expression>,
 ) -> Result<Expression, EvalError> {
+    let mut result = Expression::Nil;
+    for e in body {
+        result = eval(env, e)?;
+    }
+    Ok(result)
+}
+
+/// Evaluate the arguments `args` of an anonymous function call and bind them to `argument_symbols`
+/// in a new layer, to evaluate the body in.
+fn bind_arguments(
+    env: &Environment,
+    argument_symbols: &[String],
+    args: Expression,
+) -> Result<EnvironmentLayer, EvalError> {
     let mut args: Vec<Expression> = args.try_into()?;
 
     let mut overlay = EnvironmentLayer::new();
@@ -100,22 +407,356 @@ fn dispatch_anonymous_function(
         overlay.set(symbol.to_owned(), eval(env, arg.to_owned())?);
     }
 
-    eval(&env.overlay(overlay), body)
+    Ok(overlay)
 }
 
-/// Evaluate an expression inside an environment
+/// Evaluate an expression inside an environment. When the stack runs low, function calls
+/// continue on a newly allocated stack segment, so deep recursion cannot overflow the stack of
+/// the calling thread.
+///
+/// Symbols starting with `:` are keywords, which evaluate to themselves to name options like
+/// `:overwrite` or serve as map keys without quoting. They are never looked up, so a value bound
+/// to a keyword cannot be read back.
 pub fn eval(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     match expr {
-        Expression::Cell(lhs, rhs) => match eval(env, *lhs)? {
-            Expression::Function(f) => f(env, *rhs),
-            Expression::AnonymousFunction {
-                argument_symbols,
-                body,
-            } => dispatch_anonymous_function(env, argument_symbols, *body, *rhs),
-            a => Err(EvalError::NotAFunction(a)),
-        },
-        Expression::Quote(e) => Ok(*e),
+        Expression::Cell(lhs, rhs) => stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            let _call = env.enter_call()?;
+            let head = Arc::clone(&lhs);
+            let function = eval(env, Arc::unwrap_or_clone(lhs))?;
+            let builtin = matches!(
+                function,
+                Expression::Function(_) | Expression::NativeClosure(_)
+            );
+            let traced = env.traces(&head);
+            let call = || match function {
+                Expression::Function(f) if traced => {
+                    let args = Arc::unwrap_or_clone(rhs);
+                    trace_call(env, &head, args.clone(), || f(env, args))
+                }
+                Expression::Function(f) => f(env, Arc::unwrap_or_clone(rhs)),
+                Expression::NativeClosure(f) if traced => {
+                    let args = Arc::unwrap_or_clone(rhs);
+                    trace_call(env, &head, args.clone(), || f.call(env, args))
+                }
+                Expression::NativeClosure(f) => f.call(env, Arc::unwrap_or_clone(rhs)),
+                Expression::AnonymousFunction {
+                    argument_symbols,
+                    body,
+                } => {
+                    let overlay =
+                        bind_arguments(env, &argument_symbols, Arc::unwrap_or_clone(rhs))?;
+                    let args = traced.then(|| {
+                        argument_symbols
+                            .iter()
+                            .filter_map(|s| overlay.get(s))
+                            .collect::<Vec<_>>()
+                    });
+                    let call = || eval_body(&env.overlay(overlay), body.iter().cloned());
+                    match args {
+                        Some(args) => trace_call(env, &head, args.into(), call),
+                        None => call(),
+                    }
+                }
+                a => Err(EvalError::NotAFunction(a)),
+            };
+            match env.observer() {
+                Some(observer) => {
+                    let name = truncate(&head, Some(1), Some(3)).to_string();
+                    observe_call(observer.as_ref(), &name, builtin, call)
+                }
+                None => call(),
+            }
+            .inspect_err(|_| {
+                let frame = truncate(&head, Some(1), Some(3)).to_string();
+                env.push_frame(frame, Spans::key(&head))
+            })
+        }),
+        Expression::Quote(e) => Ok(Arc::unwrap_or_clone(e)),
+        // Keywords like `:overwrite` evaluate to themselves, see above
+        Expression::Symbol(s) if s.starts_with(':') => Ok(Expression::Symbol(s)),
         Expression::Symbol(s) => env.get(&s).ok_or(EvalError::SymbolNotBound(s)),
+        Expression::False if !env.config().distinct_false => Ok(Expression::Nil),
         x => Ok(x),
     }
 }
+
+/// Parse all expressions of `source` and evaluate them in order, returning their values.
+/// Nothing is evaluated if the source does not parse, evaluation stops at the first error,
+/// which carries the source position of the failing expression.
+///
+/// ```
+/// use lispers_core::lisp::{eval_source, Environment, Expression};
+///
+/// let env = Environment::default();
+/// let values = eval_source(&env, "(set 'x 20) (+ x 22)").unwrap();
+/// assert_eq!(values[1], Expression::Integer(42));
+/// ```
+pub fn eval_source(env: &Environment, source: &str) -> Result<Vec<Expression>, LispError> {
+    let mut stream = ExpressionStream::from_char_stream(source.chars());
+    let expressions = std::iter::from_fn(|| stream.next_positioned())
+        .map(|(pos, expr)| expr.map(|e| (pos, e)))
+        .collect::<Result<Vec<_>, ParserError>>()?;
+
+    // Copies are evaluated, keeping the parsed expressions for the spans to stay valid
+    expressions
+        .iter()
+        .map(|(pos, expr)| {
+            eval(env, expr.clone())
+                .map_err(|e| LispError::Eval(locate_error(env, e, *pos, stream.spans())))
+        })
+        .collect()
+}
+
+/// Evaluate the expressions of `source` in order and get the value of the last one. Unlike
+/// `eval_source`, errors are returned as raised, so tests can match them.
+#[cfg(test)]
+pub(crate) fn eval_last(env: &Environment, source: &str) -> Result<Expression, EvalError> {
+    let mut value = Expression::Nil;
+    for expr in ExpressionStream::from_char_stream(source.chars()) {
+        value = eval(env, expr.unwrap())?;
+    }
+    Ok(value)
+}
+
+/// Attach a source position and the backtrace of the failing calls to an error of evaluating a
+/// parsed expression starting at `position`. The error is located at the innermost failing call
+/// with a position in `spans`, which have to be valid (see `ExpressionStream::spans`), or else
+/// at `position`.
+pub fn locate_error(
+    env: &Environment,
+    error: EvalError,
+    position: Position,
+    spans: &Spans,
+) -> EvalError {
+    let frames = env.take_frames();
+    let position = frames
+        .iter()
+        .find_map(|(_, list)| spans.get(*list))
+        .unwrap_or(position);
+    error
+        .at(position)
+        .traced(frames.into_iter().map(|(frame, _)| frame).collect())
+}
+
+#[test]
+fn test_error_sources() {
+    let io_error = || std::fs::read_to_string("/nonexistent/lispers.lisp").unwrap_err();
+
+    let EvalError::RuntimeError(message, Some(source)) = EvalError::from(io_error()) else {
+        panic!("Expected a runtime error with a source");
+    };
+    assert_eq!(message, io_error().to_string());
+    assert_eq!(
+        source.0.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+        Some(std::io::ErrorKind::NotFound)
+    );
+
+    let e = EvalError::with_context("main.lisp", io_error());
+    assert_eq!(
+        e.to_string(),
+        format!("Runtime error: main.lisp: {}", io_error())
+    );
+    assert_eq!(e, EvalError::with_context("main.lisp", io_error()));
+    assert!(matches!(
+        EvalError::from("1.5".parse::<i64>().unwrap_err()),
+        EvalError::RuntimeError(_, Some(_))
+    ));
+}
+
+#[test]
+fn test_backtrace() {
+    let env = Environment::default();
+    eval_source(
+        &env,
+        "(defun inner (x) (+ x missing))
+         (defun outer (x) (* 2 (inner x)))",
+    )
+    .unwrap();
+
+    let Err(LispError::Eval(e)) = eval_source(&env, "(outer 1)") else {
+        panic!("Expected an evaluation error");
+    };
+    assert_eq!(e.root(), &EvalError::SymbolNotBound("missing".to_string()));
+    assert_eq!(e.backtrace(), ["+", "inner", "*", "outer"]);
+    assert!(e.to_string().ends_with("\n  in *\n  in outer"));
+    // Called from another source, the error is located at the call
+    assert!(e.to_string().contains("(at line 1, col 1)"));
+
+    // Errors are located at the innermost failing call of the source
+    let Err(LispError::Eval(e)) = eval_source(&env, "(defun f (x)\n  (list (car x 1)))\n(f '(1))")
+    else {
+        panic!("Expected an evaluation error");
+    };
+    assert_eq!(e.backtrace(), ["car", "list", "f"]);
+    assert!(e.to_string().contains("(at line 2, col 9)"));
+    assert_eq!(e.kind(), Some("argument-error"));
+    assert!(matches!(
+        e.into_condition(),
+        Expression::Cell(kind, _) if *kind == Expression::Symbol("argument-error".to_string())
+    ));
+
+    // Handled errors do not leave calls behind
+    let values = eval_source(&env, "(catch (outer 1) (lambda (c) 0)) (car nil 1)");
+    let Err(LispError::Eval(e)) = values else {
+        panic!("Expected an evaluation error");
+    };
+    assert_eq!(e.backtrace(), ["car"]);
+
+    // Neither do failed evaluations whose backtrace was not taken
+    let expr = ExpressionStream::from_char_stream("(+ 1 'a)".chars())
+        .next()
+        .unwrap()
+        .unwrap();
+    assert!(eval(&env, expr).is_err());
+    let Err(LispError::Eval(e)) = eval_source(&env, "(car nil 1)") else {
+        panic!("Expected an evaluation error");
+    };
+    assert_eq!(e.backtrace(), ["car"]);
+}
+
+#[test]
+fn test_limits() {
+    use super::InterpreterConfig;
+    use std::time::Duration;
+
+    let run = |config: InterpreterConfig, source: &str| {
+        let mut env = Environment::default();
+        env.set_config(config);
+        eval_source(&env, source).map_err(|e| EvalError::from(e).root().clone())
+    };
+    let spin = "(defun spin (n) (spin (+ n 1))) (spin 0)";
+    let busy = "(set 'xs '(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26
+                          27 28 29 30 31 32 33 34 35 36 37 38 39))
+                (for-each (lambda (x) (for-each (lambda (y) y) xs)) xs)
+                'done";
+
+    assert!(matches!(
+        run(InterpreterConfig::default().with_max_depth(Some(50)), spin),
+        Err(EvalError::LimitExceeded(e)) if e.contains("depth")
+    ));
+    assert!(matches!(
+        run(InterpreterConfig::default().with_max_steps(Some(100)), spin),
+        Err(EvalError::LimitExceeded(e)) if e.contains("steps")
+    ));
+    assert!(matches!(
+        run(InterpreterConfig::default().with_timeout(Some(Duration::ZERO)), busy),
+        Err(EvalError::LimitExceeded(e)) if e.contains("Timeout")
+    ));
+    // Limits apply to each top level expression and cannot be caught
+    let config = InterpreterConfig::default()
+        .with_max_steps(Some(5000))
+        .with_timeout(Some(Duration::from_secs(60)));
+    assert!(run(config, &format!("{} {}", busy, busy)).is_ok());
+    let catch = format!("(catch (progn {}) (lambda (c) 'caught))", spin);
+    assert!(matches!(
+        run(
+            InterpreterConfig::default().with_max_depth(Some(50)),
+            &catch
+        ),
+        Err(EvalError::LimitExceeded(_))
+    ));
+    assert!(matches!(
+        run(
+            InterpreterConfig::default().with_max_steps(Some(100)),
+            &catch
+        ),
+        Err(EvalError::LimitExceeded(_))
+    ));
+
+    // Deep recursion does not overflow the stack, also on threads with a small stack, and
+    // endless recursion fails at the default depth
+    let deep = "(defun deep (n) (if (= n 0) 0 (+ 1 (deep (- n 1))))) (deep 3000)";
+    let endless = "(defun lp () (lp)) (lp)";
+    let small_stack = std::thread::Builder::new().stack_size(256 * 1024);
+    let (deep, endless) = small_stack
+        .spawn(move || {
+            (
+                run(InterpreterConfig::default(), deep),
+                run(InterpreterConfig::default(), endless),
+            )
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(deep.unwrap().pop(), Some(Expression::Integer(3000)));
+    assert!(matches!(endless, Err(EvalError::LimitExceeded(e)) if e.contains("depth")));
+}
+
+#[test]
+fn test_interrupt() {
+    fn interrupt(env: &Environment, _: Expression) -> Result<Expression, EvalError> {
+        env.interrupt_flag()
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        Ok(Expression::Nil)
+    }
+
+    let mut layer = EnvironmentLayer::new();
+    super::prelude::mk_prelude(&mut layer);
+    layer.set("interrupt".to_string(), Expression::Function(interrupt));
+    let env = Environment::from_layer(layer);
+
+    let result = eval_source(&env, "(catch (progn (interrupt) (+ 1 1)) (lambda (c) 2))");
+    assert!(matches!(
+        result
+            .map_err(EvalError::from)
+            .as_ref()
+            .map_err(EvalError::root),
+        Err(EvalError::Interrupted)
+    ));
+    // The flag is cleared for the next evaluation
+    assert_eq!(
+        eval_source(&env, "(+ 1 2)"),
+        Ok(vec![Expression::Integer(3)])
+    );
+}
+
+#[test]
+fn test_shared_children() {
+    let mut layer = EnvironmentLayer::new();
+    super::prelude::mk_prelude(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let values = eval_source(&env, "(define xs '(1 2 3)) xs xs (cdr xs)").unwrap();
+    match (&values[1], &values[2], &values[3]) {
+        (Expression::Cell(a, rest_a), Expression::Cell(b, _), cdr) => {
+            // Looking a list up twice shares its cells instead of copying them
+            assert!(Arc::ptr_eq(a, b));
+            assert_eq!(**rest_a, *cdr);
+        }
+        other => panic!("Expected lists, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_keywords() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+    let keyword = |s: &str| Expression::Symbol(s.to_string());
+
+    assert_eq!(run(":overwrite"), Ok(keyword(":overwrite")));
+    assert_eq!(run("':overwrite"), Ok(keyword(":overwrite")));
+    assert_eq!(
+        run("(list :a 'b)"),
+        Ok(vec![keyword(":a"), keyword("b")].into())
+    );
+    // Keywords are not looked up, even when bound
+    assert_eq!(run("(set ':x 1) :x"), Ok(keyword(":x")));
+    assert_eq!(run("(eq? :x ':x)"), Ok(Expression::True));
+    assert_eq!(run("x:"), Err(EvalError::SymbolNotBound("x:".to_string())));
+
+    // As map keys
+    run("(set 'm (make-map (list (cons :a 1) (cons 'b 2))))").unwrap();
+    assert_eq!(run("(map-get m :a)"), Ok(Expression::Integer(1)));
+    assert_eq!(run("(map-get m ':a)"), Ok(Expression::Integer(1)));
+    assert_eq!(run("(map-get m :b)"), Ok(Expression::Nil));
+    assert_eq!(
+        run("(map-get (map-set m :b 3) :b)"),
+        Ok(Expression::Integer(3))
+    );
+    assert_eq!(
+        run("(map-keys m)"),
+        Ok(vec![keyword(":a"), keyword("b")].into())
+    );
+}
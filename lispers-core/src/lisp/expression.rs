@@ -1,30 +1,41 @@
 use std::any::Any;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::sync::Arc;
 
 use as_any::AsAny;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 
 use super::environment::Environment;
+use super::environment::InterpreterConfig;
 use super::eval::CellIterator;
 use super::eval::EvalError;
 
-/// A trait for foreign data types that can be used in lisp expressions.
+/// A trait for foreign data types that can be used in lisp expressions. Foreign data has to be
+/// `Send` and `Sync` like all other expressions, so environments can be shared between threads.
 /// Note: This trait requires explicit implementation of:
 /// - partial_cmp_impl
 /// - clone_impl
 /// - eq_impl
 /// - as_any_box
+///
 /// to ensure object safety.
-pub trait ForeignData: Debug + Display + AsAny {
+pub trait ForeignData: Debug + Display + AsAny + Send + Sync {
     fn partial_cmp_impl(&self, other: &dyn ForeignData) -> Option<std::cmp::Ordering>;
     fn clone_impl(&self) -> Box<dyn ForeignData>;
     fn eq_impl(&self, other: &dyn ForeignData) -> bool;
     fn as_any_box(self: Box<Self>) -> Box<dyn Any>;
 }
 
-impl<T: Debug + Display + AsAny + PartialOrd + PartialEq + Clone + 'static> ForeignData for T {
+impl<T> ForeignData for T
+where
+    T: Debug + Display + AsAny + PartialOrd + PartialEq + Clone + Send + Sync + 'static,
+{
     fn partial_cmp_impl(&self, other: &dyn ForeignData) -> Option<std::cmp::Ordering> {
         if let Some(other) = other.as_any().downcast_ref::<T>() {
             self.partial_cmp(other)
@@ -90,7 +101,7 @@ impl ForeignDataStore {
     }
 
     /// Get the contained box as an Any-Box with type info of the actual data.
-    fn as_any_box(self) -> Box<dyn Any> {
+    fn into_any_box(self) -> Box<dyn Any> {
         self.data.as_any_box()
     }
 }
@@ -121,34 +132,262 @@ impl Display for ForeignDataStore {
     }
 }
 
+/// A native function capturing state of the host, like a handle to the application. Unlike
+/// `Expression::Function`, which only takes plain `fn` pointers, any closure can be called.
+/// Copies of the value share the closure, two closures are equal only if they are the same.
+#[derive(Clone)]
+pub struct NativeClosure(Arc<ClosureFn>);
+
+/// The closure type of `NativeClosure`.
+type ClosureFn = dyn Fn(&Environment, Expression) -> Result<Expression, EvalError> + Send + Sync;
+
+impl NativeClosure {
+    /// Wrap a closure, which gets the calling environment and its arguments unevaluated like
+    /// the functions of `Expression::Function`.
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Environment, Expression) -> Result<Expression, EvalError> + Send + Sync + 'static,
+    {
+        NativeClosure(Arc::new(f))
+    }
+
+    /// Call the closure.
+    pub fn call(&self, env: &Environment, args: Expression) -> Result<Expression, EvalError> {
+        (self.0)(env, args)
+    }
+}
+
+impl Debug for NativeClosure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NativeClosure({:p})", Arc::as_ptr(&self.0))
+    }
+}
+
+impl PartialEq for NativeClosure {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A sum type of all possible lisp expressions.
 pub enum Expression {
-    /// The classic lisp cons cell aka (a . b) used to construct expressions.
-    Cell(Box<Expression>, Box<Expression>),
-    /// A function expression pointing to native code.
-    Function(fn(&Environment, Expression) -> Result<Expression, EvalError>),
-    /// A anonymous function expression consisting of bound symbols and a body expression.
+    /// The classic lisp cons cell aka (a . b) used to construct expressions. Children are shared,
+    /// so copies of lists and code are cheap. Take them apart with `Arc::unwrap_or_clone`, which
+    /// only copies a child that is still shared.
+    Cell(
+        #[cfg_attr(feature = "serde", serde(with = "nested"))] Arc<Expression>,
+        #[cfg_attr(feature = "serde", serde(with = "nested"))] Arc<Expression>,
+    ),
+    /// A anonymous function expression consisting of bound symbols and body expressions, which
+    /// are evaluated in order.
     AnonymousFunction {
         argument_symbols: Vec<String>,
-        body: Box<Expression>,
+        #[cfg_attr(feature = "serde", serde(with = "nested"))]
+        body: Arc<[Expression]>,
     },
-    /// A foreign data expression.
-    ForeignExpression(ForeignDataStore),
     /// A Quoted expression.
-    Quote(Box<Expression>),
+    Quote(#[cfg_attr(feature = "serde", serde(with = "nested"))] Arc<Expression>),
     /// A symbol.
     Symbol(String),
     /// Integer values.
     Integer(i64),
+    /// Integer values which do not fit into an `Integer`.
+    BigInt(BigInt),
     /// Float values.
     Float(f64),
     /// String values.
     String(String),
+    /// A map from atoms to values. Maps are values: updates create a new map. Iteration and
+    /// printing follow insertion order.
+    Map(#[cfg_attr(feature = "serde", serde(with = "nested"))] IndexMap<MapKey, Expression>),
     /// True
     True,
+    /// False, only distinct from `Nil` with `InterpreterConfig::distinct_false`
+    False,
     /// Nil
     Nil,
+    // Variants which cannot be serialized, these have to stay last for the variant indices of
+    // serialized expressions to match.
+    /// A function expression pointing to native code.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Function(fn(&Environment, Expression) -> Result<Expression, EvalError>),
+    /// A foreign data expression.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ForeignExpression(ForeignDataStore),
+    /// A native function capturing state.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    NativeClosure(NativeClosure),
+}
+
+/// The deepest nesting of a deserialized `Expression`, which bounds the memory untrusted input
+/// takes. Lists nest as deep as they are long.
+#[cfg(feature = "serde")]
+pub const MAX_NESTING: usize = 100_000;
+
+/// (De)serialization of the children of an `Expression`, which continues on a new stack segment
+/// when the stack runs low, as lists nest as deep as they are long.
+#[cfg(feature = "serde")]
+mod nested {
+    use std::cell::Cell;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::super::eval::{STACK_RED_ZONE, STACK_SEGMENT};
+    use super::MAX_NESTING;
+
+    thread_local! {
+        static DEPTH: Cell<usize> = const { Cell::new(0) };
+    }
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            value.serialize(serializer)
+        })
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let depth = DEPTH.get();
+        if depth >= MAX_NESTING {
+            return Err(D::Error::custom(format!(
+                "Expression nests deeper than {MAX_NESTING} levels"
+            )));
+        }
+        DEPTH.set(depth + 1);
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            T::deserialize(deserializer)
+        });
+        DEPTH.set(depth);
+        result
+    }
+}
+
+/// A key of a `Map`. Only atoms with a well defined equality can be used as keys.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MapKey {
+    Symbol(String),
+    String(String),
+    Integer(i64),
+}
+
+impl TryFrom<Expression> for MapKey {
+    type Error = EvalError;
+    fn try_from(value: Expression) -> Result<MapKey, Self::Error> {
+        match value {
+            Expression::Symbol(s) => Ok(MapKey::Symbol(s)),
+            Expression::String(s) => Ok(MapKey::String(s)),
+            Expression::Integer(i) => Ok(MapKey::Integer(i)),
+            x => Err(EvalError::TypeError(format!(
+                "Map keys must be symbols, strings or integers, got {}",
+                x
+            ))),
+        }
+    }
+}
+
+impl From<MapKey> for Expression {
+    fn from(value: MapKey) -> Expression {
+        match value {
+            MapKey::Symbol(s) => Expression::Symbol(s),
+            MapKey::String(s) => Expression::String(s),
+            MapKey::Integer(i) => Expression::Integer(i),
+        }
+    }
+}
+
+impl Expression {
+    /// Check whether the expression counts as true in conditions, according to `config`.
+    pub fn is_truthy(&self, config: &InterpreterConfig) -> bool {
+        match self {
+            Expression::False => false,
+            Expression::Nil => config.distinct_false,
+            Expression::True => true,
+            _ => !config.strict_truthiness,
+        }
+    }
+
+    /// Get the lisp value of a boolean, which is `false` for `false` with `distinct_false` and
+    /// `nil` otherwise. Unlike `From<bool>`, this keeps false distinct from the empty list.
+    pub fn from_bool(value: bool, distinct_false: bool) -> Expression {
+        match value {
+            true => Expression::True,
+            false if distinct_false => Expression::False,
+            false => Expression::Nil,
+        }
+    }
+
+    /// Iterate the elements of a list without copying them. Like `CellIterator`, an error is
+    /// returned when the list does not end with nil.
+    pub fn list_iter(&self) -> ListIter<'_> {
+        ListIter { rest: self }
+    }
+
+    /// Get the expression of a number read from a data format, which is given as `i64`, `u64`
+    /// and `f64` as far as it fits. Integers beyond `i64` become big integers.
+    #[cfg(any(feature = "json", feature = "yaml"))]
+    pub(crate) fn from_number(i: Option<i64>, u: Option<u64>, f: Option<f64>) -> Expression {
+        match (i, u) {
+            (Some(i), _) => Expression::Integer(i),
+            (None, Some(u)) => num_bigint::BigInt::from(u).into(),
+            (None, None) => Expression::Float(f.unwrap_or(f64::NAN)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// Iterates the elements of a list by reference, see `Expression::list_iter`.
+pub struct ListIter<'a> {
+    rest: &'a Expression,
+}
+
+impl<'a> ListIter<'a> {
+    /// Get the list of the elements which were not iterated yet, sharing its cells.
+    pub fn rest(&self) -> &'a Expression {
+        self.rest
+    }
+}
+
+impl<'a> Iterator for ListIter<'a> {
+    type Item = Result<&'a Expression, EvalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rest {
+            Expression::Cell(head, tail) => {
+                self.rest = tail;
+                Some(Ok(head))
+            }
+            Expression::Nil => None,
+            _ => {
+                let error = EvalError::TypeError("Expected a cell or nil".to_string());
+                self.rest = &Expression::Nil;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl FromIterator<Expression> for Expression {
+    /// Build a list of the elements in order, without collecting them first.
+    fn from_iter<I: IntoIterator<Item = Expression>>(iter: I) -> Self {
+        let mut list = Expression::Nil;
+        let mut end = &mut list;
+        for e in iter {
+            *end = Expression::Cell(Arc::new(e), Arc::new(Expression::Nil));
+            end = match end {
+                Expression::Cell(_, tail) => Arc::get_mut(tail).expect("new cells are unshared"),
+                _ => unreachable!(),
+            };
+        }
+        list
+    }
 }
 
 impl PartialEq for Expression {
@@ -169,9 +408,16 @@ impl PartialEq for Expression {
             (ForeignExpression(f1), ForeignExpression(f2)) => PartialEq::eq(f1, f2),
             (Quote(e1), Quote(e2)) => PartialEq::eq(e1, e2),
             (Symbol(s1), Symbol(s2)) => PartialEq::eq(s1, s2),
+            (Integer(i1), Integer(i2)) => PartialEq::eq(i1, i2),
+            (BigInt(i1), BigInt(i2)) => PartialEq::eq(i1, i2),
             (Float(f1), Float(f2)) => PartialEq::eq(f1, f2),
+            (String(s1), String(s2)) => PartialEq::eq(s1, s2),
+            (Map(m1), Map(m2)) => PartialEq::eq(m1, m2),
+            (Function(f1), Function(f2)) => std::ptr::fn_addr_eq(*f1, *f2),
+            (NativeClosure(f1), NativeClosure(f2)) => PartialEq::eq(f1, f2),
             (Nil, Nil) => true,
             (True, True) => true,
+            (False, False) => true,
             _ => false,
         }
     }
@@ -197,9 +443,16 @@ impl PartialOrd for Expression {
             (ForeignExpression(f1), ForeignExpression(f2)) => f1.partial_cmp(f2),
             (Quote(e1), Quote(e2)) => e1.partial_cmp(e2),
             (Symbol(s1), Symbol(s2)) => s1.partial_cmp(s2),
+            (Integer(i1), Integer(i2)) => i1.partial_cmp(i2),
+            (BigInt(i1), BigInt(i2)) => i1.partial_cmp(i2),
+            (Integer(i1), BigInt(i2)) => num_bigint::BigInt::from(*i1).partial_cmp(i2),
+            (BigInt(i1), Integer(i2)) => i1.partial_cmp(&num_bigint::BigInt::from(*i2)),
             (Float(f1), Float(f2)) => f1.partial_cmp(f2),
+            (String(s1), String(s2)) => s1.partial_cmp(s2),
+            (Map(m1), Map(m2)) if m1 == m2 => Some(std::cmp::Ordering::Equal),
             (Nil, Nil) => Some(std::cmp::Ordering::Equal),
             (True, True) => Some(std::cmp::Ordering::Equal),
+            (False, False) => Some(std::cmp::Ordering::Equal),
             _ => None,
         }
     }
@@ -215,7 +468,7 @@ impl<T: ForeignData> TryFrom<Expression> for ForeignDataWrapper<T> {
     type Error = EvalError;
     fn try_from(value: Expression) -> Result<Self, Self::Error> {
         match value {
-            Expression::ForeignExpression(f) => match f.as_any_box().downcast::<T>() {
+            Expression::ForeignExpression(f) => match f.into_any_box().downcast::<T>() {
                 Ok(data) => Ok(ForeignDataWrapper(data)),
                 Err(_) => Err(EvalError::TypeError(
                     "Expression is not a ForeignDataWrapper".to_string(),
@@ -234,33 +487,35 @@ impl From<fn(&Environment, Expression) -> Result<Expression, EvalError>> for Exp
     }
 }
 
-impl From<Vec<Expression>> for Expression {
-    fn from(mut value: Vec<Expression>) -> Self {
-        let mut current = Expression::Nil;
-
-        for e in value.iter_mut().rev() {
-            current = Expression::Cell(Box::new(e.to_owned()), Box::new(current));
-        }
+impl From<NativeClosure> for Expression {
+    fn from(f: NativeClosure) -> Self {
+        Expression::NativeClosure(f)
+    }
+}
 
-        current
+impl<T: Into<Expression>> From<Vec<T>> for Expression {
+    fn from(value: Vec<T>) -> Self {
+        value.into_iter().map(Into::into).collect()
     }
 }
 
 impl<const N: usize> From<[Expression; N]> for Expression {
-    fn from(mut value: [Expression; N]) -> Self {
-        let mut current = Expression::Nil;
-
-        for e in value.iter_mut().rev() {
-            current = Expression::Cell(Box::new(e.to_owned()), Box::new(current));
-        }
+    fn from(value: [Expression; N]) -> Self {
+        value.into_iter().collect()
+    }
+}
 
-        current
+impl<T: Into<Expression>> From<Option<T>> for Expression {
+    /// `None` becomes nil.
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Expression::Nil, Into::into)
     }
 }
 
-impl From<(Expression, Expression)> for Expression {
-    fn from(value: (Expression, Expression)) -> Self {
-        Expression::Cell(Box::new(value.0), Box::new(value.1))
+impl<A: Into<Expression>, B: Into<Expression>> From<(A, B)> for Expression {
+    /// Convert a pair into a Cell of `A` and `B`, e.g. `(1.5, "a")` into `(1.5 . "a")`.
+    fn from(value: (A, B)) -> Self {
+        Expression::Cell(Arc::new(value.0.into()), Arc::new(value.1.into()))
     }
 }
 
@@ -270,6 +525,16 @@ impl From<i64> for Expression {
     }
 }
 
+impl From<BigInt> for Expression {
+    /// Integers which fit into an i64 become an `Integer`, larger ones a `BigInt`.
+    fn from(value: BigInt) -> Expression {
+        match i64::try_from(&value) {
+            Ok(i) => Expression::Integer(i),
+            Err(_) => Expression::BigInt(value),
+        }
+    }
+}
+
 impl From<f64> for Expression {
     fn from(value: f64) -> Self {
         Expression::Float(value)
@@ -283,12 +548,18 @@ impl From<String> for Expression {
 }
 
 impl From<bool> for Expression {
+    /// Convert with the default configuration, false is `nil`. See `Expression::from_bool` to
+    /// convert according to `distinct_false`.
     fn from(value: bool) -> Self {
-        if value {
-            Expression::True
-        } else {
-            Expression::Nil
-        }
+        Expression::from_bool(value, false)
+    }
+}
+
+impl TryFrom<Expression> for bool {
+    type Error = EvalError;
+    /// Convert with the default truthiness: `nil` and `false` are false, everything else is true.
+    fn try_from(value: Expression) -> Result<bool, Self::Error> {
+        Ok(value.is_truthy(&InterpreterConfig::default()))
     }
 }
 
@@ -304,11 +575,50 @@ impl TryFrom<Expression> for i64 {
     }
 }
 
+macro_rules! integer_try_from {
+    ($($t:ty),*) => {$(
+        impl TryFrom<Expression> for $t {
+            type Error = EvalError;
+            /// Convert an Integer, failing if it is out of the range of the type.
+            fn try_from(value: Expression) -> Result<$t, Self::Error> {
+                let integer = match &value {
+                    Expression::Integer(i) => <$t>::try_from(*i).ok(),
+                    Expression::BigInt(i) => i.to_i128().and_then(|i| <$t>::try_from(i).ok()),
+                    _ => {
+                        return Err(EvalError::TypeError(
+                            "Expression is not an Integer".to_string(),
+                        ))
+                    }
+                };
+                integer.ok_or_else(|| {
+                    EvalError::TypeError(format!("{} is not a valid {}", value, stringify!($t)))
+                })
+            }
+        }
+    )*};
+}
+
+integer_try_from!(i32, u32, u64, usize);
+
+impl TryFrom<Expression> for BigInt {
+    type Error = EvalError;
+    fn try_from(value: Expression) -> Result<BigInt, Self::Error> {
+        match value {
+            Expression::Integer(i) => Ok(i.into()),
+            Expression::BigInt(i) => Ok(i),
+            _ => Err(EvalError::TypeError(
+                "Expression is not an Integer".to_string(),
+            )),
+        }
+    }
+}
+
 impl TryFrom<Expression> for f64 {
     type Error = EvalError;
     fn try_from(value: Expression) -> Result<f64, Self::Error> {
         match value {
             Expression::Integer(i) => Ok(i as f64),
+            Expression::BigInt(i) => Ok(i.to_f64().unwrap_or(f64::NAN)),
             Expression::Float(f) => Ok(f),
             _ => Err(EvalError::TypeError(
                 "Expression is not a Float".to_string(),
@@ -329,6 +639,65 @@ impl TryFrom<Expression> for String {
     }
 }
 
+impl From<IndexMap<MapKey, Expression>> for Expression {
+    fn from(value: IndexMap<MapKey, Expression>) -> Expression {
+        Expression::Map(value)
+    }
+}
+
+impl<V: Into<Expression>, S> From<HashMap<String, V, S>> for Expression {
+    /// Convert into a Map with string keys, sorted as a `HashMap` has no order.
+    fn from(value: HashMap<String, V, S>) -> Expression {
+        let mut entries: Vec<(String, V)> = value.into_iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Expression::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (MapKey::String(k), v.into()))
+                .collect(),
+        )
+    }
+}
+
+impl<K, V> TryFrom<Expression> for HashMap<K, V>
+where
+    K: TryFrom<Expression, Error = EvalError> + Eq + std::hash::Hash,
+    V: TryFrom<Expression, Error = EvalError>,
+{
+    type Error = EvalError;
+    /// Convert a Map, converting each key from its atom.
+    fn try_from(value: Expression) -> Result<HashMap<K, V>, Self::Error> {
+        IndexMap::<MapKey, Expression>::try_from(value)?
+            .into_iter()
+            .map(|(k, v)| Ok((Expression::from(k).try_into()?, v.try_into()?)))
+            .collect()
+    }
+}
+
+impl TryFrom<Expression> for IndexMap<MapKey, Expression> {
+    type Error = EvalError;
+    fn try_from(value: Expression) -> Result<IndexMap<MapKey, Expression>, Self::Error> {
+        match value {
+            Expression::Map(m) => Ok(m),
+            _ => Err(EvalError::TypeError("Expression is not a Map".to_string())),
+        }
+    }
+}
+
+impl<ToExpr> TryFrom<Expression> for Option<ToExpr>
+where
+    ToExpr: TryFrom<Expression, Error = EvalError>,
+{
+    type Error = EvalError;
+    /// Nil becomes `None`.
+    fn try_from(value: Expression) -> Result<Option<ToExpr>, Self::Error> {
+        match value {
+            Expression::Nil => Ok(None),
+            value => value.try_into().map(Some),
+        }
+    }
+}
+
 impl TryFrom<Expression> for Vec<Expression> {
     type Error = EvalError;
 
@@ -377,11 +746,22 @@ impl<const N: usize> TryFrom<Expression> for [Expression; N] {
     }
 }
 
-impl TryFrom<Expression> for (Expression, Expression) {
+/// Convert the car and cdr of a Cell, the inverse of `From<(A, B)>`, e.g. into an
+/// `(f64, String)` or, without converting, into `(Expression, Expression)`. Larger tuples are
+/// converted from lists.
+impl<A, B> TryFrom<Expression> for (A, B)
+where
+    A: TryFrom<Expression>,
+    B: TryFrom<Expression>,
+    EvalError: From<A::Error> + From<B::Error>,
+{
     type Error = EvalError;
-    fn try_from(value: Expression) -> Result<(Expression, Expression), Self::Error> {
+    fn try_from(value: Expression) -> Result<(A, B), Self::Error> {
         match value {
-            Expression::Cell(a, b) => Ok((*a, *b)),
+            Expression::Cell(a, b) => Ok((
+                A::try_from(Arc::unwrap_or_clone(a))?,
+                B::try_from(Arc::unwrap_or_clone(b))?,
+            )),
             _ => Err(EvalError::TypeError(
                 "Expression must be a Cell".to_string(),
             )),
@@ -389,6 +769,82 @@ impl TryFrom<Expression> for (Expression, Expression) {
     }
 }
 
+macro_rules! tuple_try_from {
+    ($n:literal; $($t:ident $v:ident),+) => {
+        impl<$($t),+> TryFrom<Expression> for ($($t,)+)
+        where
+            $($t: TryFrom<Expression, Error = EvalError>),+
+        {
+            type Error = EvalError;
+            /// Convert a list of exactly as many elements as the tuple has. A list of two
+            /// elements is not converted into a tuple, as a pair is the car and cdr of a Cell.
+            fn try_from(value: Expression) -> Result<($($t,)+), Self::Error> {
+                let elements: Vec<Expression> = value.try_into()?;
+                let n = elements.len();
+                let [$($v),+]: [Expression; $n] = elements.try_into().map_err(|_| {
+                    EvalError::TypeError(format!("Expected a list of {} elements, got {}", $n, n))
+                })?;
+                Ok(($($t::try_from($v)?,)+))
+            }
+        }
+    };
+}
+
+tuple_try_from!(3; A a, B b, C c);
+tuple_try_from!(4; A a, B b, C c, D d);
+
+/// A numeric argument converted from either an Integer or a Float, as long as the value fits:
+/// a Float only converts to an integer type if it has no fractional part. `#[native_lisp_function]`
+/// converts numeric arguments through this, so `(camera ... 40 1920.0 1080)` works as well.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Default)]
+pub struct Coerce<T>(pub T);
+
+macro_rules! coerce_integer {
+    ($($t:ty),*) => {$(
+        impl TryFrom<Expression> for Coerce<$t> {
+            type Error = EvalError;
+            fn try_from(value: Expression) -> Result<Coerce<$t>, Self::Error> {
+                let integer = match &value {
+                    Expression::Integer(i) => Some(*i as i128),
+                    Expression::BigInt(i) => i.to_i128(),
+                    Expression::Float(f) if f.is_finite() && f.fract() == 0.0 => Some(*f as i128),
+                    Expression::Float(_) => None,
+                    _ => {
+                        return Err(EvalError::TypeError(
+                            "Expression is not an Integer".to_string(),
+                        ))
+                    }
+                };
+                integer
+                    .and_then(|i| <$t>::try_from(i).ok())
+                    .map(Coerce)
+                    .ok_or_else(|| {
+                        EvalError::TypeError(format!(
+                            "{} is not a valid {}",
+                            value,
+                            stringify!($t)
+                        ))
+                    })
+            }
+        }
+    )*};
+}
+
+coerce_integer!(i32, i64, u32, u64, usize);
+
+macro_rules! coerce_float {
+    ($($t:ty),*) => {$(
+        impl TryFrom<Expression> for Coerce<$t> {
+            type Error = EvalError;
+            fn try_from(value: Expression) -> Result<Coerce<$t>, Self::Error> {
+                Ok(Coerce(f64::try_from(value)? as $t))
+            }
+        }
+    )*};
+}
+
+coerce_float!(f32, f64);
+
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -406,17 +862,42 @@ impl Display for Expression {
                     Err(_) => write!(f, "({} . {})", a, b),
                 }
             }
-            Expression::Function(_) => write!(f, "<function>"),
+            Expression::Function(_) | Expression::NativeClosure(_) => write!(f, "<function>"),
             Expression::AnonymousFunction {
                 argument_symbols,
                 body,
-            } => write!(f, "(lambda ({}) {})", argument_symbols.join(" "), body),
+            } => write!(
+                f,
+                "(lambda ({}) {})",
+                argument_symbols.join(" "),
+                body.iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
             Expression::Quote(e) => write!(f, "'{}", e),
             Expression::Symbol(s) => write!(f, "{}", s),
             Expression::Integer(i) => write!(f, "{}", i),
+            Expression::BigInt(i) => write!(f, "{}", i),
+            // Keep the decimal point of whole numbers, so printed code reads back as a float
+            Expression::Float(fl) if fl.is_finite() && fl.fract() == 0.0 => write!(f, "{:.1}", fl),
             Expression::Float(fl) => write!(f, "{}", fl),
-            Expression::String(s) => write!(f, "\"{}\"", s),
+            // Escape quotes and backslashes, so printed strings read back (see `read`)
+            Expression::String(s) => {
+                write!(f, "\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+            // Print maps as the expression constructing them
+            Expression::Map(m) if m.is_empty() => write!(f, "(make-map)"),
+            Expression::Map(m) => write!(
+                f,
+                "(make-map '({}))",
+                m.iter()
+                    .map(|(k, v)| format!("({} . {})", Expression::from(k.clone()), v))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
             Expression::True => write!(f, "true"),
+            Expression::False => write!(f, "false"),
             Expression::Nil => write!(f, "nil"),
         }
     }
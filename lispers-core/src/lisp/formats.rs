@@ -0,0 +1,160 @@
+//! Builtins reading configuration formats into lisp data, each behind the cargo feature of its
+//! format. Tables and mappings become maps, arrays and sequences lists, like `json-parse` does.
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::environment::InterpreterConfig;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::{Expression, MapKey};
+
+/// Convert a TOML value into lisp data. Dates and times become strings in TOML notation.
+#[cfg(feature = "toml")]
+pub fn toml_to_expression(value: toml::Value, config: &InterpreterConfig) -> Expression {
+    match value {
+        toml::Value::String(s) => Expression::String(s),
+        toml::Value::Integer(i) => Expression::Integer(i),
+        toml::Value::Float(f) => Expression::Float(f),
+        toml::Value::Boolean(b) => config.boolean(b),
+        toml::Value::Datetime(d) => Expression::String(d.to_string()),
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(|v| toml_to_expression(v, config))
+            .collect(),
+        toml::Value::Table(table) => Expression::Map(
+            table
+                .into_iter()
+                .map(|(k, v)| (MapKey::String(k), toml_to_expression(v, config)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parse a TOML document into a map: `(toml-parse s)`. Tables become maps with string keys,
+/// arrays lists and dates strings.
+#[cfg(feature = "toml")]
+pub fn formats_toml_parse(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    let table: toml::Table = s
+        .parse()
+        .map_err(|e| EvalError::with_context("Invalid TOML", e))?;
+    Ok(toml_to_expression(toml::Value::Table(table), env.config()))
+}
+
+/// Convert a YAML value into lisp data. Tags are dropped, and mapping keys must be strings or
+/// integers.
+#[cfg(feature = "yaml")]
+pub fn yaml_to_expression(
+    value: serde_yaml_ng::Value,
+    config: &InterpreterConfig,
+) -> Result<Expression, EvalError> {
+    use serde_yaml_ng::Value;
+
+    Ok(match value {
+        Value::Null => Expression::Nil,
+        Value::Bool(b) => config.boolean(b),
+        Value::Number(n) => Expression::from_number(n.as_i64(), n.as_u64(), n.as_f64()),
+        Value::String(s) => Expression::String(s),
+        Value::Sequence(values) => values
+            .into_iter()
+            .map(|v| yaml_to_expression(v, config))
+            .collect::<Result<Vec<_>, _>>()?
+            .into(),
+        Value::Mapping(mapping) => Expression::Map(
+            mapping
+                .into_iter()
+                .map(|(k, v)| {
+                    let key = match yaml_to_expression(k, config)? {
+                        Expression::String(s) => MapKey::String(s),
+                        k => k.try_into()?,
+                    };
+                    Ok((key, yaml_to_expression(v, config)?))
+                })
+                .collect::<Result<_, EvalError>>()?,
+        ),
+        Value::Tagged(tagged) => yaml_to_expression(tagged.value, config)?,
+    })
+}
+
+/// Parse a YAML document into lisp data: `(yaml-parse s)`. Mappings become maps, sequences
+/// lists and `null` nil.
+#[cfg(feature = "yaml")]
+pub fn formats_yaml_parse(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    let value: serde_yaml_ng::Value =
+        serde_yaml_ng::from_str(&s).map_err(|e| EvalError::with_context("Invalid YAML", e))?;
+    yaml_to_expression(value, env.config())
+}
+
+/// Adds the builtins of the enabled configuration formats to the given environment layer.
+pub fn mk_formats(layer: &mut EnvironmentLayer) {
+    #[cfg(feature = "toml")]
+    layer.set(
+        "toml-parse".to_string(),
+        Expression::Function(formats_toml_parse),
+    );
+    #[cfg(feature = "yaml")]
+    layer.set(
+        "yaml-parse".to_string(),
+        Expression::Function(formats_yaml_parse),
+    );
+}
+
+#[test]
+fn test_formats() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let run = |source: &str| eval_source(&env, source).map(|mut values| values.pop().unwrap());
+
+    // Lisp strings cannot contain quotes, configuration is usually read from a file
+    #[cfg(feature = "toml")]
+    {
+        env.shared_set(
+            "source".to_string(),
+            "title = \"spheres\"\nrelease = 2024-05-01\n\n[camera]\nfovy = 45.0\nsize = [640, 480]\n\
+             [[light]]\npos = [0, 10, 0]\n"
+                .to_string()
+                .into(),
+        );
+        assert_eq!(
+            run("(set 'config (toml-parse source)) (map-keys config)"),
+            run("'(\"title\" \"release\" \"camera\" \"light\")")
+        );
+        assert_eq!(
+            run("(map-get (map-get config \"camera\") \"size\")"),
+            run("'(640 480)")
+        );
+        assert_eq!(run("(map-get config \"release\")"), run("\"2024-05-01\""));
+        assert_eq!(
+            run("(map-get (car (map-get config \"light\")) \"pos\")"),
+            run("'(0 10 0)")
+        );
+        assert!(run("(toml-parse \"a = \")").is_err());
+    }
+
+    #[cfg(feature = "yaml")]
+    {
+        env.shared_set(
+            "source".to_string(),
+            "title: spheres\nlights:\n  - pos: [0, 10, 0]\n    on: true\n  - pos: [1, 2, 3]\n    \
+             on: false\n1: !tagged one\nempty: ~\n"
+                .to_string()
+                .into(),
+        );
+        assert_eq!(
+            run("(set 'config (yaml-parse source)) (map-keys config)"),
+            run("'(\"title\" \"lights\" 1 \"empty\")")
+        );
+        assert_eq!(
+            run("(map (lambda (l) (map-get l \"on\")) (map-get config \"lights\"))"),
+            run("'(true nil)")
+        );
+        assert_eq!(run("(map-get config 1)"), run("\"one\""));
+        assert_eq!(run("(map-get config \"empty\" 0)"), run("nil"));
+        assert!(run("(yaml-parse \"[1, 2\")").is_err());
+        assert!(run("(yaml-parse \"{[1]: 2}\")").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+
+/// The prefix of the symbols the functions of a hook are bound to.
+const HOOK_PREFIX: &str = "LISPERS-HOOK-";
+
+/// Get the name of a hook argument.
+fn hook_name(env: &Environment, hook: Expression) -> Result<String, EvalError> {
+    match eval(env, hook)? {
+        Expression::Symbol(s) => Ok(s),
+        x => Err(EvalError::NotASymbol(x)),
+    }
+}
+
+/// Get the functions registered for a hook, in the order they were added.
+fn hook_functions(env: &Environment, name: &str) -> Result<Vec<Expression>, EvalError> {
+    match env.shared_get(&format!("{}{}", HOOK_PREFIX, name)) {
+        Some(functions) => functions.try_into(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Call the functions registered for the hook `name` with `args`, in the order they were
+/// added. The arguments are not evaluated again. Returns the first error of a function.
+pub fn run_hook(env: &Environment, name: &str, args: Vec<Expression>) -> Result<(), EvalError> {
+    for f in hook_functions(env, name)? {
+        let call = std::iter::once(f)
+            .chain(args.iter().map(|a| Expression::Quote(Arc::new(a.clone()))))
+            .collect::<Vec<_>>();
+        eval(env, call.into())?;
+    }
+    Ok(())
+}
+
+/// Register a function to be called by a hook: `(add-hook 'hook f)`. Functions added twice are
+/// only called once. Returns the function.
+pub fn hooks_add(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [hook, f] = expr.try_into()?;
+    let name = hook_name(env, hook)?;
+    let f = eval(env, f)?;
+
+    let mut functions = hook_functions(env, &name)?;
+    if !functions.contains(&f) {
+        functions.push(f.clone());
+    }
+    env.shared_set(format!("{}{}", HOOK_PREFIX, name), functions.into());
+    Ok(f)
+}
+
+/// Unregister a function from a hook: `(remove-hook 'hook f)`. Returns whether the function was
+/// registered.
+pub fn hooks_remove(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [hook, f] = expr.try_into()?;
+    let name = hook_name(env, hook)?;
+    let f = eval(env, f)?;
+
+    let mut functions = hook_functions(env, &name)?;
+    let len = functions.len();
+    functions.retain(|g| *g != f);
+    let removed = functions.len() < len;
+    env.shared_set(format!("{}{}", HOOK_PREFIX, name), functions.into());
+    Ok(env.config().boolean(removed))
+}
+
+/// Call the functions of a hook with arguments: `(run-hook 'hook args...)`. Returns nil.
+pub fn hooks_run(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let mut args: Vec<Expression> = expr.try_into()?;
+    if args.is_empty() {
+        return Err(EvalError::ArgumentError("Expected a hook name".to_string()));
+    }
+    let name = hook_name(env, args.remove(0))?;
+    let args = args
+        .into_iter()
+        .map(|a| eval(env, a))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    run_hook(env, &name, args)?;
+    Ok(Expression::Nil)
+}
+
+/// Adds the hook builtins to the given environment layer.
+pub fn mk_hooks(layer: &mut EnvironmentLayer) {
+    layer.set("add-hook".to_string(), Expression::Function(hooks_add));
+    layer.set(
+        "remove-hook".to_string(),
+        Expression::Function(hooks_remove),
+    );
+    layer.set("run-hook".to_string(), Expression::Function(hooks_run));
+}
+
+#[test]
+fn test_hooks() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let values = eval_source(
+        &env,
+        "(set 'seen nil)
+         (defun remember (x) (set 'seen (cons x seen)))
+         (add-hook 'saved remember)
+         (add-hook 'saved remember)
+         (add-hook 'saved (lambda (x) (set 'seen (cons (* 10 x) seen))))
+         (run-hook 'saved (+ 1 1))
+         (run-hook 'other 3)
+         (remove-hook 'saved remember)
+         (run-hook 'saved 3)
+         seen
+         (remove-hook 'saved remember)",
+    )
+    .unwrap();
+
+    assert_eq!(values[9].to_string(), "(30 20 2)");
+    assert_eq!(values[7], Expression::True);
+    assert_eq!(values[10], Expression::Nil);
+}
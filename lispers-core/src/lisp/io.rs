@@ -0,0 +1,384 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::eval_body;
+use super::eval::EvalError;
+use super::expression::Expression;
+use super::expression::ForeignDataWrapper;
+use super::prelude::expand_path;
+use super::prelude::split_body;
+
+/// Evaluate a path argument, expanding `~` and environment variables like `expand-path`.
+/// Relative paths are relative to the working directory.
+fn path_argument(env: &Environment, expr: Expression) -> Result<PathBuf, EvalError> {
+    let path: String = eval(env, expr)?.try_into()?;
+    Ok(PathBuf::from(expand_path(&path)?))
+}
+
+/// Read a whole file as a string: `(read-file path)`
+pub fn io_read_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path] = expr.try_into()?;
+    let path = path_argument(env, path)?;
+    let content =
+        std::fs::read_to_string(&path).map_err(|e| EvalError::with_context(path.display(), e))?;
+    Ok(content.into())
+}
+
+/// Write a string to a file, replacing its content: `(write-file path content)`. Returns the
+/// path.
+pub fn io_write_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path, content] = expr.try_into()?;
+    let path = path_argument(env, path)?;
+    let content: String = eval(env, content)?.try_into()?;
+    std::fs::write(&path, content).map_err(|e| EvalError::with_context(path.display(), e))?;
+    Ok(path.display().to_string().into())
+}
+
+/// Append a string to a file, creating it if it does not exist: `(append-file path content)`.
+/// Returns the path.
+pub fn io_append_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path, content] = expr.try_into()?;
+    let path = path_argument(env, path)?;
+    let content: String = eval(env, content)?.try_into()?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|e| EvalError::with_context(path.display(), e))?;
+    Ok(path.display().to_string().into())
+}
+
+/// Check whether a file or directory exists: `(file-exists? path)`
+pub fn io_file_exists(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path] = expr.try_into()?;
+    let path = path_argument(env, path)?;
+    Ok(env.config().boolean(path.exists()))
+}
+
+/// Delete a file: `(delete-file path)`. Directories are not deleted. Returns the path.
+pub fn io_delete_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path] = expr.try_into()?;
+    let path = path_argument(env, path)?;
+    std::fs::remove_file(&path).map_err(|e| EvalError::with_context(path.display(), e))?;
+    Ok(path.display().to_string().into())
+}
+
+/// Get the sorted names of the entries of a directory: `(list-dir path)`
+pub fn io_list_dir(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path] = expr.try_into()?;
+    let path = path_argument(env, path)?;
+    let mut names = std::fs::read_dir(&path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, std::io::Error>>()
+        })
+        .map_err(|e| EvalError::with_context(path.display(), e))?;
+    names.sort();
+
+    Ok(names
+        .into_iter()
+        .map(Expression::from)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// The stream of an open file.
+#[derive(Debug)]
+enum FileStream {
+    Read(BufReader<File>),
+    Write(File),
+}
+
+/// A file opened with `open-file`. Copies of a handle refer to the same file, which is closed
+/// for all of them by `close-file`.
+#[derive(Clone, Debug)]
+pub struct FileHandle {
+    path: PathBuf,
+    stream: Arc<Mutex<Option<FileStream>>>,
+}
+
+impl FileHandle {
+    /// Open a file for `read`, `write` or `append`.
+    fn open(path: PathBuf, mode: &str) -> Result<FileHandle, EvalError> {
+        let stream = match mode {
+            "read" => File::open(&path).map(|f| FileStream::Read(BufReader::new(f))),
+            "write" => File::create(&path).map(FileStream::Write),
+            "append" => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map(FileStream::Write),
+            _ => {
+                return Err(EvalError::ArgumentError(format!(
+                    "Unknown file mode {}, expected read, write or append",
+                    mode
+                )))
+            }
+        }
+        .map_err(|e| EvalError::with_context(path.display(), e))?;
+
+        Ok(FileHandle {
+            path,
+            stream: Arc::new(Mutex::new(Some(stream))),
+        })
+    }
+
+    /// Close the file. Returns whether it was open.
+    fn close(&self) -> bool {
+        self.stream.lock().unwrap().take().is_some()
+    }
+
+    /// Call `f` with the stream of the open file.
+    fn with_stream<T>(
+        &self,
+        f: impl FnOnce(&mut FileStream) -> Result<T, EvalError>,
+    ) -> Result<T, EvalError> {
+        match self.stream.lock().unwrap().as_mut() {
+            Some(stream) => f(stream),
+            None => Err(EvalError::RuntimeError(
+                format!("{} is closed", self.path.display()),
+                None,
+            )),
+        }
+    }
+}
+
+impl PartialEq for FileHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.stream, &other.stream)
+    }
+}
+
+impl PartialOrd for FileHandle {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (self == other).then_some(std::cmp::Ordering::Equal)
+    }
+}
+
+impl Display for FileHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self.stream.lock().unwrap().is_some() {
+            true => "open",
+            false => "closed",
+        };
+        write!(f, "File({}, {})", self.path.display(), state)
+    }
+}
+
+/// Open a file: `(open-file path [mode])`, where `mode` is `'read` (the default), `'write` or
+/// `'append`. The file stays open until it is closed with `close-file`, prefer
+/// `with-open-file`.
+pub fn io_open_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (path, mode) = match <[Expression; 2]>::try_from(args) {
+        Ok([path, mode]) => (path, eval(env, mode)?),
+        Err(args) => {
+            let [path] = Expression::from(args).try_into()?;
+            (path, Expression::Symbol("read".to_string()))
+        }
+    };
+    let path = path_argument(env, path)?;
+    let mode = match mode {
+        Expression::Symbol(mode) => mode,
+        x => return Err(EvalError::NotASymbol(x)),
+    };
+
+    Ok(ForeignDataWrapper::new(FileHandle::open(path, &mode)?).into())
+}
+
+/// Close a file: `(close-file file)`. Returns whether the file was open.
+pub fn io_close_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [file] = expr.try_into()?;
+    let file: ForeignDataWrapper<FileHandle> = eval(env, file)?.try_into()?;
+    Ok(env.config().boolean(file.close()))
+}
+
+/// Read the next line of a file opened for reading, without the line break: `(read-line file)`.
+/// Returns nil at the end of the file.
+pub fn io_read_line(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [file] = expr.try_into()?;
+    let file: ForeignDataWrapper<FileHandle> = eval(env, file)?.try_into()?;
+    file.with_stream(|stream| match stream {
+        FileStream::Read(reader) => {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(Expression::Nil);
+            }
+            let len = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(len);
+            Ok(line.into())
+        }
+        FileStream::Write(_) => Err(EvalError::RuntimeError(
+            format!("{} is not open for reading", file.path.display()),
+            None,
+        )),
+    })
+}
+
+/// Write a string to a file opened for writing: `(write-string file string)`. Returns the
+/// string.
+pub fn io_write_string(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [file, string] = expr.try_into()?;
+    let file: ForeignDataWrapper<FileHandle> = eval(env, file)?.try_into()?;
+    let string: String = eval(env, string)?.try_into()?;
+    file.with_stream(|stream| match stream {
+        FileStream::Write(f) => Ok(f.write_all(string.as_bytes())?),
+        FileStream::Read(_) => Err(EvalError::RuntimeError(
+            format!("{} is not open for writing", file.path.display()),
+            None,
+        )),
+    })?;
+    Ok(string.into())
+}
+
+/// Evaluate a body with a file bound to a symbol, and close the file afterwards, even if the
+/// body fails: `(with-open-file (symbol path [mode]) body...)`. The modes are the same as for
+/// `open-file`. Returns the value of the body.
+pub fn io_with_open_file(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let ([spec], body) = split_body(expr)?;
+    let mut spec: Vec<Expression> = spec.try_into()?;
+    if spec.is_empty() {
+        return Err(EvalError::ArgumentError(
+            "Expected (symbol path [mode])".to_string(),
+        ));
+    }
+    let symbol = match spec.remove(0) {
+        Expression::Symbol(s) => s,
+        x => return Err(EvalError::NotASymbol(x)),
+    };
+    let file: ForeignDataWrapper<FileHandle> = io_open_file(env, spec.into())?.try_into()?;
+
+    let mut layer = EnvironmentLayer::new();
+    layer.set(
+        symbol,
+        ForeignDataWrapper::new(file.0.as_ref().clone()).into(),
+    );
+    let result = eval_body(&env.overlay(layer), body);
+    file.close();
+    result
+}
+
+/// Adds the file I/O builtins to the given environment layer. These are not part of the
+/// prelude, hosts opt in to give scripts access to the file system.
+pub fn mk_io(layer: &mut EnvironmentLayer) {
+    layer.set("read-file".to_string(), Expression::Function(io_read_file));
+    layer.set(
+        "write-file".to_string(),
+        Expression::Function(io_write_file),
+    );
+    layer.set(
+        "append-file".to_string(),
+        Expression::Function(io_append_file),
+    );
+    layer.set(
+        "file-exists?".to_string(),
+        Expression::Function(io_file_exists),
+    );
+    layer.set(
+        "delete-file".to_string(),
+        Expression::Function(io_delete_file),
+    );
+    layer.set("list-dir".to_string(), Expression::Function(io_list_dir));
+    layer.set("open-file".to_string(), Expression::Function(io_open_file));
+    layer.set(
+        "close-file".to_string(),
+        Expression::Function(io_close_file),
+    );
+    layer.set("read-line".to_string(), Expression::Function(io_read_line));
+    layer.set(
+        "write-string".to_string(),
+        Expression::Function(io_write_string),
+    );
+    layer.set(
+        "with-open-file".to_string(),
+        Expression::Function(io_with_open_file),
+    );
+}
+
+#[test]
+fn test_io() {
+    use super::eval_source;
+
+    let dir = std::env::temp_dir().join(format!("lispers-test-io-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut layer = EnvironmentLayer::new();
+    super::prelude::mk_prelude(&mut layer);
+    mk_io(&mut layer);
+    layer.set("DIR".to_string(), dir.display().to_string().into());
+    let env = Environment::from_layer(layer);
+
+    let values = eval_source(
+        &env,
+        r#"
+        (write-file (concat DIR "/b.txt") "first")
+        (append-file (concat DIR "/b.txt") " second")
+        (append-file (concat DIR "/a.txt") "new")
+        (read-file (concat DIR "/b.txt"))
+        (list-dir DIR)
+        (delete-file (concat DIR "/a.txt"))
+        (file-exists? (concat DIR "/a.txt"))
+        (file-exists? DIR)
+        "#,
+    )
+    .unwrap();
+    let string = |s: &str| Expression::String(s.to_string());
+
+    assert_eq!(values[3], string("first second"));
+    assert_eq!(values[4], vec![string("a.txt"), string("b.txt")].into());
+    assert_eq!(values[6], Expression::Nil);
+    assert_eq!(values[7], Expression::True);
+    assert!(matches!(
+        eval_source(&env, "(read-file (concat DIR \"/a.txt\"))"),
+        Err(super::LispError::Eval(e)) if matches!(e.root(), EvalError::RuntimeError(_, Some(_)))
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_with_open_file() {
+    use super::eval_source;
+
+    let path = std::env::temp_dir().join(format!("lispers-test-files-{}", std::process::id()));
+    let mut layer = EnvironmentLayer::new();
+    super::prelude::mk_prelude(&mut layer);
+    mk_io(&mut layer);
+    layer.set("PATH".to_string(), path.display().to_string().into());
+    let env = Environment::from_layer(layer);
+
+    let values = eval_source(
+        &env,
+        r#"
+        (with-open-file (f PATH 'write) (write-string f "one
+two
+"))
+        (with-open-file (f PATH) (list (read-line f) (read-line f) (read-line f)))
+        (catch (with-open-file (f PATH 'append) (set 'leaked f) (throw 'failed))
+               (lambda (c) 'caught))
+        leaked
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(values[1].to_string(), "(\"one\" \"two\" nil)");
+    assert_eq!(values[2], Expression::Symbol("caught".to_string()));
+    // The file is closed when the body fails
+    assert!(values[3].to_string().ends_with("closed)"));
+    assert!(eval_source(&env, "(write-string leaked \"three\")").is_err());
+    assert!(eval_source(&env, "(with-open-file (f PATH 'delete) nil)").is_err());
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,184 @@
+use num_traits::ToPrimitive;
+use serde_json::{Map, Number, Value};
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::environment::InterpreterConfig;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::{Expression, MapKey};
+
+/// Convert JSON data into lisp data: objects become maps with string keys, arrays lists and
+/// `null` nil. Booleans are the values of `config`.
+pub fn json_to_expression(value: Value, config: &InterpreterConfig) -> Expression {
+    match value {
+        Value::Null => Expression::Nil,
+        Value::Bool(b) => config.boolean(b),
+        Value::Number(n) => Expression::from_number(n.as_i64(), n.as_u64(), n.as_f64()),
+        Value::String(s) => Expression::String(s),
+        Value::Array(values) => values
+            .into_iter()
+            .map(|v| json_to_expression(v, config))
+            .collect(),
+        Value::Object(entries) => Expression::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (MapKey::String(k), json_to_expression(v, config)))
+                .collect(),
+        ),
+    }
+}
+
+/// Convert lisp data into JSON data: maps become objects, lists arrays and nil `null`. Symbols
+/// are written as strings, as are integer map keys. BigInts beyond 64 bits cannot be encoded.
+///
+/// As the empty list is nil, which is also false unless `distinct_false` is set, decoding
+/// `false` or `[]` and encoding the result again gives `null`.
+pub fn expression_to_json(expr: &Expression) -> Result<Value, EvalError> {
+    let unsupported = || EvalError::TypeError(format!("Cannot encode {} as JSON", expr));
+    Ok(match expr {
+        Expression::Nil => Value::Null,
+        Expression::True => Value::Bool(true),
+        Expression::False => Value::Bool(false),
+        Expression::Integer(i) => Value::Number((*i).into()),
+        Expression::BigInt(i) => match (i.to_i64(), i.to_u64()) {
+            (Some(i), _) => Value::Number(i.into()),
+            (None, Some(u)) => Value::Number(u.into()),
+            (None, None) => return Err(unsupported()),
+        },
+        Expression::Float(f) => Value::Number(Number::from_f64(*f).ok_or_else(unsupported)?),
+        Expression::String(s) | Expression::Symbol(s) => Value::String(s.clone()),
+        Expression::Cell(_, _) => Value::Array(
+            expr.list_iter()
+                .map(|e| expression_to_json(e?))
+                .collect::<Result<_, _>>()?,
+        ),
+        Expression::Map(m) => Value::Object(
+            m.iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        MapKey::Symbol(s) | MapKey::String(s) => s.clone(),
+                        MapKey::Integer(i) => i.to_string(),
+                    };
+                    Ok((key, expression_to_json(v)?))
+                })
+                .collect::<Result<Map<_, _>, EvalError>>()?,
+        ),
+        _ => return Err(unsupported()),
+    })
+}
+
+/// Parse a JSON string into lisp data: `(json-parse s)`. Objects become maps with string keys,
+/// arrays lists and `null` nil. Like the empty array, `false` becomes nil unless
+/// `distinct_false` is set.
+pub fn json_parse(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    let value: Value =
+        serde_json::from_str(&s).map_err(|e| EvalError::with_context("Invalid JSON", e))?;
+    Ok(json_to_expression(value, env.config()))
+}
+
+/// Encode lisp data as a JSON string: `(json-encode x [pretty])`, indented if `pretty` is true.
+/// Maps become objects, lists arrays and nil `null`.
+pub fn json_encode(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (x, pretty) = match <[Expression; 2]>::try_from(args) {
+        Ok([x, pretty]) => (x, Some(pretty)),
+        Err(args) => {
+            let [x]: [Expression; 1] = Expression::from(args).try_into()?;
+            (x, None)
+        }
+    };
+
+    let value = expression_to_json(&eval(env, x)?)?;
+    let pretty = match pretty {
+        Some(pretty) => eval(env, pretty)?.is_truthy(env.config()),
+        None => false,
+    };
+    let json = if pretty {
+        serde_json::to_string_pretty(&value)
+    } else {
+        serde_json::to_string(&value)
+    };
+    Ok(json.map_err(EvalError::from_error)?.into())
+}
+
+/// Adds the JSON builtins to the given environment layer.
+pub fn mk_json(layer: &mut EnvironmentLayer) {
+    layer.set("json-parse".to_string(), Expression::Function(json_parse));
+    layer.set("json-encode".to_string(), Expression::Function(json_encode));
+}
+
+#[test]
+fn test_json() {
+    use super::{eval_source, LispError};
+
+    let env = Environment::default();
+    let run = |source: &str| eval_source(&env, source).map(|mut values| values.pop().unwrap());
+    let string = |s: &str| Ok(Expression::String(s.to_string()));
+
+    // Lisp strings cannot contain quotes, JSON is usually read from a file
+    env.shared_set(
+        "source".to_string(),
+        r#"{"frames": [{"t": 0, "pos": [0, 1.5, -2]}], "loop": true, "name": null}"#
+            .to_string()
+            .into(),
+    );
+    assert_eq!(
+        run("(set 'scene (json-parse source)) (map-get scene \"loop\")"),
+        Ok(Expression::True)
+    );
+    assert_eq!(
+        run(r#"(map-get (car (map-get scene "frames")) "pos")"#),
+        run("'(0 1.5 -2)")
+    );
+    assert_eq!(
+        run(r#"(map-get scene "name" 'missing)"#),
+        Ok(Expression::Nil)
+    );
+    assert_eq!(
+        run(r#"(map-keys scene)"#),
+        run(r#"'("frames" "loop" "name")"#)
+    );
+    assert_eq!(
+        run("(json-parse \"18446744073709551615\")"),
+        run("18446744073709551615")
+    );
+    assert_eq!(
+        run("(json-encode (json-parse \"18446744073709551615\"))"),
+        string("18446744073709551615")
+    );
+    assert_eq!(
+        run("(json-encode (json-parse \"[false, [], -9223372036854775808]\"))"),
+        string("[null,null,-9223372036854775808]")
+    );
+    assert_eq!(
+        run("(json-encode scene)"),
+        string(r#"{"frames":[{"t":0,"pos":[0,1.5,-2]}],"loop":true,"name":null}"#)
+    );
+    assert_eq!(
+        run("(json-encode (map-set (map-set (make-map) 'a '(x \"y\")) 1 2.0) true)"),
+        string("{\n  \"a\": [\n    \"x\",\n    \"y\"\n  ],\n  \"1\": 2.0\n}")
+    );
+    let error = |source: &str| match run(source) {
+        Err(LispError::Eval(e)) => e.root().clone(),
+        x => panic!("Expected an error, got {:?}", x),
+    };
+    assert!(matches!(
+        error("(json-parse \"{\")"),
+        EvalError::RuntimeError(_, Some(_))
+    ));
+    assert!(matches!(
+        error("(json-encode (lambda (x) x))"),
+        EvalError::TypeError(_)
+    ));
+    assert!(matches!(
+        error("(json-encode '(1 . 2))"),
+        EvalError::TypeError(_)
+    ));
+    assert!(matches!(
+        error("(json-encode 18446744073709551616)"),
+        EvalError::TypeError(_)
+    ));
+}
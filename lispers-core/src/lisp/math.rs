@@ -0,0 +1,291 @@
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+use super::prelude::compare;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
+use std::cmp::Ordering;
+
+/// Evaluate the single argument of a float function and apply `f` to it.
+fn float_function(
+    env: &Environment,
+    expr: Expression,
+    f: fn(f64) -> f64,
+) -> Result<Expression, EvalError> {
+    let [x] = expr.try_into()?;
+    let x: f64 = eval(env, x)?.try_into()?;
+    Ok(Expression::Float(f(x)))
+}
+
+/// Evaluate the single argument of a rounding function. Integers are returned as is, floats are
+/// rounded with `f` and converted to an integer.
+fn rounding_function(
+    env: &Environment,
+    expr: Expression,
+    f: fn(f64) -> f64,
+) -> Result<Expression, EvalError> {
+    let [x] = expr.try_into()?;
+    match eval(env, x)? {
+        x @ (Expression::Integer(_) | Expression::BigInt(_)) => Ok(x),
+        Expression::Float(x) => match BigInt::from_f64(f(x)) {
+            Some(i) => Ok(i.into()),
+            None => Err(EvalError::RuntimeError(
+                format!("Cannot round {} to an integer", x),
+                None,
+            )),
+        },
+        x => Err(EvalError::NotANumber(x)),
+    }
+}
+
+pub fn math_sin(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::sin)
+}
+
+pub fn math_cos(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::cos)
+}
+
+pub fn math_tan(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::tan)
+}
+
+/// The angle of the point `(x, y)`: `(atan2 y x)`
+pub fn math_atan2(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [y, x] = expr.try_into()?;
+    let y: f64 = eval(env, y)?.try_into()?;
+    let x: f64 = eval(env, x)?.try_into()?;
+    Ok(Expression::Float(y.atan2(x)))
+}
+
+pub fn math_exp(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::exp)
+}
+
+/// The natural logarithm, or the logarithm to a base: `(log x [base])`
+pub fn math_log(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    match <[Expression; 2]>::try_from(args) {
+        Ok([x, base]) => {
+            let x: f64 = eval(env, x)?.try_into()?;
+            let base: f64 = eval(env, base)?.try_into()?;
+            Ok(Expression::Float(x.log(base)))
+        }
+        Err(args) => float_function(env, args.into(), f64::ln),
+    }
+}
+
+pub fn math_sqrt(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::sqrt)
+}
+
+/// Raise a number to a power: `(expt base power)`. Integers raised to a non-negative integer
+/// power stay exact, everything else is a float. Exact powers larger than
+/// `InterpreterConfig::max_integer_bits` are an error.
+pub fn math_expt(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [base, power] = expr.try_into()?;
+    let base = eval(env, base)?;
+    let power = eval(env, power)?;
+
+    match (&base, &power) {
+        (Expression::Integer(_) | Expression::BigInt(_), Expression::Integer(p)) if *p >= 0 => {
+            let base: BigInt = base.try_into()?;
+            // The power has floor(p * log2(|base|)) + 1 bits, powers of 0, 1 and -1 do not grow
+            let log2 = match base.abs().to_f64() {
+                Some(b) if b.is_finite() => b.log2(),
+                _ => base.bits() as f64,
+            };
+            if let Some(max) = env.config().max_integer_bits.filter(|_| log2 > 0.0) {
+                if (*p as f64 * log2).floor() + 1.0 > max as f64 {
+                    return Err(EvalError::LimitExceeded(format!(
+                        "{} to the power of {} has more than {} bits",
+                        base, p, max
+                    )));
+                }
+            }
+            let p = u32::try_from(*p).map_err(|_| {
+                EvalError::RuntimeError(format!("Exponent {} is too large", p), None)
+            })?;
+            Ok(base.pow(p).into())
+        }
+        _ => {
+            let base: f64 = base.try_into()?;
+            let power: f64 = power.try_into()?;
+            Ok(Expression::Float(base.powf(power)))
+        }
+    }
+}
+
+pub fn math_abs(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [x] = expr.try_into()?;
+    match eval(env, x)? {
+        Expression::Integer(i) => Ok(match i.checked_abs() {
+            Some(i) => Expression::Integer(i),
+            None => BigInt::from(i).abs().into(),
+        }),
+        Expression::BigInt(i) => Ok(i.abs().into()),
+        Expression::Float(f) => Ok(Expression::Float(f.abs())),
+        x => Err(EvalError::NotANumber(x)),
+    }
+}
+
+/// Get the argument which is ordered first by `order`, comparing numbers by value.
+fn extremum(env: &Environment, expr: Expression, order: Ordering) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let mut result: Option<Expression> = None;
+
+    for arg in args {
+        let arg = eval(env, arg)?;
+        if !matches!(
+            arg,
+            Expression::Integer(_) | Expression::BigInt(_) | Expression::Float(_)
+        ) {
+            return Err(EvalError::NotANumber(arg));
+        }
+        result = match result {
+            Some(r) if compare(&arg, &r) != Some(order) => Some(r),
+            _ => Some(arg),
+        };
+    }
+
+    result.ok_or_else(|| EvalError::ArgumentError("Expected at least one number".to_string()))
+}
+
+/// The smallest of one or more numbers: `(min 3 1.5 2)`
+pub fn math_min(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    extremum(env, expr, Ordering::Less)
+}
+
+/// The largest of one or more numbers: `(max 3 1.5 2)`
+pub fn math_max(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    extremum(env, expr, Ordering::Greater)
+}
+
+pub fn math_floor(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    rounding_function(env, expr, f64::floor)
+}
+
+pub fn math_ceil(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    rounding_function(env, expr, f64::ceil)
+}
+
+/// Round to the nearest integer, rounding half-way cases away from zero.
+pub fn math_round(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    rounding_function(env, expr, f64::round)
+}
+
+/// Convert an angle from degrees to radians: `(deg->rad 180)`
+pub fn math_deg_to_rad(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::to_radians)
+}
+
+/// Convert an angle from radians to degrees: `(rad->deg pi)`
+pub fn math_rad_to_deg(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    float_function(env, expr, f64::to_degrees)
+}
+
+/// Adds the math functions and constants to the given environment layer.
+pub fn mk_math(layer: &mut EnvironmentLayer) {
+    layer.set("pi".to_string(), std::f64::consts::PI.into());
+    layer.set("tau".to_string(), std::f64::consts::TAU.into());
+    layer.set("e".to_string(), std::f64::consts::E.into());
+    layer.set("sin".to_string(), Expression::Function(math_sin));
+    layer.set("cos".to_string(), Expression::Function(math_cos));
+    layer.set("tan".to_string(), Expression::Function(math_tan));
+    layer.set("atan2".to_string(), Expression::Function(math_atan2));
+    layer.set("exp".to_string(), Expression::Function(math_exp));
+    layer.set("log".to_string(), Expression::Function(math_log));
+    layer.set("sqrt".to_string(), Expression::Function(math_sqrt));
+    layer.set("expt".to_string(), Expression::Function(math_expt));
+    layer.set("abs".to_string(), Expression::Function(math_abs));
+    layer.set("min".to_string(), Expression::Function(math_min));
+    layer.set("max".to_string(), Expression::Function(math_max));
+    layer.set("floor".to_string(), Expression::Function(math_floor));
+    layer.set("ceil".to_string(), Expression::Function(math_ceil));
+    layer.set("round".to_string(), Expression::Function(math_round));
+    layer.set(
+        "deg->rad".to_string(),
+        Expression::Function(math_deg_to_rad),
+    );
+    layer.set(
+        "rad->deg".to_string(),
+        Expression::Function(math_rad_to_deg),
+    );
+}
+
+#[test]
+fn test_math() {
+    use super::environment::InterpreterConfig;
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    for (program, expected) in [
+        (
+            "(atan2 1 0)",
+            Expression::Float(std::f64::consts::FRAC_PI_2),
+        ),
+        ("(sqrt 16)", Expression::Float(4.0)),
+        ("(log 8 2)", Expression::Float(3.0)),
+        ("(log (exp 2))", Expression::Float(2.0)),
+        ("(expt 2 10)", Expression::Integer(1024)),
+        ("(expt 4 0.5)", Expression::Float(2.0)),
+        ("(expt 2 -1)", Expression::Float(0.5)),
+        ("(abs -3)", Expression::Integer(3)),
+        ("(abs -2.5)", Expression::Float(2.5)),
+        ("(min 3 1.5 2)", Expression::Float(1.5)),
+        ("(max 3 1.5 2)", Expression::Integer(3)),
+        ("(floor -2.5)", Expression::Integer(-3)),
+        ("(ceil 2.1)", Expression::Integer(3)),
+        ("(round 2.5)", Expression::Integer(3)),
+        ("(round 7)", Expression::Integer(7)),
+    ] {
+        assert_eq!(run(program), Ok(expected), "{}", program);
+    }
+    assert_eq!(
+        run("(expt 2 64)").map(|e| e.to_string()),
+        Ok("18446744073709551616".to_string())
+    );
+    assert!(matches!(run("(min)"), Err(EvalError::ArgumentError(_))));
+    assert!(matches!(run("(max 1 'a)"), Err(EvalError::NotANumber(_))));
+
+    // Huge exact powers exceed the configured limit, unless they cannot grow
+    assert!(matches!(
+        run("(expt 10 100000000)"),
+        Err(EvalError::LimitExceeded(_))
+    ));
+    assert!(matches!(
+        run("(expt (expt 2 100) 100000)"),
+        Err(EvalError::LimitExceeded(_))
+    ));
+    assert_eq!(run("(expt -1 100000001)"), Ok(Expression::Integer(-1)));
+    assert_eq!(
+        run("(expt 10 100000000.0)"),
+        Ok(Expression::Float(f64::INFINITY))
+    );
+    let mut env = Environment::default();
+    env.set_config(InterpreterConfig::default().with_max_integer_bits(Some(64)));
+    assert!(matches!(
+        eval_last(&env, "(expt 2 64)"),
+        Err(EvalError::LimitExceeded(_))
+    ));
+    assert_eq!(
+        eval_last(&env, "(expt 2 62)"),
+        Ok(Expression::Integer(1 << 62))
+    );
+}
+
+#[test]
+fn test_constants() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s).and_then(f64::try_from).unwrap();
+
+    assert_eq!(run("(* 2 pi)"), run("tau"));
+    assert!((run("(deg->rad 180)") - std::f64::consts::PI).abs() < 1e-12);
+    assert!((run("(rad->deg (/ pi 2))") - 90.0).abs() < 1e-12);
+}
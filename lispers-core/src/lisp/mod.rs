@@ -1,8 +1,35 @@
+pub mod builder;
+#[cfg(feature = "serde")]
+pub mod cache;
+#[cfg(feature = "serde")]
+pub mod canonical;
 pub mod environment;
 pub mod eval;
 pub mod expression;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub mod formats;
+pub mod hooks;
+pub mod io;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod math;
+pub mod modules;
+pub mod output;
 pub mod prelude;
+pub mod printer;
+pub mod profile;
+pub mod random;
+pub mod record;
+pub mod registry;
+pub mod stdlib;
+pub mod strings;
+pub mod trace;
+pub mod version;
 
 pub use environment::Environment;
+pub use environment::InterpreterConfig;
 pub use eval::eval;
+pub use eval::eval_source;
+pub use eval::LispError;
+pub use eval::Warning;
 pub use expression::Expression;
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval_body;
+use super::eval::EvalError;
+use super::expression::Expression;
+use super::expression::MapKey;
+
+/// The symbol bound to the list of modules imported with `import`.
+pub(crate) const IMPORTS_SYMBOL: &str = "LISPERS-IMPORTS";
+
+/// Qualify the symbols in `expr` which name members of `module`, so functions of a module find
+/// the other members when called from outside. Quoted expressions are left as they are.
+fn qualify(expr: Expression, module: &str, members: &HashSet<String>) -> Expression {
+    match expr {
+        Expression::Symbol(s) if members.contains(&s) => {
+            Expression::Symbol(format!("{}/{}", module, s))
+        }
+        Expression::Cell(a, b) => Expression::Cell(
+            Arc::new(qualify(Arc::unwrap_or_clone(a), module, members)),
+            Arc::new(qualify(Arc::unwrap_or_clone(b), module, members)),
+        ),
+        Expression::AnonymousFunction {
+            argument_symbols,
+            body,
+        } => {
+            // Arguments shadow the members of the same name
+            let mut members = members.clone();
+            for arg in &argument_symbols {
+                members.remove(arg);
+            }
+            Expression::AnonymousFunction {
+                argument_symbols,
+                body: body
+                    .iter()
+                    .map(|e| qualify(e.clone(), module, &members))
+                    .collect(),
+            }
+        }
+        e => e,
+    }
+}
+
+/// Define a module: `(module geometry body...)`. The body is evaluated in isolation like
+/// `sandbox`, and everything it defines with `define`, `defun` or `set` becomes a member of the
+/// module instead of a global. The module is bound to its name as a map from the member names to
+/// their values, so `geometry/area` refers to the member `area`. Returns the module name.
+///
+/// References between members are qualified, so they keep working when a member function is
+/// called from outside the module. Other modules have to be referred to by qualified names, as
+/// imports within the module body do not apply to those calls.
+pub fn module_define(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let mut args: Vec<Expression> = expr.try_into()?;
+    if args.is_empty() {
+        return Err(EvalError::ArgumentError(
+            "Expected a module name".to_string(),
+        ));
+    }
+    let body = args.split_off(1);
+    let name = match args.remove(0) {
+        Expression::Symbol(s) if !s.contains('/') => s,
+        x => return Err(EvalError::NotASymbol(x)),
+    };
+
+    let module_env = env.fork_isolated();
+    eval_body(&module_env, body)?;
+
+    let bindings: Vec<(String, Expression)> = module_env
+        .shared_bindings()
+        .into_iter()
+        .filter(|(k, _)| !k.starts_with("LISPERS-"))
+        .collect();
+    let members: HashSet<String> = bindings.iter().map(|(k, _)| k.clone()).collect();
+    let module: IndexMap<MapKey, Expression> = bindings
+        .into_iter()
+        .map(|(k, v)| (MapKey::Symbol(k), qualify(v, &name, &members)))
+        .collect();
+
+    env.shared_set(name.clone(), Expression::Map(module));
+    Ok(Expression::Symbol(name))
+}
+
+/// Make the members of modules available by their unqualified names: `(import geometry)`.
+/// Bindings of the same name and earlier imports take precedence. Returns the imported module
+/// names.
+pub fn module_import(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let modules: Vec<Expression> = expr.try_into()?;
+    let mut imports: Vec<Expression> = match env.shared_get(IMPORTS_SYMBOL) {
+        Some(imports) => imports.try_into()?,
+        None => Vec::new(),
+    };
+
+    for module in &modules {
+        match module {
+            Expression::Symbol(name) => match env.get(name) {
+                Some(Expression::Map(_)) => {}
+                _ => {
+                    return Err(EvalError::ArgumentError(format!(
+                        "{} is not a module",
+                        name
+                    )))
+                }
+            },
+            x => return Err(EvalError::NotASymbol(x.clone())),
+        }
+        if !imports.contains(module) {
+            imports.push(module.clone());
+        }
+    }
+
+    env.shared_set(IMPORTS_SYMBOL.to_string(), imports.into());
+    Ok(modules.into())
+}
+
+/// Adds the module system to the given environment layer.
+pub fn mk_modules(layer: &mut EnvironmentLayer) {
+    layer.set("module".to_string(), Expression::Function(module_define));
+    layer.set("import".to_string(), Expression::Function(module_import));
+}
+
+#[test]
+fn test_modules() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let values = eval_source(
+        &env,
+        "(module geometry
+           (define pi 3)
+           (defun square (x) (* x x))
+           (defun area (r) (* pi (square r))))
+         (module util (defun square (s) (concat s s)))
+         (geometry/area 2)
+         (util/square \"ab\")
+         (import geometry)
+         (area 1)
+         (square 3)
+         (let ((pi 4)) (area 1))",
+    )
+    .unwrap();
+
+    assert_eq!(values[2], Expression::Integer(12));
+    assert_eq!(values[3], Expression::String("abab".to_string()));
+    assert_eq!(values[5], Expression::Integer(3));
+    assert_eq!(values[6], Expression::Integer(9));
+    // Members refer to each other by qualified names, which local bindings do not shadow
+    assert_eq!(values[7], Expression::Integer(3));
+    // Imports do not shadow global bindings
+    assert_eq!(env.get("geometry/pi"), Some(Expression::Integer(3)));
+    assert!(matches!(env.get("pi"), Some(Expression::Float(_))));
+    assert_eq!(env.get("geometry/missing"), None);
+    assert!(eval_source(&env, "(import missing)").is_err());
+}
@@ -0,0 +1,124 @@
+use std::io::Write;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval_body;
+use super::eval::EvalError;
+use super::expression::Expression;
+
+/// Where the printing builtins write to: the innermost capture, the writer of the host or
+/// standard output.
+#[derive(Default)]
+pub(crate) struct OutputSink {
+    /// The writer set by the host, standard output if `None`.
+    writer: Option<Box<dyn Write + Send>>,
+    /// The output captured by `with-captured-output`, innermost last.
+    captures: Vec<String>,
+}
+
+impl OutputSink {
+    /// Write a string to the sink.
+    pub fn write(&mut self, s: &str) -> std::io::Result<()> {
+        match (self.captures.last_mut(), self.writer.as_mut()) {
+            (Some(capture), _) => {
+                capture.push_str(s);
+                Ok(())
+            }
+            (None, Some(writer)) => writer.write_all(s.as_bytes()),
+            (None, None) => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(s.as_bytes())?;
+                stdout.flush()
+            }
+        }
+    }
+
+    /// Replace the writer output goes to when it is not captured.
+    pub fn set_writer(&mut self, writer: Box<dyn Write + Send>) {
+        self.writer = Some(writer);
+    }
+
+    /// Start capturing the output, until `end_capture`. Captures nest.
+    pub fn begin_capture(&mut self) {
+        self.captures.push(String::new());
+    }
+
+    /// Stop the innermost capture and get its output.
+    pub fn end_capture(&mut self) -> String {
+        self.captures.pop().unwrap_or_default()
+    }
+}
+
+impl std::fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutputSink")
+            .field("redirected", &self.writer.is_some())
+            .field("captures", &self.captures)
+            .finish()
+    }
+}
+
+impl PartialEq for OutputSink {
+    /// Writers cannot be compared, so only the captured output is.
+    fn eq(&self, other: &Self) -> bool {
+        self.captures == other.captures
+    }
+}
+
+/// Evaluate a body and capture what it prints: `(with-captured-output body...)`. Returns the
+/// pair `(result . output)`. The output is captured even if the body fails, but then discarded
+/// with the error.
+pub fn output_with_captured_output(
+    env: &Environment,
+    expr: Expression,
+) -> Result<Expression, EvalError> {
+    let body: Vec<Expression> = expr.try_into()?;
+    let (result, output) = env.capture_output(|| eval_body(env, body));
+    Ok((result?, Expression::String(output)).into())
+}
+
+/// Adds the output builtins to the given environment layer.
+pub fn mk_output(layer: &mut EnvironmentLayer) {
+    layer.set(
+        "with-captured-output".to_string(),
+        Expression::Function(output_with_captured_output),
+    );
+}
+
+#[test]
+fn test_captured_output() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let values = eval_source(
+        &env,
+        "(with-captured-output (print 1) (println \"two\") 3)
+         (with-captured-output
+           (print 'outer)
+           (with-captured-output (print 'inner))
+           (pp '(a b)))
+         (catch (with-captured-output (print 'lost) (throw 'failed)) (lambda (e) 'caught))
+         (with-captured-output (print 'after))",
+    )
+    .unwrap();
+
+    assert_eq!(values[0].to_string(), "(3 . \"1\\\"two\\\"\n\")");
+    assert_eq!(values[1].to_string(), "((a b) . \"outer(a b)\n\")");
+    assert_eq!(values[3].to_string(), "(after . \"after\")");
+
+    // Hosts can redirect the output
+    #[derive(Clone, Default)]
+    struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let buffer = Shared::default();
+    env.set_output(Box::new(buffer.clone()));
+    eval_source(&env, "(println 'redirected)").unwrap();
+    assert_eq!(buffer.0.lock().unwrap().as_slice(), b"redirected\n");
+}
@@ -1,89 +1,259 @@
 use crate::parser::ExpressionStream;
-use crate::parser::ParserError;
 
 use super::environment::Environment;
 use super::environment::EnvironmentLayer;
 use super::eval::eval;
+use super::eval::eval_body;
+use super::eval::eval_source;
 use super::eval::CellIterator;
 use super::eval::EvalError;
+use super::eval::Warning;
 use super::expression::Expression;
-use std::collections::HashMap;
+use super::expression::MapKey;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+use super::formats::mk_formats;
+use super::hooks::mk_hooks;
+#[cfg(feature = "json")]
+use super::json::mk_json;
+use super::math::mk_math;
+use super::modules::mk_modules;
+use super::output::mk_output;
+use super::printer::mk_printer;
+use super::profile::mk_profile;
+use super::random::mk_random;
+use super::registry::mk_registry;
+use super::stdlib::mk_stdlib;
+use super::strings::mk_strings;
+use super::trace::mk_trace;
+use super::version::language_version;
+use super::version::lispers_version;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
+use std::cmp::Ordering;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
 
-pub fn prelude_add(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
+/// Apply an arithmetic operation to two numbers. Integer results which overflow an i64
+/// (`int` returns `None`) are computed with `big` instead, any float operand makes the
+/// result a float.
+fn arithmetic(
+    a: Expression,
+    b: Expression,
+    int: fn(i64, i64) -> Option<i64>,
+    big: fn(BigInt, BigInt) -> BigInt,
+    float: fn(f64, f64) -> f64,
+) -> Result<Expression, EvalError> {
+    use Expression::{BigInt as Big, Float, Integer};
 
-    match eval(env, a)? {
-        Expression::Integer(a) => match eval(env, b)? {
-            Expression::Integer(b) => Ok(Expression::Integer(a + b)),
-            Expression::Float(b) => Ok(Expression::Float(a as f64 + b)),
-            x => Err(EvalError::NotANumber(x)),
-        },
-        Expression::Float(a) => match eval(env, b)? {
-            Expression::Float(b) => Ok(Expression::Float(a + b)),
-            Expression::Integer(b) => Ok(Expression::Float(a + b as f64)),
-            x => Err(EvalError::NotANumber(x)),
+    match (a, b) {
+        (Integer(a), Integer(b)) => Ok(match int(a, b) {
+            Some(r) => Integer(r),
+            None => big(a.into(), b.into()).into(),
+        }),
+        (a @ (Integer(_) | Big(_)), b @ (Integer(_) | Big(_))) => {
+            Ok(big(a.try_into()?, b.try_into()?).into())
+        }
+        (a @ (Integer(_) | Big(_) | Float(_)), b @ (Integer(_) | Big(_) | Float(_))) => {
+            Ok(Float(float(a.try_into()?, b.try_into()?)))
+        }
+        (Integer(_) | Big(_) | Float(_), x) => Err(EvalError::NotANumber(x)),
+        (x, _) => Err(EvalError::NotANumber(x)),
+    }
+}
+
+/// Evaluate the two arguments of a binary function. A proper two element list is destructured
+/// directly, which keeps collecting the arguments off the hot path of arithmetic.
+pub fn binary_operands(
+    env: &Environment,
+    expr: Expression,
+) -> Result<(Expression, Expression), EvalError> {
+    let [a, b] = match expr {
+        Expression::Cell(a, rest) => match Arc::unwrap_or_clone(rest) {
+            Expression::Cell(b, end) if *end == Expression::Nil => {
+                [Arc::unwrap_or_clone(a), Arc::unwrap_or_clone(b)]
+            }
+            rest => Expression::Cell(a, Arc::new(rest)).try_into()?,
         },
-        x => Err(EvalError::NotANumber(x)),
+        expr => expr.try_into()?,
+    };
+    Ok((eval(env, a)?, eval(env, b)?))
+}
+
+/// Add two evaluated numbers like `+`.
+pub fn add_numbers(a: Expression, b: Expression) -> Result<Expression, EvalError> {
+    match (a, b) {
+        (Expression::Integer(a), Expression::Integer(b)) if a.checked_add(b).is_some() => {
+            Ok(Expression::Integer(a + b))
+        }
+        (a, b) => arithmetic(a, b, i64::checked_add, |a, b| a + b, |a, b| a + b),
     }
 }
 
-pub fn prelude_sub(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
+/// Subtract two evaluated numbers like `-`.
+pub fn sub_numbers(a: Expression, b: Expression) -> Result<Expression, EvalError> {
+    match (a, b) {
+        (Expression::Integer(a), Expression::Integer(b)) if a.checked_sub(b).is_some() => {
+            Ok(Expression::Integer(a - b))
+        }
+        (a, b) => arithmetic(a, b, i64::checked_sub, |a, b| a - b, |a, b| a - b),
+    }
+}
 
-    match eval(env, a)? {
-        Expression::Integer(a) => match eval(env, b)? {
-            Expression::Integer(b) => Ok(Expression::Integer(a - b)),
-            Expression::Float(b) => Ok(Expression::Float(a as f64 - b)),
-            x => Err(EvalError::NotANumber(x)),
-        },
-        Expression::Float(a) => match eval(env, b)? {
-            Expression::Float(b) => Ok(Expression::Float(a - b)),
-            Expression::Integer(b) => Ok(Expression::Float(a - b as f64)),
-            x => Err(EvalError::NotANumber(x)),
-        },
-        x => Err(EvalError::NotANumber(x)),
+/// Multiply two evaluated numbers like `*`.
+pub fn mul_numbers(a: Expression, b: Expression) -> Result<Expression, EvalError> {
+    match (a, b) {
+        (Expression::Integer(a), Expression::Integer(b)) if a.checked_mul(b).is_some() => {
+            Ok(Expression::Integer(a * b))
+        }
+        (a, b) => arithmetic(a, b, i64::checked_mul, |a, b| a * b, |a, b| a * b),
     }
 }
 
+pub fn prelude_add(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = binary_operands(env, expr)?;
+    add_numbers(a, b)
+}
+
+pub fn prelude_sub(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = binary_operands(env, expr)?;
+    sub_numbers(a, b)
+}
+
 pub fn prelude_mul(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
+    let (a, b) = binary_operands(env, expr)?;
+    mul_numbers(a, b)
+}
 
-    match eval(env, a)? {
-        Expression::Integer(a) => match eval(env, b)? {
-            Expression::Integer(b) => Ok(Expression::Integer(a * b)),
-            Expression::Float(b) => Ok(Expression::Float(a as f64 * b)),
-            x => Err(EvalError::NotANumber(x)),
-        },
-        Expression::Float(a) => match eval(env, b)? {
-            Expression::Float(b) => Ok(Expression::Float(a * b)),
-            Expression::Integer(b) => Ok(Expression::Float(a * b as f64)),
-            x => Err(EvalError::NotANumber(x)),
-        },
-        x => Err(EvalError::NotANumber(x)),
+/// Evaluate the operands of an integer division, which must not divide an integer by zero.
+fn division_operands(
+    env: &Environment,
+    expr: Expression,
+) -> Result<(Expression, Expression), EvalError> {
+    let (a, b) = binary_operands(env, expr)?;
+    if b == Expression::Integer(0) && matches!(a, Expression::Integer(_) | Expression::BigInt(_)) {
+        return Err(EvalError::RuntimeError(
+            "Integer division by zero".to_string(),
+            None,
+        ));
+    }
+    Ok((a, b))
+}
+
+/// Divide two evaluated numbers like `/`. The quotient of integers is an integer if it is exact,
+/// a float otherwise.
+pub fn div_numbers(a: Expression, b: Expression) -> Result<Expression, EvalError> {
+    use Expression::{BigInt as Big, Float, Integer};
+
+    match (a, b) {
+        (Integer(_) | Big(_), Integer(0)) => Err(EvalError::RuntimeError(
+            "Integer division by zero".to_string(),
+            None,
+        )),
+        (Integer(x), Integer(y)) if x.checked_rem(y) == Some(0) => Ok(Integer(x / y)),
+        (a @ (Integer(_) | Big(_)), b @ (Integer(_) | Big(_))) => {
+            let (x, y): (BigInt, BigInt) = (a.clone().try_into()?, b.clone().try_into()?);
+            match (&x % &y).is_zero() {
+                true => Ok((x / y).into()),
+                false => Ok(Float(f64::try_from(a)? / f64::try_from(b)?)),
+            }
+        }
+        (a, b) => arithmetic(a, b, i64::checked_div, |a, b| a / b, |a, b| a / b),
     }
 }
 
+/// Divide two numbers like `div_numbers`. Scripts declaring a dialect version before 0.2 get the
+/// truncated quotient of integers, which the prelude `/` gave then.
 pub fn prelude_div(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
+    let (a, b) = binary_operands(env, expr)?;
+    let is_integer = |e: &Expression| matches!(e, Expression::Integer(_) | Expression::BigInt(_));
+    if language_version(env) < (0, 2) && is_integer(&a) && is_integer(&b) {
+        return prelude_quotient(env, [a, b].into());
+    }
+    div_numbers(a, b)
+}
+
+/// Divide two numbers, truncating towards zero: `(quotient 7 -2)` is `-3`.
+pub fn prelude_quotient(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = division_operands(env, expr)?;
+    arithmetic(a, b, i64::checked_div, |a, b| a / b, |a, b| (a / b).trunc())
+}
+
+/// Divide two numbers, rounding towards negative infinity: `(floor-div 7 -2)` is `-4`.
+pub fn prelude_floor_div(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = division_operands(env, expr)?;
+    arithmetic(
+        a,
+        b,
+        |a, b| {
+            let q = a.checked_div(b)?;
+            match a % b != 0 && (a < 0) != (b < 0) {
+                true => Some(q - 1),
+                false => Some(q),
+            }
+        },
+        |a, b| {
+            let q = &a / &b;
+            match !(&a % &b).is_zero() && a.is_negative() != b.is_negative() {
+                true => q - 1,
+                false => q,
+            }
+        },
+        |a, b| (a / b).floor(),
+    )
+}
+
+/// The remainder of `quotient`, which has the sign of the dividend: `(rem -7 2)` is `-1`.
+pub fn prelude_rem(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = division_operands(env, expr)?;
+    arithmetic(a, b, i64::checked_rem, |a, b| a % b, |a, b| a % b)
+}
 
-    match eval(env, a)? {
-        Expression::Integer(a) => match eval(env, b)? {
-            Expression::Integer(b) => Ok(Expression::Integer(a / b)),
-            Expression::Float(b) => Ok(Expression::Float(a as f64 / b)),
-            x => Err(EvalError::NotANumber(x)),
+/// The remainder of `floor-div`, which has the sign of the divisor: `(mod -7 2)` is `1`.
+pub fn prelude_mod(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = division_operands(env, expr)?;
+    arithmetic(
+        a,
+        b,
+        |a, b| {
+            let r = a.checked_rem(b)?;
+            match r != 0 && (r < 0) != (b < 0) {
+                true => Some(r + b),
+                false => Some(r),
+            }
         },
-        Expression::Float(a) => match eval(env, b)? {
-            Expression::Float(b) => Ok(Expression::Float(a / b)),
-            Expression::Integer(b) => Ok(Expression::Float(a / b as f64)),
-            x => Err(EvalError::NotANumber(x)),
+        |a, b| {
+            let r = &a % &b;
+            match !r.is_zero() && r.is_negative() != b.is_negative() {
+                true => r + b,
+                false => r,
+            }
         },
-        x => Err(EvalError::NotANumber(x)),
+        |a, b| a - b * (a / b).floor(),
+    )
+}
+
+/// Split the arguments of a special form into its `N` leading arguments and at least one body
+/// expression.
+pub(crate) fn split_body<const N: usize>(
+    expr: Expression,
+) -> Result<([Expression; N], Vec<Expression>), EvalError> {
+    let mut args: Vec<Expression> = expr.try_into()?;
+    if args.len() <= N {
+        return Err(EvalError::ArgumentError(format!(
+            "Expected {} arguments and a body, got {} arguments",
+            N,
+            args.len()
+        )));
     }
+    let body = args.split_off(N);
+    Ok((Expression::from(args).try_into()?, body))
 }
 
 pub fn prelude_lambda(_env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [args, body]: [Expression; 2] = expr.try_into()?;
+    let ([args], body) = split_body(expr)?;
     let mut arg_exprs: Vec<Expression> = args.try_into()?;
     let argument_symbols: Vec<String> = arg_exprs
         .iter_mut()
@@ -94,12 +264,12 @@ pub fn prelude_lambda(_env: &Environment, expr: Expression) -> Result<Expression
         .collect::<Result<Vec<String>, EvalError>>()?;
     Ok(Expression::AnonymousFunction {
         argument_symbols,
-        body: Box::new(body),
+        body: body.into(),
     })
 }
 
 pub fn prelude_defun(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [name, args, body]: [Expression; 3] = expr.try_into()?;
+    let ([name, args], body) = split_body(expr)?;
     let name = match name {
         Expression::Symbol(s) => s,
         x => return Err(EvalError::NotASymbol(x)),
@@ -115,7 +285,7 @@ pub fn prelude_defun(env: &Environment, expr: Expression) -> Result<Expression,
 
     let f = Expression::AnonymousFunction {
         argument_symbols,
-        body: Box::new(body),
+        body: body.into(),
     };
     env.shared_set(name, f.clone());
     Ok(f)
@@ -132,76 +302,217 @@ pub fn prelude_define(env: &Environment, expr: Expression) -> Result<Expression,
     Ok(value)
 }
 
+/// Bind symbols while evaluating a body: `(let ((a 1) (b 2)) body...)`. Alternatively the
+/// bindings can be given by an expression evaluating to an alist: `(let '((a . 1)) body...)`.
 pub fn prelude_let(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [bindings, body] = expr.try_into()?;
+    let ([bindings], body) = split_body(expr)?;
 
-    let bindings = CellIterator::new(eval(env, bindings)?)
+    // A list of lists is the conventional binding list, anything else evaluates to an alist
+    let conventional =
+        matches!(&bindings, Expression::Cell(head, _) if matches!(**head, Expression::Cell(_, _)));
+    let bindings = match conventional {
+        true => bindings,
+        false => eval(env, bindings)?,
+    };
+
+    let bindings = CellIterator::new(bindings)
         .map(|e| {
-            let (s, e) = e?.try_into()?;
+            let (s, e) = match conventional {
+                true => {
+                    let [s, e]: [Expression; 2] = e?.try_into()?;
+                    (s, e)
+                }
+                false => e?.try_into()?,
+            };
             if let Expression::Symbol(s) = s {
                 Ok((s, eval(env, e)?))
             } else {
                 Err(EvalError::ArgumentError(
-                    "Let bindings must be an alist with elements (symbol . expr)".to_string(),
+                    "Let bindings must be a list of (symbol expr) or an alist with elements (symbol . expr)".to_string(),
                 ))
             }
         })
-        .collect::<Result<HashMap<String, Expression>, EvalError>>()?;
+        .collect::<Result<IndexMap<String, Expression>, EvalError>>()?;
 
-    eval(&env.overlay(bindings.into()), body)
+    eval_body(&env.overlay(bindings.into()), body)
 }
 
-pub fn prelude_if(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [predicate, e_then, e_else] = expr.try_into()?;
+/// Evaluate a body if a condition is true: `(when condition body...)`. Returns the value of the
+/// body, or nil.
+pub fn prelude_when(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let ([condition], body) = split_body(expr)?;
 
-    match eval(env, predicate)? {
-        Expression::Nil => eval(env, e_else),
-        _ => eval(env, e_then),
+    if eval(env, condition)?.is_truthy(env.config()) {
+        eval_body(env, body)
+    } else {
+        Ok(Expression::Nil)
     }
 }
 
-pub fn prelude_eq(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
-    let a = eval(env, a)?;
-    let b = eval(env, b)?;
+/// Evaluate a body if a condition is false: `(unless condition body...)`. Returns the value of
+/// the body, or nil.
+pub fn prelude_unless(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let ([condition], body) = split_body(expr)?;
 
-    if a == b {
-        Ok(Expression::True)
-    } else {
+    if eval(env, condition)?.is_truthy(env.config()) {
         Ok(Expression::Nil)
+    } else {
+        eval_body(env, body)
     }
 }
 
-pub fn prelude_lt(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
-    let a = eval(env, a)?;
-    let b = eval(env, b)?;
+pub fn prelude_if(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [predicate, e_then, e_else] = expr.try_into()?;
 
-    if a < b {
-        Ok(Expression::True)
+    if eval(env, predicate)?.is_truthy(env.config()) {
+        eval(env, e_then)
     } else {
-        Ok(Expression::Nil)
+        eval(env, e_else)
     }
 }
 
-pub fn prelude_gt(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [a, b] = expr.try_into()?;
-    let a = eval(env, a)?;
-    let b = eval(env, b)?;
+/// Compare two values. Numbers are compared by value, also between integers and floats.
+/// Other values are ordered like `Expression`, which returns `None` for different types.
+pub(crate) fn compare(a: &Expression, b: &Expression) -> Option<Ordering> {
+    use Expression::{BigInt as Big, Float, Integer};
 
-    if a > b {
-        Ok(Expression::True)
-    } else {
-        Ok(Expression::Nil)
+    match (a, b) {
+        (Float(_), Integer(_) | Big(_) | Float(_)) | (Integer(_) | Big(_), Float(_)) => {
+            let a: f64 = a.clone().try_into().ok()?;
+            let b: f64 = b.clone().try_into().ok()?;
+            a.partial_cmp(&b)
+        }
+        _ => a.partial_cmp(b),
+    }
+}
+
+/// Check two values for equality. Numbers are equal if they have the same value, other values
+/// if they are structurally equal.
+fn equal(a: &Expression, b: &Expression) -> bool {
+    use Expression::{BigInt as Big, Float, Integer};
+
+    match (a, b) {
+        (Integer(_) | Big(_) | Float(_), Integer(_) | Big(_) | Float(_)) => {
+            compare(a, b) == Some(Ordering::Equal)
+        }
+        _ => a == b,
+    }
+}
+
+/// Evaluate a binary comparison of two values with `test`. Two integers are compared with
+/// `int` instead, skipping the generic comparison of expressions.
+fn comparison(
+    env: &Environment,
+    expr: Expression,
+    int: fn(&i64, &i64) -> bool,
+    test: fn(&Expression, &Expression) -> bool,
+) -> Result<Expression, EvalError> {
+    let result = match binary_operands(env, expr)? {
+        (Expression::Integer(a), Expression::Integer(b)) => int(&a, &b),
+        (a, b) => test(&a, &b),
+    };
+
+    Ok(env.config().boolean(result))
+}
+
+/// Check two values for identity. Only atoms have an identity, which is their value: symbols,
+/// numbers of the same type, booleans, `nil` and native functions and closures. Strings, lists,
+/// maps, lambdas and foreign data are never identical, even to themselves. Evaluation shares
+/// them instead of copying (lists by their `Arc` cells), but the sharing is not observable.
+fn identical(a: &Expression, b: &Expression) -> bool {
+    use Expression::*;
+
+    match (a, b) {
+        (Symbol(_), Symbol(_))
+        | (Integer(_), Integer(_))
+        | (BigInt(_), BigInt(_))
+        | (Float(_), Float(_))
+        | (Function(_), Function(_))
+        | (NativeClosure(_), NativeClosure(_))
+        | (True, True)
+        | (False, False)
+        | (Nil, Nil) => a == b,
+        _ => false,
     }
 }
 
+/// Check two values for identity: `(eq? a b)`. See `identical`.
+pub fn prelude_is_eq(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = binary_operands(env, expr)?;
+    Ok(env.config().boolean(identical(&a, &b)))
+}
+
+/// Check two values for structural equality: `(equal? a b)`. Lists, maps and lambdas are equal
+/// if their elements are, foreign data if its `PartialEq` says so. Unlike `=`, numbers of
+/// different types are never equal: `(equal? 1 1.0)` is false.
+pub fn prelude_is_equal(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (a, b) = binary_operands(env, expr)?;
+    Ok(env.config().boolean(a == b))
+}
+
+/// Compare numbers by value, other values structurally: `(= a b)`
+pub fn prelude_eq(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    comparison(env, expr, i64::eq, equal)
+}
+
+pub fn prelude_ne(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    comparison(env, expr, i64::ne, |a, b| !equal(a, b))
+}
+
+pub fn prelude_lt(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    comparison(env, expr, i64::lt, |a, b| {
+        compare(a, b) == Some(Ordering::Less)
+    })
+}
+
+pub fn prelude_gt(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    comparison(env, expr, i64::gt, |a, b| {
+        compare(a, b) == Some(Ordering::Greater)
+    })
+}
+
+pub fn prelude_le(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    comparison(env, expr, i64::le, |a, b| {
+        matches!(compare(a, b), Some(Ordering::Less | Ordering::Equal))
+    })
+}
+
+pub fn prelude_ge(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    comparison(env, expr, i64::ge, |a, b| {
+        matches!(compare(a, b), Some(Ordering::Greater | Ordering::Equal))
+    })
+}
+
 pub fn prelude_not(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [a] = expr.try_into()?;
-    match eval(env, a)? {
-        Expression::Nil => Ok(Expression::True),
-        _ => Ok(Expression::Nil),
+    let a = eval(env, a)?;
+    Ok(env.config().boolean(!a.is_truthy(env.config())))
+}
+
+pub fn prelude_and(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let mut result = Expression::True;
+
+    for e in CellIterator::new(expr) {
+        result = eval(env, e?)?;
+        if !result.is_truthy(env.config()) {
+            break;
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn prelude_or(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let mut result = env.config().boolean(false);
+
+    for e in CellIterator::new(expr) {
+        result = eval(env, e?)?;
+        if result.is_truthy(env.config()) {
+            break;
+        }
     }
+
+    Ok(result)
 }
 
 pub fn prelude_set(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
@@ -217,37 +528,54 @@ pub fn prelude_set(env: &Environment, expr: Expression) -> Result<Expression, Ev
     }
 }
 
+/// Update the innermost existing binding of a symbol: `(setq symbol value)`. Unlike `set`, the
+/// symbol is not evaluated and local bindings like function arguments can be changed.
+pub fn prelude_setq(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s, e] = expr.try_into()?;
+
+    match s {
+        Expression::Symbol(s) => {
+            let e = eval(env, e)?;
+            match env.update(&s, e.clone()) {
+                true => Ok(e),
+                false => Err(EvalError::SymbolNotBound(s)),
+            }
+        }
+        x => Err(EvalError::NotASymbol(x)),
+    }
+}
+
 pub fn prelude_println(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [e] = expr.try_into()?;
     let e = eval(env, e)?;
-    println!("{}", e);
+    env.write_output(&format!("{}\n", e))?;
     Ok(e)
 }
 
 pub fn prelude_print(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [e] = expr.try_into()?;
     let e = eval(env, e)?;
-    print!("{}", e);
+    env.write_output(&e.to_string())?;
     Ok(e)
 }
 
 pub fn prelude_cons(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [a, b] = expr.try_into()?;
     Ok(Expression::Cell(
-        Box::new(eval(env, a)?),
-        Box::new(eval(env, b)?),
+        Arc::new(eval(env, a)?),
+        Arc::new(eval(env, b)?),
     ))
 }
 
 pub fn prelude_car(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [arg] = expr.try_into()?;
-    let (a, _) = eval(env, arg)?.try_into()?;
+    let (a, _): (Expression, Expression) = eval(env, arg)?.try_into()?;
     Ok(a)
 }
 
 pub fn prelude_cdr(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [arg] = expr.try_into()?;
-    let (_, b) = eval(env, arg)?.try_into()?;
+    let (_, b): (Expression, Expression) = eval(env, arg)?.try_into()?;
     Ok(b)
 }
 
@@ -266,26 +594,229 @@ pub fn prelude_progn(env: &Environment, expr: Expression) -> Result<Expression,
     Ok(result)
 }
 
+/// Evaluate expressions like `progn`, but in an isolated environment: `(sandbox expr...)`.
+/// Global bindings made with `set` are visible inside, but discarded afterwards.
+pub fn prelude_sandbox(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    prelude_progn(&env.fork_isolated(), expr)
+}
+
+/// Collect unreachable values: `(gc)`. Returns the number of collected values, which is always
+/// zero, as values are freed when they are unbound (see `Environment`). Long running scripts
+/// may call it anyway, should values with reference cycles be added.
+pub fn prelude_gc(_env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let []: [Expression; 0] = expr.try_into()?;
+    Ok(Expression::Integer(0))
+}
+
+/// Evaluate an expression and measure the time it takes: `(time expr)` returns
+/// `(value . milliseconds)`.
+pub fn prelude_time(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    let start = Instant::now();
+    let value = eval(env, e)?;
+    let millis = start.elapsed().as_secs_f64() * 1000.0;
+    Ok(Expression::Cell(Arc::new(value), Arc::new(millis.into())))
+}
+
 pub fn prelude_list(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let exprs: Vec<Expression> = expr.try_into()?;
+    expr.list_iter().map(|e| eval(env, e?.clone())).collect()
+}
 
-    let evaled_exprs: Vec<_> = exprs
-        .iter()
-        .map(|e| eval(env, e.to_owned()))
-        .collect::<Result<_, _>>()?;
+/// Count the elements of a list, failing if it does not end with nil.
+fn list_length(list: &Expression) -> Result<usize, EvalError> {
+    list.list_iter().try_fold(0, |n, e| e.map(|_| n + 1))
+}
+
+/// Get the element at index `n` of a list: `(nth n list)`
+pub fn prelude_nth(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [n, list] = expr.try_into()?;
+    let n: i64 = eval(env, n)?.try_into()?;
+    let list = eval(env, list)?;
+    let len = list_length(&list)?;
+    match list.list_iter().nth(env.config().index(n, len)?) {
+        Some(e) => Ok(e?.clone()),
+        None => Err(EvalError::IndexOutOfRange(n, len)),
+    }
+}
+
+/// Get the number of elements of a list: `(length list)`
+pub fn prelude_length(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [list] = expr.try_into()?;
+    Ok(Expression::Integer(list_length(&eval(env, list)?)? as i64))
+}
+
+pub fn prelude_reverse(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [list] = expr.try_into()?;
+    eval(env, list)?
+        .list_iter()
+        .try_fold(Expression::Nil, |reversed, e| {
+            Ok(Expression::Cell(Arc::new(e?.clone()), Arc::new(reversed)))
+        })
+}
+
+/// Get the last element of a non-empty list: `(last list)`
+pub fn prelude_last(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [list] = expr.try_into()?;
+    match eval(env, list)?.list_iter().last() {
+        Some(e) => Ok(e?.clone()),
+        None => Err(EvalError::ArgumentError(
+            "Expected a non-empty list".to_string(),
+        )),
+    }
+}
+
+/// Get the first `n` elements of a list: `(take n list)`
+pub fn prelude_take(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [n, list] = expr.try_into()?;
+    let n: i64 = eval(env, n)?.try_into()?;
+    let list = eval(env, list)?;
+    let n = env.config().bound(n, list_length(&list)?)?;
+    list.list_iter().take(n).map(|e| e.cloned()).collect()
+}
+
+/// Get a list without its first `n` elements: `(drop n list)`. The result shares the cells of
+/// the list.
+pub fn prelude_drop(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [n, list] = expr.try_into()?;
+    let n: i64 = eval(env, n)?.try_into()?;
+    let list = eval(env, list)?;
+    let n = env.config().bound(n, list_length(&list)?)?;
+    let mut rest = list.list_iter();
+    rest.by_ref().take(n).try_for_each(|e| e.map(|_| ()))?;
+    Ok(rest.rest().clone())
+}
+
+/// Get the integers from `start` up to (excluding) `end`: `(range 0 3)` is `(0 1 2)`
+pub fn prelude_range(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [start, end] = expr.try_into()?;
+    let start: i64 = eval(env, start)?.try_into()?;
+    let end: i64 = eval(env, end)?.try_into()?;
+    Ok((start..end)
+        .map(Expression::Integer)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Get a list of `n` times `x`: `(repeat n x)`
+pub fn prelude_repeat(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [n, x] = expr.try_into()?;
+    let n: i64 = eval(env, n)?.try_into()?;
+    let x = eval(env, x)?;
+    Ok(vec![x; n.max(0) as usize].into())
+}
+
+/// Pair up the elements of two lists, up to the end of the shorter one: `(zip xs ys)`
+pub fn prelude_zip(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [xs, ys] = expr.try_into()?;
+    let (xs, ys) = (eval(env, xs)?, eval(env, ys)?);
+    xs.list_iter()
+        .zip(ys.list_iter())
+        .map(|(x, y)| Ok((x?.clone(), y?.clone()).into()))
+        .collect()
+}
+
+/// Stable merge sort with a fallible comparison, `less(a, b)` tells whether `a` goes before `b`.
+fn merge_sort<F>(list: Vec<Expression>, less: &mut F) -> Result<Vec<Expression>, EvalError>
+where
+    F: FnMut(&Expression, &Expression) -> Result<bool, EvalError>,
+{
+    if list.len() < 2 {
+        return Ok(list);
+    }
+
+    let mut left = list;
+    let right = left.split_off(left.len() / 2);
+    let mut left = merge_sort(left, less)?.into_iter().peekable();
+    let mut right = merge_sort(right, less)?.into_iter().peekable();
+
+    let mut sorted = Vec::with_capacity(left.len() + right.len());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Take from the right only if it is strictly before, which keeps the sort stable
+        match less(r, l)? {
+            true => sorted.extend(right.next()),
+            false => sorted.extend(left.next()),
+        }
+    }
+    sorted.extend(left);
+    sorted.extend(right);
+    Ok(sorted)
+}
+
+/// Sort a list: `(sort list [less])`. Without a comparator, numbers are sorted by value and
+/// strings alphabetically. The sort is stable.
+pub fn prelude_sort(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (list, less) = match <[Expression; 2]>::try_from(args) {
+        Ok([list, less]) => (list, Some(eval(env, less)?)),
+        Err(args) => {
+            let [list]: [Expression; 1] = Expression::from(args).try_into()?;
+            (list, None)
+        }
+    };
+    let list: Vec<Expression> = eval(env, list)?.try_into()?;
+
+    let sorted = match less {
+        Some(f) => merge_sort(list, &mut |a, b| {
+            Ok(apply(env, &f, vec![a.clone(), b.clone()])?.is_truthy(env.config()))
+        })?,
+        None => merge_sort(list, &mut |a, b| match compare(a, b) {
+            Some(o) => Ok(o == Ordering::Less),
+            None => Err(EvalError::TypeError(format!(
+                "Cannot compare {} and {}",
+                a, b
+            ))),
+        })?,
+    };
 
-    Ok(evaled_exprs.into())
+    Ok(sorted.into())
+}
+
+/// Get the tail of a list starting at the first element equal to `x`, or false if there is
+/// none: `(member x list)`
+pub fn prelude_member(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [x, list] = expr.try_into()?;
+    let x = eval(env, x)?;
+    let list = eval(env, list)?;
+
+    let mut rest = list.list_iter();
+    loop {
+        let tail = rest.rest();
+        match rest.next().transpose()? {
+            Some(e) if equal(e, &x) => return Ok(tail.clone()),
+            Some(_) => {}
+            None => return Ok(env.config().boolean(false)),
+        }
+    }
+}
+
+/// Get the first pair of an association list whose key is equal to `key`, or false if there is
+/// none: `(assoc key '((a . 1) (b . 2)))`
+pub fn prelude_assoc(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [key, alist] = expr.try_into()?;
+    let key = eval(env, key)?;
+    let alist = eval(env, alist)?;
+
+    for pair in alist.list_iter() {
+        let pair = pair?;
+        let (k, _): (Expression, Expression) = pair.clone().try_into()?;
+        if equal(&k, &key) {
+            return Ok(pair.clone());
+        }
+    }
+    Ok(env.config().boolean(false))
 }
 
 pub fn prelude_append(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let exprs: Vec<Expression> = expr.try_into()?;
+    let lists = expr
+        .list_iter()
+        .map(|e| eval(env, e?.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let evaled_exprs: Vec<_> = exprs
+    lists
         .iter()
-        .map(|e| eval(env, e.to_owned())?.try_into())
-        .collect::<Result<Vec<Vec<Expression>>, _>>()?;
-
-    Ok(evaled_exprs.concat().into())
+        .flat_map(Expression::list_iter)
+        .map(|e| e.cloned())
+        .collect()
 }
 
 pub fn prelude_concat(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
@@ -303,95 +834,585 @@ pub fn prelude_map(env: &Environment, expr: Expression) -> Result<Expression, Ev
     let [f, list]: [Expression; 2] = expr.try_into()?;
 
     let f = eval(env, f)?;
-    let list: Vec<Expression> = eval(env, list)?.try_into()?;
 
-    let list: Vec<Expression> = list
-        .iter()
+    eval(env, list)?
+        .list_iter()
         .map(|e| {
             eval(
                 env,
                 Expression::Cell(
-                    Box::new(f.clone()),
-                    Box::new(Expression::Cell(
-                        Box::new(e.to_owned()),
-                        Box::new(Expression::Nil),
+                    Arc::new(f.clone()),
+                    Arc::new(Expression::Cell(
+                        Arc::new(e?.clone()),
+                        Arc::new(Expression::Nil),
                     )),
                 ),
             )
         })
-        .collect::<Result<_, _>>()?;
+        .collect()
+}
 
-    Ok(list.into())
+/// Call a function with already evaluated arguments.
+fn apply(
+    env: &Environment,
+    f: &Expression,
+    args: Vec<Expression>,
+) -> Result<Expression, EvalError> {
+    let call: Vec<Expression> = std::iter::once(f.clone())
+        .chain(args.into_iter().map(|a| Expression::Quote(Arc::new(a))))
+        .collect();
+    eval(env, call.into())
 }
 
-pub fn prelude_to_string(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [e] = expr.try_into()?;
-    Ok(Expression::String(format!("{}", eval(env, e)?)))
+/// Keep the elements of a list for which a predicate is true: `(filter f list)`
+pub fn prelude_filter(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, list]: [Expression; 2] = expr.try_into()?;
+
+    let f = eval(env, f)?;
+
+    eval(env, list)?
+        .list_iter()
+        .map(|e| {
+            let e = e?;
+            let keep = apply(env, &f, vec![e.clone()])?.is_truthy(env.config());
+            Ok(keep.then(|| e.clone()))
+        })
+        .filter_map(Result::transpose)
+        .collect()
 }
 
-pub fn prelude_load(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [expr] = expr.try_into()?;
-    let lisp_string: String = eval(env, expr)?.try_into()?;
+/// Get the first element of a list for which a predicate is true, or nil: `(find f list)`
+pub fn prelude_find(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, list]: [Expression; 2] = expr.try_into()?;
 
-    let mut last_result = Expression::Nil;
+    let f = eval(env, f)?;
 
-    for expr in ExpressionStream::from_char_stream(lisp_string.chars())
-        .collect::<Result<Vec<Expression>, ParserError>>()?
-    {
-        last_result = eval(env, expr)?;
+    for e in eval(env, list)?.list_iter() {
+        let e = e?;
+        if apply(env, &f, vec![e.clone()])?.is_truthy(env.config()) {
+            return Ok(e.clone());
+        }
     }
+    Ok(Expression::Nil)
+}
+
+/// Check whether a predicate is true for any element of a list: `(any? f list)`
+pub fn prelude_any(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, list]: [Expression; 2] = expr.try_into()?;
 
-    Ok(last_result)
+    let f = eval(env, f)?;
+
+    for e in eval(env, list)?.list_iter() {
+        if apply(env, &f, vec![e?.clone()])?.is_truthy(env.config()) {
+            return Ok(env.config().boolean(true));
+        }
+    }
+    Ok(env.config().boolean(false))
 }
 
-pub fn prelude_include(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
-    let [expr] = expr.try_into()?;
-    let lisp_file: String = eval(env, expr)?.try_into()?;
+/// Check whether a predicate is true for all elements of a list: `(all? f list)`
+pub fn prelude_all(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, list]: [Expression; 2] = expr.try_into()?;
 
-    // Try to resolve as relative to FILE
-    let resolved_lisp_file: PathBuf = PathBuf::from(
-        env.get("FILE")
-            .map(|x| x.try_into())
-            .unwrap_or(Ok(String::new()))?,
-    )
-    .parent()
-    .ok_or(EvalError::RuntimeError(
-        "Could not get parent of current file.".to_string(),
-    ))?
-    .join(&lisp_file);
+    let f = eval(env, f)?;
 
-    let lisp_string = std::fs::read_to_string(&resolved_lisp_file)
-        .map_err(|e| EvalError::RuntimeError(e.to_string()))?;
+    for e in eval(env, list)?.list_iter() {
+        if !apply(env, &f, vec![e?.clone()])?.is_truthy(env.config()) {
+            return Ok(env.config().boolean(false));
+        }
+    }
+    Ok(env.config().boolean(true))
+}
 
-    // Use enviroment for resolved file or fallback to the lisp_file argument
-    let mut env = env.mk_inner();
-    env.set(
-        "FILE".to_string(),
-        resolved_lisp_file
-            .to_str()
-            .unwrap_or(&lisp_file)
-            .to_string()
-            .into(),
-    );
+/// Combine the elements of a list from the left: `(reduce f init list)` evaluates
+/// `(f (f init x1) x2) ...`
+pub fn prelude_reduce(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, init, list]: [Expression; 3] = expr.try_into()?;
 
-    prelude_load(&env, [lisp_string.into()].into())
+    let f = eval(env, f)?;
+    let init = eval(env, init)?;
+
+    eval(env, list)?
+        .list_iter()
+        .try_fold(init, |acc, e| apply(env, &f, vec![acc, e?.clone()]))
 }
 
-pub fn mk_prelude(layer: &mut EnvironmentLayer) {
-    layer.set("+".to_string(), Expression::Function(prelude_add));
-    layer.set("-".to_string(), Expression::Function(prelude_sub));
-    layer.set("*".to_string(), Expression::Function(prelude_mul));
-    layer.set("/".to_string(), Expression::Function(prelude_div));
-    layer.set("lambda".to_string(), Expression::Function(prelude_lambda));
+/// Combine the elements of a list from the right: `(foldr f init list)` evaluates
+/// `(f x1 (f x2 ... (f xn init)))`
+pub fn prelude_foldr(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, init, list]: [Expression; 3] = expr.try_into()?;
+
+    let f = eval(env, f)?;
+    let init = eval(env, init)?;
+    let list: Vec<Expression> = eval(env, list)?.try_into()?;
+
+    list.into_iter()
+        .rev()
+        .try_fold(init, |acc, e| apply(env, &f, vec![e, acc]))
+}
+
+/// Call a function on each element of a list for its side effects: `(for-each f list)`
+pub fn prelude_for_each(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, list]: [Expression; 2] = expr.try_into()?;
+
+    let f = eval(env, f)?;
+
+    for e in eval(env, list)?.list_iter() {
+        apply(env, &f, vec![e?.clone()])?;
+    }
+
+    Ok(Expression::Nil)
+}
+
+pub fn prelude_to_string(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    Ok(Expression::String(format!("{}", eval(env, e)?)))
+}
+
+/// Parse a string into a single expression without evaluating it: `(read "(+ 1 2)")`
+pub fn prelude_read(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+
+    let exprs = ExpressionStream::from_char_stream(s.chars()).collect::<Result<Vec<_>, _>>()?;
+    let [e] = <[Expression; 1]>::try_from(exprs).map_err(|exprs| {
+        EvalError::ArgumentError(format!(
+            "Expected a string with one expression, got {}",
+            exprs.len()
+        ))
+    })?;
+    Ok(e)
+}
+
+/// Check that the printed form of a value reads back as an equal value. Lambdas and maps read
+/// back as the expression constructing them.
+fn check_readable(e: &Expression) -> Result<(), EvalError> {
+    match e {
+        Expression::Function(_)
+        | Expression::NativeClosure(_)
+        | Expression::ForeignExpression(_) => Err(EvalError::TypeError(format!(
+            "{} has no readable representation",
+            e
+        ))),
+        Expression::Float(f) if !f.is_finite() => {
+            Err(EvalError::TypeError(format!("{} cannot be read back", f)))
+        }
+        Expression::Cell(a, b) => check_readable(a).and_then(|_| check_readable(b)),
+        Expression::Quote(e) => check_readable(e),
+        Expression::AnonymousFunction { body, .. } => body.iter().try_for_each(check_readable),
+        Expression::Map(m) => m.values().try_for_each(check_readable),
+        _ => Ok(()),
+    }
+}
+
+/// Print a value as a string which `read` parses back into an equal value: `(write x)`.
+/// Unlike `to-string`, values without such a representation are an error.
+pub fn prelude_write(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    let e = eval(env, e)?;
+    check_readable(&e)?;
+    Ok(Expression::String(e.to_string()))
+}
+
+/// Evaluate lisp source code in the current environment, returning the last value:
+/// `(load-string "(defun inc (x) (+ x 1))")`
+pub fn prelude_load_string(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [expr] = expr.try_into()?;
+    let lisp_string: String = eval(env, expr)?.try_into()?;
+
+    Ok(eval_source(env, &lisp_string)?
+        .pop()
+        .unwrap_or(Expression::Nil))
+}
+
+/// Resolve a file path relative to the directory of the currently evaluated file (`FILE`), or
+/// the working directory outside of a file.
+fn resolve_relative(env: &Environment, lisp_file: &str) -> Result<PathBuf, EvalError> {
+    let Some(file) = env.get("FILE") else {
+        return Ok(PathBuf::from(lisp_file));
+    };
+    let file: String = file.try_into()?;
+    Ok(Path::new(&file)
+        .parent()
+        .ok_or(EvalError::RuntimeError(
+            "Could not get parent of current file.".to_string(),
+            None,
+        ))?
+        .join(lisp_file))
+}
+
+/// Expand a leading `~` to the home directory and `$VAR` or `${VAR}` to the value of the
+/// environment variable `VAR`.
+pub(crate) fn expand_path(path: &str) -> Result<String, EvalError> {
+    expand_path_with(path, |name| std::env::var(name).ok())
+}
+
+/// Expand a path like `expand_path`, looking up variables and `HOME` with `lookup`.
+fn expand_path_with(
+    path: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, EvalError> {
+    let var = |name: &str| {
+        lookup(name).ok_or_else(|| {
+            EvalError::RuntimeError(format!("Environment variable {} is not set", name), None)
+        })
+    };
+
+    let mut expanded = String::new();
+    let mut rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            expanded.push_str(&var("HOME")?);
+            rest
+        }
+        _ => path,
+    };
+    while let Some(i) = rest.find('$') {
+        expanded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let (name, tail) = match rest.strip_prefix('{') {
+            Some(braced) => braced.split_once('}').ok_or_else(|| {
+                EvalError::RuntimeError(format!("Unterminated ${{ in {}", path), None)
+            })?,
+            None => rest.split_at(
+                rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len()),
+            ),
+        };
+        match name.is_empty() {
+            true => expanded.push('$'),
+            false => expanded.push_str(&var(name)?),
+        }
+        rest = tail;
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Expand `~` and environment variables in a path: `(expand-path "${HOME}/renders/out.png")`
+pub fn prelude_expand_path(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [path] = expr.try_into()?;
+    let path: String = eval(env, path)?.try_into()?;
+    Ok(expand_path(&path)?.into())
+}
+
+/// Get an inner environment for evaluating the resolved file, binding `FILE` to it.
+fn file_environment<'a>(env: &'a Environment, resolved: &Path, lisp_file: &str) -> Environment<'a> {
+    // Use enviroment for resolved file or fallback to the lisp_file argument
+    let mut env = env.mk_inner();
+    env.set(
+        "FILE".to_string(),
+        resolved.to_str().unwrap_or(lisp_file).to_string().into(),
+    );
+    env
+}
+
+pub fn prelude_include(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [expr] = expr.try_into()?;
+    let lisp_file: String = eval(env, expr)?.try_into()?;
+
+    // Try to resolve as relative to FILE
+    let resolved_lisp_file = resolve_relative(env, &lisp_file)?;
+
+    let lisp_string = std::fs::read_to_string(&resolved_lisp_file)
+        .map_err(|e| EvalError::with_context(&lisp_file, e))?;
+
+    let env = file_environment(env, &resolved_lisp_file, &lisp_file);
+    prelude_load_string(&env, [lisp_string.into()].into())
+}
+
+/// Evaluate a lisp file, returning its last value: `(load "lib.lisp")`. Like `include`,
+/// relative paths are resolved next to the loading file. Before lispers 0.3, `load` evaluated
+/// a string of source code like `load-string`, which older scripts still get.
+pub fn prelude_load(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    if language_version(env) < (0, 3) {
+        return prelude_load_string(env, expr);
+    }
+    prelude_include(env, expr)
+}
+
+/// Like `include`, but keeps the parsed expressions in a binary `.lispc` file next to the
+/// included file, which is used instead of parsing as long as the source is unchanged.
+/// `(load-cached "scene.lisp")`
+#[cfg(feature = "serde")]
+pub fn prelude_load_cached(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [expr] = expr.try_into()?;
+    let lisp_file: String = eval(env, expr)?.try_into()?;
+    let resolved_lisp_file = resolve_relative(env, &lisp_file)?;
+
+    let exprs = super::cache::parse_cached(&resolved_lisp_file)?;
+
+    let env = file_environment(env, &resolved_lisp_file, &lisp_file);
+    eval_body(&env, exprs)
+}
+
+/// Report a warning without aborting the evaluation: `(warn "message")`. Non-string values are
+/// formatted like `to-string`.
+pub fn prelude_warn(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    let message = match eval(env, e)? {
+        Expression::String(s) => s,
+        e => e.to_string(),
+    };
+    env.warn(Warning::new(message));
+    Ok(Expression::Nil)
+}
+
+/// Format a message, replacing each `{}` with the next of `args`. Strings are inserted without
+/// quotes, other values are formatted like `to-string`.
+fn format_message(format: &str, args: Vec<Expression>) -> Result<String, EvalError> {
+    let n_placeholders = format.matches("{}").count();
+    if n_placeholders != args.len() {
+        return Err(EvalError::ArgumentError(format!(
+            "The message has {} placeholders, but got {} arguments",
+            n_placeholders,
+            args.len()
+        )));
+    }
+
+    let mut args = args.into_iter();
+    let mut parts = format.split("{}");
+    let mut message = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        match args.next() {
+            Some(Expression::String(s)) => message.push_str(&s),
+            Some(e) => message.push_str(&e.to_string()),
+            None => {}
+        }
+        message.push_str(part);
+    }
+    Ok(message)
+}
+
+/// Fail with an error carrying a message: `(error "format" args...)`. Each `{}` in the format
+/// is replaced with the next argument. Any other value can be raised on its own, like
+/// `(error 'bad-input)`. `catch` receives the message or the value unchanged.
+pub fn prelude_error(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let mut args: Vec<Expression> = expr.try_into()?;
+    if args.is_empty() {
+        return Err(EvalError::ArgumentError(
+            "Expected an error message".to_string(),
+        ));
+    }
+    let format = eval(env, args.remove(0))?;
+    let args = args
+        .into_iter()
+        .map(|a| eval(env, a))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match format {
+        Expression::String(format) => Err(EvalError::Raised(format_message(&format, args)?.into())),
+        value if args.is_empty() => Err(EvalError::Raised(value)),
+        value => Err(EvalError::TypeError(format!(
+            "Expected a format string, got {}",
+            value
+        ))),
+    }
+}
+
+/// Fail with an error unless a condition is true: `(assert condition ["message"])`. `catch`
+/// receives the message `"Assertion failed: message"`, or the condition without a message.
+/// Messages which are not strings are raised unchanged. Returns the value of the condition.
+pub fn prelude_assert(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (condition, message) = match <[Expression; 2]>::try_from(args) {
+        Ok([condition, message]) => (condition, Some(message)),
+        Err(args) => {
+            let [condition]: [Expression; 1] = Expression::from(args).try_into()?;
+            (condition, None)
+        }
+    };
+
+    let value = eval(env, condition.clone())?;
+    if value.is_truthy(env.config()) {
+        return Ok(value);
+    }
+    let message = match message {
+        Some(message) => match eval(env, message)? {
+            Expression::String(s) => s,
+            e => return Err(EvalError::Raised(e)),
+        },
+        None => condition.to_string(),
+    };
+    Err(EvalError::Raised(
+        format!("Assertion failed: {}", message).into(),
+    ))
+}
+
+pub fn prelude_throw(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    Err(EvalError::LispThrow(eval(env, e)?))
+}
+
+pub fn prelude_catch(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [body, handler] = expr.try_into()?;
+
+    match eval(env, body) {
+        Ok(e) => Ok(e),
+        Err(err) if err.is_abort() => Err(err),
+        Err(err) => {
+            // The error is handled, so its calls are not part of later backtraces
+            env.take_backtrace();
+            let handler = eval(env, handler)?;
+            let condition = Expression::Quote(Arc::new(err.into_condition()));
+            eval(env, [handler, condition].into())
+        }
+    }
+}
+
+/// Evaluate an expression and then the cleanup expressions, even if the expression fails:
+/// `(unwind-protect expr cleanup...)`. Returns the value or error of `expr`, unless the cleanup
+/// fails.
+pub fn prelude_unwind_protect(
+    env: &Environment,
+    expr: Expression,
+) -> Result<Expression, EvalError> {
+    let (body, cleanup) = expr.try_into()?;
+
+    let result = eval(env, body);
+    prelude_progn(env, cleanup)?;
+    result
+}
+
+/// Create a map, optionally from an association list: `(make-map '((a . 1) (b . 2)))`
+pub fn prelude_make_map(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let mut map = IndexMap::new();
+    match args.as_slice() {
+        [] => {}
+        [alist] => {
+            let entries: Vec<(Expression, Expression)> = eval(env, alist.clone())?.try_into()?;
+            for (k, v) in entries {
+                map.insert(MapKey::try_from(k)?, v);
+            }
+        }
+        _ => {
+            return Err(EvalError::ArgumentError(
+                "make-map expects at most one association list".to_string(),
+            ))
+        }
+    }
+    Ok(map.into())
+}
+
+/// Get the value of a key: `(map-get m key [default])`, default is nil.
+pub fn prelude_map_get(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (m, k, default) = match <[Expression; 3]>::try_from(args) {
+        Ok([m, k, default]) => (m, k, Some(default)),
+        Err(args) => {
+            let [m, k]: [Expression; 2] = Expression::from(args).try_into()?;
+            (m, k, None)
+        }
+    };
+
+    let m: IndexMap<MapKey, Expression> = eval(env, m)?.try_into()?;
+    let k: MapKey = eval(env, k)?.try_into()?;
+    match (m.get(&k), default) {
+        (Some(v), _) => Ok(v.clone()),
+        (None, Some(default)) => eval(env, default),
+        (None, None) => Ok(Expression::Nil),
+    }
+}
+
+pub fn prelude_map_set(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [m, k, v] = expr.try_into()?;
+    let mut m: IndexMap<MapKey, Expression> = eval(env, m)?.try_into()?;
+    m.insert(eval(env, k)?.try_into()?, eval(env, v)?);
+    Ok(m.into())
+}
+
+pub fn prelude_map_remove(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [m, k] = expr.try_into()?;
+    let mut m: IndexMap<MapKey, Expression> = eval(env, m)?.try_into()?;
+    let k: MapKey = eval(env, k)?.try_into()?;
+    m.shift_remove(&k);
+    Ok(m.into())
+}
+
+pub fn prelude_map_keys(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [m] = expr.try_into()?;
+    let m: IndexMap<MapKey, Expression> = eval(env, m)?.try_into()?;
+    let keys: Vec<Expression> = m.into_keys().map(Expression::from).collect();
+    Ok(keys.into())
+}
+
+pub fn prelude_map_contains(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [m, k] = expr.try_into()?;
+    let m: IndexMap<MapKey, Expression> = eval(env, m)?.try_into()?;
+    let k: MapKey = eval(env, k)?.try_into()?;
+    Ok(env.config().boolean(m.contains_key(&k)))
+}
+
+/// Adds the prelude builtins accessing the file system, like `include`, to the given environment
+/// layer. They are left out by `EnvironmentBuilder::with_io(false)`.
+pub fn mk_loading(layer: &mut EnvironmentLayer) {
+    layer.set("load".to_string(), Expression::Function(prelude_load));
+    layer.set("include".to_string(), Expression::Function(prelude_include));
+    layer.set(
+        "expand-path".to_string(),
+        Expression::Function(prelude_expand_path),
+    );
+    #[cfg(feature = "serde")]
+    layer.set(
+        "load-cached".to_string(),
+        Expression::Function(prelude_load_cached),
+    );
+}
+
+pub fn mk_prelude(layer: &mut EnvironmentLayer) {
+    mk_math(layer);
+    mk_random(layer);
+    mk_strings(layer);
+    mk_modules(layer);
+    mk_printer(layer);
+    mk_hooks(layer);
+    mk_output(layer);
+    mk_profile(layer);
+    mk_trace(layer);
+    mk_registry(layer);
+    #[cfg(feature = "json")]
+    mk_json(layer);
+    #[cfg(any(feature = "toml", feature = "yaml"))]
+    mk_formats(layer);
+    layer.set(
+        "lispers-version".to_string(),
+        Expression::Function(lispers_version),
+    );
+    layer.set("+".to_string(), Expression::Function(prelude_add));
+    layer.set("-".to_string(), Expression::Function(prelude_sub));
+    layer.set("*".to_string(), Expression::Function(prelude_mul));
+    layer.set("/".to_string(), Expression::Function(prelude_div));
+    layer.set(
+        "quotient".to_string(),
+        Expression::Function(prelude_quotient),
+    );
+    layer.set(
+        "floor-div".to_string(),
+        Expression::Function(prelude_floor_div),
+    );
+    layer.set("rem".to_string(), Expression::Function(prelude_rem));
+    layer.set("mod".to_string(), Expression::Function(prelude_mod));
+    layer.set("lambda".to_string(), Expression::Function(prelude_lambda));
     layer.set("defun".to_string(), Expression::Function(prelude_defun));
     layer.set("define".to_string(), Expression::Function(prelude_define));
     layer.set("if".to_string(), Expression::Function(prelude_if));
+    layer.set("when".to_string(), Expression::Function(prelude_when));
+    layer.set("unless".to_string(), Expression::Function(prelude_unless));
     layer.set("=".to_string(), Expression::Function(prelude_eq));
+    layer.set("eq?".to_string(), Expression::Function(prelude_is_eq));
+    layer.set("equal?".to_string(), Expression::Function(prelude_is_equal));
     layer.set("<".to_string(), Expression::Function(prelude_lt));
     layer.set(">".to_string(), Expression::Function(prelude_gt));
+    layer.set("/=".to_string(), Expression::Function(prelude_ne));
+    layer.set("<=".to_string(), Expression::Function(prelude_le));
+    layer.set(">=".to_string(), Expression::Function(prelude_ge));
     layer.set("not".to_string(), Expression::Function(prelude_not));
+    layer.set("and".to_string(), Expression::Function(prelude_and));
+    layer.set("or".to_string(), Expression::Function(prelude_or));
     layer.set("let".to_string(), Expression::Function(prelude_let));
     layer.set("set".to_string(), Expression::Function(prelude_set));
+    layer.set("setq".to_string(), Expression::Function(prelude_setq));
+    layer.set("set!".to_string(), Expression::Function(prelude_setq));
     layer.set("println".to_string(), Expression::Function(prelude_println));
     layer.set("print".to_string(), Expression::Function(prelude_print));
     layer.set("cons".to_string(), Expression::Function(prelude_cons));
@@ -399,14 +1420,755 @@ pub fn mk_prelude(layer: &mut EnvironmentLayer) {
     layer.set("cdr".to_string(), Expression::Function(prelude_cdr));
     layer.set("eval".to_string(), Expression::Function(prelude_eval));
     layer.set("progn".to_string(), Expression::Function(prelude_progn));
+    layer.set("sandbox".to_string(), Expression::Function(prelude_sandbox));
+    layer.set("time".to_string(), Expression::Function(prelude_time));
+    layer.set("gc".to_string(), Expression::Function(prelude_gc));
     layer.set("list".to_string(), Expression::Function(prelude_list));
+    layer.set("nth".to_string(), Expression::Function(prelude_nth));
+    layer.set("length".to_string(), Expression::Function(prelude_length));
+    layer.set("reverse".to_string(), Expression::Function(prelude_reverse));
+    layer.set("last".to_string(), Expression::Function(prelude_last));
+    layer.set("take".to_string(), Expression::Function(prelude_take));
+    layer.set("drop".to_string(), Expression::Function(prelude_drop));
+    layer.set("range".to_string(), Expression::Function(prelude_range));
+    layer.set("repeat".to_string(), Expression::Function(prelude_repeat));
+    layer.set("zip".to_string(), Expression::Function(prelude_zip));
+    layer.set("sort".to_string(), Expression::Function(prelude_sort));
+    layer.set("member".to_string(), Expression::Function(prelude_member));
+    layer.set("assoc".to_string(), Expression::Function(prelude_assoc));
     layer.set("append".to_string(), Expression::Function(prelude_append));
     layer.set("concat".to_string(), Expression::Function(prelude_concat));
     layer.set("map".to_string(), Expression::Function(prelude_map));
+    layer.set("filter".to_string(), Expression::Function(prelude_filter));
+    layer.set("find".to_string(), Expression::Function(prelude_find));
+    layer.set("any?".to_string(), Expression::Function(prelude_any));
+    layer.set("all?".to_string(), Expression::Function(prelude_all));
+    layer.set("reduce".to_string(), Expression::Function(prelude_reduce));
+    layer.set("foldr".to_string(), Expression::Function(prelude_foldr));
+    layer.set(
+        "for-each".to_string(),
+        Expression::Function(prelude_for_each),
+    );
     layer.set(
         "to-string".to_string(),
         Expression::Function(prelude_to_string),
     );
-    layer.set("load".to_string(), Expression::Function(prelude_load));
-    layer.set("include".to_string(), Expression::Function(prelude_include));
+    layer.set("read".to_string(), Expression::Function(prelude_read));
+    layer.set(
+        "read-string".to_string(),
+        Expression::Function(prelude_read),
+    );
+    layer.set("write".to_string(), Expression::Function(prelude_write));
+    layer.set(
+        "make-map".to_string(),
+        Expression::Function(prelude_make_map),
+    );
+    layer.set("map-get".to_string(), Expression::Function(prelude_map_get));
+    layer.set("map-set".to_string(), Expression::Function(prelude_map_set));
+    layer.set(
+        "map-remove".to_string(),
+        Expression::Function(prelude_map_remove),
+    );
+    layer.set(
+        "map-keys".to_string(),
+        Expression::Function(prelude_map_keys),
+    );
+    layer.set(
+        "map-contains?".to_string(),
+        Expression::Function(prelude_map_contains),
+    );
+    layer.set(
+        "load-string".to_string(),
+        Expression::Function(prelude_load_string),
+    );
+    mk_loading(layer);
+    layer.set("warn".to_string(), Expression::Function(prelude_warn));
+    layer.set("throw".to_string(), Expression::Function(prelude_throw));
+    layer.set("error".to_string(), Expression::Function(prelude_error));
+    layer.set("assert".to_string(), Expression::Function(prelude_assert));
+    layer.set("catch".to_string(), Expression::Function(prelude_catch));
+    layer.set(
+        "unwind-protect".to_string(),
+        Expression::Function(prelude_unwind_protect),
+    );
+
+    // The standard library is written in lisp with the builtins above, which test_stdlib checks
+    mk_stdlib(layer).expect("The standard library only uses builtins of the prelude");
+}
+
+#[test]
+fn test_and_or() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    assert_eq!(run("(and)"), Ok(Expression::True));
+    assert_eq!(run("(or)"), Ok(Expression::Nil));
+    assert_eq!(run("(and 1 2 3)"), Ok(Expression::Integer(3)));
+    assert_eq!(run("(or nil 2 3)"), Ok(Expression::Integer(2)));
+    assert_eq!(run("(and 1 nil (undefined-fn))"), Ok(Expression::Nil));
+    assert_eq!(run("(or 1 (undefined-fn))"), Ok(Expression::Integer(1)));
+    assert_eq!(
+        run("(set 'x 0) (and nil (set 'x 1)) (or true (set 'x 2)) x"),
+        Ok(Expression::Integer(0))
+    );
+}
+
+#[test]
+fn test_truthiness() {
+    use super::environment::InterpreterConfig;
+    use super::eval::eval_last;
+
+    let mut env = Environment::default();
+
+    assert_eq!(
+        eval_last(&env, "(if 0 'yes 'no)"),
+        Ok(Expression::Symbol("yes".to_string()))
+    );
+    assert_eq!(eval_last(&env, "(not \"\")"), Ok(Expression::Nil));
+    assert_eq!(bool::try_from(Expression::Integer(0)), Ok(true));
+    assert_eq!(bool::try_from(Expression::Nil), Ok(false));
+
+    env.set_config(InterpreterConfig {
+        strict_truthiness: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        eval_last(&env, "(if 0 'yes 'no)"),
+        Ok(Expression::Symbol("no".to_string()))
+    );
+    assert_eq!(
+        eval_last(&env, "(if (< 1 2) 'yes 'no)"),
+        Ok(Expression::Symbol("yes".to_string()))
+    );
+    assert_eq!(eval_last(&env, "(not \"\")"), Ok(Expression::True));
+    assert_eq!(eval_last(&env, "(or 1 true)"), Ok(Expression::True));
+    assert_eq!(
+        eval_last(&env, "((lambda (x) (if x 'yes 'no)) 1)"),
+        Ok(Expression::Symbol("no".to_string()))
+    );
+}
+
+#[test]
+fn test_distinct_false() {
+    use super::environment::InterpreterConfig;
+    use super::eval::eval_last;
+
+    let mut env = Environment::default();
+
+    assert_eq!(eval_last(&env, "false"), Ok(Expression::Nil));
+    assert_eq!(
+        eval_last(&env, "(if false 1 2)"),
+        Ok(Expression::Integer(2))
+    );
+
+    env.set_config(InterpreterConfig {
+        distinct_false: true,
+        ..Default::default()
+    });
+    assert_eq!(eval_last(&env, "false"), Ok(Expression::False));
+    assert_eq!(eval_last(&env, "(< 2 1)"), Ok(Expression::False));
+    assert_eq!(eval_last(&env, "(not true)"), Ok(Expression::False));
+    assert_eq!(eval_last(&env, "(not false)"), Ok(Expression::True));
+    assert_eq!(
+        eval_last(&env, "(if false 1 2)"),
+        Ok(Expression::Integer(2))
+    );
+    assert_eq!(eval_last(&env, "(if '() 1 2)"), Ok(Expression::Integer(1)));
+    assert_eq!(eval_last(&env, "(or)"), Ok(Expression::False));
+}
+
+#[test]
+fn test_throw_catch() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    assert_eq!(
+        run("(throw 'oops)"),
+        Err(EvalError::LispThrow(Expression::Symbol("oops".to_string())))
+    );
+    assert_eq!(
+        run("(catch (+ 1 (throw 41)) (lambda (c) (+ c 1)))"),
+        Ok(Expression::Integer(42))
+    );
+    assert_eq!(
+        run("(catch (undefined-fn) (lambda (c) (car c)))"),
+        Ok(Expression::Symbol("symbol-not-bound".to_string()))
+    );
+    assert_eq!(
+        run("(set 'cleaned nil) (catch (unwind-protect (throw 1) (set 'cleaned true)) (lambda (c) c)) cleaned"),
+        Ok(Expression::True)
+    );
+    assert_eq!(run("(unwind-protect 1 2 3)"), Ok(Expression::Integer(1)));
+    assert_eq!(
+        run("(error \"Expected {} to be {}, got {}\" 'width \"positive\" -1)"),
+        Err(EvalError::Raised(
+            "Expected width to be positive, got -1".to_string().into()
+        ))
+    );
+    assert!(matches!(
+        run("(error \"{} and {}\" 1)"),
+        Err(EvalError::ArgumentError(_))
+    ));
+    // Handlers get the raised value unchanged
+    assert_eq!(
+        run("(catch (error \"failed\") (lambda (c) c))"),
+        Ok("failed".to_string().into())
+    );
+    assert_eq!(
+        run("(catch (error '(bad-input 3)) (lambda (c) (cadr c)))"),
+        Ok(Expression::Integer(3))
+    );
+    assert_eq!(
+        run("(catch (assert nil 'width) (lambda (c) c))"),
+        Ok(Expression::Symbol("width".to_string()))
+    );
+    assert_eq!(run("(assert (+ 1 1))"), Ok(Expression::Integer(2)));
+    assert_eq!(
+        run("(assert (> 1 2))"),
+        Err(EvalError::Raised(
+            "Assertion failed: (> 1 2)".to_string().into()
+        ))
+    );
+    assert_eq!(
+        run("(assert nil \"width must be positive\")"),
+        Err(EvalError::Raised(
+            "Assertion failed: width must be positive"
+                .to_string()
+                .into()
+        ))
+    );
+}
+
+#[test]
+fn test_warn() {
+    let env = Environment::default();
+    let values = eval_source(&env, "(warn \"careful\") (let ((x 2)) (warn x) x)").unwrap();
+
+    assert_eq!(values, vec![Expression::Nil, Expression::Integer(2)]);
+    assert_eq!(
+        env.take_warnings(),
+        vec![
+            Warning::new("careful".to_string()),
+            Warning::new("2".to_string())
+        ]
+    );
+    assert!(env.take_warnings().is_empty());
+}
+
+#[test]
+fn test_maps() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    run("(set 'm (map-set (make-map '((width . 800) (\"name\" . \"test\"))) 'height 600))")
+        .unwrap();
+    assert_eq!(run("(map-get m 'width)"), Ok(Expression::Integer(800)));
+    assert_eq!(run("(map-get m \"name\")"), Ok("test".to_string().into()));
+    assert_eq!(run("(map-get m 'depth)"), Ok(Expression::Nil));
+    assert_eq!(run("(map-get m 'depth 4)"), Ok(Expression::Integer(4)));
+    assert_eq!(run("(map-contains? m 'height)"), Ok(Expression::True));
+    assert_eq!(
+        run("(map-contains? (map-remove m 'height) 'height)"),
+        Ok(Expression::Nil)
+    );
+    // Keys are kept in insertion order
+    assert_eq!(
+        run("(map-keys m)").unwrap().to_string(),
+        "(width \"name\" height)"
+    );
+    assert_eq!(
+        run("(map-remove m \"name\")").unwrap().to_string(),
+        "(make-map '((width . 800) (height . 600)))"
+    );
+
+    // Updates do not modify the original map, printed maps evaluate to an equal map
+    assert_eq!(run("(map-contains? m 'depth)"), Ok(Expression::Nil));
+    let printed = run("(map-remove m \"name\")").unwrap().to_string();
+    assert_eq!(run(&printed), run("(map-remove m \"name\")"));
+
+    assert!(matches!(
+        run("(map-set m 1.5 1)"),
+        Err(EvalError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_time() {
+    let env = Environment::default();
+    let [e] = ExpressionStream::from_char_stream("(time (+ 1 2))".chars())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+    let (value, millis): (i64, f64) = eval(&env, e).unwrap().try_into().unwrap();
+    assert_eq!(value, 3);
+    assert!(millis >= 0.0);
+}
+
+#[test]
+fn test_bignum() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    let product = run("(* 99999999999 99999999999)").unwrap();
+    assert!(matches!(product, Expression::BigInt(_)));
+    assert_eq!(product.to_string(), "9999999999800000000001");
+
+    // Printed bignums read back, results which fit an i64 are plain integers again
+    assert_eq!(
+        run("(- 9999999999800000000001 1)").unwrap().to_string(),
+        "9999999999800000000000"
+    );
+    assert_eq!(
+        run("(/ (* 99999999999 99999999999) 99999999999)"),
+        Ok(Expression::Integer(99999999999))
+    );
+    assert_eq!(
+        run("(+ 9223372036854775807 1)").unwrap().to_string(),
+        "9223372036854775808"
+    );
+    assert_eq!(
+        run("(* 0.5 (* 99999999999 99999999999))"),
+        Ok(Expression::Float(4.9999999999e21))
+    );
+    assert!(matches!(run("(/ 1 0)"), Err(EvalError::RuntimeError(..))));
+}
+
+#[test]
+fn test_indexing() {
+    use super::environment::InterpreterConfig;
+    use super::eval::eval_last;
+
+    let mut env = Environment::default();
+
+    assert_eq!(
+        eval_last(&env, "(nth 1 '(a b c))"),
+        Ok(Expression::Symbol("b".to_string()))
+    );
+    assert_eq!(
+        eval_last(&env, "(substring \"hello\" 1 3)"),
+        Ok("el".to_string().into())
+    );
+    assert_eq!(
+        eval_last(&env, "(substring \"hello\" 5)"),
+        Ok("".to_string().into())
+    );
+    assert_eq!(
+        eval_last(&env, "(nth 3 '(a b c))"),
+        Err(EvalError::IndexOutOfRange(3, 3))
+    );
+    assert_eq!(
+        eval_last(&env, "(nth -1 '(a b c))"),
+        Err(EvalError::IndexOutOfRange(-1, 3))
+    );
+    assert_eq!(
+        eval_last(&env, "(substring \"hello\" 1 6)"),
+        Err(EvalError::IndexOutOfRange(6, 5))
+    );
+
+    env.set_config(InterpreterConfig {
+        negative_indices: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        eval_last(&env, "(nth -1 '(a b c))"),
+        Ok(Expression::Symbol("c".to_string()))
+    );
+    assert_eq!(
+        eval_last(&env, "(substring \"hello\" -4 -1)"),
+        Ok("ell".to_string().into())
+    );
+    assert_eq!(
+        eval_last(&env, "(nth -4 '(a b c))"),
+        Err(EvalError::IndexOutOfRange(-4, 3))
+    );
+}
+
+#[test]
+fn test_sandbox() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    run("(set 'x 1)").unwrap();
+    assert_eq!(
+        run("(sandbox (set 'x (+ x 1)) (set 'y 5) (+ x y))"),
+        Ok(Expression::Integer(7))
+    );
+    assert_eq!(run("x"), Ok(Expression::Integer(1)));
+    assert_eq!(run("y"), Err(EvalError::SymbolNotBound("y".to_string())));
+}
+
+#[test]
+fn test_multi_expression_bodies() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    run("(defun f (x) (set 'calls (+ calls 1)) (* x 2))").unwrap();
+    run("(set 'calls 0)").unwrap();
+    assert_eq!(run("(f 21)"), Ok(Expression::Integer(42)));
+    assert_eq!(run("calls"), Ok(Expression::Integer(1)));
+    assert_eq!(
+        run("((lambda (x) (set 'calls x) (+ x 1)) 5)"),
+        Ok(Expression::Integer(6))
+    );
+    assert_eq!(
+        run("(let '((y . 2)) (set 'calls y) (* y 3))"),
+        Ok(Expression::Integer(6))
+    );
+    assert_eq!(run("calls"), Ok(Expression::Integer(2)));
+    assert!(matches!(
+        run("(lambda (x))"),
+        Err(EvalError::ArgumentError(_))
+    ));
+}
+
+#[test]
+fn test_let_forms() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    assert_eq!(
+        run("(let ((a 1) (b (+ 1 1))) (+ a b))"),
+        Ok(Expression::Integer(3))
+    );
+    assert_eq!(
+        run("(let '((a . 1) (b . 2)) (+ a b))"),
+        Ok(Expression::Integer(3))
+    );
+    assert_eq!(
+        run("(let (list (cons 'a 5)) a)"),
+        Ok(Expression::Integer(5))
+    );
+    assert_eq!(run("(let () 7)"), Ok(Expression::Integer(7)));
+    assert!(matches!(
+        run("(let ((a 1 2)) a)"),
+        Err(EvalError::ArgumentError(_))
+    ));
+}
+
+#[test]
+fn test_setq() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    run("(defun count-down (n acc) (if (< n 1) acc (progn (setq acc (+ acc n)) (set! n (- n 1)) (count-down n acc))))").unwrap();
+    assert_eq!(run("(count-down 4 0)"), Ok(Expression::Integer(10)));
+    assert_eq!(
+        run("(let ((x 1)) (setq x 2) x)"),
+        Ok(Expression::Integer(2))
+    );
+    assert_eq!(
+        run("(let ((x 1)) (let ((y 0)) (setq x 5)) x)"),
+        Ok(Expression::Integer(5))
+    );
+    assert_eq!(
+        run("(setq undefined-symbol 1)"),
+        Err(EvalError::SymbolNotBound("undefined-symbol".to_string()))
+    );
+    run("(set 'g 1)").unwrap();
+    run("(setq g 2)").unwrap();
+    assert_eq!(run("g"), Ok(Expression::Integer(2)));
+
+    // A sandbox binds locally instead of changing the bindings of the host
+    run("(defun host-value () 1)").unwrap();
+    assert_eq!(
+        run("(sandbox (setq car 5) (setq g 3) ((lambda () (setq host-value 7))) (list car g host-value))"),
+        run("'(5 3 7)")
+    );
+    assert_eq!(run("(car '(1 2))"), Ok(Expression::Integer(1)));
+    assert_eq!(run("g"), Ok(Expression::Integer(2)));
+    assert_eq!(run("(host-value)"), Ok(Expression::Integer(1)));
+}
+
+#[test]
+fn test_higher_order_functions() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+    let list = |v: Vec<i64>| {
+        Ok(v.into_iter()
+            .map(Expression::Integer)
+            .collect::<Vec<_>>()
+            .into())
+    };
+
+    assert_eq!(
+        run("(filter (lambda (x) (< 2 x)) '(1 4 2 3))"),
+        list(vec![4, 3])
+    );
+    assert_eq!(run("(reduce + 0 '(1 2 3 4))"), Ok(Expression::Integer(10)));
+    assert_eq!(run("(reduce - 10 '(1 2))"), Ok(Expression::Integer(7)));
+    assert_eq!(run("(foldr - 0 '(1 2 3))"), Ok(Expression::Integer(2)));
+    assert_eq!(run("(foldr cons '() '(a b))"), run("'(a b)"));
+    assert_eq!(run("(reduce + 5 '())"), Ok(Expression::Integer(5)));
+    run("(set 'total 0)").unwrap();
+    assert_eq!(
+        run("(for-each (lambda (x) (set 'total (+ total x))) '(1 2 3))"),
+        Ok(Expression::Nil)
+    );
+    assert_eq!(run("total"), Ok(Expression::Integer(6)));
+}
+
+#[test]
+fn test_comparisons() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s).unwrap();
+
+    for (program, expected) in [
+        ("(< 1 2.0)", true),
+        ("(< 2.5 2)", false),
+        ("(> 3 2.5)", true),
+        ("(= 2 2.0)", true),
+        ("(/= 2 2.5)", true),
+        ("(/= 'a 'a)", false),
+        ("(<= 2 2.0)", true),
+        ("(<= 3 2)", false),
+        ("(>= 2.0 2)", true),
+        ("(>= 1 100000000000000000000)", false),
+        ("(< 1.5 100000000000000000000)", true),
+        ("(= '(1 2) '(1 2))", true),
+        ("(= '(1 2) '(1 3))", false),
+        ("(< \"a\" \"b\")", true),
+        ("(< 1 \"b\")", false),
+        ("(>= 1 \"b\")", false),
+        ("(eq? 'a 'a)", true),
+        ("(eq? 2 2)", true),
+        ("(eq? 2 2.0)", false),
+        ("(eq? car car)", true),
+        ("(eq? '() nil)", true),
+        ("(eq? \"a\" \"a\")", false),
+        ("(eq? '(1 2) '(1 2))", false),
+        ("(equal? '(1 (2 \"b\")) '(1 (2 \"b\")))", true),
+        ("(equal? '(1 2) '(1 2.0))", false),
+        ("(equal? (make-map '((a . 1))) (make-map '((a . 1))))", true),
+        ("(equal? car cdr)", false),
+    ] {
+        assert_eq!(run(program), run(&expected.to_string()), "{}", program);
+    }
+}
+
+#[test]
+fn test_integer_division() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    for (program, expected) in [
+        ("(/ 6 3)", Expression::Integer(2)),
+        ("(/ 7 2)", Expression::Float(3.5)),
+        ("(quotient 7 -2)", Expression::Integer(-3)),
+        ("(floor-div 7 -2)", Expression::Integer(-4)),
+        ("(floor-div -8 2)", Expression::Integer(-4)),
+        ("(rem -7 2)", Expression::Integer(-1)),
+        ("(mod -7 2)", Expression::Integer(1)),
+        ("(mod 7 -2)", Expression::Integer(-1)),
+        ("(mod 6 -2)", Expression::Integer(0)),
+        ("(mod -7.5 2)", Expression::Float(0.5)),
+        ("(floor-div 7.5 -2)", Expression::Float(-4.0)),
+        (
+            "(mod (- 0 100000000000000000001) 10)",
+            Expression::Integer(9),
+        ),
+    ] {
+        assert_eq!(run(program), Ok(expected), "{}", program);
+    }
+    assert!(matches!(run("(mod 1 0)"), Err(EvalError::RuntimeError(..))));
+}
+
+#[test]
+fn test_expand_path() {
+    let lookup = |name: &str| match name {
+        "HOME" => Some("/home/user".to_string()),
+        "LISPERS_TEST_DIR" => Some("/renders".to_string()),
+        _ => None,
+    };
+    let expand_path = |path: &str| expand_path_with(path, lookup);
+
+    assert_eq!(
+        expand_path("${LISPERS_TEST_DIR}/out.png"),
+        Ok("/renders/out.png".to_string())
+    );
+    assert_eq!(
+        expand_path("$LISPERS_TEST_DIR-1/a$"),
+        Ok("/renders-1/a$".to_string())
+    );
+    assert_eq!(
+        expand_path("~/out.png"),
+        Ok("/home/user/out.png".to_string())
+    );
+    assert_eq!(expand_path("~"), Ok("/home/user".to_string()));
+    assert_eq!(expand_path("a~b"), Ok("a~b".to_string()));
+    assert_eq!(expand_path("~user/a"), Ok("~user/a".to_string()));
+    assert!(expand_path("${LISPERS_UNSET_VARIABLE}").is_err());
+    assert!(expand_path("${LISPERS_TEST_DIR").is_err());
+    assert!(expand_path_with("~/out.png", |_| None).is_err());
+}
+
+#[test]
+fn test_list_functions() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    for (program, expected) in [
+        ("(length '(a b c))", "3"),
+        ("(length nil)", "0"),
+        ("(reverse '(1 2 3))", "(3 2 1)"),
+        ("(last '(1 2 3))", "3"),
+        ("(take 2 '(1 2 3))", "(1 2)"),
+        ("(drop 2 '(1 2 3))", "(3)"),
+        ("(drop 3 '(1 2 3))", "nil"),
+        ("(sort '(3 1.5 2 -1))", "(-1 1.5 2 3)"),
+        ("(sort '(\"b\" \"c\" \"a\"))", "(\"a\" \"b\" \"c\")"),
+        ("(sort '(1 3 2) (lambda (a b) (> a b)))", "(3 2 1)"),
+        (
+            "(sort '((1 . b) (0 . a) (1 . a)) (lambda (x y) (< (car x) (car y))))",
+            "((0 . a) (1 . b) (1 . a))",
+        ),
+        ("(member 2 '(1 2 3))", "(2 3)"),
+        ("(member 2.0 '(1 2 3))", "(2 3)"),
+        ("(member 4 '(1 2 3))", "nil"),
+        ("(assoc 'b '((a . 1) (b . 2)))", "(b . 2)"),
+        ("(assoc 'c '((a . 1) (b . 2)))", "nil"),
+    ] {
+        assert_eq!(
+            run(program).map(|e| e.to_string()),
+            Ok(expected.to_string()),
+            "{}",
+            program
+        );
+    }
+    assert!(matches!(
+        run("(last nil)"),
+        Err(EvalError::ArgumentError(_))
+    ));
+    assert_eq!(
+        run("(take 4 '(1 2 3))"),
+        Err(EvalError::IndexOutOfRange(4, 3))
+    );
+    assert!(matches!(run("(sort '(1 a))"), Err(EvalError::TypeError(_))));
+    assert!(matches!(
+        run("(length '(1 . 2))"),
+        Err(EvalError::TypeError(_))
+    ));
+
+    // Lists are built in order, and dropping elements shares the remaining cells
+    let list: Expression = (1..=3).map(Expression::Integer).collect();
+    assert_eq!(list.to_string(), "(1 2 3)");
+    run("(set 'xs '(1 2 3))").unwrap();
+    let (xs, dropped) = (env.get("xs").unwrap(), run("(drop 1 xs)").unwrap());
+    let (Expression::Cell(_, tail), Expression::Cell(second, _)) = (&xs, &dropped) else {
+        panic!("Expected lists");
+    };
+    let Expression::Cell(head, _) = &**tail else {
+        panic!("Expected a list");
+    };
+    assert!(Arc::ptr_eq(head, second));
+}
+
+#[test]
+fn test_read_write() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    assert_eq!(run("(read \"(+ 1 2)\")"), run("'(+ 1 2)"));
+    assert_eq!(
+        run("(eval (read-string \"(+ 1 2)\"))"),
+        Ok(Expression::Integer(3))
+    );
+    for value in [
+        "'(1 -2.0 \"three\" (four . 5) nil true)",
+        "100000000000000000000",
+        r#""say \"hi\"""#,
+        r#"'("C:\\dir\\" "\d+")"#,
+    ] {
+        assert_eq!(
+            run(&format!("(read (write {}))", value)),
+            run(value),
+            "{}",
+            value
+        );
+    }
+    assert_eq!(
+        run("(eval (read (write (lambda (x) (* x 2.5)))))"),
+        run("(lambda (x) (* x 2.5))")
+    );
+    assert_eq!(
+        run("(write 2.0)"),
+        Ok(Expression::String("2.0".to_string()))
+    );
+    assert_eq!(
+        run(r#"(write "say \"hi\"")"#),
+        Ok(Expression::String(r#""say \"hi\"""#.to_string()))
+    );
+    assert_eq!(
+        run(r#"(read (write "say \"hi\""))"#),
+        Ok(Expression::String(r#"say "hi""#.to_string()))
+    );
+    assert!(matches!(
+        run("(read \"1 2\")"),
+        Err(EvalError::ArgumentError(_))
+    ));
+    assert!(matches!(
+        run("(read \"(1\")"),
+        Err(EvalError::ParserError(_))
+    ));
+    assert!(matches!(
+        run("(write (list car))"),
+        Err(EvalError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_load() {
+    let dir = std::env::temp_dir().join(format!("lispers-test-load-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("main.lisp"), "(load \"lib/util.lisp\")").unwrap();
+    std::fs::write(
+        dir.join("lib/util.lisp"),
+        "(defun util-inc (x) (+ x 1)) (util-inc 41)",
+    )
+    .unwrap();
+    let main = dir.join("main.lisp").display().to_string();
+
+    let env = Environment::default();
+    let values = eval_source(&env, &format!("(load {:?}) (util-inc 1)", main)).unwrap();
+    assert_eq!(
+        values,
+        vec![Expression::Integer(42), Expression::Integer(2)]
+    );
+    assert_eq!(
+        eval_source(&env, "(load-string \"(util-inc 2) (util-inc 3)\")"),
+        Ok(vec![Expression::Integer(4)])
+    );
+
+    let env = Environment::default();
+    let values = eval_source(&env, "(lispers-version \"0.2\") (load \"(+ 1 2)\")").unwrap();
+    assert_eq!(values[1], Expression::Integer(3));
+
+    std::fs::remove_dir_all(dir).unwrap();
 }
@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+
+/// The symbol which limits how deep nested lists are printed by `pp` and the REPL when bound to
+/// a non-negative integer.
+pub const PRINT_DEPTH_SYMBOL: &str = "PRINT-DEPTH";
+
+/// The symbol which limits how many elements of a list are printed by `pp` and the REPL when
+/// bound to a non-negative integer.
+pub const PRINT_LENGTH_SYMBOL: &str = "PRINT-LENGTH";
+
+/// The line width of `pp`.
+pub const PP_WIDTH: usize = 80;
+
+/// The symbol replacing the omitted parts of a truncated expression.
+const ELLIPSIS: &str = "...";
+
+/// Format an expression, breaking lists which do not fit into `width`. The head of a broken
+/// list stays on the first line together with following short elements (like the name and
+/// arguments of a `defun`), the remaining elements are put on separate lines indented by two
+/// more spaces. `indent` is the column the expression starts at.
+pub fn pretty(expr: &Expression, indent: usize, width: usize) -> String {
+    let flat = expr.to_string();
+    if indent + flat.len() <= width {
+        return flat;
+    }
+
+    match expr {
+        Expression::Quote(e) => format!("'{}", pretty(e, indent + 1, width)),
+        Expression::Cell(_, _) => match Vec::<Expression>::try_from(expr.clone()) {
+            Ok(elements) => {
+                let mut elements = elements.iter();
+                let mut line = format!("({}", elements.next().unwrap());
+                let mut rest = elements.peekable();
+                while let Some(e) = rest.peek() {
+                    let e = e.to_string();
+                    if e.len() > 20 || indent + line.len() + e.len() + 1 > width {
+                        break;
+                    }
+                    line = format!("{} {}", line, e);
+                    rest.next();
+                }
+
+                let pad = " ".repeat(indent + 2);
+                for e in rest {
+                    line = format!("{}\n{}{}", line, pad, pretty(e, indent + 2, width));
+                }
+                format!("{})", line)
+            }
+            Err(_) => flat,
+        },
+        _ => flat,
+    }
+}
+
+/// Replace the lists nested deeper than `depth` and the elements of lists after the first
+/// `length` by `...`, so huge values print in a few lines.
+pub fn truncate(expr: &Expression, depth: Option<usize>, length: Option<usize>) -> Expression {
+    let compound = matches!(
+        expr,
+        Expression::Cell(_, _) | Expression::Map(_) | Expression::AnonymousFunction { .. }
+    );
+    if compound && depth == Some(0) {
+        return Expression::Symbol(ELLIPSIS.to_string());
+    }
+    let inner = depth.map(|d| d.saturating_sub(1));
+
+    match expr {
+        Expression::Cell(a, b) => match Vec::<Expression>::try_from(expr.clone()) {
+            Ok(elements) => {
+                let n = elements.len();
+                let mut truncated: Vec<Expression> = elements
+                    .iter()
+                    .take(length.unwrap_or(n))
+                    .map(|e| truncate(e, inner, length))
+                    .collect();
+                if truncated.len() < n {
+                    truncated.push(Expression::Symbol(ELLIPSIS.to_string()));
+                }
+                truncated.into()
+            }
+            Err(_) => Expression::Cell(
+                Arc::new(truncate(a, inner, length)),
+                Arc::new(truncate(b, inner, length)),
+            ),
+        },
+        Expression::Map(m) => Expression::Map(
+            m.iter()
+                .map(|(k, v)| (k.clone(), truncate(v, inner, length)))
+                .collect(),
+        ),
+        Expression::Quote(e) => Expression::Quote(Arc::new(truncate(e, depth, length))),
+        e => e.clone(),
+    }
+}
+
+/// Get the limit bound to a symbol, if it is a non-negative integer.
+fn print_limit(env: &Environment, symbol: &str) -> Option<usize> {
+    match env.get(symbol) {
+        Some(Expression::Integer(i)) => usize::try_from(i).ok(),
+        _ => None,
+    }
+}
+
+/// Truncate an expression to the limits bound to `PRINT-DEPTH` and `PRINT-LENGTH`.
+pub fn printable(env: &Environment, expr: &Expression) -> Expression {
+    truncate(
+        expr,
+        print_limit(env, PRINT_DEPTH_SYMBOL),
+        print_limit(env, PRINT_LENGTH_SYMBOL),
+    )
+}
+
+/// Print an expression with indentation, breaking long lists into lines: `(pp expr)`. Nesting
+/// and list length are limited by `PRINT-DEPTH` and `PRINT-LENGTH`. Returns the expression.
+pub fn printer_pp(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    let e = eval(env, e)?;
+    env.write_output(&format!("{}\n", pretty(&printable(env, &e), 0, PP_WIDTH)))?;
+    Ok(e)
+}
+
+/// Adds the pretty printer to the given environment layer.
+pub fn mk_printer(layer: &mut EnvironmentLayer) {
+    layer.set("pp".to_string(), Expression::Function(printer_pp));
+}
+
+#[test]
+fn test_printer() {
+    use crate::parser::ExpressionStream;
+
+    let parse = |s: &str| {
+        ExpressionStream::from_char_stream(s.chars())
+            .next()
+            .unwrap()
+            .unwrap()
+    };
+
+    let expr = parse(
+        "(scene (color 0.1 0.1 0.1) '(sphere-one sphere-two sphere-three plane) '(light-one light-two))",
+    );
+    assert_eq!(
+        pretty(&expr, 0, 80),
+        "(scene (color 0.1 0.1 0.1)\n  '(sphere-one sphere-two sphere-three plane)\n  '(light-one light-two))"
+    );
+    assert_eq!(pretty(&Expression::Integer(1), 0, 80), "1");
+
+    let expr = parse("(1 (2 (3 4)) 5 6)");
+    assert_eq!(truncate(&expr, None, None), expr);
+    assert_eq!(
+        truncate(&expr, Some(2), None).to_string(),
+        "(1 (2 ...) 5 6)"
+    );
+    assert_eq!(
+        truncate(&expr, None, Some(2)).to_string(),
+        "(1 (2 (3 4)) ...)"
+    );
+    assert_eq!(truncate(&expr, Some(0), None).to_string(), "...");
+    assert_eq!(
+        truncate(&parse("((1 2) . 3)"), Some(1), Some(1)).to_string(),
+        "(... . 3)"
+    );
+
+    let mut layer = EnvironmentLayer::new();
+    layer.set(PRINT_LENGTH_SYMBOL.to_string(), Expression::Integer(1));
+    let env = Environment::from_layer(layer);
+    assert_eq!(printable(&env, &expr).to_string(), "(1 ...)");
+}
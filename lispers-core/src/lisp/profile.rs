@@ -0,0 +1,197 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+
+/// Receives the function calls of evaluations in an `Environment`, attached with
+/// `Environment::set_observer`. Functions are named by the printed head of their call, e.g. `+`
+/// for `(+ 1 2)`. Calls can be observed from several threads at once if the environment is
+/// shared.
+pub trait EvalObserver: Send + Sync {
+    /// Called before a function is called. `builtin` tells whether it is implemented in Rust.
+    fn enter_function(&self, _name: &str, _builtin: bool) {}
+
+    /// Called after a function returned or failed, with the time spent in the call including
+    /// the calls it made.
+    fn exit_function(&self, _name: &str, _builtin: bool, _elapsed: Duration) {}
+}
+
+impl std::fmt::Debug for dyn EvalObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EvalObserver")
+    }
+}
+
+/// Call `f`, reporting it as a call of the function `name` to `observer`.
+pub(crate) fn observe_call<T>(
+    observer: &dyn EvalObserver,
+    name: &str,
+    builtin: bool,
+    f: impl FnOnce() -> T,
+) -> T {
+    observer.enter_function(name, builtin);
+    let start = Instant::now();
+    let result = f();
+    observer.exit_function(name, builtin, start.elapsed());
+    result
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+/// The calls of a function recorded by a `Profiler`.
+pub struct CallStats {
+    /// The number of calls.
+    pub calls: u64,
+    /// The time spent in the calls, including the calls they made. Recursive calls are only
+    /// counted once, by their outermost call.
+    pub total: Duration,
+    /// The time spent in the calls, excluding the calls they made.
+    pub own: Duration,
+    /// Whether the function is implemented in Rust.
+    pub builtin: bool,
+    /// The number of calls which have not returned yet.
+    active: usize,
+}
+
+#[derive(Debug, Default)]
+struct ProfilerState {
+    /// The statistics by function, in the order of the first call.
+    stats: IndexMap<String, CallStats>,
+    /// The time spent in the calls made by each running call, innermost last.
+    stack: Vec<Duration>,
+}
+
+#[derive(Debug, Default)]
+/// An `EvalObserver` counting the calls of each function and the time spent in them. The own
+/// times are only exact if a single evaluation is observed at once.
+pub struct Profiler {
+    state: Mutex<ProfilerState>,
+}
+
+impl Profiler {
+    /// Construct a `Profiler` which has not recorded any calls.
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Get the recorded statistics by function, the functions taking the most own time first.
+    pub fn stats(&self) -> Vec<(String, CallStats)> {
+        let mut stats: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .stats
+            .clone()
+            .into_iter()
+            .collect();
+        stats.sort_by_key(|(_, s)| std::cmp::Reverse(s.own));
+        stats
+    }
+
+    /// Format the recorded statistics as a table, the functions taking the most own time first.
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:<32} {:>10} {:>12} {:>12}\n",
+            "function", "calls", "total ms", "own ms"
+        );
+        for (name, stats) in self.stats() {
+            report.push_str(&format!(
+                "{:<32} {:>10} {:>12.3} {:>12.3}\n",
+                name,
+                stats.calls,
+                stats.total.as_secs_f64() * 1000.0,
+                stats.own.as_secs_f64() * 1000.0
+            ));
+        }
+        report
+    }
+}
+
+impl EvalObserver for Profiler {
+    fn enter_function(&self, name: &str, builtin: bool) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.stats.entry(name.to_string()).or_default();
+        stats.builtin = builtin;
+        stats.active += 1;
+        state.stack.push(Duration::ZERO);
+    }
+
+    fn exit_function(&self, name: &str, _builtin: bool, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let children = state.stack.pop().unwrap_or_default();
+        if let Some(caller) = state.stack.last_mut() {
+            *caller += elapsed;
+        }
+        if let Some(stats) = state.stats.get_mut(name) {
+            stats.calls += 1;
+            stats.own += elapsed.saturating_sub(children);
+            stats.active = stats.active.saturating_sub(1);
+            if stats.active == 0 {
+                stats.total += elapsed;
+            }
+        }
+    }
+}
+
+/// Evaluate an expression and print how often each function was called and the time spent in
+/// it: `(profile expr)` returns the value of `expr`. Observers attached by the host are
+/// suspended meanwhile.
+pub fn profile_profile(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [e] = expr.try_into()?;
+    let profiler = Arc::new(Profiler::new());
+    let outer = env.set_observer(Some(profiler.clone()));
+    let value = eval(env, e);
+    env.set_observer(outer);
+    env.write_output(&profiler.report())?;
+    value
+}
+
+/// Adds the profiling builtins to the given environment layer.
+pub fn mk_profile(layer: &mut EnvironmentLayer) {
+    layer.set("profile".to_string(), Expression::Function(profile_profile));
+}
+
+#[test]
+fn test_profile() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let profiler = Arc::new(Profiler::new());
+    env.set_observer(Some(profiler.clone()));
+    eval_source(
+        &env,
+        "(defun fib (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))
+         (fib 10)",
+    )
+    .unwrap();
+    env.set_observer(None);
+
+    let stats: IndexMap<_, _> = profiler.stats().into_iter().collect();
+    assert_eq!(stats["fib"].calls, 177);
+    assert!(!stats["fib"].builtin);
+    assert_eq!(stats["<"].calls, 177);
+    assert!(stats["<"].builtin);
+    assert!(stats["fib"].own <= stats["fib"].total);
+    assert!(stats["if"].total <= stats["fib"].total);
+
+    env.set_observer(Some(profiler.clone()));
+    let values = eval_source(&env, "(with-captured-output (profile (fib 5)))").unwrap();
+    let Expression::Cell(value, report) = &values[0] else {
+        panic!("Expected the value and output");
+    };
+    assert_eq!(**value, Expression::Integer(5));
+    let report = report.to_string();
+    assert!(report.contains("function"));
+    assert!(report
+        .lines()
+        .any(|l| l.starts_with("fib ") && l.contains(" 15 ")));
+    // The profiled calls are not reported to the outer observer
+    let outer: IndexMap<_, _> = profiler.stats().into_iter().collect();
+    assert_eq!(outer["fib"].calls, 177);
+    assert_eq!(outer["profile"].calls, 1);
+}
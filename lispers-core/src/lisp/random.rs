@@ -0,0 +1,155 @@
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+
+/// The seed of a new random number generator, so unseeded scripts are reproducible, too.
+const DEFAULT_SEED: u64 = 0x5EED;
+
+/// A small deterministic pseudo random number generator (SplitMix64). The same seed always
+/// yields the same sequence, independent of the platform.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    /// Get the next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Get a uniformly distributed float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Get a uniformly distributed integer in `[0, n)`, `n` must not be zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        // Reject the incomplete last block of `n` values to avoid a bias
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next_u64();
+            if x < limit {
+                return x % n;
+            }
+        }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(DEFAULT_SEED)
+    }
+}
+
+/// Get a random float in `[0, 1)`: `(random)`
+pub fn random(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let []: [Expression; 0] = expr.try_into()?;
+    Ok(Expression::Float(env.rng().next_f64()))
+}
+
+/// Get a random integer in `[0, n)`: `(random-int n)`
+pub fn random_int(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [n] = expr.try_into()?;
+    let n: i64 = eval(env, n)?.try_into()?;
+    if n < 1 {
+        return Err(EvalError::ArgumentError(format!(
+            "Expected a positive bound, got {}",
+            n
+        )));
+    }
+    Ok(Expression::Integer(env.rng().below(n as u64) as i64))
+}
+
+/// Get a random number in `[low, high)`: `(random-range low high)`. The result is an integer
+/// if both bounds are integers, a float otherwise.
+pub fn random_range(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [low, high] = expr.try_into()?;
+    let low = eval(env, low)?;
+    let high = eval(env, high)?;
+
+    match (low, high) {
+        (Expression::Integer(low), Expression::Integer(high)) if low < high => {
+            let span = (high as i128 - low as i128) as u64;
+            Ok(Expression::Integer(
+                (low as i128 + env.rng().below(span) as i128) as i64,
+            ))
+        }
+        (low, high) => {
+            let low: f64 = low.try_into()?;
+            let high: f64 = high.try_into()?;
+            if low >= high {
+                return Err(EvalError::ArgumentError(format!(
+                    "Expected a non-empty range, got [{}, {})",
+                    low, high
+                )));
+            }
+            Ok(Expression::Float(low + (high - low) * env.rng().next_f64()))
+        }
+    }
+}
+
+/// Reset the random number generator to a seed: `(random-seed n)`
+pub fn random_seed(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [seed] = expr.try_into()?;
+    let seed: i64 = eval(env, seed)?.try_into()?;
+    *env.rng() = Rng::new(seed as u64);
+    Ok(Expression::Integer(seed))
+}
+
+/// Adds the random number builtins to the given environment layer.
+pub fn mk_random(layer: &mut EnvironmentLayer) {
+    layer.set("random".to_string(), Expression::Function(random));
+    layer.set("random-int".to_string(), Expression::Function(random_int));
+    layer.set(
+        "random-range".to_string(),
+        Expression::Function(random_range),
+    );
+    layer.set("random-seed".to_string(), Expression::Function(random_seed));
+}
+
+#[test]
+fn test_random() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+
+    run("(random-seed 42)").unwrap();
+    let first = run("(list (random) (random-int 10) (random-range -5 5) (random-range 1 1.5))");
+    run("(random-seed 42)").unwrap();
+    assert_eq!(
+        run("(list (random) (random-int 10) (random-range -5 5) (random-range 1 1.5))"),
+        first
+    );
+
+    for _ in 0..100 {
+        let Ok(Expression::Integer(i)) = run("(random-range -5 5)") else {
+            panic!("Expected an integer");
+        };
+        assert!((-5..5).contains(&i));
+        let Ok(Expression::Float(f)) = run("(random-range 1 1.5)") else {
+            panic!("Expected a float");
+        };
+        assert!((1.0..1.5).contains(&f));
+    }
+    assert!(matches!(
+        run("(random-int 0)"),
+        Err(EvalError::ArgumentError(_))
+    ));
+    assert!(matches!(
+        run("(random-range 2 1)"),
+        Err(EvalError::ArgumentError(_))
+    ));
+}
@@ -0,0 +1,17 @@
+use super::environment::EnvironmentLayer;
+use super::expression::ForeignData;
+
+/// A Rust struct exposed to lisp as foreign data, with a constructor, accessors and setters of
+/// its fields. Usually derived with `#[derive(LispRecord)]` from `lispers-macro`, which also
+/// converts the struct from and into an `Expression`.
+pub trait LispRecord: ForeignData + Sized {
+    /// The lisp name of the record, which prefixes its functions, e.g. `point`.
+    const NAME: &'static str;
+    /// The lisp names of the fields, in the order the constructor takes them.
+    const FIELDS: &'static [&'static str];
+
+    /// Adds the constructor `(make-point x y)`, the accessors `(point-x p)` and the setters
+    /// `(set-point-x p x)` to the given environment layer. Records are values like all
+    /// expressions, so setters return a copy with the field replaced.
+    fn mk_record(layer: &mut EnvironmentLayer);
+}
@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+use crate::NativeFunction;
+
+#[doc(hidden)]
+pub use inventory;
+
+/// A native function registered for a module with `#[native_lisp_function(module = ...)]` or
+/// `#[register_lisp_function(module = ...)]` from `lispers-macro`. The `mk_*` function generated
+/// with `collect_module!` adds all functions of a module to an environment layer.
+#[derive(Debug)]
+pub struct NativeRegistration {
+    /// The module, e.g. `raytrace` for the functions added by `mk_raytrace`.
+    pub module: &'static str,
+    /// The symbol the function is bound to.
+    pub name: &'static str,
+    /// The native function.
+    pub function: NativeFunction,
+}
+
+inventory::collect!(NativeRegistration);
+
+/// Get the names and functions registered for a module, sorted by name.
+///
+/// # Panics
+/// If two functions are registered with the same name in the module.
+pub fn registered_functions(module: &str) -> Vec<(&'static str, NativeFunction)> {
+    let mut functions: Vec<_> = inventory::iter::<NativeRegistration>
+        .into_iter()
+        .filter(|r| r.module == module)
+        .map(|r| (r.name, r.function))
+        .collect();
+    functions.sort_by_key(|(name, _)| *name);
+    if let Some(pair) = functions.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        panic!(
+            "The native function {} is registered twice in module {}",
+            pair[0].0, module
+        );
+    }
+    functions
+}
+
+/// Adds the native functions registered for `module` to the given environment layer.
+pub fn mk_module(layer: &mut EnvironmentLayer, module: &str) {
+    for (name, function) in registered_functions(module) {
+        layer.set(name.to_string(), Expression::Function(function));
+    }
+}
+
+/// The documentation and parameters of a native function, recorded by
+/// `#[native_lisp_function]`, `native_lisp_function_proxy!` and `#[register_lisp_function]` for
+/// the `doc` and `arity` builtins.
+#[derive(Debug)]
+pub struct FunctionMeta {
+    /// The native function.
+    pub function: NativeFunction,
+    /// The doc comment of the function.
+    pub doc: &'static str,
+    /// The parameter names, if the arguments are converted by the macro.
+    pub parameters: Option<&'static [&'static str]>,
+    /// The number of arguments which cannot be left out.
+    pub required: usize,
+    /// Whether the last parameter collects all remaining arguments.
+    pub variadic: bool,
+    /// The implementations a proxy dispatches to, in order.
+    pub overloads: &'static [NativeFunction],
+}
+
+inventory::collect!(FunctionMeta);
+
+impl FunctionMeta {
+    /// Get the recorded metadata of a native function.
+    pub fn of(function: NativeFunction) -> Option<&'static FunctionMeta> {
+        inventory::iter::<FunctionMeta>
+            .into_iter()
+            .find(|m| std::ptr::fn_addr_eq(m.function, function))
+    }
+
+    /// Get the smallest and largest number of arguments, without an upper bound for variadic
+    /// functions. Proxies accept the arguments of any of their implementations.
+    pub fn arity(&self) -> Option<(usize, Option<usize>)> {
+        if let Some(parameters) = self.parameters {
+            return Some((self.required, (!self.variadic).then_some(parameters.len())));
+        }
+        self.overloads
+            .iter()
+            .map(|f| FunctionMeta::of(*f)?.arity())
+            .reduce(|a, b| {
+                let (a, b) = (a?, b?);
+                Some((a.0.min(b.0), a.1.zip(b.1).map(|(a, b)| a.max(b))))
+            })
+            .flatten()
+    }
+
+    /// Format the call of the function bound to `name`, e.g. `(camera pos cnt [up])` with
+    /// optional arguments in brackets and `rest...` for variadic ones. Proxies have a line for
+    /// each implementation.
+    pub fn signature(&self, name: &str) -> Option<String> {
+        let Some(parameters) = self.parameters else {
+            let signatures: Option<Vec<_>> = self
+                .overloads
+                .iter()
+                .map(|f| FunctionMeta::of(*f)?.signature(name))
+                .collect();
+            return signatures.filter(|s| !s.is_empty()).map(|s| s.join("\n"));
+        };
+        let mut call = format!("({}", name);
+        for (i, parameter) in parameters.iter().enumerate() {
+            if self.variadic && i + 1 == parameters.len() {
+                call.push_str(&format!(" {}...", parameter));
+            } else if i >= self.required {
+                call.push_str(&format!(" [{}]", parameter));
+            } else {
+                call.push_str(&format!(" {}", parameter));
+            }
+        }
+        call.push(')');
+        Some(call)
+    }
+}
+
+/// Get the function bound to a symbol given as argument of the introspection builtins.
+fn documented_function(
+    env: &Environment,
+    expr: Expression,
+) -> Result<(String, Expression), EvalError> {
+    let [name] = expr.try_into()?;
+    match eval(env, name)? {
+        Expression::Symbol(name) => match env.get(&name) {
+            Some(function) => Ok((name, function)),
+            None => Err(EvalError::SymbolNotBound(name)),
+        },
+        x => Err(EvalError::NotASymbol(x)),
+    }
+}
+
+/// Get the documentation of a function: `(doc 'sphere)` returns its call with the parameter
+/// names, followed by its doc comment. Lambdas are documented by a string starting their body.
+/// Returns nil for functions without documentation.
+pub fn registry_doc(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (name, function) = documented_function(env, expr)?;
+    let (signature, doc) = match &function {
+        Expression::Function(f) => match FunctionMeta::of(*f) {
+            Some(meta) => (meta.signature(&name), meta.doc.to_string()),
+            None => (None, String::new()),
+        },
+        Expression::AnonymousFunction {
+            argument_symbols,
+            body,
+        } => {
+            let call = std::iter::once(name.as_str())
+                .chain(argument_symbols.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let doc = match &body[..] {
+                [Expression::String(doc), _, ..] => doc.clone(),
+                _ => String::new(),
+            };
+            (Some(format!("({})", call)), doc)
+        }
+        _ => (None, String::new()),
+    };
+    Ok(match (signature, doc) {
+        (Some(signature), doc) if doc.is_empty() => signature.into(),
+        (Some(signature), doc) => format!("{}\n\n{}", signature, doc).into(),
+        (None, doc) if doc.is_empty() => Expression::Nil,
+        (None, doc) => doc.into(),
+    })
+}
+
+/// Get the number of arguments a function takes: `(arity 'sphere)` returns the list of the
+/// smallest and the largest number, which is nil if any number of further arguments is
+/// accepted. Returns nil if the arguments of a native function are not known.
+pub fn registry_arity(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (_, function) = documented_function(env, expr)?;
+    let arity = match &function {
+        Expression::Function(f) => FunctionMeta::of(*f).and_then(FunctionMeta::arity),
+        Expression::AnonymousFunction {
+            argument_symbols, ..
+        } => Some((argument_symbols.len(), Some(argument_symbols.len()))),
+        _ => None,
+    };
+    Ok(match arity {
+        Some((min, max)) => vec![
+            Expression::Integer(min as i64),
+            max.map_or(Expression::Nil, |max| Expression::Integer(max as i64)),
+        ]
+        .into(),
+        None => Expression::Nil,
+    })
+}
+
+/// List the global bindings: `(bindings)` returns pairs of each bound symbol and its kind,
+/// `function`, `foreign` or `value`, in the order they were first bound. Internal symbols of the
+/// interpreter are left out.
+pub fn registry_bindings(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let []: [Expression; 0] = expr.try_into()?;
+    Ok(env
+        .iter_globals()
+        .filter(|(name, _)| !name.starts_with("LISPERS-"))
+        .map(|(name, value)| {
+            let kind = match value {
+                Expression::Function(_)
+                | Expression::NativeClosure(_)
+                | Expression::AnonymousFunction { .. } => "function",
+                Expression::ForeignExpression(_) => "foreign",
+                _ => "value",
+            };
+            Expression::Cell(
+                Arc::new(Expression::Symbol(name)),
+                Arc::new(Expression::Symbol(kind.to_string())),
+            )
+        })
+        .collect())
+}
+
+/// Adds the introspection builtins to the given environment layer.
+pub fn mk_registry(layer: &mut EnvironmentLayer) {
+    layer.set("doc".to_string(), Expression::Function(registry_doc));
+    layer.set("arity".to_string(), Expression::Function(registry_arity));
+    layer.set(
+        "bindings".to_string(),
+        Expression::Function(registry_bindings),
+    );
+}
+
+#[test]
+#[should_panic(expected = "registered twice")]
+fn test_duplicate_registration() {
+    fn one(_: &Environment, _: Expression) -> Result<Expression, EvalError> {
+        Ok(Expression::Integer(1))
+    }
+    inventory::submit! {
+        NativeRegistration { module: "duplicates", name: "one", function: one }
+    }
+    inventory::submit! {
+        NativeRegistration { module: "duplicates", name: "one", function: one }
+    }
+
+    registered_functions("duplicates");
+}
+
+#[test]
+fn test_doc() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    assert_eq!(
+        eval_source(
+            &env,
+            "(defun area (w h) \"The area of a w by h rectangle.\" (* w h))
+             (defun id (x) \"x\")
+             (doc 'area) (arity 'area) (doc 'id) (arity 'car)"
+        )
+        .map(|values| values[2..6].to_vec()),
+        Ok(vec![
+            "(area w h)\n\nThe area of a w by h rectangle."
+                .to_string()
+                .into(),
+            vec![Expression::Integer(2), Expression::Integer(2)].into(),
+            "(id x)".to_string().into(),
+            Expression::Nil,
+        ])
+    );
+    assert!(eval_source(&env, "(doc 'undefined)").is_err());
+}
+
+#[test]
+fn test_bindings() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let bindings = eval_source(
+        &env,
+        "(defun area (w h) (* w h))
+         (set 'size 3)
+         (import)
+         (bindings)",
+    )
+    .unwrap()
+    .pop()
+    .unwrap();
+    let kinds: Vec<(String, String)> = Vec::<Expression>::try_from(bindings)
+        .unwrap()
+        .into_iter()
+        .map(|binding| match binding {
+            Expression::Cell(name, kind) => (name.to_string(), kind.to_string()),
+            x => panic!("Expected a pair, got {}", x),
+        })
+        .collect();
+    let kind = |name: &str| {
+        kinds
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, kind)| kind.as_str())
+    };
+    assert_eq!(kind("car"), Some("function"));
+    assert_eq!(kind("area"), Some("function"));
+    assert_eq!(kind("size"), Some("value"));
+    assert_eq!(kind("LISPERS-IMPORTS"), None);
+    assert_eq!(kinds.last().map(|(n, _)| n.as_str()), Some("size"));
+    assert!(eval_source(&env, "(bindings 'extra)").is_err());
+}
@@ -0,0 +1,29 @@
+;; The standard library written in lisp, loaded with the prelude (see `mk_stdlib`). Functions
+;; walking whole lists are builtins of the prelude, as calls here nest as deep as the recursion.
+;;
+;; Function calls bind arguments dynamically: while a function runs, its arguments are visible to
+;; every function it calls. Arguments are thus named with a leading `%`, which user code does not
+;; use, so a lambda passed in still sees the caller's variables, e.g. `xs` in
+;; `(let ((xs 10)) (find (lambda (x) (> x xs)) '(5 15)))`.
+
+(defun identity (%x) %x)
+
+(defun null? (%x) (eq? %x nil))
+
+(defun inc (%n) (+ %n 1))
+
+(defun dec (%n) (- %n 1))
+
+(defun cadr (%xs) (car (cdr %xs)))
+
+(defun cddr (%xs) (cdr (cdr %xs)))
+
+(defun caar (%xs) (car (car %xs)))
+
+(defun sum (%xs) (reduce + 0 %xs))
+
+(defun product (%xs) (reduce * 1 %xs))
+
+(defun count-if (%f %xs) (length (filter %f %xs)))
+
+(defun remove-if (%f %xs) (filter (lambda (%x) (not (%f %x))) %xs))
@@ -0,0 +1,89 @@
+use std::sync::LazyLock;
+
+use crate::parser::token::Position;
+use crate::parser::{ExpressionStream, ParserError, Spans};
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::locate_error;
+use super::expression::Expression;
+use super::{eval, LispError};
+
+/// The source of the standard library.
+pub const STDLIB: &str = include_str!("stdlib.lisp");
+
+/// The expressions of the standard library with their positions, parsed once on first use. The
+/// spans stay valid, as the parsed expressions are kept.
+type Parsed = (Vec<(Position, Expression)>, Spans);
+static PARSED: LazyLock<Result<Parsed, ParserError>> = LazyLock::new(|| {
+    let mut stream = ExpressionStream::from_char_stream(STDLIB.chars());
+    let expressions = std::iter::from_fn(|| stream.next_positioned())
+        .map(|(pos, expr)| expr.map(|e| (pos, e)))
+        .collect::<Result<Vec<_>, ParserError>>()?;
+    Ok((expressions, stream.spans().clone()))
+});
+
+/// Adds the functions of the standard library, which is written in lisp, to the given
+/// environment layer. The library is evaluated with the builtins already in `layer`, so this
+/// has to be called after registering the prelude. On an error, `layer` is left unchanged.
+pub fn mk_stdlib(layer: &mut EnvironmentLayer) -> Result<(), LispError> {
+    let (expressions, spans) = PARSED.as_ref().map_err(|e| LispError::Parser(e.clone()))?;
+    let env = Environment::from_layer(layer.clone());
+    for (position, expr) in expressions {
+        eval(&env, expr.clone())
+            .map_err(|e| LispError::Eval(locate_error(&env, e, *position, spans)))?;
+    }
+    for (name, value) in env.shared_bindings() {
+        layer.set(name, value);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_stdlib() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_source(&env, s).unwrap().pop().unwrap().to_string();
+
+    assert_eq!(run("(range 0 4)"), "(0 1 2 3)");
+    assert_eq!(run("(repeat 2 'a)"), "(a a)");
+    assert_eq!(run("(sum (range 1 5))"), "10");
+    assert_eq!(run("(product '(2 3 4))"), "24");
+    assert_eq!(run("(any? (lambda (x) (> x 2)) '(1 2 3))"), "true");
+    assert_eq!(run("(any? (lambda (x) (> x 3)) '(1 2 3))"), "nil");
+    assert_eq!(run("(all? (lambda (x) (> x 0)) '(1 2 3))"), "true");
+    assert_eq!(run("(find (lambda (x) (> x 1)) '(1 2 3))"), "2");
+    assert_eq!(run("(count-if (lambda (x) (> x 1)) '(1 2 3))"), "2");
+    assert_eq!(run("(remove-if (lambda (x) (> x 1)) '(1 2 3))"), "(1)");
+    assert_eq!(run("(zip '(a b c) '(1 2))"), "((a . 1) (b . 2))");
+    // Long lists do not nest calls
+    assert_eq!(run("(sum (range 0 5000))"), "12497500");
+    assert_eq!(
+        run("(length (zip (range 0 5000) (repeat 5000 'a)))"),
+        "5000"
+    );
+    assert_eq!(run("(find (lambda (x) (> x 4998)) (range 0 5000))"), "4999");
+    assert_eq!(run("(all? (lambda (x) (>= x 0)) (range 0 5000))"), "true");
+    assert_eq!(run("(cadr '(1 2 3))"), "2");
+    assert_eq!(run("(when (> 2 1) 'a 'b)"), "b");
+    assert_eq!(run("(unless (> 2 1) 'a)"), "nil");
+    // Arguments of library functions do not shadow variables of functions passed in
+    assert_eq!(
+        run("(let ((xs 10)) (find (lambda (x) (> x xs)) '(5 15)))"),
+        "15"
+    );
+}
+
+#[test]
+fn test_stdlib_errors() {
+    use super::eval::EvalError;
+
+    // Without the prelude, the library cannot define its functions
+    let mut layer = EnvironmentLayer::new();
+    let Err(LispError::Eval(e)) = mk_stdlib(&mut layer) else {
+        panic!("Expected an evaluation error");
+    };
+    assert_eq!(e.root(), &EvalError::SymbolNotBound("defun".to_string()));
+    assert!(layer.get("identity").is_none());
+}
@@ -0,0 +1,332 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use crate::parser::ExpressionStream;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+
+/// Evaluate the single string argument of a string function and apply `f` to it.
+fn string_function(
+    env: &Environment,
+    expr: Expression,
+    f: fn(&str) -> String,
+) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    Ok(f(&s).into())
+}
+
+/// Get the number of characters of a string: `(string-length s)`
+pub fn string_length(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    Ok(Expression::Integer(s.chars().count() as i64))
+}
+
+/// Get the characters from `start` up to (excluding) `end` of a string:
+/// `(substring s start [end])`, `end` defaults to the end of the string.
+pub fn string_substring(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (s, start, end) = match <[Expression; 3]>::try_from(args) {
+        Ok([s, start, end]) => (s, start, Some(end)),
+        Err(args) => {
+            let [s, start]: [Expression; 2] = Expression::from(args).try_into()?;
+            (s, start, None)
+        }
+    };
+
+    let s: String = eval(env, s)?.try_into()?;
+    let chars: Vec<char> = s.chars().collect();
+    let start = env
+        .config()
+        .bound(eval(env, start)?.try_into()?, chars.len())?;
+    let end = match end {
+        Some(end) => env
+            .config()
+            .bound(eval(env, end)?.try_into()?, chars.len())?,
+        None => chars.len(),
+    };
+    if start > end {
+        return Err(EvalError::ArgumentError(format!(
+            "substring start {} is after its end {}",
+            start, end
+        )));
+    }
+
+    Ok(chars[start..end].iter().collect::<String>().into())
+}
+
+/// Split a string at a separator: `(string-split s [sep])`. Without a separator, the string is
+/// split at whitespace and empty parts are dropped.
+pub fn string_split(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (s, sep) = match <[Expression; 2]>::try_from(args) {
+        Ok([s, sep]) => (s, Some(sep)),
+        Err(args) => {
+            let [s]: [Expression; 1] = Expression::from(args).try_into()?;
+            (s, None)
+        }
+    };
+
+    let s: String = eval(env, s)?.try_into()?;
+    let parts: Vec<Expression> = match sep {
+        Some(sep) => {
+            let sep: String = eval(env, sep)?.try_into()?;
+            if sep.is_empty() {
+                return Err(EvalError::ArgumentError(
+                    "Expected a non-empty separator".to_string(),
+                ));
+            }
+            s.split(&sep).map(|p| p.to_string().into()).collect()
+        }
+        None => s.split_whitespace().map(|p| p.to_string().into()).collect(),
+    };
+
+    Ok(parts.into())
+}
+
+/// Join a list of strings with a separator: `(string-join list [sep])`, `sep` defaults to `""`.
+pub fn string_join(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (list, sep) = match <[Expression; 2]>::try_from(args) {
+        Ok([list, sep]) => (list, eval(env, sep)?.try_into()?),
+        Err(args) => {
+            let [list]: [Expression; 1] = Expression::from(args).try_into()?;
+            (list, String::new())
+        }
+    };
+
+    let list: Vec<Expression> = eval(env, list)?.try_into()?;
+    let parts: Vec<String> = list
+        .into_iter()
+        .map(String::try_from)
+        .collect::<Result<_, _>>()?;
+
+    Ok(parts.join(&sep).into())
+}
+
+pub fn string_upcase(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    string_function(env, expr, str::to_uppercase)
+}
+
+pub fn string_downcase(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    string_function(env, expr, str::to_lowercase)
+}
+
+/// Remove leading and trailing whitespace: `(string-trim s)`
+pub fn string_trim(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    string_function(env, expr, |s| s.trim().to_string())
+}
+
+/// Replace all occurrences of `from` in a string: `(string-replace s from to)`
+pub fn string_replace(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s, from, to] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    let from: String = eval(env, from)?.try_into()?;
+    let to: String = eval(env, to)?.try_into()?;
+    if from.is_empty() {
+        return Err(EvalError::ArgumentError(
+            "Expected a non-empty pattern".to_string(),
+        ));
+    }
+    Ok(s.replace(&from, &to).into())
+}
+
+/// Parse a number like the reader does: `(string->number "1.5")`. Returns false if the string
+/// is not a single number.
+pub fn string_to_number(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+
+    let parsed = ExpressionStream::from_char_stream(s.chars()).collect::<Result<Vec<_>, _>>();
+    match parsed.as_deref() {
+        Ok([n @ (Expression::Integer(_) | Expression::BigInt(_) | Expression::Float(_))]) => {
+            Ok(n.clone())
+        }
+        _ => Ok(env.config().boolean(false)),
+    }
+}
+
+/// Format a number as a string: `(number->string 42)`
+pub fn number_to_string(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [n] = expr.try_into()?;
+    match eval(env, n)? {
+        n @ (Expression::Integer(_) | Expression::BigInt(_) | Expression::Float(_)) => {
+            Ok(n.to_string().into())
+        }
+        n => Err(EvalError::NotANumber(n)),
+    }
+}
+
+/// Check whether a string contains another: `(string-contains? s sub)`
+pub fn string_contains(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s, sub] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    let sub: String = eval(env, sub)?.try_into()?;
+    Ok(env.config().boolean(s.contains(&sub)))
+}
+
+/// Get the name of a symbol: `(symbol->string 'abc)`
+pub fn symbol_to_string(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    match eval(env, s)? {
+        Expression::Symbol(s) => Ok(s.into()),
+        x => Err(EvalError::NotASymbol(x)),
+    }
+}
+
+/// Get the symbol with a name: `(string->symbol "abc")`, also available as `intern`. Symbols are
+/// identified by their name only, so the same name always gives the same symbol.
+pub fn string_to_symbol(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [s] = expr.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+    if s.is_empty() {
+        return Err(EvalError::ArgumentError(
+            "Expected a non-empty symbol name".to_string(),
+        ));
+    }
+    Ok(Expression::Symbol(s))
+}
+
+/// The number of the next symbol created by `gensym`.
+static GENSYM_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+/// Create a new symbol which is not bound: `(gensym [prefix])`, the prefix defaults to `"G"`.
+/// Generated symbols are numbered, like `G__1`, so they do not clash with each other.
+pub fn string_gensym(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let prefix = match <[Expression; 1]>::try_from(args) {
+        Ok([prefix]) => eval(env, prefix)?.try_into()?,
+        Err(args) => {
+            let []: [Expression; 0] = Expression::from(args).try_into()?;
+            "G".to_string()
+        }
+    };
+
+    loop {
+        let n = GENSYM_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let symbol = format!("{}__{}", prefix, n);
+        if env.get(&symbol).is_none() {
+            return Ok(Expression::Symbol(symbol));
+        }
+    }
+}
+
+/// Adds the string and symbol functions to the given environment layer.
+pub fn mk_strings(layer: &mut EnvironmentLayer) {
+    layer.set(
+        "string-length".to_string(),
+        Expression::Function(string_length),
+    );
+    layer.set(
+        "substring".to_string(),
+        Expression::Function(string_substring),
+    );
+    layer.set(
+        "string-split".to_string(),
+        Expression::Function(string_split),
+    );
+    layer.set("string-join".to_string(), Expression::Function(string_join));
+    layer.set(
+        "string-upcase".to_string(),
+        Expression::Function(string_upcase),
+    );
+    layer.set(
+        "string-downcase".to_string(),
+        Expression::Function(string_downcase),
+    );
+    layer.set("string-trim".to_string(), Expression::Function(string_trim));
+    layer.set(
+        "string-replace".to_string(),
+        Expression::Function(string_replace),
+    );
+    layer.set(
+        "string->number".to_string(),
+        Expression::Function(string_to_number),
+    );
+    layer.set(
+        "number->string".to_string(),
+        Expression::Function(number_to_string),
+    );
+    layer.set(
+        "string-contains?".to_string(),
+        Expression::Function(string_contains),
+    );
+    layer.set(
+        "symbol->string".to_string(),
+        Expression::Function(symbol_to_string),
+    );
+    layer.set(
+        "string->symbol".to_string(),
+        Expression::Function(string_to_symbol),
+    );
+    layer.set("intern".to_string(), Expression::Function(string_to_symbol));
+    layer.set("gensym".to_string(), Expression::Function(string_gensym));
+}
+
+#[test]
+fn test_strings() {
+    use super::eval::eval_last;
+
+    let env = Environment::default();
+    let run = |s: &str| eval_last(&env, s);
+    let string = |s: &str| Ok(Expression::String(s.to_string()));
+
+    assert_eq!(run("(string-length \"héllo\")"), Ok(Expression::Integer(5)));
+    assert_eq!(
+        run("(string-split \" a  b c \")"),
+        run("'(\"a\" \"b\" \"c\")")
+    );
+    assert_eq!(
+        run("(string-split \"a,,b\" \",\")"),
+        run("'(\"a\" \"\" \"b\")")
+    );
+    assert_eq!(run("(string-join '(\"a\" \"b\") \", \")"), string("a, b"));
+    assert_eq!(run("(string-join '())"), string(""));
+    assert_eq!(run("(string-upcase \"abc\")"), string("ABC"));
+    assert_eq!(run("(string-downcase \"ABC\")"), string("abc"));
+    assert_eq!(run("(string-trim \"  abc \")"), string("abc"));
+    assert_eq!(
+        run("(string-replace \"a-b-c\" \"-\" \"+\")"),
+        string("a+b+c")
+    );
+    assert_eq!(
+        run("(string->number \"-12\")"),
+        Ok(Expression::Integer(-12))
+    );
+    assert_eq!(run("(string->number \"1.5\")"), Ok(Expression::Float(1.5)));
+    assert_eq!(run("(string->number \"1 2\")"), Ok(Expression::Nil));
+    assert_eq!(run("(string->number \"abc\")"), Ok(Expression::Nil));
+    assert_eq!(run("(number->string 42)"), string("42"));
+    assert_eq!(
+        run("(string-contains? \"hello\" \"ell\")"),
+        Ok(Expression::True)
+    );
+    assert_eq!(
+        run("(string-contains? \"hello\" \"xyz\")"),
+        Ok(Expression::Nil)
+    );
+    assert!(matches!(
+        run("(string-split \"abc\" \"\")"),
+        Err(EvalError::ArgumentError(_))
+    ));
+    assert!(matches!(
+        run("(number->string \"1\")"),
+        Err(EvalError::NotANumber(_))
+    ));
+    assert_eq!(run("(symbol->string 'abc)"), string("abc"));
+    assert_eq!(
+        run("(string->symbol \"a b\")"),
+        Ok(Expression::Symbol("a b".to_string()))
+    );
+    assert_eq!(run("(eq? (intern \"abc\") 'abc)"), Ok(Expression::True));
+    assert_eq!(run("(eq? (gensym) (gensym))"), Ok(Expression::Nil));
+    assert!(matches!(
+        run("(symbol->string (gensym \"tmp\"))"),
+        Ok(Expression::String(s)) if s.starts_with("tmp__")
+    ));
+}
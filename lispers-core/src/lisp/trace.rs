@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::environment::Environment;
+use super::environment::EnvironmentLayer;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::expression::Expression;
+use super::printer::truncate;
+
+#[derive(Debug, Default, PartialEq)]
+/// The calls printed while evaluating, see `Environment::trace` and
+/// `Environment::set_step_depth`.
+pub(crate) struct TraceState {
+    /// The names of the traced functions.
+    pub(crate) functions: BTreeSet<String>,
+    /// Trace all calls nested at most this deep.
+    pub(crate) step_depth: Option<usize>,
+    /// The number of traced calls which have not returned yet.
+    pub(crate) indent: usize,
+}
+
+/// Call `f`, printing the call of `head` with `args` before and its value or error after.
+/// Traced calls inside `f` are indented.
+pub(crate) fn trace_call(
+    env: &Environment,
+    head: &Expression,
+    args: Expression,
+    f: impl FnOnce() -> Result<Expression, EvalError>,
+) -> Result<Expression, EvalError> {
+    let call = Expression::Cell(Arc::new(head.clone()), Arc::new(args));
+    let indent = "  ".repeat(env.trace_enter());
+    let result = env
+        .write_output(&format!(
+            "{}> {}\n",
+            indent,
+            truncate(&call, Some(3), Some(8))
+        ))
+        .and_then(|_| f());
+    env.trace_leave();
+    match &result {
+        Ok(value) => env.write_output(&format!(
+            "{}< {}\n",
+            indent,
+            truncate(value, Some(3), Some(8))
+        ))?,
+        Err(e) => env.write_output(&format!("{}! {}\n", indent, e.root()))?,
+    }
+    result
+}
+
+/// Get the function names of trace arguments.
+fn function_names(env: &Environment, expr: Expression) -> Result<Vec<String>, EvalError> {
+    let names: Vec<Expression> = expr.try_into()?;
+    names
+        .into_iter()
+        .map(|name| match eval(env, name)? {
+            Expression::Symbol(s) => Ok(s),
+            x => Err(EvalError::NotASymbol(x)),
+        })
+        .collect()
+}
+
+/// Print the calls of functions with their arguments and values: `(trace 'f 'g ...)`. Returns
+/// the list of traced functions, so `(trace)` only lists them.
+pub fn trace_trace(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    for name in function_names(env, expr)? {
+        env.trace(&name);
+    }
+    Ok(env
+        .traced_functions()
+        .into_iter()
+        .map(Expression::Symbol)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Stop printing the calls of functions: `(untrace 'f 'g ...)`, or of all functions with
+/// `(untrace)`. Returns the list of functions which were traced.
+pub fn trace_untrace(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let names = match function_names(env, expr)? {
+        names if names.is_empty() => env.traced_functions(),
+        names => names,
+    };
+    Ok(names
+        .into_iter()
+        .filter(|name| env.untrace(name))
+        .map(Expression::Symbol)
+        .collect::<Vec<_>>()
+        .into())
+}
+
+/// Print all calls nested at most `depth` deep, counting from the top level expression:
+/// `(step-mode depth)`, or stop with `(step-mode nil)`. Returns the previous depth.
+pub fn trace_step_mode(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [depth] = expr.try_into()?;
+    let depth = match eval(env, depth)? {
+        Expression::Nil => None,
+        depth => Some(i64::try_from(depth)?.max(0) as usize),
+    };
+    Ok(match env.set_step_depth(depth) {
+        Some(depth) => Expression::Integer(depth as i64),
+        None => Expression::Nil,
+    })
+}
+
+/// Adds the tracing builtins to the given environment layer.
+pub fn mk_trace(layer: &mut EnvironmentLayer) {
+    layer.set("trace".to_string(), Expression::Function(trace_trace));
+    layer.set("untrace".to_string(), Expression::Function(trace_untrace));
+    layer.set(
+        "step-mode".to_string(),
+        Expression::Function(trace_step_mode),
+    );
+}
+
+#[test]
+fn test_trace() {
+    use super::eval_source;
+
+    let env = Environment::default();
+    let output = |source: &str| {
+        let (result, output) = env.capture_output(|| eval_source(&env, source));
+        result.unwrap();
+        output
+    };
+
+    output("(defun fact (n) (if (< n 1) 1 (* n (fact (- n 1)))))");
+    assert_eq!(output("(trace 'fact) (trace)"), "");
+    assert_eq!(
+        output("(fact 2)"),
+        "> (fact 2)\n  > (fact 1)\n    > (fact 0)\n    < 1\n  < 1\n< 2\n"
+    );
+    assert_eq!(
+        eval_source(&env, "(untrace 'fact) (untrace)"),
+        Ok(vec![
+            vec![Expression::Symbol("fact".to_string())].into(),
+            Expression::Nil
+        ])
+    );
+    assert_eq!(output("(fact 2)"), "");
+
+    // Builtins are shown with their arguments as written
+    env.set_step_depth(Some(2));
+    assert_eq!(
+        output("(fact 0)"),
+        "> (fact 0)\n  > (if (< n 1) 1 (* n (fact ...)))\n  < 1\n< 1\n"
+    );
+    assert_eq!(output("(step-mode nil)"), "> (step-mode nil)\n< 2\n");
+    assert_eq!(
+        output("(trace 'car) (catch (car 1) (lambda (c) nil))"),
+        "> (car 1)\n! Type error: Expression must be a Cell\n"
+    );
+}
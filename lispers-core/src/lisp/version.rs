@@ -0,0 +1,102 @@
+use super::environment::Environment;
+use super::eval::eval;
+use super::eval::EvalError;
+use super::eval::Warning;
+use super::expression::Expression;
+
+/// A `(major, minor)` version of the lisp dialect.
+pub type Version = (u32, u32);
+
+/// The current version of the lisp dialect, increased with changes that break existing scripts.
+pub const LANGUAGE_VERSION: Version = (0, 3);
+
+/// The symbol bound to the version declared by `lispers-version`.
+const VERSION_SYMBOL: &str = "LISPERS-VERSION";
+
+/// The incompatible changes of the dialect, with the version introducing them.
+const MIGRATIONS: [(Version, &str); 3] = [
+    (
+        (0, 2),
+        "`/` of integers returns a float unless the quotient is exact, use `quotient` for \
+         truncating division",
+    ),
+    (
+        (0, 2),
+        "`=`, `<` and `>` compare integers with floats by value",
+    ),
+    (
+        (0, 3),
+        "`load` evaluates a file, use `load-string` to evaluate a string of source code",
+    ),
+];
+
+/// Parse a `"major.minor"` version string, a patch version is ignored.
+pub fn parse_version(version: &str) -> Option<Version> {
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+    match (parts.next(), parts.next()) {
+        (Some(Some(major)), Some(Some(minor))) => Some((major, minor)),
+        (Some(Some(major)), None) => Some((major, 0)),
+        _ => None,
+    }
+}
+
+/// Get the dialect version of the running script, which is `LANGUAGE_VERSION` unless an older
+/// version was declared with `lispers-version`.
+pub fn language_version(env: &Environment) -> Version {
+    env.get(VERSION_SYMBOL)
+        .and_then(|v| String::try_from(v).ok())
+        .and_then(|v| parse_version(&v))
+        .map_or(LANGUAGE_VERSION, |v| v.min(LANGUAGE_VERSION))
+}
+
+/// Declare the dialect version a script was written for: `(lispers-version "0.1")`
+///
+/// Older scripts get a warning for each incompatible change since their version. They keep the
+/// old behavior of `/` and `load` (see `language_version`), but not of the comparisons. Scripts
+/// for a newer version than the interpreter get a warning, too. The declaration applies to the
+/// rest of the session.
+pub fn lispers_version(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [version] = expr.try_into()?;
+    let declared: String = eval(env, version)?.try_into()?;
+    let version = parse_version(&declared)
+        .ok_or_else(|| EvalError::ArgumentError(format!("Invalid version {:?}", declared)))?;
+
+    if version > LANGUAGE_VERSION {
+        env.warn(Warning::new(format!(
+            "script is written for lispers {}.{}, but the interpreter supports {}.{}",
+            version.0, version.1, LANGUAGE_VERSION.0, LANGUAGE_VERSION.1
+        )));
+    }
+    for (since, change) in MIGRATIONS.iter().filter(|(since, _)| *since > version) {
+        env.warn(Warning::new(format!(
+            "since lispers {}.{}, {}",
+            since.0, since.1, change
+        )));
+    }
+
+    env.shared_set(VERSION_SYMBOL.to_string(), declared.clone().into());
+    Ok(declared.into())
+}
+
+#[test]
+fn test_lispers_version() {
+    use super::prelude::prelude_div;
+
+    let env = Environment::default();
+    let div = |a: i64, b: i64| prelude_div(&env, [a.into(), b.into()].into());
+
+    assert_eq!(parse_version("0.3"), Some((0, 3)));
+    assert_eq!(parse_version("1.2.3"), Some((1, 2)));
+    assert_eq!(parse_version("v1"), None);
+    assert_eq!(language_version(&env), LANGUAGE_VERSION);
+    assert_eq!(div(7, 2), Ok(Expression::Float(3.5)));
+
+    lispers_version(&env, ["0.1".to_string().into()].into()).unwrap();
+    assert_eq!(env.take_warnings().len(), MIGRATIONS.len());
+    assert_eq!(language_version(&env), (0, 1));
+    assert_eq!(div(7, 2), Ok(Expression::Integer(3)));
+    assert!(matches!(
+        lispers_version(&env, ["latest".to_string().into()].into()),
+        Err(EvalError::ArgumentError(_))
+    ));
+}
@@ -1,6 +1,8 @@
+#[allow(clippy::module_inception)]
 pub mod parser;
 pub mod token;
 pub mod tokenizer;
 
 pub use parser::ExpressionStream;
 pub use parser::ParserError;
+pub use parser::Spans;
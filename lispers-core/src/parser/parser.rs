@@ -1,14 +1,20 @@
+use super::token::Position;
 use super::token::Token;
 use super::tokenizer::tokenize;
+use super::tokenizer::PositionedTokenStream;
 use super::tokenizer::TokenStream;
 use super::tokenizer::TokenizerError;
 use crate::lisp::Expression;
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::iter::Peekable;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+/// Errors of parsing expressions from tokens.
 pub enum ParserError {
-    UnexpectedToken(Token),
+    UnexpectedToken(Token, Position),
     TokenizerError(TokenizerError),
     UnexpectedEndOfInput,
 }
@@ -23,47 +29,91 @@ impl Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             ParserError::TokenizerError(t) => write!(f, "Tokenizer Error: {}", t),
-            ParserError::UnexpectedToken(t) => write!(f, "Unexpecte Token: {}", t),
+            ParserError::UnexpectedToken(t, p) => write!(f, "Unexpected Token at {}: {}", p, t),
             ParserError::UnexpectedEndOfInput => write!(f, "Unexpected end of input."),
         }
     }
 }
 
-fn parse_list<I>(stream: &mut Peekable<TokenStream<I>>) -> Result<Expression, ParserError>
+impl std::error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParserError::TokenizerError(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// The source positions of the lists of parsed expressions. A list is identified by the address
+/// of its first element (see `Spans::key`), which stays the same as long as the parsed
+/// expression is kept, also in copies of it and in lambdas defined by it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spans(HashMap<usize, Position>);
+
+impl Spans {
+    /// Get the key of the list whose first element is `head`.
+    pub fn key(head: &Arc<Expression>) -> usize {
+        Arc::as_ptr(head) as usize
+    }
+
+    /// Get the position of the list with the given key.
+    pub fn get(&self, key: usize) -> Option<Position> {
+        self.0.get(&key).copied()
+    }
+
+    fn insert(&mut self, list: &Expression, position: Position) {
+        if let Expression::Cell(head, _) = list {
+            self.0.insert(Self::key(head), position);
+        }
+    }
+}
+
+type Tokens<I> = Peekable<PositionedTokenStream<I>>;
+
+fn parse_list<I>(
+    stream: &mut Tokens<I>,
+    spans: &mut Spans,
+    start: Position,
+) -> Result<Expression, ParserError>
 where
     I: Iterator<Item = char>,
 {
-    let mut list = Vec::new();
+    let mut list: Vec<Expression> = Vec::new();
 
     loop {
         match stream.peek() {
             // Return current list or nil
-            Some(Ok(Token::ParClose)) => {
+            Some((_, Ok(Token::ParClose))) => {
                 stream.next();
-                if list.len() == 0 {
+                if list.is_empty() {
                     return Ok(Expression::Nil);
                 } else {
-                    return Ok(list.into());
+                    let list = list.into();
+                    spans.insert(&list, start);
+                    return Ok(list);
                 }
             }
             // Switch to cons-pair parsing
-            Some(Ok(Token::Dot)) => {
+            Some((pos, Ok(Token::Dot))) => {
+                let pos = *pos;
                 stream.next();
-                if list.len() > 1 || list.len() == 0 {
-                    return Err(ParserError::UnexpectedToken(Token::Dot));
+                if list.len() != 1 {
+                    return Err(ParserError::UnexpectedToken(Token::Dot, pos));
                 } else {
-                    let second_expr = parse_expression(stream)?;
+                    let second_expr = parse_expression(stream, spans)?;
                     match stream.next() {
-                        Some(Ok(Token::ParClose)) => {
-                            return Ok(Expression::Cell(
-                                Box::new(list[0].to_owned()),
-                                Box::new(second_expr),
-                            ));
+                        Some((_, Ok(Token::ParClose))) => {
+                            let pair = Expression::Cell(
+                                Arc::new(list[0].to_owned()),
+                                Arc::new(second_expr),
+                            );
+                            spans.insert(&pair, start);
+                            return Ok(pair);
                         }
-                        Some(Ok(t)) => {
-                            return Err(ParserError::UnexpectedToken(t));
+                        Some((pos, Ok(t))) => {
+                            return Err(ParserError::UnexpectedToken(t, pos));
                         }
-                        Some(Err(e)) => {
+                        Some((_, Err(e))) => {
                             return Err(e.into());
                         }
                         None => {
@@ -74,44 +124,65 @@ where
             }
             _ => {}
         }
-        list.push(parse_expression(stream)?);
+        list.push(parse_expression(stream, spans)?);
     }
 }
 
-fn parse_expression<I>(stream: &mut Peekable<TokenStream<I>>) -> Result<Expression, ParserError>
+fn parse_expression<I>(stream: &mut Tokens<I>, spans: &mut Spans) -> Result<Expression, ParserError>
 where
     I: Iterator<Item = char>,
 {
     match stream.next() {
-        Some(Ok(Token::ParOpen)) => parse_list(stream),
-        Some(Ok(Token::Nil)) => Ok(Expression::Nil),
-        Some(Ok(Token::IntLiteral(n))) => Ok(Expression::Integer(n)),
-        Some(Ok(Token::FloatLiteral(f))) => Ok(Expression::Float(f)),
-        Some(Ok(Token::StringLiteral(s))) => Ok(Expression::String(s)),
-        Some(Ok(Token::True)) => Ok(Expression::True),
-        Some(Ok(Token::Symbol(s))) => Ok(Expression::Symbol(s)),
-        Some(Ok(Token::Quote)) => Ok(Expression::Quote(Box::new(parse_expression(stream)?))),
-        Some(Err(e)) => Err(ParserError::TokenizerError(e)),
-        Some(Ok(x)) => Err(ParserError::UnexpectedToken(x)),
+        Some((pos, Ok(Token::ParOpen))) => parse_list(stream, spans, pos),
+        Some((_, Ok(Token::Nil))) => Ok(Expression::Nil),
+        Some((_, Ok(Token::IntLiteral(n)))) => Ok(Expression::Integer(n)),
+        Some((_, Ok(Token::BigIntLiteral(n)))) => Ok(Expression::BigInt(n)),
+        Some((_, Ok(Token::FloatLiteral(f)))) => Ok(Expression::Float(f)),
+        Some((_, Ok(Token::StringLiteral(s)))) => Ok(Expression::String(s)),
+        Some((_, Ok(Token::True))) => Ok(Expression::True),
+        Some((_, Ok(Token::False))) => Ok(Expression::False),
+        Some((_, Ok(Token::Symbol(s)))) => Ok(Expression::Symbol(s)),
+        Some((_, Ok(Token::Quote))) => Ok(Expression::Quote(Arc::new(parse_expression(
+            stream, spans,
+        )?))),
+        Some((_, Err(e))) => Err(ParserError::TokenizerError(e)),
+        Some((pos, Ok(x))) => Err(ParserError::UnexpectedToken(x, pos)),
         None => Err(ParserError::UnexpectedEndOfInput),
     }
 }
 
 pub struct ExpressionStream<I: Iterator<Item = char>> {
-    token_stream: Peekable<TokenStream<I>>,
+    token_stream: Tokens<I>,
+    spans: Spans,
 }
 
 impl<I: Iterator<Item = char>> ExpressionStream<I> {
     pub fn from_token_stream(token_stream: TokenStream<I>) -> Self {
         ExpressionStream {
-            token_stream: token_stream.peekable(),
+            token_stream: token_stream.positioned().peekable(),
+            spans: Spans::default(),
         }
     }
 
     pub fn from_char_stream(char_stream: I) -> Self {
-        ExpressionStream {
-            token_stream: tokenize(char_stream).peekable(),
-        }
+        Self::from_token_stream(tokenize(char_stream))
+    }
+
+    /// Like `next`, but additionally returns the source position where the expression starts.
+    pub fn next_positioned(&mut self) -> Option<(Position, Result<Expression, ParserError>)> {
+        let (pos, _) = self.token_stream.peek()?;
+        let pos = *pos;
+
+        Some((
+            pos,
+            parse_expression(&mut self.token_stream, &mut self.spans),
+        ))
+    }
+
+    /// Get the positions of the lists parsed so far. They are only valid while the parsed
+    /// expressions are kept, otherwise another list may take the address of a dropped one.
+    pub fn spans(&self) -> &Spans {
+        &self.spans
     }
 }
 
@@ -119,11 +190,7 @@ impl<I: Iterator<Item = char>> Iterator for ExpressionStream<I> {
     type Item = Result<Expression, ParserError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.token_stream.peek() == None {
-            return None;
-        }
-
-        Some(parse_expression(&mut self.token_stream))
+        self.next_positioned().map(|(_, e)| e)
     }
 }
 
@@ -149,8 +216,8 @@ fn test_parser() {
             ]
             .into(),
             Expression::Cell(
-                Box::new(Expression::Integer(1)),
-                Box::new(Expression::Integer(2)),
+                Arc::new(Expression::Integer(1)),
+                Arc::new(Expression::Integer(2)),
             ),
             vec![
                 Expression::Integer(1),
@@ -159,7 +226,7 @@ fn test_parser() {
             ]
             .into(),
             Expression::String("test".to_string()),
-            Expression::Quote(Box::new(
+            Expression::Quote(Arc::new(
                 vec![
                     Expression::Symbol("a".to_string()),
                     Expression::Symbol("b".to_string()),
@@ -172,3 +239,44 @@ fn test_parser() {
         ])
     );
 }
+
+#[test]
+fn test_parser_positions() {
+    let mut es = ExpressionStream::from_char_stream("(a b)\n  (c . d e)".chars());
+
+    let (pos, expr) = es.next_positioned().unwrap();
+    assert_eq!(pos, Position { line: 1, col: 1 });
+    assert!(expr.is_ok());
+
+    let (pos, expr) = es.next_positioned().unwrap();
+    assert_eq!(pos, Position { line: 2, col: 3 });
+    assert_eq!(
+        expr,
+        Err(ParserError::UnexpectedToken(
+            Token::Symbol("e".to_string()),
+            Position { line: 2, col: 10 }
+        ))
+    );
+
+    // Nested lists have positions, too
+    let mut es = ExpressionStream::from_char_stream("(a\n (b (c)) '(d . e))".chars());
+    let Some((_, Ok(Expression::Cell(a, rest)))) = es.next_positioned() else {
+        panic!("Expected a list");
+    };
+    let [_, b, quoted]: [Expression; 3] = Expression::Cell(a.clone(), rest).try_into().unwrap();
+    let key = |list: &Expression| match list {
+        Expression::Cell(head, _) => Spans::key(head),
+        Expression::Quote(quoted) => match quoted.as_ref() {
+            Expression::Cell(head, _) => Spans::key(head),
+            _ => panic!("Expected a quoted list"),
+        },
+        _ => panic!("Expected a list"),
+    };
+    let spans = es.spans();
+    assert_eq!(
+        spans.get(Spans::key(&a)),
+        Some(Position { line: 1, col: 1 })
+    );
+    assert_eq!(spans.get(key(&b)), Some(Position { line: 2, col: 2 }));
+    assert_eq!(spans.get(key(&quoted)), Some(Position { line: 2, col: 11 }));
+}
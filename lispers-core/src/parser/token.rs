@@ -1,11 +1,17 @@
 use std::fmt::Display;
 
+use num_bigint::BigInt;
+
 #[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
 /// Sum type of different tokens
 pub enum Token {
     FloatLiteral(f64),
     IntLiteral(i64),
+    /// An integer literal which does not fit into an i64
+    BigIntLiteral(BigInt),
     Dot,
+    False,
     Nil,
     ParClose,
     ParOpen,
@@ -20,7 +26,9 @@ impl Display for Token {
         match self {
             Token::FloatLiteral(x) => write!(f, "{}", x),
             Token::IntLiteral(x) => write!(f, "{}", x),
+            Token::BigIntLiteral(x) => write!(f, "{}", x),
             Token::Dot => write!(f, "."),
+            Token::False => write!(f, "false"),
             Token::Nil => write!(f, "nil"),
             Token::ParClose => write!(f, ")"),
             Token::ParOpen => write!(f, "("),
@@ -31,3 +39,55 @@ impl Display for Token {
         }
     }
 }
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+/// A token with its location in the source text, as yielded by `tokenize_spanned`.
+/// Offsets count characters from the start of the source.
+pub struct SpannedToken {
+    pub token: Token,
+    /// Offset of the first character of the token.
+    pub start: usize,
+    /// Offset after the last character of the token.
+    pub end: usize,
+    /// Line of the first character, starting at 1.
+    pub line: usize,
+    /// Column of the first character, starting at 1.
+    pub col: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+/// A position in the source text. Lines and columns start at 1.
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    /// Create a position from a line and a column, both starting at 1.
+    pub fn new(line: usize, col: usize) -> Self {
+        Position { line, col }
+    }
+
+    /// The position of the first character of a source text.
+    pub fn start() -> Self {
+        Position { line: 1, col: 1 }
+    }
+
+    /// Advance the position past the character `c`.
+    pub fn advance(&mut self, c: char) {
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, col {}", self.line, self.col)
+    }
+}
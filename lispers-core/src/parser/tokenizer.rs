@@ -1,22 +1,29 @@
 use std::fmt::Display;
 
+use super::token::Position;
+use super::token::SpannedToken;
 use super::token::Token;
 
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 /// Errors the tokenizer can yield.
 pub enum TokenizerError {
-    /// The tokenizer could not read the associated sequence.
-    UnmatchedSequence(String),
+    /// The tokenizer could not read the associated sequence starting at the given position.
+    UnmatchedSequence(String, Position),
 }
 
 impl Display for TokenizerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TokenizerError::UnmatchedSequence(s) => write!(f, "Unmatched sequence: {}", s),
+            TokenizerError::UnmatchedSequence(s, p) => {
+                write!(f, "Unmatched sequence at {}: {}", p, s)
+            }
         }
     }
 }
 
+impl std::error::Error for TokenizerError {}
+
 /// A reader used to wrap the `TokenStream`.
 /// When reading, it starts with the staging buffer of the stream, once
 /// it's end is reached, the input stream is copied character wise to
@@ -69,6 +76,10 @@ pub struct TokenStream<InputStream> {
     staging: Vec<char>,
     input: InputStream,
     error: bool,
+    /// Position of the first character in the staging buffer
+    position: Position,
+    /// Character offset of the first character in the staging buffer
+    offset: usize,
 }
 
 impl<I> TokenStream<I>
@@ -80,13 +91,22 @@ where
             staging: Vec::new(),
             input,
             error: false,
+            position: Position::start(),
+            offset: 0,
         }
     }
 
+    /// Convert into an iterator yielding each token with its start position.
+    pub fn positioned(self) -> PositionedTokenStream<I> {
+        PositionedTokenStream(self)
+    }
+
     fn skip_whitespace(&mut self) {
         // Drop whitespace of the staging buffer
         while let Some(c) = self.staging.first() {
             if c.is_whitespace() {
+                self.position.advance(*c);
+                self.offset += 1;
                 self.staging.remove(0);
             } else {
                 return; // Readable character next, keep input untouched
@@ -94,14 +114,31 @@ where
         }
 
         // Staging buffer is empty, drop whitespace from input
-        while let Some(c) = self.input.next() {
+        for c in self.input.by_ref() {
             if !c.is_whitespace() {
                 self.staging.push(c);
                 return;
             }
+            self.position.advance(c);
+            self.offset += 1;
         }
     }
 
+    /// Drop a comment at the start of the staging buffer. Returns whether a comment was dropped.
+    fn skip_comment(&mut self) -> bool {
+        let mut reader = StagingReader::new(self);
+        if scan_comment(&mut reader).is_none() {
+            return false;
+        }
+
+        let n_read = reader.head;
+        for c in self.staging.drain(0..n_read) {
+            self.position.advance(c);
+        }
+        self.offset += n_read;
+        true
+    }
+
     fn run_scanners(&mut self) -> Option<(Token, usize)> {
         let scanners = [
             scan_symbol,
@@ -109,6 +146,7 @@ where
             scan_integer,
             scan_float,
             scan_true,
+            scan_false,
             scan_quote,
             scan_dot,
             scan_nil,
@@ -138,28 +176,105 @@ where
     /// stream has still elements an error is returned. Each successive call to
     /// `next` will then return `None`.
     fn next(&mut self) -> Option<Self::Item> {
+        self.next_positioned().map(|(_, t)| t)
+    }
+}
+
+impl<I> TokenStream<I>
+where
+    I: Iterator<Item = char>,
+{
+    /// Like `next`, but additionally returns the start position of the token.
+    fn next_positioned(&mut self) -> Option<(Position, Result<Token, TokenizerError>)> {
+        Some(match self.next_spanned()? {
+            Ok(t) => (
+                Position {
+                    line: t.line,
+                    col: t.col,
+                },
+                Ok(t.token),
+            ),
+            Err(TokenizerError::UnmatchedSequence(s, p)) => {
+                (p, Err(TokenizerError::UnmatchedSequence(s, p)))
+            }
+        })
+    }
+
+    /// Like `next`, but returns the token with its span.
+    fn next_spanned(&mut self) -> Option<Result<SpannedToken, TokenizerError>> {
         if self.error {
             return None;
         }
 
         self.skip_whitespace();
+        while self.skip_comment() {
+            self.skip_whitespace();
+        }
+        let start = self.position;
+        let start_offset = self.offset;
 
         match self.run_scanners() {
             Some((tkn, n_read)) => {
-                self.staging.drain(0..n_read);
-                Some(Ok(tkn))
+                for c in self.staging.drain(0..n_read) {
+                    self.position.advance(c);
+                }
+                self.offset += n_read;
+                Some(Ok(SpannedToken {
+                    token: tkn,
+                    start: start_offset,
+                    end: self.offset,
+                    line: start.line,
+                    col: start.col,
+                }))
             }
             None if self.staging.is_empty() => None,
             None => {
                 let remaining = self.staging.iter().collect();
                 self.staging.clear();
                 self.error = true;
-                Some(Err(TokenizerError::UnmatchedSequence(remaining)))
+                Some(Err(TokenizerError::UnmatchedSequence(remaining, start)))
             }
         }
     }
 }
 
+/// A `TokenStream` yielding each token together with its start position.
+pub struct PositionedTokenStream<I>(TokenStream<I>);
+
+impl<I> Iterator for PositionedTokenStream<I>
+where
+    I: Iterator<Item = char>,
+{
+    type Item = (Position, Result<Token, TokenizerError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_positioned()
+    }
+}
+
+/// A `TokenStream` yielding each token with its span, see `tokenize_spanned`.
+pub struct SpannedTokenStream<I>(TokenStream<I>);
+
+impl<I> Iterator for SpannedTokenStream<I>
+where
+    I: Iterator<Item = char>,
+{
+    type Item = Result<SpannedToken, TokenizerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_spanned()
+    }
+}
+
+/// Run the tokenizer on an iterator of chars and return an iterator of tokens with their
+/// location in the source, e.g. for syntax highlighting.
+pub fn tokenize_spanned<I>(input: I) -> SpannedTokenStream<I>
+where
+    I: Iterator<Item = char>,
+{
+    SpannedTokenStream(TokenStream::new(input))
+}
+
 /// Run the tokenizer on an iterator of chars and return an
 /// iterator of tokens as a result.
 pub fn tokenize<I>(input: I) -> TokenStream<I>
@@ -210,6 +325,51 @@ where
     }
 }
 
+/// Scan a `; line comment` or a (possibly nested) `#| block comment |#`.
+fn scan_comment<I>(reader: &mut StagingReader<I>) -> Option<()>
+where
+    I: Iterator<Item = char>,
+{
+    match reader.next()? {
+        ';' => {
+            for c in reader {
+                if c == '\n' {
+                    break;
+                }
+            }
+            Some(())
+        }
+        '#' => {
+            if reader.next()? != '|' {
+                return None;
+            }
+
+            let mut depth = 1;
+            let mut last = ' ';
+            for c in reader {
+                match (last, c) {
+                    ('|', '#') => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(());
+                        }
+                        last = ' ';
+                    }
+                    ('#', '|') => {
+                        depth += 1;
+                        last = ' ';
+                    }
+                    _ => last = c,
+                }
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Scan a string literal, in which `\"` and `\\` escape a quote and a backslash. Other
+/// backslashes are kept as they are.
 fn scan_string_literal<I>(reader: &mut StagingReader<I>) -> Option<Token>
 where
     I: Iterator<Item = char>,
@@ -217,11 +377,18 @@ where
     let mut lit = String::new();
 
     if reader.next()? == '"' {
-        for c in reader {
+        while let Some(c) = reader.next() {
             match c {
                 '"' => {
                     return Some(Token::StringLiteral(lit));
                 }
+                '\\' => match reader.next()? {
+                    c @ ('"' | '\\') => lit.push(c),
+                    c => {
+                        lit.push('\\');
+                        lit.push(c);
+                    }
+                },
                 c => {
                     lit.push(c);
                 }
@@ -229,7 +396,7 @@ where
         }
     }
 
-    return None;
+    None
 }
 
 fn scan_nil<I>(reader: &mut StagingReader<I>) -> Option<Token>
@@ -265,7 +432,7 @@ where
     // Allow some special chars and alphanumeric
     while let Some(c) = reader.next() {
         match c {
-            '_' | '-' | '<' | '>' | '=' | '*' | '/' | '+' | '%' | '!' | '?' => sym.push(c),
+            '_' | '-' | '<' | '>' | '=' | '*' | '/' | '+' | '%' | '!' | '?' | ':' => sym.push(c),
             c if c.is_ascii_alphanumeric() => sym.push(c),
             _ => {
                 reader.step_back(1);
@@ -274,13 +441,30 @@ where
         }
     }
 
-    if sym.len() > 0 {
+    if !sym.is_empty() {
         Some(Token::Symbol(sym))
     } else {
         None
     }
 }
 
+fn scan_false<I>(reader: &mut StagingReader<I>) -> Option<Token>
+where
+    I: Iterator<Item = char>,
+{
+    if reader.next()? == 'f'
+        && reader.next()? == 'a'
+        && reader.next()? == 'l'
+        && reader.next()? == 's'
+        && reader.next()? == 'e'
+    {
+        Some(Token::False)
+    } else {
+        reader.step_back(5);
+        None
+    }
+}
+
 fn scan_true<I>(reader: &mut StagingReader<I>) -> Option<Token>
 where
     I: Iterator<Item = char>,
@@ -304,9 +488,7 @@ where
     let mut buf = String::new();
 
     while let Some(c) = reader.next() {
-        if buf.is_empty() && c == '-' {
-            buf.push(c);
-        } else if c.is_ascii_digit() {
+        if (buf.is_empty() && c == '-') || c.is_ascii_digit() {
             buf.push(c);
         } else {
             reader.step_back(1);
@@ -314,8 +496,11 @@ where
         }
     }
 
-    if buf.len() > 0 {
-        buf.parse().map(Token::IntLiteral).ok()
+    if !buf.is_empty() {
+        buf.parse()
+            .map(Token::IntLiteral)
+            .or_else(|_| buf.parse().map(Token::BigIntLiteral))
+            .ok()
     } else {
         None
     }
@@ -329,9 +514,7 @@ where
     let mut has_dot = false;
 
     while let Some(c) = reader.next() {
-        if buf.is_empty() && c == '-' {
-            buf.push(c);
-        } else if c.is_ascii_digit() {
+        if (buf.is_empty() && c == '-') || c.is_ascii_digit() {
             buf.push(c);
         } else if c == '.' && !has_dot {
             buf.push(c);
@@ -342,7 +525,7 @@ where
         }
     }
 
-    if buf.len() > 0 && has_dot {
+    if !buf.is_empty() && has_dot {
         buf.parse().map(Token::FloatLiteral).ok()
     } else {
         None
@@ -350,6 +533,7 @@ where
 }
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn test_tokenize() {
     let test_str =
         "(\"abcdefg( )123\" )(\n\t 'nil true \"true\")00987463 123.125 -20 -3.14 . 0+-*/go=";
@@ -382,3 +566,92 @@ fn test_tokenize() {
         Token::Symbol("0+-*/go=".to_string())
     );
 }
+
+#[test]
+fn test_tokenize_keywords() {
+    let tokens: Vec<_> = tokenize(":overwrite (:a b:c) ':q".chars())
+        .map(|t| t.unwrap())
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Symbol(":overwrite".to_string()),
+            Token::ParOpen,
+            Token::Symbol(":a".to_string()),
+            Token::Symbol("b:c".to_string()),
+            Token::ParClose,
+            Token::Quote,
+            Token::Symbol(":q".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_token_positions() {
+    let tokens: Vec<_> = tokenize("(a\n  \"b c\" 12)\n'x".chars())
+        .positioned()
+        .map(|(p, t)| (p.line, p.col, t.unwrap()))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            (1, 1, Token::ParOpen),
+            (1, 2, Token::Symbol("a".to_string())),
+            (2, 3, Token::StringLiteral("b c".to_string())),
+            (2, 9, Token::IntLiteral(12)),
+            (2, 11, Token::ParClose),
+            (3, 1, Token::Quote),
+            (3, 2, Token::Symbol("x".to_string())),
+        ]
+    );
+
+    let (_, err) = tokenize("(a\n #".chars()).positioned().last().unwrap();
+    assert_eq!(
+        err,
+        Err(TokenizerError::UnmatchedSequence(
+            "#".to_string(),
+            Position { line: 2, col: 2 }
+        ))
+    );
+}
+
+#[test]
+fn test_tokenize_comments() {
+    let input = "; leading comment\n(a ; trailing comment\n #| block #| nested |# |# b)\n; last";
+    let tokens: Vec<_> = tokenize(input.chars())
+        .positioned()
+        .map(|(p, t)| (p.line, p.col, t.unwrap()))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![
+            (2, 1, Token::ParOpen),
+            (2, 2, Token::Symbol("a".to_string())),
+            (3, 27, Token::Symbol("b".to_string())),
+            (3, 28, Token::ParClose),
+        ]
+    );
+
+    let tokens: Vec<_> = tokenize("\"a ; b\" #| open".chars()).collect();
+    assert_eq!(tokens[0], Ok(Token::StringLiteral("a ; b".to_string())));
+    assert!(matches!(
+        tokens[1],
+        Err(TokenizerError::UnmatchedSequence(_, _))
+    ));
+}
+
+#[test]
+fn test_tokenize_spanned() {
+    let tokens: Vec<_> = tokenize_spanned("(ab ; c\n \"d\")".chars())
+        .map(|t| t.unwrap())
+        .map(|t| (t.start, t.end, t.line, t.col))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        vec![(0, 1, 1, 1), (1, 3, 1, 2), (9, 12, 2, 2), (12, 13, 2, 5)]
+    );
+}
@@ -1,11 +1,17 @@
 extern crate proc_macro;
+use lispers_core::lisp::Expression;
+use lispers_core::parser::ExpressionStream;
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, punctuated::Punctuated, FnArg, Ident, ItemFn, Pat, PatType, Token};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, Data, DeriveInput, Fields, FnArg, Ident, ItemFn,
+    LitStr, Pat, PatType, ReturnType, Signature, Token, Type,
+};
 
 enum FlagOrKV {
     Flag(Ident),
     KV(Ident, Ident),
+    KVStr(Ident, LitStr),
 }
 
 impl syn::parse::Parse for FlagOrKV {
@@ -13,17 +19,127 @@ impl syn::parse::Parse for FlagOrKV {
         let ident: Ident = input.parse()?;
         if input.peek(Token![=]) {
             input.parse::<Token![=]>()?;
-            let value: Ident = input.parse()?;
-            Ok(FlagOrKV::KV(ident, value))
+            if input.peek(LitStr) {
+                Ok(FlagOrKV::KVStr(ident, input.parse()?))
+            } else {
+                Ok(FlagOrKV::KV(ident, input.parse()?))
+            }
         } else {
             Ok(FlagOrKV::Flag(ident))
         }
     }
 }
 
+#[derive(Default)]
+/// The `module` and `name` keys, registering a native function for the `mk_*` function
+/// generated by `collect_module!`.
+struct Registration {
+    pub module: Option<Ident>,
+    pub name: Option<LitStr>,
+}
+
+impl Registration {
+    /// Take a `module = ident` or `name = "symbol"` key, returning other keys.
+    fn parse_key(&mut self, e: FlagOrKV) -> Option<FlagOrKV> {
+        match e {
+            FlagOrKV::KV(k, v) if k == "module" => self.module = Some(v),
+            FlagOrKV::KVStr(k, v) if k == "name" => self.name = Some(v),
+            e => return Some(e),
+        }
+        None
+    }
+
+    /// Register `function` for its module, bound to `name` or its own name in kebab case.
+    fn submit(&self, function: &Ident) -> syn::Result<proc_macro2::TokenStream> {
+        let Some(module) = &self.module else {
+            return match &self.name {
+                Some(name) => Err(syn::Error::new_spanned(
+                    name,
+                    "a `name` requires a `module` to register the function in",
+                )),
+                None => Ok(quote! {}),
+            };
+        };
+        let module = module.to_string();
+        let name = match &self.name {
+            Some(name) => name.value(),
+            None => kebab_case(&function.to_string()),
+        };
+        Ok(quote! {
+            ::lispers_core::lisp::registry::inventory::submit! {
+                ::lispers_core::lisp::registry::NativeRegistration {
+                    module: #module,
+                    name: #name,
+                    function: #function,
+                }
+            }
+        })
+    }
+}
+
+/// Get the doc comment of an item, without the space following each `///`.
+fn doc_comment(attrs: &[syn::Attribute]) -> String {
+    attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(doc),
+                        ..
+                    }),
+                ..
+            }) => Some(doc.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').unwrap_or(&line).to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Record the doc comment and parameters of a native function for the `doc` and `arity`
+/// builtins. The `parameters` are the names, the number of required ones and whether the last
+/// one is variadic, if the macro converts the arguments.
+fn function_meta(
+    function: &Ident,
+    doc: &str,
+    parameters: Option<(Vec<String>, usize, bool)>,
+    overloads: &[Ident],
+) -> proc_macro2::TokenStream {
+    let (parameters, required, variadic) = match parameters {
+        Some((names, required, variadic)) => (quote! { Some(&[#(#names),*]) }, required, variadic),
+        None => (quote! { None }, 0, false),
+    };
+    quote! {
+        ::lispers_core::lisp::registry::inventory::submit! {
+            ::lispers_core::lisp::registry::FunctionMeta {
+                function: #function,
+                doc: #doc,
+                parameters: #parameters,
+                required: #required,
+                variadic: #variadic,
+                overloads: &[#(#overloads),*],
+            }
+        }
+    }
+}
+
+/// Report a key which is not known, or a `name` or `module` of the wrong kind.
+fn unknown_key(e: FlagOrKV) -> syn::Error {
+    match e {
+        FlagOrKV::Flag(flag) => syn::Error::new_spanned(flag, "Unknown flag"),
+        FlagOrKV::KV(k, _) | FlagOrKV::KVStr(k, _) => syn::Error::new_spanned(k, "Unknown key"),
+    }
+}
+
 struct NativeLispAttrs {
     pub eval: bool,
+    pub strict: bool,
     pub fname: Option<Ident>,
+    pub registration: Registration,
 }
 
 impl syn::parse::Parse for NativeLispAttrs {
@@ -32,25 +148,20 @@ impl syn::parse::Parse for NativeLispAttrs {
 
         let mut ret = NativeLispAttrs {
             eval: false,
+            strict: false,
             fname: None,
+            registration: Registration::default(),
         };
 
-        for e in exprs {
+        for e in exprs
+            .into_iter()
+            .filter_map(|e| ret.registration.parse_key(e))
+        {
             match e {
-                FlagOrKV::Flag(flag) => {
-                    if flag.to_string() == "eval" {
-                        ret.eval = true;
-                    } else {
-                        return Err(syn::Error::new_spanned(flag, "Unknown flag"));
-                    }
-                }
-                FlagOrKV::KV(k, v) => {
-                    if k.to_string() == "fname" {
-                        ret.fname = Some(v);
-                    } else {
-                        return Err(syn::Error::new_spanned(k, "Unknown key"));
-                    }
-                }
+                FlagOrKV::Flag(flag) if flag == "eval" => ret.eval = true,
+                FlagOrKV::Flag(flag) if flag == "strict" => ret.strict = true,
+                FlagOrKV::KV(k, v) if k == "fname" => ret.fname = Some(v),
+                e => return Err(unknown_key(e)),
             }
         }
 
@@ -62,6 +173,8 @@ struct NativeLispProxyAttrs {
     pub eval: bool,
     pub fname: Ident,
     pub dispatcher: Vec<Ident>,
+    pub propagate_argument_errors: bool,
+    pub registration: Registration,
 }
 
 impl syn::parse::Parse for NativeLispProxyAttrs {
@@ -72,33 +185,187 @@ impl syn::parse::Parse for NativeLispProxyAttrs {
             eval: false,
             fname: Ident::new("proxy", proc_macro2::Span::call_site()),
             dispatcher: Vec::new(),
+            propagate_argument_errors: false,
+            registration: Registration::default(),
         };
 
-        for e in exprs {
+        for e in exprs
+            .into_iter()
+            .filter_map(|e| ret.registration.parse_key(e))
+        {
             match e {
-                FlagOrKV::Flag(flag) => {
-                    if flag.to_string() == "eval" {
-                        ret.eval = true;
-                    } else {
-                        return Err(syn::Error::new_spanned(flag, "Unknown flag"));
-                    }
-                }
-                FlagOrKV::KV(k, v) => {
-                    if k.to_string() == "dispatch" {
-                        ret.dispatcher.push(v);
-                    } else if k.to_string() == "fname" {
-                        ret.fname = v;
-                    } else {
-                        return Err(syn::Error::new_spanned(k, "Unknown key"));
-                    }
+                FlagOrKV::Flag(flag) if flag == "eval" => ret.eval = true,
+                FlagOrKV::Flag(flag) if flag == "propagate_argument_errors" => {
+                    ret.propagate_argument_errors = true
                 }
+                FlagOrKV::KV(k, v) if k == "dispatch" => ret.dispatcher.push(v),
+                FlagOrKV::KV(k, v) if k == "fname" => ret.fname = v,
+                e => return Err(unknown_key(e)),
             }
         }
 
+        if ret.dispatcher.is_empty() {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "expected at least one `dispatch = function`",
+            ));
+        }
+
         Ok(ret)
     }
 }
 
+/// Get the value of the `#[default(value)]` attribute of a native function argument.
+fn default_value(arg: &PatType) -> syn::Result<Option<syn::Expr>> {
+    arg.attrs
+        .iter()
+        .find(|a| a.path().is_ident("default"))
+        .map(|a| a.parse_args())
+        .transpose()
+}
+
+/// Check whether a type is named `name`, e.g. `Option` for arguments which may be left out.
+/// Like `returns_result`, this only looks at the name of the type.
+fn is_type(ty: &Type, name: &str) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == name),
+        _ => false,
+    }
+}
+
+/// The argument types converted through `Coerce`, accepting both Integers and Floats.
+const COERCED_TYPES: &[&str] = &["i32", "i64", "u32", "u64", "usize", "f32", "f64"];
+
+/// Get the type argument of a generic type, e.g. `T` of an `Option<T>` argument.
+fn type_argument(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    match &path.path.segments.last()?.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            syn::GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// Generate the conversion of the `Expression` `value` into an argument of type `ty`, giving a
+/// `Result` with an `EvalError`. Numeric types are converted through `Coerce`, unless `strict`.
+fn conversion(
+    ty: &Type,
+    value: proc_macro2::TokenStream,
+    strict: bool,
+) -> proc_macro2::TokenStream {
+    if !strict && COERCED_TYPES.iter().any(|name| is_type(ty, name)) {
+        quote! { ::lispers_core::Coerce::<#ty>::try_from(#value).map(|c| c.0) }
+    } else {
+        quote! { #value.try_into().map_err(Into::<EvalError>::into) }
+    }
+}
+
+/// Check that a function can be wrapped as a native lisp function, pointing an error at each
+/// unsupported part of the signature.
+fn check_signature(sig: &Signature) -> syn::Result<()> {
+    let mut errors = Vec::new();
+
+    if !sig.generics.params.is_empty() {
+        errors.push(syn::Error::new_spanned(
+            &sig.generics,
+            "native lisp functions cannot be generic",
+        ));
+    }
+    if let Some(asyncness) = &sig.asyncness {
+        errors.push(syn::Error::new_spanned(
+            asyncness,
+            "native lisp functions cannot be async",
+        ));
+    }
+    let mut optional = false;
+    for (i, arg) in sig.inputs.iter().enumerate() {
+        if let FnArg::Typed(arg) = arg {
+            let variadic = i + 1 == sig.inputs.len() && is_type(&arg.ty, "Vec");
+            match default_value(arg) {
+                Ok(default) if default.is_some() || variadic || is_type(&arg.ty, "Option") => {
+                    optional = true
+                }
+                Ok(_) if optional => errors.push(syn::Error::new_spanned(
+                    arg,
+                    "required arguments of native lisp functions cannot follow optional ones",
+                )),
+                Ok(_) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        match arg {
+            FnArg::Receiver(receiver) => errors.push(syn::Error::new_spanned(
+                receiver,
+                "native lisp functions cannot take `self`",
+            )),
+            FnArg::Typed(PatType { pat, .. }) if !matches!(pat.as_ref(), Pat::Ident(_)) => errors
+                .push(syn::Error::new_spanned(
+                    pat,
+                    "native lisp function arguments must be plain identifiers, destructure \
+                     them in the function body instead",
+                )),
+            FnArg::Typed(_) => {}
+        }
+    }
+    if let ReturnType::Default = sig.output {
+        errors.push(syn::Error::new_spanned(
+            &sig.ident,
+            "native lisp functions must return a value convertible into an `Expression`, or a \
+             `Result` of it",
+        ));
+    }
+
+    match errors.into_iter().reduce(|mut all, e| {
+        all.combine(e);
+        all
+    }) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Check whether a function returns a `Result`. Types are not known to the macro, so this
+/// only looks at the name of the returned type: an alias of `Result` is taken as a plain value.
+fn returns_result(ret: &ReturnType) -> bool {
+    match ret {
+        ReturnType::Type(_, ty) => is_type(ty, "Result"),
+        ReturnType::Default => false,
+    }
+}
+
+/// Check whether a function returns a `bool`, plain or in a `Result`.
+fn returns_bool(ret: &ReturnType) -> bool {
+    match ret {
+        ReturnType::Type(_, ty) if is_type(ty, "Result") => {
+            type_argument(ty).is_some_and(|ty| is_type(ty, "bool"))
+        }
+        ReturnType::Type(_, ty) => is_type(ty, "bool"),
+        ReturnType::Default => false,
+    }
+}
+
+/// Wrap a function as a native lisp function, converting each argument from an `Expression`
+/// (evaluated first with the `eval` flag). The function may return a plain value convertible
+/// into an `Expression`, or a `Result` of one whose error converts into an `EvalError`.
+///
+/// Trailing arguments may be left out: `Option` arguments are then `None`, as they are for a
+/// nil argument, and arguments with a `#[default(value)]` attribute get the value instead. A
+/// final `Vec` argument collects all remaining arguments.
+///
+/// Numeric arguments accept both Integers and Floats, see `Coerce`: an `i64` argument may be
+/// given as `1920.0`, but not as `1.5`. With the `strict` flag, they only accept their own kind
+/// of number, e.g. for an Integer overload of a `native_lisp_function_proxy!` which is tried
+/// before a Float one.
+///
+/// The doc comment and the parameter names are recorded for the `doc` and `arity` builtins.
 #[proc_macro_attribute]
 pub fn native_lisp_function(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Parse function
@@ -112,48 +379,134 @@ pub fn native_lisp_function(attr: TokenStream, item: TokenStream) -> TokenStream
     // Parse attrs
     let attr = parse_macro_input!(attr as NativeLispAttrs);
 
+    if let Err(e) = check_signature(sig) {
+        return e.to_compile_error().into();
+    }
+
     // Extract argument conversion statements
     let mut conversion_statements = Vec::new();
+    let mut parameters = Vec::new();
+    let mut required = 0;
+    let mut variadic = false;
 
-    for arg in &sig.inputs {
-        if let FnArg::Typed(PatType { pat, ty, .. }) = arg {
+    for (i, arg) in sig.inputs.iter().enumerate() {
+        if let FnArg::Typed(pat_type @ PatType { pat, ty, .. }) = arg {
             if let Pat::Ident(ident) = pat.as_ref() {
                 let arg_name_str = ident.ident.to_string();
-                if attr.eval {
-                    conversion_statements.push(quote! {
-                        let #ident: #ty = eval(env, args_iter.next().ok_or(EvalError::ArgumentError(format!("Missing Argument {}", #arg_name_str)))?)?.try_into()?;
-                    });
+                parameters.push(kebab_case(arg_name_str.trim_start_matches('_')));
+                let convert = if attr.eval {
+                    quote! { eval(env, arg)? }
                 } else {
+                    quote! { arg }
+                };
+                let default = default_value(pat_type).ok().flatten();
+                let element = type_argument(ty).unwrap_or(ty);
+                if i + 1 == sig.inputs.len() && is_type(ty, "Vec") {
+                    variadic = true;
+                    let element = conversion(element, convert, attr.strict);
                     conversion_statements.push(quote! {
-                        let #ident: #ty = args_iter.next().ok_or(EvalError::ArgumentError(format!("Missing Argument {}", #arg_name_str)))?.try_into()?;
+                        let #ident: #ty = args_iter
+                            .by_ref()
+                            .map(|arg| #element)
+                            .collect::<Result<_, _>>()?;
                     });
+                    continue;
                 }
+                let statement = match (is_type(ty, "Option"), default) {
+                    (true, default) => {
+                        let default = default.map_or(quote! { None }, |d| quote! { #d });
+                        let element = conversion(element, quote! { arg }, attr.strict);
+                        quote! {
+                            let #ident: #ty = match args_iter.next() {
+                                Some(arg) => match #convert {
+                                    Expression::Nil => #default,
+                                    arg => Some(#element?),
+                                },
+                                None => #default,
+                            };
+                        }
+                    }
+                    (false, Some(default)) => {
+                        let convert = conversion(ty, convert, attr.strict);
+                        quote! {
+                            let #ident: #ty = match args_iter.next() {
+                                Some(arg) => #convert?,
+                                None => #default,
+                            };
+                        }
+                    }
+                    (false, None) => {
+                        required += 1;
+                        let convert = conversion(ty, convert, attr.strict);
+                        quote! {
+                            let #ident: #ty = {
+                                let arg = args_iter.next().ok_or(EvalError::ArgumentError(format!("Missing Argument {}", #arg_name_str)))?;
+                                #convert?
+                            };
+                        }
+                    }
+                };
+                conversion_statements.push(statement);
             }
         }
     }
 
+    // Errors of the function only need to convert into an `EvalError`, plain values are wrapped
+    let value = if returns_result(ret) {
+        quote! { (|| #ret #block)().map_err(Into::<EvalError>::into)? }
+    } else {
+        quote! { (|| #ret #block)() }
+    };
+    // Booleans follow the configuration, so false is distinct from nil with `distinct_false`
+    let call = if returns_bool(ret) {
+        quote! { Ok(Expression::from_bool(#value, env.config().distinct_false)) }
+    } else {
+        quote! { Ok(#value.into()) }
+    };
+
     let func_name = match attr.fname {
         Some(fname) => fname,
         None => func_name.clone(),
     };
+    let registration = match attr.registration.submit(&func_name) {
+        Ok(registration) => registration,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let attrs = &input.attrs;
+    let meta = function_meta(
+        &func_name,
+        &doc_comment(attrs),
+        Some((parameters, required, variadic)),
+        &[],
+    );
 
     quote! {
+        #(#attrs)*
         #vis fn #func_name(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
             let args: Vec<Expression> = expr.try_into()?;
             let mut args_iter = args.into_iter();
 
             #(#conversion_statements)*
 
-            Ok((|| #ret #block)()?.into())
+            #call
         }
+
+        #registration
+        #meta
     }
     .into()
 }
 
+/// Generate the native function `fname`, calling the `dispatch` implementations in order until
+/// one does not fail with a type or argument error, e.g. for overloads on the argument types.
+/// If none applies, the error lists the call of each implementation with its error. With the
+/// `propagate_argument_errors` flag, an argument error of an implementation is returned instead
+/// of trying the next one.
 #[proc_macro]
 pub fn native_lisp_function_proxy(item: TokenStream) -> TokenStream {
     let args = parse_macro_input!(item as NativeLispProxyAttrs);
     let fname = &args.fname;
+    let fname_str = fname.to_string();
 
     let eval_statement = if args.eval {
         quote! {
@@ -169,25 +522,434 @@ pub fn native_lisp_function_proxy(item: TokenStream) -> TokenStream {
         .dispatcher
         .iter()
         .map(|impl_name| {
+            let impl_str = impl_name.to_string();
+            let pattern = if args.propagate_argument_errors {
+                quote! { e @ EvalError::TypeError(_) }
+            } else {
+                quote! { e @ (EvalError::ArgumentError(_) | EvalError::TypeError(_)) }
+            };
             quote! {
                 match #impl_name(env, expr.clone()) {
-                    Err(EvalError::ArgumentError(e)) => {/*Pass*/},
-                    Err(EvalError::TypeError(e)) => {/*Pass*/},
+                    Err(#pattern) => {
+                        // Try the next implementation, dropping the calls of the failed one
+                        env.take_backtrace();
+                        let call = ::lispers_core::lisp::registry::FunctionMeta::of(#impl_name)
+                            .and_then(|m| m.signature(#fname_str))
+                            .unwrap_or(#impl_str.to_string());
+                        errors.push(format!("\n  {}: {}", call, e));
+                    },
                     x => return x,
                 }
             }
         })
         .collect::<Vec<_>>();
 
-    let fname_str = fname.to_string();
+    let registration = match args.registration.submit(fname) {
+        Ok(registration) => registration,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let meta = function_meta(fname, "", None, &args.dispatcher);
+
     quote! {
         fn #fname(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
             #eval_statement
 
+            let mut errors = Vec::new();
             #(#try_apply_statements)*
 
-            Err(EvalError::TypeError(format!("Could not call {} with arguments {} ", #fname_str, expr).to_string()))
+            Err(EvalError::TypeError(format!(
+                "Could not call {} with arguments {}, tried:{}",
+                #fname_str,
+                expr,
+                errors.concat()
+            )))
         }
+
+        #registration
+        #meta
+    }
+    .into()
+}
+
+/// Register a hand-written native function, i.e. a `fn(&Environment, Expression) ->
+/// Result<Expression, EvalError>`, for the `mk_*` function generated by `collect_module!`:
+/// `#[register_lisp_function(module = raytrace, name = "+")]`. The function is bound to its own
+/// name in kebab case if no `name` is given.
+#[proc_macro_attribute]
+pub fn register_lisp_function(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemFn);
+    let exprs = parse_macro_input!(attr with Punctuated::<FlagOrKV, Token![,]>::parse_terminated);
+
+    let mut registration = Registration::default();
+    if let Some(e) = exprs.into_iter().find_map(|e| registration.parse_key(e)) {
+        return unknown_key(e).to_compile_error().into();
+    }
+    if registration.module.is_none() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "expected a `module = name` to register the function in",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let registration = match registration.submit(&input.sig.ident) {
+        Ok(registration) => registration,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let meta = function_meta(&input.sig.ident, &doc_comment(&input.attrs), None, &[]);
+
+    quote! {
+        #input
+
+        #registration
+        #meta
+    }
+    .into()
+}
+
+/// Generate the function `mk_<module>`, adding the native functions registered for the module
+/// with `#[native_lisp_function(module = ...)]`, `native_lisp_function_proxy!(module = ...)` or
+/// `#[register_lisp_function(module = ...)]` to an environment layer. Further registration
+/// functions, e.g. for constants, can follow the module and are called first:
+/// `collect_module!(raytrace, mk_raytrace_constants)`.
+#[proc_macro]
+pub fn collect_module(item: TokenStream) -> TokenStream {
+    let idents = parse_macro_input!(item with Punctuated::<syn::Path, Token![,]>::parse_terminated);
+    let mut idents = idents.into_iter();
+    let Some(module) = idents.next().and_then(|m| m.get_ident().cloned()) else {
+        return syn::Error::new(proc_macro2::Span::call_site(), "expected a module name")
+            .to_compile_error()
+            .into();
+    };
+    let extensions: Vec<_> = idents.collect();
+
+    let mk_name = Ident::new(&format!("mk_{}", module), module.span());
+    let module_str = module.to_string();
+    let doc = format!(
+        " Adds the native functions of the `{}` module to the given environment layer.",
+        module_str
+    );
+
+    quote! {
+        #[doc = #doc]
+        pub fn #mk_name(layer: &mut EnvironmentLayer) {
+            #(#extensions(layer);)*
+            ::lispers_core::lisp::registry::mk_module(layer, #module_str);
+        }
+    }
+    .into()
+}
+
+/// How a field of a record is converted from and into an `Expression`.
+enum RecordFieldKind {
+    /// With the `TryFrom<Expression>` and `Into<Expression>` impls of the field type.
+    Plain,
+    /// Wrapped in a `ForeignDataWrapper`, for foreign types without conversions.
+    Foreign,
+    /// Not exposed to lisp, the constructor uses `Default::default()`.
+    Skip,
+}
+
+/// Parse the `#[lisp_record(name = "...")]` attribute of a record.
+fn record_name(input: &DeriveInput) -> syn::Result<String> {
+    let mut name = kebab_case(&input.ident.to_string());
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("lisp_record"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("Unknown key"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+/// Parse the `#[lisp_record(foreign)]` or `#[lisp_record(skip)]` attribute of a record field.
+fn record_field_kind(field: &syn::Field) -> syn::Result<RecordFieldKind> {
+    let mut kind = RecordFieldKind::Plain;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("lisp_record"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("foreign") {
+                kind = RecordFieldKind::Foreign;
+                Ok(())
+            } else if meta.path.is_ident("skip") {
+                kind = RecordFieldKind::Skip;
+                Ok(())
+            } else {
+                Err(meta.error("Unknown flag"))
+            }
+        })?;
+    }
+    Ok(kind)
+}
+
+/// Convert a Rust identifier to a lisp name, e.g. `PointLight` and `point_light` to
+/// `point-light`.
+fn kebab_case(ident: &str) -> String {
+    let ident = ident.trim_start_matches("r#");
+    let mut name = String::new();
+    for (i, c) in ident.chars().enumerate() {
+        if c == '_' {
+            name.push('-');
+        } else if c.is_uppercase() {
+            if i > 0 && !name.ends_with('-') {
+                name.push('-');
+            }
+            name.extend(c.to_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+/// Expose a struct with named fields to lisp as foreign data, implementing `LispRecord` and
+/// the conversions from and into an `Expression`. The struct has to implement `ForeignData`,
+/// i.e. `Clone`, `Debug`, `Display`, `PartialEq` and `PartialOrd`.
+///
+/// The record is named after the struct in kebab case, or with `#[lisp_record(name = "...")]`.
+/// Fields are converted with the `TryFrom<Expression>` and `Into<Expression>` impls of their
+/// type, fields of other foreign types are marked with `#[lisp_record(foreign)]`, and fields
+/// marked with `#[lisp_record(skip)]` are not exposed and set to their default by the
+/// constructor.
+#[proc_macro_derive(LispRecord, attributes(lisp_record))]
+pub fn derive_lisp_record(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    let ident = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return syn::Error::new_spanned(&input.generics, "lisp records cannot be generic")
+            .to_compile_error()
+            .into();
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return syn::Error::new_spanned(ident, "lisp records must have named fields")
+                    .to_compile_error()
+                    .into()
+            }
+        },
+        _ => {
+            return syn::Error::new_spanned(ident, "lisp records must be structs")
+                .to_compile_error()
+                .into()
+        }
+    };
+    let name = match record_name(&input) {
+        Ok(name) => name,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let mut field_names = Vec::new();
+    let mut arguments = Vec::new();
+    let mut initializers = Vec::new();
+    let mut functions = Vec::new();
+
+    for field in fields {
+        let field_ident = field.ident.as_ref().expect("named fields have identifiers");
+        let ty = &field.ty;
+        let kind = match record_field_kind(field) {
+            Ok(kind) => kind,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let (from_expr, into_expr) = match kind {
+            RecordFieldKind::Plain => (
+                quote! { eval(env, value)?.try_into()? },
+                quote! { record.#field_ident.into() },
+            ),
+            RecordFieldKind::Foreign => (
+                quote! { *ForeignDataWrapper::<#ty>::try_from(eval(env, value)?)?.0 },
+                quote! { ForeignDataWrapper::new(record.#field_ident).into() },
+            ),
+            RecordFieldKind::Skip => {
+                initializers.push(quote! { #field_ident: Default::default() });
+                continue;
+            }
+        };
+
+        let field_name = kebab_case(&field_ident.to_string());
+        let getter_name = format!("{}-{}", name, field_name);
+        let setter_name = format!("set-{}-{}", name, field_name);
+        let argument = Ident::new(&format!("arg_{}", field_ident), field_ident.span());
+
+        initializers.push(quote! {
+            #field_ident: {
+                let value = #argument;
+                #from_expr
+            }
+        });
+        functions.push(quote! {
+            {
+                fn get(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+                    let [record]: [Expression; 1] = expr.try_into()?;
+                    let record: #ident = eval(env, record)?.try_into()?;
+                    Ok(#into_expr)
+                }
+                fn set(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+                    let [record, value]: [Expression; 2] = expr.try_into()?;
+                    let mut record: #ident = eval(env, record)?.try_into()?;
+                    record.#field_ident = #from_expr;
+                    Ok(record.into())
+                }
+                layer.set(#getter_name.to_string(), Expression::Function(get));
+                layer.set(#setter_name.to_string(), Expression::Function(set));
+            }
+        });
+        arguments.push(argument);
+        field_names.push(field_name);
+    }
+
+    let constructor_name = format!("make-{}", name);
+    let type_error = format!("Expression is not a {}", name);
+    let n_arguments = arguments.len();
+
+    quote! {
+        impl LispRecord for #ident {
+            const NAME: &'static str = #name;
+            const FIELDS: &'static [&'static str] = &[#(#field_names),*];
+
+            fn mk_record(layer: &mut EnvironmentLayer) {
+                fn construct(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+                    let _ = env;
+                    let [#(#arguments),*]: [Expression; #n_arguments] = expr.try_into()?;
+                    Ok(#ident { #(#initializers),* }.into())
+                }
+                layer.set(#constructor_name.to_string(), Expression::Function(construct));
+                #(#functions)*
+            }
+        }
+
+        impl From<#ident> for Expression {
+            fn from(record: #ident) -> Expression {
+                ForeignDataWrapper::new(record).into()
+            }
+        }
+
+        impl TryFrom<Expression> for #ident {
+            type Error = EvalError;
+            fn try_from(value: Expression) -> Result<Self, Self::Error> {
+                ForeignDataWrapper::<#ident>::try_from(value)
+                    .map(|record| *record.0)
+                    .map_err(|_| EvalError::TypeError(#type_error.to_string()))
+            }
+        }
+    }
+    .into()
+}
+
+/// Generate the code constructing an `Expression` parsed at compile time.
+fn expression_tokens(expr: &Expression) -> proc_macro2::TokenStream {
+    let e = quote! { ::lispers_core::lisp::Expression };
+    match expr {
+        Expression::Cell(car, cdr) => {
+            let (car, cdr) = (expression_tokens(car), expression_tokens(cdr));
+            quote! { #e::Cell(::std::sync::Arc::new(#car), ::std::sync::Arc::new(#cdr)) }
+        }
+        Expression::Quote(quoted) => {
+            let quoted = expression_tokens(quoted);
+            quote! { #e::Quote(::std::sync::Arc::new(#quoted)) }
+        }
+        Expression::Symbol(s) => quote! { #e::Symbol(#s.to_string()) },
+        Expression::String(s) => quote! { #e::String(#s.to_string()) },
+        Expression::Integer(i) => quote! { #e::Integer(#i) },
+        Expression::BigInt(i) => {
+            let digits = i.to_string();
+            quote! { #e::BigInt(#digits.parse().expect("a valid integer")) }
+        }
+        Expression::Float(f) => {
+            let bits = f.to_bits();
+            quote! { #e::Float(f64::from_bits(#bits)) }
+        }
+        Expression::True => quote! { #e::True },
+        Expression::False => quote! { #e::False },
+        Expression::Nil => quote! { #e::Nil },
+        Expression::AnonymousFunction { .. }
+        | Expression::Map(_)
+        | Expression::Function(_)
+        | Expression::ForeignExpression(_)
+        | Expression::NativeClosure(_) => unreachable!("the parser does not produce {}", expr),
+    }
+}
+
+/// Parse a lisp program at compile time, pointing errors at `span`.
+fn parse_program(source: &str, span: proc_macro2::Span) -> syn::Result<Vec<Expression>> {
+    ExpressionStream::from_char_stream(source.chars())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| syn::Error::new(span, format!("invalid lisp: {}", e)))
+}
+
+/// Get the code of tokens as written, if it is known. The tokens would print symbols like `a-b`
+/// as `a - b`.
+fn source_text(tokens: TokenStream) -> Option<String> {
+    let mut source = String::new();
+    let mut end: Option<(usize, usize)> = None;
+    for tt in tokens {
+        let span = tt.span();
+        let start = (span.start().line(), span.start().column());
+        match end {
+            // Both tokens of a lifetime like `'a` span all of it
+            Some(end) if start < end => continue,
+            Some(end) if start > end => source.push(' '),
+            _ => {}
+        }
+        source.push_str(&span.source_text()?);
+        end = Some((span.end().line(), span.end().column()));
+    }
+    Some(source)
+}
+
+/// Embed a lisp expression written as Rust tokens, parsed at compile time:
+/// `lisp!{ (+ 1 2) }` is an `Expression`, built without parsing at runtime. Syntax errors
+/// fail the build. Rust has to tokenize the expression, so quoted lists like `'(1 2)` and
+/// comments need `lisp_str!`.
+#[proc_macro]
+pub fn lisp(item: TokenStream) -> TokenStream {
+    let source = source_text(item.clone()).unwrap_or_else(|| item.to_string());
+    let item = proc_macro2::TokenStream::from(item);
+
+    let expressions = match parse_program(&source, proc_macro2::Span::call_site()) {
+        Ok(expressions) => expressions,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    match &expressions[..] {
+        [expr] => expression_tokens(expr).into(),
+        _ => syn::Error::new_spanned(
+            item,
+            format!(
+                "expected a single lisp expression, got {}",
+                expressions.len()
+            ),
+        )
+        .to_compile_error()
+        .into(),
+    }
+}
+
+/// Embed a lisp program, parsed at compile time: `lisp_str!("(set 'x '(1 2)) (car x)")` is
+/// the `Vec<Expression>` of its expressions, built without parsing at runtime. Syntax errors
+/// fail the build.
+#[proc_macro]
+pub fn lisp_str(item: TokenStream) -> TokenStream {
+    let source = parse_macro_input!(item as LitStr);
+    let expressions = match parse_program(&source.value(), source.span()) {
+        Ok(expressions) => expressions,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let expressions = expressions.iter().map(expression_tokens);
+    quote! {
+        vec![#(#expressions),*]
     }
     .into()
 }
@@ -0,0 +1,8 @@
+/// Pin the compile errors of unsupported native function signatures. Run with
+/// `TRYBUILD=overwrite` to update the expected output after changing a diagnostic.
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass_*.rs");
+    t.compile_fail("tests/ui/fail_*.rs");
+}
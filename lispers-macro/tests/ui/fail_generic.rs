@@ -0,0 +1,8 @@
+use lispers_macro::native_lisp_function;
+
+#[native_lisp_function(eval)]
+fn identity<T>(x: T) -> Result<T, EvalError> {
+    Ok(x)
+}
+
+fn main() {}
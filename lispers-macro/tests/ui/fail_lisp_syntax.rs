@@ -0,0 +1,6 @@
+use lispers_macro::{lisp, lisp_str};
+
+fn main() {
+    let _ = lisp_str!("(+ 1 2");
+    let _ = lisp! { (+ 1 2) (+ 3 4) };
+}
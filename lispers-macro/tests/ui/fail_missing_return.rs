@@ -0,0 +1,8 @@
+use lispers_macro::native_lisp_function;
+
+#[native_lisp_function(eval)]
+fn log(message: String) {
+    println!("{}", message);
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use lispers_macro::native_lisp_function;
+
+#[native_lisp_function(eval, name = "vec3-add")]
+fn add(a: f64, b: f64) -> f64 {
+    a + b
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use lispers_macro::native_lisp_function;
+
+#[native_lisp_function(eval)]
+fn sum((a, b): (i64, i64)) -> Result<i64, EvalError> {
+    Ok(a + b)
+}
+
+fn main() {}
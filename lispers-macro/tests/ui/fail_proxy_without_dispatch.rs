@@ -0,0 +1,5 @@
+use lispers_macro::native_lisp_function_proxy;
+
+native_lisp_function_proxy!(fname = nothing, eval);
+
+fn main() {}
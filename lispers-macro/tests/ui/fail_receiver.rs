@@ -0,0 +1,12 @@
+use lispers_macro::native_lisp_function;
+
+struct Counter(i64);
+
+impl Counter {
+    #[native_lisp_function]
+    fn get(&self) -> Result<i64, EvalError> {
+        Ok(self.0)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use lispers_macro::LispRecord;
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, LispRecord)]
+struct Point(f64, f64);
+
+fn main() {}
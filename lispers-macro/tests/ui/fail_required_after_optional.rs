@@ -0,0 +1,8 @@
+use lispers_macro::native_lisp_function;
+
+#[native_lisp_function(eval)]
+fn scale(#[default(1.0)] factor: f64, x: f64) -> f64 {
+    factor * x
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use lispers_macro::native_lisp_function;
+
+#[native_lisp_function(evaluate)]
+fn one() -> Result<i64, EvalError> {
+    Ok(1)
+}
+
+fn main() {}
@@ -0,0 +1,46 @@
+use lispers_core::lisp::{eval::eval, eval::EvalError, Environment, Expression};
+use lispers_macro::{native_lisp_function, native_lisp_function_proxy};
+
+#[native_lisp_function(eval)]
+fn add(mut a: i64, b: i64) -> Result<i64, EvalError> {
+    a += b;
+    Ok(a)
+}
+
+#[native_lisp_function(eval, fname = lisp_scale)]
+pub fn scale(x: f64, factor: f64) -> Result<f64, EvalError> {
+    Ok(x * factor)
+}
+
+#[native_lisp_function(eval)]
+fn negate(x: i64) -> i64 {
+    -x
+}
+
+struct Overflow;
+
+impl From<Overflow> for EvalError {
+    fn from(_: Overflow) -> Self {
+        EvalError::RuntimeError("overflow".to_string(), None)
+    }
+}
+
+#[native_lisp_function(eval)]
+fn double(x: i64) -> Result<i64, Overflow> {
+    x.checked_mul(2).ok_or(Overflow)
+}
+
+native_lisp_function_proxy!(fname = add_or_scale, eval, dispatch = add, dispatch = lisp_scale);
+
+fn main() {
+    let env = Environment::default();
+    let args: Expression = [Expression::Integer(1), Expression::Integer(2)].into();
+    assert_eq!(add_or_scale(&env, args), Ok(Expression::Integer(3)));
+    let args: Expression = [Expression::Integer(3)].into();
+    assert_eq!(negate(&env, args), Ok(Expression::Integer(-3)));
+    let args: Expression = [Expression::Integer(i64::MAX)].into();
+    assert_eq!(
+        double(&env, args),
+        Err(EvalError::RuntimeError("overflow".to_string(), None))
+    );
+}
@@ -0,0 +1,10 @@
+;; Function call microbenchmark: the naive doubly recursive fibonacci, dominated by calls, argument
+;; binding and integer comparisons. Run with `lispers run --benchmark 5 <file>`.
+(defun fib (n)
+  (if (< n 2)
+      n
+      (+ (fib (- n 1)) (fib (- n 2)))))
+
+(if (/= (fib 20) 6765)
+    (throw "wrong fibonacci number")
+    nil)
@@ -0,0 +1,19 @@
+;; Integer arithmetic microbenchmark: sum 1..1000000 with a divide and conquer recursion, which
+;; keeps the recursion depth logarithmic. Run with `lispers run --benchmark 5 <file>`.
+(defun sum-range (lo hi)
+  (if (= lo hi)
+      lo
+      (let ((mid (quotient (+ lo hi) 2)))
+        (+ (sum-range lo mid) (sum-range (+ mid 1) hi)))))
+
+(defun count-down (n acc)
+  (if (< n 1)
+      acc
+      (count-down (- n 1) (+ acc n))))
+
+(set 'total (sum-range 1 1000000))
+(if (/= total 500000500000)
+    (throw "wrong sum of 1..1000000")
+    nil)
+(sum-range 1 1000000)
+(count-down 1000 0)
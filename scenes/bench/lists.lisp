@@ -0,0 +1,15 @@
+;; List processing microbenchmark: building, mapping, filtering, sorting and folding lists of a
+;; few hundred elements. Run with `lispers run --benchmark 5 <file>`.
+(set 'xs (range 0 500))
+
+(set 'squares (map (lambda (x) (* x x)) xs))
+(set 'odd-squares (filter (lambda (x) (= (rem x 2) 1)) squares))
+(set 'shuffled (map (lambda (x) (rem (* x 7919) 500)) xs))
+
+(if (/= (sum odd-squares) 20833250)
+    (throw "wrong sum of odd squares")
+    nil)
+(if (not (equal? (sort shuffled) xs))
+    (throw "wrong sort of a permutation")
+    nil)
+(length (append (reverse xs) (zip xs squares)))
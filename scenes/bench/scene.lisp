@@ -0,0 +1,22 @@
+;; Scene building microbenchmark: creating materials, a ring of spheres and lights through the
+;; raytracer builtins, without rendering. Run with `lispers render --benchmark 5 <file>`.
+(set 'red (material (color 1 0 0) (color 1 0 0) (color 0.5 0 0) 50 0.25))
+(set 'blue (material (color 0 0 1) (color 0 0 1) (color 0 0 0.6) 50 0.25))
+
+(defun ring-sphere (i n)
+  (sphere
+   (point (* 4 (cos (/ (* i 6.2) n))) 0.5 (* 4 (sin (/ (* i 6.2) n))))
+   0.5
+   (if (= (rem i 2) 0) red blue)))
+
+(defun ring (scn i n)
+  (if (< i n)
+      (scene-add (ring scn (+ i 1) n) (ring-sphere i n))
+      scn))
+
+(set 'scn (scene
+           (color 0.1 0.1 0.1)
+           (list (sphere (point 0 1 0) 1 blue))
+           (list (light (point 3 10 5) (color 1 1 1)))))
+
+(set 'scn (ring scn 0 200))
@@ -0,0 +1,182 @@
+use std::fmt::Display;
+use std::ops::Deref;
+
+use lispers_core::lisp::{
+    Environment, Expression,
+    environment::EnvironmentLayer,
+    eval::{EvalError, eval},
+    expression::ForeignDataWrapper,
+};
+use lispers_macro::native_lisp_function;
+
+/// A dual number `value + derivative * ε` with `ε² = 0`. Evaluating a function on
+/// `x + 1ε` yields its value and its derivative at `x`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dual {
+    pub value: f64,
+    pub derivative: f64,
+}
+
+impl Dual {
+    /// Create a dual number.
+    pub fn new(value: f64, derivative: f64) -> Self {
+        Dual { value, derivative }
+    }
+}
+
+impl Display for Dual {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Dual({} + {}ε)", self.value, self.derivative)
+    }
+}
+
+impl PartialOrd for Dual {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.value.partial_cmp(&other.value)
+    }
+}
+
+/// A dual number argument. Besides a `Dual`, plain numbers are accepted as constants.
+pub struct DualArg(pub Dual);
+
+impl Deref for DualArg {
+    type Target = Dual;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl TryFrom<Expression> for DualArg {
+    type Error = EvalError;
+
+    fn try_from(value: Expression) -> Result<Self, Self::Error> {
+        match value {
+            Expression::ForeignExpression(_) => {
+                let d: ForeignDataWrapper<Dual> = value.try_into()?;
+                Ok(DualArg(*d))
+            }
+            x => Ok(DualArg(Dual::new(x.try_into()?, 0.0))),
+        }
+    }
+}
+
+/// Create a dual number: `(dual value derivative)`
+#[native_lisp_function(eval)]
+pub fn dual(value: f64, derivative: f64) -> Result<ForeignDataWrapper<Dual>, EvalError> {
+    Ok(ForeignDataWrapper::new(Dual::new(value, derivative)))
+}
+
+/// Get the value of a dual number, plain numbers are returned as floats.
+#[native_lisp_function(eval)]
+pub fn dual_value(a: DualArg) -> Result<f64, EvalError> {
+    Ok(a.value)
+}
+
+/// Get the derivative of a dual number, which is 0 for plain numbers.
+#[native_lisp_function(eval)]
+pub fn dual_derivative(a: DualArg) -> Result<f64, EvalError> {
+    Ok(a.derivative)
+}
+
+// The arithmetic of dual numbers. These are dispatch targets of the overloaded arithmetic
+// operators, which try them after the plain number and vector implementations.
+
+#[native_lisp_function(eval)]
+pub fn add_dual(a: DualArg, b: DualArg) -> Result<ForeignDataWrapper<Dual>, EvalError> {
+    Ok(ForeignDataWrapper::new(Dual::new(
+        a.value + b.value,
+        a.derivative + b.derivative,
+    )))
+}
+
+#[native_lisp_function(eval)]
+pub fn sub_dual(a: DualArg, b: DualArg) -> Result<ForeignDataWrapper<Dual>, EvalError> {
+    Ok(ForeignDataWrapper::new(Dual::new(
+        a.value - b.value,
+        a.derivative - b.derivative,
+    )))
+}
+
+#[native_lisp_function(eval)]
+pub fn mul_dual(a: DualArg, b: DualArg) -> Result<ForeignDataWrapper<Dual>, EvalError> {
+    Ok(ForeignDataWrapper::new(Dual::new(
+        a.value * b.value,
+        a.derivative * b.value + a.value * b.derivative,
+    )))
+}
+
+#[native_lisp_function(eval)]
+pub fn div_dual(a: DualArg, b: DualArg) -> Result<ForeignDataWrapper<Dual>, EvalError> {
+    Ok(ForeignDataWrapper::new(Dual::new(
+        a.value / b.value,
+        (a.derivative * b.value - a.value * b.derivative) / (b.value * b.value),
+    )))
+}
+
+/// Get the derivative of a numeric function of one argument: `(grad f)` returns a function
+/// of `x` evaluating `f` on the dual number `x + 1ε` and returning its derivative.
+pub fn grad(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f]: [Expression; 1] = expr.try_into()?;
+    let f = eval(env, f)?;
+    if !matches!(
+        f,
+        Expression::Function(_)
+            | Expression::NativeClosure(_)
+            | Expression::AnonymousFunction { .. }
+    ) {
+        return Err(EvalError::NotAFunction(f));
+    }
+
+    let x = Expression::Symbol("grad-x".to_string());
+    let seed: Expression = [
+        Expression::Symbol("dual".to_string()),
+        x,
+        Expression::Float(1.0),
+    ]
+    .into();
+    let call: Expression = [f, seed].into();
+    Ok(Expression::AnonymousFunction {
+        argument_symbols: vec!["grad-x".to_string()],
+        body: [[Expression::Symbol("dual-derivative".to_string()), call].into()].into(),
+    })
+}
+
+/// Adds the automatic differentiation functions to the given environment layer. Arithmetic on
+/// dual numbers is provided by the arithmetic operators of `mk_raytrace`.
+pub fn mk_autodiff(layer: &mut EnvironmentLayer) {
+    layer.set("dual".to_string(), Expression::Function(dual));
+    layer.set("dual-value".to_string(), Expression::Function(dual_value));
+    layer.set(
+        "dual-derivative".to_string(),
+        Expression::Function(dual_derivative),
+    );
+    layer.set("grad".to_string(), Expression::Function(grad));
+}
+
+#[test]
+fn test_grad() {
+    use crate::raytracer::lisp::mk_raytrace;
+    use lispers_core::lisp::{eval_source, prelude::mk_prelude};
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    mk_autodiff(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let values = eval_source(
+        &env,
+        r#"
+        (defun f (x) (+ (* 3 (* x x)) (/ 1 x)))
+        ((grad f) 2)
+        ((grad (lambda (x) (- 5 x))) 1)
+        ((grad (lambda (x) 4)) 1)
+        "#,
+    )
+    .unwrap();
+    // d/dx 3x² + 1/x = 6x - 1/x²
+    assert_eq!(values[1], Expression::Float(12.0 - 0.25));
+    assert_eq!(values[2], Expression::Float(-1.0));
+    assert_eq!(values[3], Expression::Float(0.0));
+}
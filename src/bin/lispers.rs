@@ -0,0 +1,5 @@
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    lispers::cli::main(std::env::args().skip(1).collect())
+}
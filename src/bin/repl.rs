@@ -1,34 +1,7 @@
-use lispers_core::lisp::Expression;
-use lispers_core::parser::ParserError;
+use std::process::ExitCode;
 
-use lispers_core::{lisp, parser};
-use std::io::Write;
-
-fn main() {
-    let env = lisp::Environment::default();
-
-    loop {
-        print!("> ");
-        std::io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        if std::io::stdin().read_line(&mut input).unwrap() == 0 {
-            println!("Exiting REPL...");
-            break;
-        }
-
-        match parser::ExpressionStream::from_char_stream(input.chars())
-            .collect::<Result<Vec<Expression>, ParserError>>()
-        {
-            Err(e) => println!("Parser Error: {:?}", e),
-            Ok(exprs) => {
-                for expr in exprs {
-                    match lisp::eval(&env, expr) {
-                        Err(e) => println!("Eval Error: {}", e),
-                        Ok(val) => println!("{}", val),
-                    }
-                }
-            }
-        }
-    }
+/// Alias for `lispers repl`
+fn main() -> ExitCode {
+    let args = ["repl"].map(String::from);
+    lispers::cli::main(args.into_iter().chain(std::env::args().skip(1)).collect())
 }
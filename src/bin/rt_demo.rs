@@ -13,14 +13,14 @@ fn main() {
 
     scene.set_ambient(Color::new(0.1, 0.1, 0.1));
 
-    scene.add_light(Light {
-        position: Point3::new(5.0, 7.0, 10.0),
-        color: Color::new(1.0, 1.0, 1.0),
-    });
-    scene.add_light(Light {
-        position: Point3::new(-2.0, 7.0, 10.0),
-        color: Color::new(1.0, 1.0, 1.0),
-    });
+    scene.add_light(Light::new(
+        Point3::new(5.0, 7.0, 10.0),
+        Color::new(1.0, 1.0, 1.0),
+    ));
+    scene.add_light(Light::new(
+        Point3::new(-2.0, 7.0, 10.0),
+        Color::new(1.0, 1.0, 1.0),
+    ));
 
     scene.add_object(RTObjectWrapper::new(Box::new(Checkerboard::new(
         Point3::new(0.0, -1.0, 0.0),
@@ -1,45 +1,7 @@
-use std::env;
+use std::process::ExitCode;
 
-use lispers::raytracer::lisp::mk_raytrace;
-use lispers_core::lisp::environment::EnvironmentLayer;
-use lispers_core::lisp::prelude::mk_prelude;
-use lispers_core::lisp::{eval, Environment};
-use lispers_core::parser::ExpressionStream;
-
-fn main() {
-    let program_paths: Vec<_> = env::args().skip(1).collect();
-    let programs: Vec<_> = program_paths
-        .iter()
-        .map(|path| std::fs::read_to_string(path).unwrap())
-        .collect();
-
-    let mut layer = EnvironmentLayer::new();
-    mk_prelude(&mut layer);
-    mk_raytrace(&mut layer);
-
-    let mut environment = Environment::from_layer(layer);
-
-    for (program, path) in programs.iter().zip(program_paths) {
-        environment.set("FILE".to_string(), path.clone().into());
-
-        for (i, r) in ExpressionStream::from_char_stream(program.chars()).enumerate() {
-            match r {
-                Err(err) => {
-                    println!(
-                        "ParserError in File {} Expression {}: {:?}",
-                        path,
-                        i + 1,
-                        err
-                    );
-                    break;
-                }
-                Ok(expr) => match eval(&environment, expr) {
-                    Ok(_) => {}
-                    Err(e) => println!("Error evaluating Expression {}: {}", i + 1, e),
-                },
-            }
-        }
-    }
-
-    println!("Interpreter Done!");
+/// Alias for `lispers render`
+fn main() -> ExitCode {
+    let args = ["render"].map(String::from);
+    lispers::cli::main(args.into_iter().chain(std::env::args().skip(1)).collect())
 }
@@ -0,0 +1,363 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use crate::session::EvalSession;
+use lispers_core::lisp::cache::{COMPILED_EXTENSION, write_compiled};
+use lispers_core::lisp::eval::EvalError;
+use lispers_core::lisp::printer::{pretty, printable};
+use lispers_core::lisp::{Expression, LispError, eval_source};
+use lispers_core::parser::ExpressionStream;
+use lispers_macro::lisp_str;
+use nix::sys::signal::{SigHandler, Signal, signal};
+
+const SCENES_DIR: &str = env!("SCENES_DIR");
+
+const USAGE: &str = "Usage: lispers <command> [args]
+
+Commands:
+  repl                             Interactive lisp shell with raytracer builtins, Ctrl-C
+                                   interrupts the evaluation and Ctrl-D exits
+  run [flags] <file.lisp>...       Evaluate lisp files with the prelude only
+  render [flags] <file.lisp>...    Evaluate scene files with raytracer builtins
+  demo [lisp | <scene>]            Run a builtin demo, lists the demos without argument
+  fmt <file.lisp>                  Print a file with normalized layout (drops comments)
+  check <file.lisp>...             Parse files and report syntax errors
+  compile <file.lisp>...           Pre-parse files to <file>.lispb, which run and render
+                                   evaluate without parsing
+
+Flags of run and render:
+  --no-clobber                     Do not overwrite existing images
+  --distinct-false                 Scheme-like booleans: false is distinct from nil
+  --negative-indices               Allow indices counting from the end, like -1
+  --benchmark <N>                  Evaluate the files N times without writing images and
+                                   report the timings";
+
+/// Small lisp programs showing off the interpreter, run by `demo lisp`.
+fn lisp_demo() -> Vec<Expression> {
+    lisp_str!(
+        r#"
+        ((lambda (x y) (+ (if (< x 10) (* x 11) x) y)) 2 20)
+        (set 'myvar "hello world!")
+        (print myvar) (print 'myvar)
+        (car (cons 'a 'b)) (cdr (cons 'c 'd)) (cons 'a 'b)
+        (eval (car (cons 'myvar 'b)))
+        (set 'pow (lambda (a b) (if (= b 0) 1 (* a (pow a (- b 1))))))
+        pow
+        (pow 2 10)
+        (let '((fib . (lambda (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))))) (fib 10))
+        (defun do-n-times (f n) (if (= n 0) '() (cons (f) (do-n-times f (- n 1)))))
+        (do-n-times (lambda () (print 'hello)) 5)
+        (progn (print 'hello) (print 'world))
+        (load-string "(defun loaded-foo (x) (+ x 1))")
+        (loaded-foo 1)
+        "#
+    )
+}
+
+/// Maximum line width of `fmt` output
+const FMT_WIDTH: usize = 80;
+
+/// Run the `lispers` command line tool with `args` (excluding the program name).
+pub fn main(args: Vec<String>) -> ExitCode {
+    let mut args = args.into_iter();
+    let command = args.next();
+    let args: Vec<String> = args.collect();
+
+    match command.as_deref() {
+        Some("repl") if args.is_empty() => repl(),
+        Some("run") => run(EvalSession::lisp_only(), args),
+        Some("render") => {
+            let status = run(EvalSession::new(), args);
+            println!("Interpreter Done!");
+            status
+        }
+        Some("demo") => demo(&args),
+        Some("fmt") => fmt(&args),
+        Some("check") => check(&args),
+        Some("compile") => compile(&args),
+        _ => {
+            println!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The interrupt flag of the REPL session, set by `on_sigint`.
+static REPL_INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+extern "C" fn on_sigint(_: std::ffi::c_int) {
+    if let Some(flag) = REPL_INTERRUPT.get() {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+fn repl() -> ExitCode {
+    let session = EvalSession::new();
+
+    // Ctrl-C interrupts the running evaluation instead of killing the process
+    if REPL_INTERRUPT
+        .set(session.environment().interrupt_flag())
+        .is_ok()
+    {
+        // Safety: the handler only stores to an atomic flag, which is async-signal-safe
+        if let Err(e) = unsafe { signal(Signal::SIGINT, SigHandler::Handler(on_sigint)) } {
+            println!("Could not install the Ctrl-C handler: {}", e);
+        }
+    }
+
+    loop {
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        if std::io::stdin().read_line(&mut input).unwrap() == 0 {
+            println!("Exiting REPL...");
+            return ExitCode::SUCCESS;
+        }
+
+        let result = eval_source(session.environment(), &input);
+        session.report_warnings();
+        match result {
+            Ok(values) => values
+                .iter()
+                .for_each(|v| println!("{}", printable(session.environment(), v))),
+            Err(LispError::Eval(e)) => println!("Eval Error: {}", e),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Evaluate files in `session` after applying the flags, reporting evaluation errors.
+fn run(mut session: EvalSession, args: Vec<String>) -> ExitCode {
+    let mut benchmark_runs = None;
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--no-clobber" => session.set("NO-CLOBBER", Expression::True),
+            "--distinct-false" => {
+                let config = session.environment().config().with_distinct_false(true);
+                session.set_config(config);
+            }
+            "--negative-indices" => {
+                let config = session.environment().config().with_negative_indices(true);
+                session.set_config(config);
+            }
+            "--benchmark" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => {
+                    session.set("BENCHMARK", Expression::True);
+                    benchmark_runs = Some(n);
+                }
+                _ => {
+                    println!("--benchmark expects a positive number of runs");
+                    return ExitCode::FAILURE;
+                }
+            },
+            f if !f.starts_with("--") => paths.push(flag),
+            f => {
+                println!("Unknown flag {}", f);
+                println!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if paths.is_empty() {
+        println!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let Some(runs) = benchmark_runs else {
+        return eval_files(&mut session, &paths);
+    };
+
+    let mut timings = Vec::with_capacity(runs);
+    for run in 1..=runs {
+        println!("Benchmark run {}/{}", run, runs);
+        let start = Instant::now();
+        if eval_files(&mut session, &paths) == ExitCode::FAILURE {
+            return ExitCode::FAILURE;
+        }
+        timings.push(start.elapsed().as_secs_f64());
+    }
+
+    let mean = timings.iter().sum::<f64>() / runs as f64;
+    let variance = timings.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / runs as f64;
+    let min = timings.iter().copied().fold(f64::INFINITY, f64::min);
+    println!(
+        "Benchmark of {} runs: min {:.3}s, mean {:.3}s, stddev {:.3}s",
+        runs,
+        min,
+        mean,
+        variance.sqrt()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Evaluate files in `session`, reporting evaluation errors.
+fn eval_files(session: &mut EvalSession, paths: &[String]) -> ExitCode {
+    let mut status = ExitCode::SUCCESS;
+    for path in paths {
+        let evaluated = session.eval_file(Path::new(path), |_, r| {
+            if let Err(e) = r {
+                println!("Error evaluating File {}: {}", path, e);
+                status = ExitCode::FAILURE;
+            }
+        });
+        if let Err(e) = evaluated {
+            println!("Error in File {}: {}", path, e);
+            status = ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+/// Get the demo scene files of the scenes directory.
+fn demo_scenes() -> Vec<PathBuf> {
+    let mut scenes: Vec<PathBuf> = std::fs::read_dir(Path::new(SCENES_DIR))
+        .expect("Failed to read scenes directory")
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            p.is_file() && name.starts_with("demo-") && name.ends_with(".lisp")
+        })
+        .collect();
+    scenes.sort();
+    scenes
+}
+
+fn demo(args: &[String]) -> ExitCode {
+    let on_result = |expr: &Expression, r: Result<Expression, EvalError>| {
+        println!("Evaluating: {}", expr);
+        match r {
+            Ok(e) => println!("=> {}", e),
+            Err(e) => println!("Error: {}", e),
+        }
+    };
+
+    match args {
+        [name] if name == "lisp" => {
+            EvalSession::lisp_only().eval_expressions(lisp_demo(), on_result);
+        }
+        [name] => {
+            let scenes = demo_scenes();
+            let Some(scene) = scenes.iter().find(|p| {
+                p.file_name().is_some_and(|n| *n == **name)
+                    || p.file_stem().is_some_and(|n| *n == **name)
+            }) else {
+                println!("Scene file {} not found", name);
+                return ExitCode::FAILURE;
+            };
+            println!("Loading scene {}", scene.display());
+            let source = std::fs::read_to_string(scene).expect("Failed to read scene file");
+            if let Err(e) = EvalSession::new().eval_source(&source, on_result) {
+                println!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+        _ => {
+            println!("Usage: lispers demo [lisp | <scene>]");
+            println!("Available demos:");
+            println!("  lisp");
+            for scene in demo_scenes() {
+                println!("  {}", scene.file_name().unwrap().to_string_lossy());
+            }
+            return ExitCode::FAILURE;
+        }
+    }
+
+    println!("Interpreter Done!");
+    ExitCode::SUCCESS
+}
+
+fn fmt(args: &[String]) -> ExitCode {
+    let [path] = args else {
+        println!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let source = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("{}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let exprs =
+        match ExpressionStream::from_char_stream(source.chars()).collect::<Result<Vec<_>, _>>() {
+            Ok(exprs) => exprs,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+    let formatted: Vec<String> = exprs.iter().map(|e| pretty(e, 0, FMT_WIDTH)).collect();
+    println!("{}", formatted.join("\n\n"));
+    ExitCode::SUCCESS
+}
+
+fn check(paths: &[String]) -> ExitCode {
+    if paths.is_empty() {
+        println!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for path in paths {
+        let parsed = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                ExpressionStream::from_char_stream(source.chars())
+                    .try_fold(0, |n, e| e.map(|_| n + 1))
+                    .map_err(|e| e.to_string())
+            });
+        match parsed {
+            Ok(n) => println!("{}: ok ({} expressions)", path, n),
+            Err(e) => {
+                println!("{}: {}", path, e);
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+fn compile(paths: &[String]) -> ExitCode {
+    if paths.is_empty() {
+        println!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for path in paths {
+        let compiled = Path::new(path).with_extension(COMPILED_EXTENSION);
+        let written = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                ExpressionStream::from_char_stream(source.chars())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.to_string())
+            })
+            .and_then(|exprs| {
+                let n = exprs.len();
+                write_compiled(&compiled, exprs)
+                    .map(|_| n)
+                    .map_err(|e| e.to_string())
+            });
+        match written {
+            Ok(n) => println!("{}: {} ({} expressions)", path, compiled.display(), n),
+            Err(e) => {
+                println!("{}: {}", path, e);
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
@@ -0,0 +1,104 @@
+//! The error type of the whole library, for hosts embedding the interpreter and renderer.
+
+use std::fmt::Display;
+
+use lispers_core::lisp::LispError;
+use lispers_core::lisp::eval::EvalError;
+use lispers_core::parser::ParserError;
+
+use crate::raytracer::RTError;
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Any error of parsing, evaluating, rendering or reading files. Every error of the library
+/// converts into it, so `?` works across their functions, and it implements `std::error::Error`
+/// for use with error handling crates like `anyhow`.
+pub enum LispersError {
+    Parser(ParserError),
+    Eval(EvalError),
+    Render(RTError),
+    Io(std::io::Error),
+}
+
+impl From<ParserError> for LispersError {
+    fn from(value: ParserError) -> Self {
+        LispersError::Parser(value)
+    }
+}
+
+impl From<EvalError> for LispersError {
+    fn from(value: EvalError) -> Self {
+        LispersError::Eval(value)
+    }
+}
+
+impl From<LispError> for LispersError {
+    fn from(value: LispError) -> Self {
+        match value {
+            LispError::Parser(e) => LispersError::Parser(e),
+            e => LispersError::Eval(e.into()),
+        }
+    }
+}
+
+impl From<RTError> for LispersError {
+    fn from(value: RTError) -> Self {
+        LispersError::Render(value)
+    }
+}
+
+impl From<std::io::Error> for LispersError {
+    fn from(value: std::io::Error) -> Self {
+        LispersError::Io(value)
+    }
+}
+
+impl Display for LispersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LispersError::Parser(e) => write!(f, "Parser error: {}", e),
+            LispersError::Eval(e) => write!(f, "{}", e),
+            LispersError::Render(e) => write!(f, "Render error: {}", e),
+            LispersError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LispersError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LispersError::Parser(e) => Some(e),
+            LispersError::Eval(e) => e.source(),
+            LispersError::Render(e) => e.source(),
+            LispersError::Io(e) => Some(e),
+        }
+    }
+}
+
+#[test]
+fn test_error_chain() {
+    use crate::prelude::*;
+    use std::error::Error;
+
+    fn run(source: &str) -> Result<(), Box<dyn Error>> {
+        let mut layer = EnvironmentLayer::new();
+        mk_prelude(&mut layer);
+        mk_io(&mut layer);
+        let env = Environment::from_layer(layer);
+        eval_source(&env, source).map_err(LispersError::from)?;
+        Ok(())
+    }
+
+    assert!(run("(+ 1 2)").is_ok());
+
+    let e = run("(read-file \"/nonexistent/lispers.lisp\")").unwrap_err();
+    let source = e.source().expect("Expected the IO error as source");
+    assert_eq!(
+        source.downcast_ref::<std::io::Error>().map(|e| e.kind()),
+        Some(std::io::ErrorKind::NotFound)
+    );
+
+    let e = run("(+ 1").unwrap_err();
+    assert!(e.downcast_ref::<LispersError>().is_some());
+    assert!(e.to_string().starts_with("Parser error"));
+}
@@ -1 +1,18 @@
+#[cfg(feature = "lisp-bindings")]
+pub mod autodiff;
+#[cfg(feature = "lisp-bindings")]
+pub mod cli;
+#[cfg(feature = "lisp-bindings")]
+pub mod error;
+#[cfg(feature = "lisp-bindings")]
+pub mod prelude;
 pub mod raytracer;
+#[cfg(feature = "lisp-bindings")]
+pub mod regex;
+#[cfg(feature = "lisp-bindings")]
+pub mod session;
+
+#[cfg(feature = "lisp-bindings")]
+pub use lispers_core;
+#[cfg(feature = "lisp-bindings")]
+pub use lispers_macro;
@@ -0,0 +1,28 @@
+//! Everything needed to embed the interpreter and write native functions, for a single
+//! `use lispers::prelude::*;`.
+//!
+//! The macros expand to code naming `Environment`, `EnvironmentLayer`, `Expression`,
+//! `EvalError`, `ForeignDataWrapper`, `LispRecord` and `eval` unqualified, which are all in scope
+//! with this import.
+
+pub use lispers_core::lisp::builder::EnvironmentBuilder;
+pub use lispers_core::lisp::environment::EnvironmentLayer;
+pub use lispers_core::lisp::eval::EvalError;
+pub use lispers_core::lisp::expression::{ForeignData, ForeignDataWrapper, NativeClosure};
+pub use lispers_core::lisp::io::mk_io;
+pub use lispers_core::lisp::prelude::mk_prelude;
+pub use lispers_core::lisp::record::LispRecord;
+pub use lispers_core::lisp::{
+    Environment, Expression, InterpreterConfig, LispError, Warning, eval, eval_source,
+};
+pub use lispers_core::parser::ExpressionStream;
+pub use lispers_macro::{
+    LispRecord, collect_module, lisp, lisp_str, native_lisp_function, native_lisp_function_proxy,
+    register_lisp_function,
+};
+
+pub use crate::autodiff::mk_autodiff;
+pub use crate::error::LispersError;
+pub use crate::raytracer::lisp::{RaytracerBuilderExt, mk_raytrace};
+pub use crate::regex::mk_regex;
+pub use crate::session::EvalSession;
@@ -1,21 +1,87 @@
-use std::{fmt::Display, path::Path};
+use std::{
+    fmt::Display,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 use super::{
+    checkpoint::Checkpoint,
+    framebuffer::Framebuffer,
+    integrator::IntegratorWrapper,
+    sampler::SamplerWrapper,
     scene::Scene,
+    spectral::band_wavelengths,
     types::{Color, Point3, Ray, Scalar, Vector3},
     RTError,
 };
-use image::RgbImage;
-use lispers_core::lisp::eval::EvalError;
+use image::{imageops, RgbImage};
 use ndarray::Array3;
 use rayon::prelude::*;
 use video_rs::{encode::Settings, Encoder, Time};
 
+/// Progress of a running render, reported each time a row of the image is finished.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderProgress {
+    /// The number of finished rows.
+    pub rows_done: usize,
+    /// The number of rows of the image.
+    pub rows: usize,
+    /// The time since the render started.
+    pub elapsed: Duration,
+}
+
+impl RenderProgress {
+    /// Estimate the remaining render time from the average time per finished row.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.rows_done == 0 {
+            return None;
+        }
+        let remaining = (self.rows - self.rows_done) as f64 / self.rows_done as f64;
+        Some(self.elapsed.mul_f64(remaining))
+    }
+}
+
+/// A rectangular region of the image in pixels, with `(x, y)` as the top left corner.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Crop {
+    /// Create a crop window.
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Crop {
+        Crop {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Check whether a pixel lies in the crop window.
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
 /// A camera that can render a scene.
 #[derive(Clone, PartialEq, Debug)]
 pub struct Camera {
     /// Position of the camera's eye.
     position: Point3,
+    /// The point the camera looks at, which lies in the center of the image plane.
+    center: Point3,
+    /// The up vector the camera was created with.
+    up: Vector3,
+    /// The vertical field of view. [deg]
+    fovy: Scalar,
     /// The lower left point of the image plane.
     lower_left: Point3,
     /// The direction of the x-axis on the image plane. (length is equal to the image width)
@@ -26,6 +92,12 @@ pub struct Camera {
     width: usize,
     /// The height of the image. [px]
     height: usize,
+    /// The part of the image to render, everything else stays black.
+    crop: Option<Crop>,
+    /// The positions of the subpixel samples.
+    sampler: SamplerWrapper,
+    /// The rendering algorithm.
+    integrator: IntegratorWrapper,
 }
 
 impl Camera {
@@ -53,14 +125,74 @@ impl Camera {
 
         Camera {
             position,
+            center,
+            up,
+            fovy,
             lower_left,
             x_dir,
             y_dir,
             width,
             height,
+            crop: None,
+            sampler: SamplerWrapper::default(),
+            integrator: IntegratorWrapper::default(),
+        }
+    }
+
+    /// Get a copy of the camera which only renders the pixels inside `crop`, limited to the
+    /// image. Pixels outside of the crop window are skipped and stay black.
+    pub fn with_crop(&self, crop: Crop) -> Camera {
+        let x = crop.x.min(self.width);
+        let y = crop.y.min(self.height);
+        Camera {
+            crop: Some(Crop::new(
+                x,
+                y,
+                crop.width.min(self.width - x),
+                crop.height.min(self.height - y),
+            )),
+            ..self.clone()
+        }
+    }
+
+    /// Get a copy of the camera which renders with `integrator`.
+    pub fn with_integrator(&self, integrator: IntegratorWrapper) -> Camera {
+        Camera {
+            integrator,
+            ..self.clone()
+        }
+    }
+
+    /// Get a copy of the camera with an image size of `width` x `height`, keeping the position,
+    /// orientation, field of view, sampler and integrator. A crop window is dropped.
+    pub fn with_resolution(&self, width: usize, height: usize) -> Camera {
+        Camera {
+            sampler: self.sampler.clone(),
+            integrator: self.integrator.clone(),
+            ..Camera::new(
+                self.position,
+                self.center,
+                self.up,
+                self.fovy,
+                width,
+                height,
+            )
         }
     }
 
+    /// Get a copy of the camera which places the subpixel samples with `sampler`.
+    pub fn with_sampler(&self, sampler: SamplerWrapper) -> Camera {
+        Camera {
+            sampler,
+            ..self.clone()
+        }
+    }
+
+    /// Get the image size `(width, height)` in pixels.
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
     /// Get a ray pointing from the camera to a relative position on the image plane.
     /// `x` and `y` are expected to be in the range `[0, 1]`.
     pub fn ray_at_relative(&self, x: Scalar, y: Scalar) -> Ray {
@@ -80,37 +212,234 @@ impl Camera {
         self.ray_at_relative(x, 1.0 - y)
     }
 
+    /// Project a point onto the image plane, which is the inverse of `ray_at`. Returns the
+    /// pixel coordinates `(x, y)`, which may lie outside of the image, or `None` if the point is
+    /// not in front of the camera.
+    pub fn project(&self, point: Point3) -> Option<(Scalar, Scalar)> {
+        let view = self.center - self.position;
+        let dir = point - self.position;
+        let depth = view.dot(&dir);
+        if depth <= 0.0 {
+            return None;
+        }
+
+        // Intersect the line of sight with the image plane, which is orthogonal to `view`.
+        let on_plane = self.position + dir * (view.norm_squared() / depth) - self.lower_left;
+        let x = on_plane.dot(&self.x_dir) / self.x_dir.norm_squared();
+        let y = on_plane.dot(&self.y_dir) / self.y_dir.norm_squared();
+        Some((x * self.width as Scalar, (1.0 - y) * self.height as Scalar))
+    }
+
     /// Render the scene from the camera's perspective.
     /// - `depth` is the maximum number of reflections to calculate.
     /// - `subp` is the number of subpixels to use for antialiasing.
     pub fn render(&self, scene: &Scene, depth: u32, subp: u32) -> RgbImage {
+        self.render_with_progress(scene, depth, subp, None, |_| {})
+    }
+
+    /// Render the scene from the camera's perspective in spectral mode, tracing each of
+    /// `bands` wavelength bands separately. Slower than `render`, but models dispersion.
+    /// - `depth` is the maximum number of reflections to calculate.
+    /// - `subp` is the number of subpixels to use for antialiasing.
+    /// - `bands` is the number of wavelength bands.
+    pub fn render_spectral(&self, scene: &Scene, depth: u32, subp: u32, bands: usize) -> RgbImage {
+        self.render_with_progress(scene, depth, subp, Some(bands), |_| {})
+    }
+
+    /// Render the scene like `render`, or like `render_spectral` if `bands` is given.
+    /// `progress` is called from the render threads each time a row is finished.
+    pub fn render_with_progress<P: Fn(RenderProgress) + Sync>(
+        &self,
+        scene: &Scene,
+        depth: u32,
+        subp: u32,
+        bands: Option<usize>,
+        progress: P,
+    ) -> RgbImage {
+        self.render_resumable(scene, depth, subp, bands, None, progress)
+    }
+
+    /// Render the scene like `render_with_progress`. Rows finished in the `checkpoint` are
+    /// taken from it, newly finished rows are stored in it. The progress only counts the rows
+    /// which are rendered.
+    pub fn render_resumable<P: Fn(RenderProgress) + Sync>(
+        &self,
+        scene: &Scene,
+        depth: u32,
+        subp: u32,
+        bands: Option<usize>,
+        checkpoint: Option<&Mutex<Checkpoint>>,
+        progress: P,
+    ) -> RgbImage {
+        let mut img = RgbImage::new(self.width as u32, self.height as u32);
+        self.trace_into(scene, depth, subp, bands, checkpoint, &mut img, progress);
+        img
+    }
+
+    /// Render the scene like `render_resumable` into `buffer`, which has the camera's resolution.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_into<B: Framebuffer + Send, P: Fn(RenderProgress) + Sync>(
+        &self,
+        scene: &Scene,
+        depth: u32,
+        subp: u32,
+        bands: Option<usize>,
+        checkpoint: Option<&Mutex<Checkpoint>>,
+        buffer: &mut B,
+        progress: P,
+    ) {
+        match bands {
+            Some(bands) => {
+                let wavelengths = band_wavelengths(bands.max(1));
+                self.render_with(subp, checkpoint, buffer, progress, |ray| {
+                    self.integrator
+                        .trace_spectral(scene, ray, depth, &wavelengths)
+                })
+            }
+            None => self.render_with(subp, checkpoint, buffer, progress, |ray| {
+                self.integrator.trace(scene, ray, depth)
+            }),
+        }
+    }
+
+    /// Render the scene like `render_with_progress` into `buffer`, at the resolution of the
+    /// buffer. The rows are rendered in parallel and written into the buffer as they finish.
+    pub fn render_into<B: Framebuffer + Send, P: Fn(RenderProgress) + Sync>(
+        &self,
+        scene: &Scene,
+        depth: u32,
+        subp: u32,
+        bands: Option<usize>,
+        buffer: &mut B,
+        progress: P,
+    ) {
+        let (width, height) = buffer.dimensions();
+        if (width, height) == self.resolution() {
+            self.trace_into(scene, depth, subp, bands, None, buffer, progress);
+        } else {
+            self.with_resolution(width, height)
+                .trace_into(scene, depth, subp, bands, None, buffer, progress);
+        }
+    }
+
+    /// Get the cameras of a left and a right eye, which are `ipd` apart along the horizontal axis
+    /// of the image plane and look in the same direction as this camera.
+    pub fn stereo_pair(&self, ipd: Scalar) -> (Camera, Camera) {
+        let offset = self.x_dir.normalize() * (ipd / 2.0);
+        let eye = |offset: Vector3| Camera {
+            crop: self.crop,
+            sampler: self.sampler.clone(),
+            integrator: self.integrator.clone(),
+            ..Camera::new(
+                self.position + offset,
+                self.center + offset,
+                self.up,
+                self.fovy,
+                self.width,
+                self.height,
+            )
+        };
+        (eye(-offset), eye(offset))
+    }
+
+    /// Render a side-by-side stereo image of twice the camera width, with the view of the left
+    /// eye on the left (see `stereo_pair`). The arguments are the same as for
+    /// `render_with_progress`, the reported progress covers both eyes.
+    pub fn render_stereo_with_progress<P: Fn(RenderProgress) + Sync>(
+        &self,
+        scene: &Scene,
+        ipd: Scalar,
+        depth: u32,
+        subp: u32,
+        bands: Option<usize>,
+        progress: P,
+    ) -> RgbImage {
+        let (left, right) = self.stereo_pair(ipd);
+        let start = Instant::now();
+        let mut img = RgbImage::new(2 * self.width as u32, self.height as u32);
+
+        for (i, eye) in [left, right].iter().enumerate() {
+            let eye_img = eye.render_with_progress(scene, depth, subp, bands, |p| {
+                progress(RenderProgress {
+                    rows_done: i * p.rows + p.rows_done,
+                    rows: 2 * p.rows,
+                    elapsed: start.elapsed(),
+                })
+            });
+            imageops::replace(&mut img, &eye_img, (i * self.width) as i64, 0);
+        }
+        img
+    }
+
+    /// Render an image, using `trace` to get the color of each subpixel ray.
+    fn render_with<B, P, F>(
+        &self,
+        subp: u32,
+        checkpoint: Option<&Mutex<Checkpoint>>,
+        buffer: &mut B,
+        progress: P,
+        trace: F,
+    ) where
+        B: Framebuffer + Send,
+        P: Fn(RenderProgress) + Sync,
+        F: Fn(&Ray) -> Color + Sync,
+    {
         let dx = 1.0 / self.width as Scalar;
         let dy = 1.0 / self.height as Scalar;
-        let dsx = dx / subp as Scalar;
-        let dsy = dy / subp as Scalar;
-        let mut img = RgbImage::new(self.width as u32, self.height as u32);
+        let samples = (subp * subp) as usize;
+        let crop = self
+            .crop
+            .unwrap_or(Crop::new(0, 0, self.width, self.height));
+        let buffer = Mutex::new(buffer);
+        let write_row = |y: usize, row: &[u8]| {
+            let mut buffer = buffer.lock().unwrap();
+            for (x, pixel) in row.chunks_exact(3).enumerate() {
+                if crop.contains(x, y) {
+                    buffer.set_pixel(x, y, [pixel[0], pixel[1], pixel[2]]);
+                }
+            }
+        };
+        let resumed =
+            |y: usize| checkpoint.and_then(|c| c.lock().unwrap().row(y).map(<[u8]>::to_vec));
+        let rows = (crop.y..crop.y + crop.height)
+            .filter(|y| resumed(*y).is_none())
+            .count();
+        let start = Instant::now();
+        let rows_done = AtomicUsize::new(0);
 
-        img.enumerate_rows_mut().par_bridge().for_each(|(_, row)| {
-            for (x, y, pixel) in row {
-                let y = y as Scalar * dy;
-                let x = x as Scalar * dx;
-                let mut color = Color::new(0.0, 0.0, 0.0);
-                for sx in 0..subp {
-                    for sy in 0..subp {
-                        color += scene.trace(
-                            &self.ray_at_relative(
-                                x + sx as Scalar * dsx,
-                                1.0 - (y + sy as Scalar * dsy),
-                            ),
-                            depth,
-                        );
+        (crop.y..crop.y + crop.height)
+            .into_par_iter()
+            .for_each(|y| {
+                if let Some(resumed) = resumed(y) {
+                    write_row(y, &resumed);
+                    return;
+                }
+                let mut row = vec![0; self.width * 3];
+                for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                    if !crop.contains(x, y) {
+                        continue;
+                    }
+                    let mut color = Color::new(0.0, 0.0, 0.0);
+                    for i in 0..samples {
+                        let (sx, sy) = self.sampler.sample(x, y, i, samples);
+                        color += trace(&self.ray_at_relative(
+                            (x as Scalar + sx) * dx,
+                            1.0 - (y as Scalar + sy) * dy,
+                        ));
                     }
+                    color *= 255.0 / samples as Scalar;
+                    pixel.copy_from_slice(&[color.x as u8, color.y as u8, color.z as u8]);
                 }
-                color *= 255.0 / (subp * subp) as Scalar;
-                *pixel = [color.x as u8, color.y as u8, color.z as u8].into();
-            }
-        });
-        img
+                if let Some(Err(e)) = checkpoint.map(|c| c.lock().unwrap().finish_row(y, &row)) {
+                    eprintln!("Failed to save render checkpoint: {}", e);
+                }
+                write_row(y, &row);
+                progress(RenderProgress {
+                    rows_done: rows_done.fetch_add(1, Ordering::Relaxed) + 1,
+                    rows,
+                    elapsed: start.elapsed(),
+                });
+            });
     }
 
     pub fn reposition(
@@ -120,49 +449,70 @@ impl Camera {
         up: Vector3,
         fovy: Scalar,
     ) -> Camera {
-        Camera::new(position, center, up, fovy, self.width, self.height)
+        Camera {
+            sampler: self.sampler.clone(),
+            integrator: self.integrator.clone(),
+            ..Camera::new(position, center, up, fovy, self.width, self.height)
+        }
     }
 
+    /// Render an animation of `frames` frames and encode it to a video at `path`. Without a
+    /// path, the frames are rendered but not encoded, as for benchmarks.
+    #[allow(clippy::too_many_arguments)]
     pub fn render_animation<
-        SFn: Fn(u32) -> Result<Scene, EvalError>,
-        CFn: Fn(u32, &Camera) -> Result<Camera, EvalError>,
+        E,
+        SFn: Fn(u32) -> Result<Scene, E>,
+        CFn: Fn(u32, &Camera) -> Result<Camera, E>,
     >(
         &self,
-        path: &Path,
+        path: Option<&Path>,
         scene_fn: SFn,
         update_cam: CFn,
         frames: u32,
         fps: u32,
         depth: u32,
         subp: u32,
-    ) -> Result<(), RTError> {
-        let mut encoder = Encoder::new(
-            path,
-            Settings::preset_h264_yuv420p(self.width, self.height, false),
-        )?;
+    ) -> Result<(), RTError>
+    where
+        RTError: From<E>,
+    {
+        let mut encoder = path
+            .map(|path| {
+                Encoder::new(
+                    path,
+                    Settings::preset_h264_yuv420p(self.width, self.height, false),
+                )
+            })
+            .transpose()?;
         let frame_duration = Time::from_nth_of_a_second(fps as usize);
         let mut timestamp = Time::zero();
 
         let mut cam = self.to_owned();
         for t in 0..frames {
-            println!(
-                "Rendering frame {}/{} for {}",
-                t + 1,
-                frames,
-                path.display()
-            );
+            match path {
+                Some(path) => println!(
+                    "Rendering frame {}/{} for {}",
+                    t + 1,
+                    frames,
+                    path.display()
+                ),
+                None => println!("Rendering frame {}/{}", t + 1, frames),
+            }
             cam = update_cam(t, &cam)?;
             let img = cam.render(&scene_fn(t)?, depth, subp);
 
-            let frame = Array3::from_shape_fn((self.height, self.width, 3), |(y, x, c)| {
-                img.get_pixel(x as u32, y as u32)[c]
-            });
-
-            encoder.encode(&frame, timestamp)?;
+            if let Some(encoder) = encoder.as_mut() {
+                let frame = Array3::from_shape_fn((self.height, self.width, 3), |(y, x, c)| {
+                    img.get_pixel(x as u32, y as u32)[c]
+                });
+                encoder.encode(&frame, timestamp)?;
+            }
             timestamp = timestamp.aligned_with(frame_duration).add();
         }
 
-        encoder.finish()?;
+        if let Some(encoder) = encoder.as_mut() {
+            encoder.finish()?;
+        }
         Ok(())
     }
 }
@@ -179,3 +529,97 @@ impl PartialOrd for Camera {
         None
     }
 }
+
+#[test]
+fn test_project() {
+    let cam = Camera::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        200,
+        100,
+    );
+
+    for (x, y) in [(0, 0), (100, 50), (37, 81)] {
+        let ray = cam.ray_at(x, y);
+        let (px, py) = cam.project(ray.origin + ray.direction * 5.0).unwrap();
+        assert!((px - x as Scalar).abs() < 1e-9 && (py - y as Scalar).abs() < 1e-9);
+    }
+    assert_eq!(cam.project(Point3::new(0.0, 0.0, 1.0)), None);
+}
+
+#[test]
+fn test_stereo_pair() {
+    let cam = Camera::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        200,
+        100,
+    );
+
+    let (left, right) = cam.stereo_pair(0.1);
+    assert!((left.position - Point3::new(-0.05, 0.0, 0.0)).norm() < 1e-9);
+    assert!((right.position - Point3::new(0.05, 0.0, 0.0)).norm() < 1e-9);
+    assert!((left.x_dir - cam.x_dir).norm() < 1e-9);
+    assert!((right.y_dir - cam.y_dir).norm() < 1e-9);
+}
+
+#[test]
+fn test_crop() {
+    let cam = Camera::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        20,
+        10,
+    )
+    .with_crop(Crop::new(15, 2, 10, 3));
+    assert_eq!(cam.crop, Some(Crop::new(15, 2, 5, 3)));
+
+    let mut img = RgbImage::new(20, 10);
+    cam.render_with(1, None, &mut img, |_| {}, |_| Color::new(1.0, 1.0, 1.0));
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let expected = if cam.crop.unwrap().contains(x as usize, y as usize) {
+            255
+        } else {
+            0
+        };
+        assert_eq!(pixel.0, [expected; 3]);
+    }
+}
+
+#[test]
+fn test_resume_from_checkpoint() {
+    let cam = Camera::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        2,
+        3,
+    );
+    let dir = std::env::temp_dir().join(format!("lispers-resume-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut checkpoint = Checkpoint::new(&dir.join("out.png.checkpoint"), Duration::MAX, 2, 3, 0);
+    checkpoint.finish_row(1, &[7; 6]).unwrap();
+    let checkpoint = Mutex::new(checkpoint);
+
+    let mut img = RgbImage::new(2, 3);
+    cam.render_with(
+        1,
+        Some(&checkpoint),
+        &mut img,
+        |_| {},
+        |_| Color::new(1.0, 1.0, 1.0),
+    );
+    assert_eq!(img.as_raw()[..6], [255; 6]);
+    assert_eq!(img.as_raw()[6..12], [7; 6]);
+    assert_eq!(img.as_raw()[12..], [255; 6]);
+    assert_eq!(checkpoint.lock().unwrap().rows_done(), 3);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
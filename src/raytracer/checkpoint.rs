@@ -0,0 +1,252 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use image::RgbImage;
+
+/// Identifies a checkpoint file and the version of its format.
+const MAGIC: &[u8; 4] = b"LRC2";
+
+/// Get the fingerprint of a render, like the scene, camera and settings, from their debug
+/// representation, which includes the ids and groups of objects and the links of lights. A
+/// checkpoint is only resumed by a render with the same fingerprint.
+#[cfg(feature = "lisp-bindings")]
+pub fn fingerprint(render: impl std::fmt::Debug) -> u64 {
+    use std::fmt::Write as _;
+    use std::hash::Hasher as _;
+
+    let mut hash = lispers_core::hash::Fnv1a::default();
+    write!(hash, "{:?}", render).unwrap();
+    hash.finish()
+}
+
+/// The finished rows of a render in progress, which are periodically saved to a file so an
+/// interrupted render can be resumed.
+///
+/// The file contains the image size, the fingerprint of the render, a flag for each row and the
+/// RGB data of the image.
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// The checkpoint file.
+    path: PathBuf,
+    /// The minimum time between two saves.
+    interval: Duration,
+    /// The time of the last save.
+    last_save: Instant,
+    /// The image, which is only valid in finished rows.
+    image: RgbImage,
+    /// Whether each row is finished.
+    done: Vec<bool>,
+    /// The fingerprint of the render, see `fingerprint`.
+    fingerprint: u64,
+}
+
+impl Checkpoint {
+    /// Create an empty checkpoint for an image of `width` x `height` rendered with the given
+    /// `fingerprint`, saved to `path` at most once per `interval`.
+    pub fn new(
+        path: &Path,
+        interval: Duration,
+        width: usize,
+        height: usize,
+        fingerprint: u64,
+    ) -> Checkpoint {
+        Checkpoint {
+            path: path.to_path_buf(),
+            interval,
+            last_save: Instant::now(),
+            image: RgbImage::new(width as u32, height as u32),
+            done: vec![false; height],
+            fingerprint,
+        }
+    }
+
+    /// Load the checkpoint at `path`, which has to belong to an image of `width` x `height`
+    /// rendered with the given `fingerprint`.
+    pub fn load(
+        path: &Path,
+        interval: Duration,
+        width: usize,
+        height: usize,
+        fingerprint: u64,
+    ) -> io::Result<Checkpoint> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 20];
+        reader.read_exact(&mut header)?;
+        let size = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a render checkpoint", path.display()),
+            ));
+        }
+        if (size(4) as usize, size(8) as usize) != (width, height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Checkpoint {} is for a {}x{} image, expected {}x{}",
+                    path.display(),
+                    size(4),
+                    size(8),
+                    width,
+                    height
+                ),
+            ));
+        }
+        if u64::from_le_bytes(header[12..].try_into().unwrap()) != fingerprint {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Checkpoint {} is for a different scene, camera or render settings",
+                    path.display()
+                ),
+            ));
+        }
+
+        let mut done = vec![0u8; height];
+        reader.read_exact(&mut done)?;
+        let mut data = vec![0u8; width * height * 3];
+        reader.read_exact(&mut data)?;
+
+        Ok(Checkpoint {
+            path: path.to_path_buf(),
+            interval,
+            last_save: Instant::now(),
+            image: RgbImage::from_raw(width as u32, height as u32, data).unwrap(),
+            done: done.into_iter().map(|d| d != 0).collect(),
+            fingerprint,
+        })
+    }
+
+    /// Get the finished row `y`, if any.
+    pub fn row(&self, y: usize) -> Option<&[u8]> {
+        let stride = self.image.width() as usize * 3;
+        match self.done[y] {
+            true => Some(&self.image.as_raw()[y * stride..(y + 1) * stride]),
+            false => None,
+        }
+    }
+
+    /// Get the number of finished rows.
+    pub fn rows_done(&self) -> usize {
+        self.done.iter().filter(|d| **d).count()
+    }
+
+    /// Store the RGB data of a finished row and save the checkpoint if the save interval has
+    /// passed.
+    pub fn finish_row(&mut self, y: usize, row: &[u8]) -> io::Result<()> {
+        let stride = self.image.width() as usize * 3;
+        self.image.as_mut()[y * stride..(y + 1) * stride].copy_from_slice(row);
+        self.done[y] = true;
+
+        if self.last_save.elapsed() >= self.interval {
+            self.last_save = Instant::now();
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Save the checkpoint. The file is replaced atomically, so an interrupted save keeps the
+    /// previous checkpoint.
+    pub fn save(&self) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.image.width().to_le_bytes())?;
+        writer.write_all(&self.image.height().to_le_bytes())?;
+        writer.write_all(&self.fingerprint.to_le_bytes())?;
+        writer.write_all(&self.done.iter().map(|d| *d as u8).collect::<Vec<_>>())?;
+        writer.write_all(self.image.as_raw())?;
+        writer.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, &self.path)
+    }
+
+    /// Remove the checkpoint file, if it was saved.
+    pub fn remove(&self) -> io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_checkpoint_roundtrip() {
+    let path = std::env::temp_dir().join(format!("lispers-checkpoint-{}", std::process::id()));
+    let render = 0x5eed;
+    let mut checkpoint = Checkpoint::new(&path, Duration::ZERO, 2, 3, render);
+    checkpoint.finish_row(1, &[1, 2, 3, 4, 5, 6]).unwrap();
+
+    let loaded = Checkpoint::load(&path, Duration::ZERO, 2, 3, render).unwrap();
+    assert_eq!(loaded.rows_done(), 1);
+    assert_eq!(loaded.row(0), None);
+    assert_eq!(loaded.row(1), Some([1, 2, 3, 4, 5, 6].as_slice()));
+    assert!(Checkpoint::load(&path, Duration::ZERO, 3, 3, render).is_err());
+    assert!(Checkpoint::load(&path, Duration::ZERO, 2, 3, render + 1).is_err());
+
+    loaded.remove().unwrap();
+    assert!(!path.exists());
+}
+
+#[cfg(feature = "lisp-bindings")]
+#[test]
+fn test_fingerprint() {
+    use super::scene::Scene;
+    use super::sphere::Sphere;
+    use super::types::{Color, Light, LightLinks, Material, Point3, RTObjectWrapper};
+
+    let material = Material::new(
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(1.0, 1.0, 1.0),
+        Color::new(0.0, 0.0, 0.0),
+        1.0,
+        0.0,
+    );
+    let sphere = |x| RTObjectWrapper::from(Sphere::new(Point3::new(x, 0.0, 0.0), 1.0, material));
+    let (a, b) = (sphere(0.0), sphere(5.0));
+    let scene = |objects: &[&RTObjectWrapper], links: LightLinks| {
+        let mut light = Light::new(Point3::new(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
+        light.link(links);
+        let mut scene = Scene::new();
+        for object in objects {
+            scene.add_object((*object).clone());
+        }
+        scene.add_light(light);
+        fingerprint(scene)
+    };
+    let key = || LightLinks {
+        objects: vec![],
+        groups: vec!["key".to_string()],
+    };
+
+    // The fingerprint must not depend on the build
+    assert_eq!(fingerprint(""), 0x07cc7607b4949e25);
+
+    let path = std::env::temp_dir().join(format!("lispers-fingerprint-{}", std::process::id()));
+    let render = scene(&[&a, &b], key());
+    Checkpoint::new(&path, Duration::ZERO, 2, 3, render)
+        .finish_row(0, &[0; 6])
+        .unwrap();
+
+    // Linking the light to other objects invalidates the checkpoint, by group or by id
+    let mut tagged = b.clone();
+    tagged.add_group("key".to_string());
+    assert_ne!(scene(&[&a, &tagged], key()), render);
+    let by_id = || LightLinks {
+        objects: vec![b.id()],
+        groups: vec![],
+    };
+    assert_ne!(scene(&[&a, &b], by_id()), render);
+    // An equal object with another id is not linked
+    assert_ne!(
+        scene(&[&a, &sphere(5.0)], by_id()),
+        scene(&[&a, &b], by_id())
+    );
+    let other = scene(&[&a, &tagged], key());
+    assert!(Checkpoint::load(&path, Duration::ZERO, 2, 3, other).is_err());
+    let checkpoint = Checkpoint::load(&path, Duration::ZERO, 2, 3, render).unwrap();
+    checkpoint.remove().unwrap();
+}
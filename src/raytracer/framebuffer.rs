@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use image::{ImageError, Rgb, RgbImage};
+
+/// An image a camera renders into. The renderer only needs to set pixels, so embedders can
+/// render into their own buffers, like textures to upload to a GPU or the canvas of a GUI.
+/// `RgbImage` of the `image` crate is the default implementation.
+pub trait Framebuffer {
+    /// The error of saving the image.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Get the image size `(width, height)` in pixels.
+    fn dimensions(&self) -> (usize, usize);
+
+    /// Set the color of the pixel at `x`, `y`, counted from the top left corner.
+    fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]);
+
+    /// Save the image to a file.
+    fn save(&self, path: &Path) -> Result<(), Self::Error>;
+}
+
+impl Framebuffer for RgbImage {
+    type Error = ImageError;
+
+    fn dimensions(&self) -> (usize, usize) {
+        (self.width() as usize, self.height() as usize)
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, rgb: [u8; 3]) {
+        self.put_pixel(x as u32, y as u32, Rgb(rgb));
+    }
+
+    fn save(&self, path: &Path) -> Result<(), ImageError> {
+        RgbImage::save(self, path)
+    }
+}
+
+#[test]
+fn test_render_into() {
+    use super::camera::Camera;
+    use super::scene::Scene;
+    use super::types::{Point3, Vector3};
+
+    /// A buffer which counts how often each pixel is set.
+    struct Counts {
+        width: usize,
+        counts: Vec<u32>,
+    }
+
+    impl Framebuffer for Counts {
+        type Error = std::io::Error;
+
+        fn dimensions(&self) -> (usize, usize) {
+            (self.width, self.counts.len() / self.width)
+        }
+
+        fn set_pixel(&mut self, x: usize, y: usize, _rgb: [u8; 3]) {
+            self.counts[y * self.width + x] += 1;
+        }
+
+        fn save(&self, _path: &Path) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+    }
+
+    let cam = Camera::new(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        90.0,
+        20,
+        10,
+    );
+    let mut buffer = Counts {
+        width: 4,
+        counts: vec![0; 8],
+    };
+    cam.render_into(&Scene::new(), 1, 1, None, &mut buffer, |_| {});
+    assert_eq!(buffer.counts, [1; 8]);
+}
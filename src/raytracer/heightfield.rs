@@ -0,0 +1,328 @@
+use super::types::{Intersect, Material, Point3, Ray, Scalar, Vector3};
+
+extern crate nalgebra as na;
+
+/// Numerical error tolerance
+const EPSILON: Scalar = 1e-5;
+
+/// A terrain-like surface defined by a grid of heights over the xz-plane, centered at the origin.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Heightfield {
+    /// Number of grid cells per side
+    resolution: usize,
+    /// Side length of the square covered by the heightfield
+    extent: Scalar,
+    /// Heights at the `(resolution + 1)^2` grid vertices, row-major in z
+    heights: Vec<Scalar>,
+    /// Lowest height, used for bounding box tests
+    min_height: Scalar,
+    /// Highest height, used for bounding box tests
+    max_height: Scalar,
+    /// PHONG material of the surface
+    material: Material,
+}
+
+impl Heightfield {
+    /// Create a new heightfield by sampling `f(x, z)` at the vertices of a `resolution` x
+    /// `resolution` grid spanning `extent` in x and z direction.
+    /// - `resolution` is the number of grid cells per side (at least 1)
+    /// - `extent` is the side length of the covered square, centered at the origin
+    /// - `material` is the material of the whole surface
+    pub fn sample<E, F: FnMut(Scalar, Scalar) -> Result<Scalar, E>>(
+        resolution: usize,
+        extent: Scalar,
+        material: Material,
+        mut f: F,
+    ) -> Result<Heightfield, E> {
+        let resolution = resolution.max(1);
+        let cell = extent / resolution as Scalar;
+        let mut heights = Vec::with_capacity((resolution + 1) * (resolution + 1));
+
+        for j in 0..=resolution {
+            for i in 0..=resolution {
+                let x = -extent / 2.0 + i as Scalar * cell;
+                let z = -extent / 2.0 + j as Scalar * cell;
+                heights.push(f(x, z)?);
+            }
+        }
+
+        let min_height = heights.iter().cloned().fold(Scalar::MAX, Scalar::min);
+        let max_height = heights.iter().cloned().fold(Scalar::MIN, Scalar::max);
+
+        Ok(Heightfield {
+            resolution,
+            extent,
+            heights,
+            min_height,
+            max_height,
+            material,
+        })
+    }
+
+    /// Get the grid vertex at index `(i, j)`
+    fn vertex(&self, i: usize, j: usize) -> Point3 {
+        let cell = self.cell_size();
+        Point3::new(
+            -self.extent / 2.0 + i as Scalar * cell,
+            self.heights[j * (self.resolution + 1) + i],
+            -self.extent / 2.0 + j as Scalar * cell,
+        )
+    }
+
+    /// Side length of one grid cell
+    fn cell_size(&self) -> Scalar {
+        self.extent / self.resolution as Scalar
+    }
+
+    /// Intersect the ray with the bounding box, returning the parameter range inside of it.
+    fn intersect_bounds(&self, ray: &Ray) -> Option<(Scalar, Scalar)> {
+        let half = self.extent / 2.0;
+        let lower = [-half, self.min_height - EPSILON, -half];
+        let upper = [half, self.max_height + EPSILON, half];
+        let mut t_min = Scalar::MIN;
+        let mut t_max = Scalar::MAX;
+
+        for axis in 0..3 {
+            let o = ray.origin[axis];
+            let d = ray.direction[axis];
+            if d == 0.0 {
+                if o < lower[axis] || o > upper[axis] {
+                    return None;
+                }
+            } else {
+                let t1 = (lower[axis] - o) / d;
+                let t2 = (upper[axis] - o) / d;
+                t_min = t_min.max(t1.min(t2));
+                t_max = t_max.min(t1.max(t2));
+            }
+        }
+
+        if t_max >= t_min.max(0.0) {
+            Some((t_min.max(0.0), t_max))
+        } else {
+            None
+        }
+    }
+
+    /// Intersect the two triangles of grid cell `(i, j)`, returning the closest hit.
+    fn intersect_cell(&self, ray: &Ray, i: usize, j: usize) -> Option<(Point3, Vector3, Scalar)> {
+        let v00 = self.vertex(i, j);
+        let v10 = self.vertex(i + 1, j);
+        let v01 = self.vertex(i, j + 1);
+        let v11 = self.vertex(i + 1, j + 1);
+
+        [
+            intersect_triangle(ray, &v00, &v01, &v10),
+            intersect_triangle(ray, &v10, &v01, &v11),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by(|(_, _, t1), (_, _, t2)| t1.partial_cmp(t2).unwrap())
+    }
+}
+
+/// Intersect a ray with a triangle (Möller–Trumbore). The returned normal faces the ray origin.
+fn intersect_triangle(
+    ray: &Ray,
+    a: &Point3,
+    b: &Point3,
+    c: &Point3,
+) -> Option<(Point3, Vector3, Scalar)> {
+    let e1 = b - a;
+    let e2 = c - a;
+    let p = ray.direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&e1);
+    let v = ray.direction.dot(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(&q) * inv_det;
+    if t <= EPSILON {
+        return None;
+    }
+
+    let mut normal = e1.cross(&e2).normalize();
+    if normal.dot(&ray.direction) > 0.0 {
+        normal = -normal;
+    }
+
+    Some((ray.origin + ray.direction * t, normal, t))
+}
+
+impl Intersect for Heightfield {
+    /// Traverse the grid cells along the ray (2D DDA in the xz-plane) and return the first hit.
+    /// The traversal stops at `tmax`.
+    fn intersect(&self, ray: &Ray, tmax: Scalar) -> Option<(Point3, Vector3, Scalar, Material)> {
+        let (t_enter, t_exit) = self.intersect_bounds(ray)?;
+        if t_enter >= tmax {
+            return None;
+        }
+        let t_exit = t_exit.min(tmax);
+
+        let cell = self.cell_size();
+        let half = self.extent / 2.0;
+        let start = ray.origin + ray.direction * t_enter;
+        let to_cell =
+            |v: Scalar| (((v + half) / cell).floor().max(0.0) as usize).min(self.resolution - 1);
+        let (mut i, mut j) = (to_cell(start.x), to_cell(start.z));
+
+        // Parameter distance between cell borders and parameter of the next border per axis
+        let setup = |o: Scalar, d: Scalar, idx: usize| -> (Scalar, Scalar) {
+            if d > 0.0 {
+                let border = -half + (idx + 1) as Scalar * cell;
+                (cell / d, (border - o) / d)
+            } else if d < 0.0 {
+                let border = -half + idx as Scalar * cell;
+                (-cell / d, (border - o) / d)
+            } else {
+                (Scalar::MAX, Scalar::MAX)
+            }
+        };
+        let (t_delta_x, mut t_next_x) = setup(ray.origin.x, ray.direction.x, i);
+        let (t_delta_z, mut t_next_z) = setup(ray.origin.z, ray.direction.z, j);
+
+        loop {
+            if let Some((pt, normal, t)) = self.intersect_cell(ray, i, j) {
+                // Hits in later cells are farther away
+                return (t < tmax).then_some((pt, normal, t, self.material));
+            }
+
+            if t_next_x < t_next_z {
+                if t_next_x > t_exit {
+                    return None;
+                }
+                t_next_x += t_delta_x;
+                if ray.direction.x > 0.0 {
+                    i += 1;
+                    if i >= self.resolution {
+                        return None;
+                    }
+                } else {
+                    i = i.checked_sub(1)?;
+                }
+            } else {
+                if t_next_z > t_exit {
+                    return None;
+                }
+                t_next_z += t_delta_z;
+                if ray.direction.z > 0.0 {
+                    j += 1;
+                    if j >= self.resolution {
+                        return None;
+                    }
+                } else {
+                    j = j.checked_sub(1)?;
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Heightfield {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "(heightfield resolution: {}, extent: {}, height range: [{}, {}], material: {})",
+            self.resolution, self.extent, self.min_height, self.max_height, self.material
+        )
+    }
+}
+
+impl PartialOrd for Heightfield {
+    fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
+        None
+    }
+}
+
+/// Hash an integer lattice point to a pseudo random value in `[0, 1]`.
+fn lattice_value(x: i64, z: i64) -> Scalar {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    (h >> 11) as Scalar / (1u64 << 53) as Scalar
+}
+
+/// Smoothly interpolated 2D value noise with 4 octaves, returning values in `[0, 1]`.
+pub fn value_noise(x: Scalar, z: Scalar) -> Scalar {
+    let octave = |x: Scalar, z: Scalar| {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (fx, fz) = (x - x0, z - z0);
+        let (sx, sz) = (fx * fx * (3.0 - 2.0 * fx), fz * fz * (3.0 - 2.0 * fz));
+        let (ix, iz) = (x0 as i64, z0 as i64);
+
+        let top = lattice_value(ix, iz) * (1.0 - sx) + lattice_value(ix + 1, iz) * sx;
+        let bottom = lattice_value(ix, iz + 1) * (1.0 - sx) + lattice_value(ix + 1, iz + 1) * sx;
+        top * (1.0 - sz) + bottom * sz
+    };
+
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+    for _ in 0..4 {
+        value += amplitude * octave(x * frequency, z * frequency);
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    value / 0.9375
+}
+
+#[test]
+fn test_heightfield_intersect() {
+    use super::types::Color;
+
+    let material = Material::new(
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(0.0, 0.0, 0.0),
+        0.0,
+        0.0,
+    );
+    let ramp = Heightfield::sample(8, 4.0, material, |x, _| Ok::<_, ()>(0.5 * x)).unwrap();
+
+    // Straight down onto the ramp
+    let down = Ray::new(Point3::new(1.0, 5.0, 0.3), Vector3::new(0.0, -1.0, 0.0));
+    let (pt, normal, t, _) = ramp.intersect(&down, Scalar::MAX).unwrap();
+    assert!((pt.y - 0.5).abs() < 1e-9);
+    assert!((t - 4.5).abs() < 1e-9);
+    assert!(normal.y > 0.0 && normal.x < 0.0);
+    // Hits beyond `tmax` are rejected
+    assert!(ramp.intersect(&down, 4.0).is_none());
+
+    // Grazing ray traversing several cells before hitting
+    let grazing = Ray::new(Point3::new(-3.0, 0.0, 0.1), Vector3::new(1.0, 0.0, 0.0));
+    let hit = ramp.intersect(&grazing, Scalar::MAX);
+    assert!((hit.unwrap().0.x).abs() < 1e-9);
+    assert!(ramp.intersect(&grazing, 2.0).is_none());
+
+    // Passing above and beside the surface
+    assert!(
+        ramp.intersect(
+            &Ray::new(Point3::new(-3.0, 5.0, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+            Scalar::MAX
+        )
+        .is_none()
+    );
+    assert!(
+        ramp.intersect(
+            &Ray::new(Point3::new(5.0, 5.0, 5.0), Vector3::new(0.0, -1.0, 0.0)),
+            Scalar::MAX
+        )
+        .is_none()
+    );
+}
@@ -0,0 +1,223 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::scene::Scene;
+use super::spectral::Channel;
+use super::spectral::bands_to_rgb;
+use super::types::Color;
+use super::types::Ray;
+use super::types::Scalar;
+use super::vec::reflect;
+use super::vec::refract;
+
+/// A rendering algorithm, which computes the color seen along a ray in a scene. New algorithms
+/// implement this trait and use the intersection and lighting of `Scene`.
+pub trait Integrator: Debug + Sync + Send {
+    /// Get the color seen along `ray` in `channel`, with at most `depth` bounces.
+    fn radiance(&self, scene: &Scene, ray: &Ray, depth: u32, channel: Channel) -> Color;
+
+    /// Get the color seen along `ray`, traced separately for each wavelength band and converted
+    /// to RGB. `wavelengths` are the center wavelengths [nm] of the bands.
+    fn radiance_spectral(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        depth: u32,
+        wavelengths: &[Scalar],
+    ) -> Color {
+        let radiances: Vec<Scalar> = wavelengths
+            .iter()
+            .map(|w| self.radiance(scene, ray, depth, Channel::Band(*w)).x)
+            .collect();
+        bands_to_rgb(wavelengths, &radiances)
+    }
+}
+
+/// Whitted style ray tracing: Phong lighting with hard shadows, perfect mirror reflections and
+/// refractions. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhittedIntegrator;
+
+impl WhittedIntegrator {
+    /// Trace a ray like `radiance`. `inside` tells whether the ray travels inside of a
+    /// refractive object.
+    fn trace(&self, scene: &Scene, ray: &Ray, depth: u32, channel: Channel, inside: bool) -> Color {
+        if depth == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        match scene.intersect(ray) {
+            Some((obj, (isect_pt, isect_norm, _, material))) => {
+                // Lighting of material at the intersection point
+                let color = scene.lighting(
+                    obj,
+                    -&ray.direction,
+                    &material,
+                    isect_pt,
+                    isect_norm,
+                    channel,
+                );
+                let mut color = (1.0 - material.mirror - material.transparency).max(0.0) * color;
+
+                // Calculate reflections, if the material has mirror properties
+                if material.mirror > 0.0 {
+                    let new_ray = Ray {
+                        origin: isect_pt,
+                        direction: reflect(ray.direction, isect_norm),
+                    };
+                    color +=
+                        material.mirror * self.trace(scene, &new_ray, depth - 1, channel, inside);
+                }
+
+                // Calculate refractions (or total internal reflection), if the material is
+                // transparent
+                if material.transparency > 0.0 {
+                    let ior = channel.ior(&material);
+                    let eta = if inside { ior } else { 1.0 / ior };
+                    let normal = if isect_norm.dot(&ray.direction) > 0.0 {
+                        -isect_norm
+                    } else {
+                        isect_norm
+                    };
+                    let (direction, inside) = match refract(ray.direction, normal, eta) {
+                        Some(d) => (d.normalize(), !inside),
+                        None => (reflect(ray.direction, normal), inside),
+                    };
+                    let new_ray = Ray {
+                        origin: isect_pt,
+                        direction,
+                    };
+                    color += material.transparency
+                        * self.trace(scene, &new_ray, depth - 1, channel, inside);
+                }
+
+                color
+            }
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, scene: &Scene, ray: &Ray, depth: u32, channel: Channel) -> Color {
+        self.trace(scene, ray, depth, channel, false)
+    }
+}
+
+/// Shows the normals of the first intersection, mapped from `[-1, 1]` to colors in `[0, 1]`.
+/// Useful to debug the geometry of objects.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(&self, scene: &Scene, ray: &Ray, _depth: u32, channel: Channel) -> Color {
+        match scene.intersect(ray) {
+            Some((_, (_, isect_norm, _, _))) => {
+                channel.project(&((isect_norm.normalize() + Color::repeat(1.0)) / 2.0))
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Shows the distance to the first intersection, from white right at the camera fading to black
+/// far away. Useful to debug the placement of objects.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator {
+    fn radiance(&self, scene: &Scene, ray: &Ray, _depth: u32, channel: Channel) -> Color {
+        match scene.intersect(ray) {
+            Some((_, (_, _, t, _))) => channel.project(&Color::repeat(1.0 / (1.0 + t))),
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// A shared integrator, which is compared by identity.
+#[derive(Clone, Debug)]
+pub struct IntegratorWrapper(Arc<dyn Integrator>);
+
+impl IntegratorWrapper {
+    pub fn new<I: Integrator + 'static>(integrator: I) -> Self {
+        Self(Arc::new(integrator))
+    }
+
+    /// Get a builtin integrator by its name: `whitted`, `normals` or `depth`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "whitted" => Some(Self::new(WhittedIntegrator)),
+            "normals" => Some(Self::new(NormalsIntegrator)),
+            "depth" => Some(Self::new(DepthIntegrator)),
+            _ => None,
+        }
+    }
+
+    /// Get the RGB color seen along `ray`, with at most `depth` bounces.
+    pub fn trace(&self, scene: &Scene, ray: &Ray, depth: u32) -> Color {
+        self.0.radiance(scene, ray, depth, Channel::Rgb)
+    }
+
+    /// Get the color seen along `ray` like `trace`, traced separately for each of the
+    /// `wavelengths` (see `Integrator::radiance_spectral`).
+    pub fn trace_spectral(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        depth: u32,
+        wavelengths: &[Scalar],
+    ) -> Color {
+        self.0.radiance_spectral(scene, ray, depth, wavelengths)
+    }
+}
+
+impl Default for IntegratorWrapper {
+    fn default() -> Self {
+        Self::new(WhittedIntegrator)
+    }
+}
+
+impl PartialEq for IntegratorWrapper {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[test]
+fn test_integrators() {
+    use super::sphere::Sphere;
+    use super::types::{Light, Material, Point3, RTObjectWrapper, Vector3};
+
+    let material = Material::new(
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(1.0, 1.0, 1.0),
+        Color::new(0.0, 0.0, 0.0),
+        1.0,
+        0.0,
+    );
+    let mut scene = Scene::new();
+    scene.add_object(RTObjectWrapper::from(Sphere::new(
+        Point3::new(0.0, 0.0, 0.0),
+        1.0,
+        material,
+    )));
+    scene.add_light(Light::new(
+        Point3::new(0.0, 0.0, 10.0),
+        Color::new(1.0, 1.0, 1.0),
+    ));
+    let hit = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let miss = Ray::new(Point3::new(5.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let rgb = |name: &str, ray: &Ray| {
+        IntegratorWrapper::from_name(name)
+            .unwrap()
+            .trace(&scene, ray, 1)
+    };
+
+    assert_eq!(rgb("whitted", &hit), scene.trace(&hit, 1));
+    assert!((rgb("normals", &hit) - Color::new(0.5, 0.5, 1.0)).norm() < 1e-9);
+    assert!((rgb("depth", &hit) - Color::repeat(0.2)).norm() < 1e-9);
+    for name in ["whitted", "normals", "depth"] {
+        assert_eq!(rgb(name, &miss), Color::new(0.0, 0.0, 0.0));
+    }
+    assert!(IntegratorWrapper::from_name("pathtracer").is_none());
+}
@@ -1,63 +1,112 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::ops::Deref;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use crate::autodiff::{add_dual, div_dual, mul_dual, sub_dual};
 use crate::raytracer::{
     scene::Scene,
     sphere::TextureSphere,
     texture::TextureWrapper,
-    types::{Light, Point2},
+    types::{Light, LightLinks, LightProfile, Point2},
 };
 
-use lispers_macro::{native_lisp_function, native_lisp_function_proxy};
+use image::RgbImage;
+use lispers_macro::{
+    collect_module, native_lisp_function, native_lisp_function_proxy, register_lisp_function,
+};
 
 use lispers_core::lisp::{
+    builder::EnvironmentBuilder,
     environment::EnvironmentLayer,
     eval::{eval, EvalError},
-    expression::ForeignDataWrapper,
+    expression::{ForeignData, ForeignDataWrapper},
+    hooks::run_hook,
+    prelude::{add_numbers, binary_operands, div_numbers, mul_numbers, sub_numbers},
+    version::language_version,
     Environment, Expression,
 };
 
 use super::{
-    camera::Camera,
+    camera::{Camera, Crop, RenderProgress},
+    checkpoint::{fingerprint, Checkpoint},
+    heightfield::{value_noise, Heightfield},
+    integrator::IntegratorWrapper,
     plane::{Checkerboard, Plane, TexturePlane},
+    sampler::SamplerWrapper,
+    spectral::Spectrum,
     sphere::Sphere,
     texture::MandelbrotTexture,
     types::{Color, Material, Point3, RTObjectWrapper, Vector3},
     RTError,
 };
 
-#[native_lisp_function(eval)]
-pub fn point(x: f64, y: f64, z: f64) -> Result<ForeignDataWrapper<Point3>, EvalError> {
-    Ok(ForeignDataWrapper::new(Point3::new(x, y, z)))
+/// A point, vector or color argument. Besides a value of the wrapped type, a list of three
+/// numbers like `'(1 2 3)` is accepted.
+pub struct Coords<T>(pub T);
+
+impl<T> Deref for Coords<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: ForeignData + From<[f64; 3]>> TryFrom<Expression> for Coords<T> {
+    type Error = EvalError;
+
+    fn try_from(value: Expression) -> Result<Self, Self::Error> {
+        match value {
+            Expression::Cell(_, _) => {
+                let xyz: [f64; 3] = value.try_into().map_err(|_| {
+                    EvalError::TypeError("Expected a list of three numbers".to_string())
+                })?;
+                Ok(Coords(T::from(xyz)))
+            }
+            x => {
+                let w: ForeignDataWrapper<T> = x.try_into()?;
+                Ok(Coords(*w.0))
+            }
+        }
+    }
+}
+
+#[native_lisp_function(eval, module = raytrace)]
+pub fn point(x: f64, y: f64, z: f64) -> ForeignDataWrapper<Point3> {
+    ForeignDataWrapper::new(Point3::new(x, y, z))
 }
 
-#[native_lisp_function(eval)]
-pub fn point2(x: f64, y: f64) -> Result<ForeignDataWrapper<Point2>, EvalError> {
-    Ok(ForeignDataWrapper::new(Point2::new(x, y)))
+#[native_lisp_function(eval, module = raytrace)]
+pub fn point2(x: f64, y: f64) -> ForeignDataWrapper<Point2> {
+    ForeignDataWrapper::new(Point2::new(x, y))
 }
 
-#[native_lisp_function(eval)]
-pub fn vector(x: f64, y: f64, z: f64) -> Result<ForeignDataWrapper<Vector3>, EvalError> {
-    Ok(ForeignDataWrapper::new(Vector3::new(x, y, z)))
+#[native_lisp_function(eval, module = raytrace)]
+pub fn vector(x: f64, y: f64, z: f64) -> ForeignDataWrapper<Vector3> {
+    ForeignDataWrapper::new(Vector3::new(x, y, z))
 }
 
-#[native_lisp_function(eval)]
-pub fn color(r: f64, g: f64, b: f64) -> Result<ForeignDataWrapper<Color>, EvalError> {
-    Ok(ForeignDataWrapper::new(Color::new(r, g, b)))
+#[native_lisp_function(eval, module = raytrace)]
+pub fn color(r: f64, g: f64, b: f64) -> ForeignDataWrapper<Color> {
+    ForeignDataWrapper::new(Color::new(r, g, b))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn light(
-    pos: ForeignDataWrapper<Point3>,
-    col: ForeignDataWrapper<Color>,
+    pos: Coords<Point3>,
+    col: Coords<Color>,
 ) -> Result<ForeignDataWrapper<Light>, EvalError> {
     Ok(ForeignDataWrapper::new(Light::new(*pos, *col)))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn material(
-    amb: ForeignDataWrapper<Color>,
-    dif: ForeignDataWrapper<Color>,
-    spe: ForeignDataWrapper<Color>,
+    amb: Coords<Color>,
+    dif: Coords<Color>,
+    spe: Coords<Color>,
     shi: f64,
     mir: f64,
 ) -> Result<ForeignDataWrapper<Material>, EvalError> {
@@ -66,18 +115,48 @@ pub fn material(
     )))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
+pub fn material_refraction(
+    mat: ForeignDataWrapper<Material>,
+    tra: f64,
+    ior: f64,
+    dis: f64,
+) -> Result<ForeignDataWrapper<Material>, EvalError> {
+    Ok(ForeignDataWrapper::new(mat.with_refraction(tra, ior, dis)))
+}
+
+/// Set the reflectance spectrum of a material for spectral rendering, sampling a lisp
+/// function of the wavelength in nm.
+/// `(material-spectrum mat (lambda (wavelength) ...))`
+#[register_lisp_function(module = raytrace)]
+pub fn material_spectrum(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [mat, f]: [Expression; 2] = expr.try_into()?;
+
+    let mat: ForeignDataWrapper<Material> = eval(env, mat)?.try_into()?;
+    let f = eval(env, f)?;
+
+    let reflectance = Spectrum::sample(|wavelength| {
+        let call: Expression = [f.clone(), wavelength.into()].into();
+        eval(env, call)?.try_into() as Result<f64, EvalError>
+    })?;
+
+    Ok(ForeignDataWrapper::new(mat.with_reflectance(reflectance)).into())
+}
+
+#[native_lisp_function(eval, module = raytrace)]
 pub fn sphere(
-    pos: ForeignDataWrapper<Point3>,
+    pos: Coords<Point3>,
     rad: f64,
     mat: ForeignDataWrapper<Material>,
 ) -> Result<ForeignDataWrapper<RTObjectWrapper>, EvalError> {
-    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(Sphere::new(*pos, rad, *mat))).into())
+    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(Sphere::new(
+        *pos, rad, *mat,
+    ))))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn texture_sphere(
-    pos: ForeignDataWrapper<Point3>,
+    pos: Coords<Point3>,
     rad: f64,
     tex: ForeignDataWrapper<TextureWrapper>,
 ) -> Result<ForeignDataWrapper<RTObjectWrapper>, EvalError> {
@@ -86,65 +165,56 @@ pub fn texture_sphere(
             *pos,
             rad,
             tex.clone(),
-        )))
-        .into(),
+        ))),
     )
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn plane(
-    pos: ForeignDataWrapper<Point3>,
-    dir: ForeignDataWrapper<Vector3>,
+    pos: Coords<Point3>,
+    dir: Coords<Vector3>,
     mat: ForeignDataWrapper<Material>,
 ) -> Result<ForeignDataWrapper<RTObjectWrapper>, EvalError> {
-    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(Plane::new(*pos, *dir, *mat))).into())
+    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(Plane::new(
+        *pos, *dir, *mat,
+    ))))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn checkerboard(
-    pos: ForeignDataWrapper<Point3>,
-    norm: ForeignDataWrapper<Vector3>,
+    pos: Coords<Point3>,
+    norm: Coords<Vector3>,
     mat1: ForeignDataWrapper<Material>,
     mat2: ForeignDataWrapper<Material>,
     sca: f64,
-    up: ForeignDataWrapper<Vector3>,
+    up: Coords<Vector3>,
 ) -> Result<ForeignDataWrapper<RTObjectWrapper>, EvalError> {
-    Ok(
-        ForeignDataWrapper::new(RTObjectWrapper::from(Checkerboard::new(
-            *pos, *norm, *mat1, *mat2, sca, *up,
-        )))
-        .into(),
-    )
+    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(
+        Checkerboard::new(*pos, *norm, *mat1, *mat2, sca, *up),
+    )))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn texture_plane(
     texture: ForeignDataWrapper<TextureWrapper>,
-    pos: ForeignDataWrapper<Point3>,
-    norm: ForeignDataWrapper<Vector3>,
+    pos: Coords<Point3>,
+    norm: Coords<Vector3>,
     sca: f64,
-    up: ForeignDataWrapper<Vector3>,
+    up: Coords<Vector3>,
 ) -> Result<ForeignDataWrapper<RTObjectWrapper>, EvalError> {
-    Ok(
-        ForeignDataWrapper::new(RTObjectWrapper::from(TexturePlane::new(
-            *pos,
-            *norm,
-            texture.clone(),
-            sca,
-            *up,
-        )))
-        .into(),
-    )
+    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(
+        TexturePlane::new(*pos, *norm, texture.clone(), sca, *up),
+    )))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn mandelbrot_texture(
     scale: f64,
     at: ForeignDataWrapper<Point2>,
     max_iter: i64,
-    ambient_color: ForeignDataWrapper<Color>,
-    diffuse_color: ForeignDataWrapper<Color>,
-    specular_color: ForeignDataWrapper<Color>,
+    ambient_color: Coords<Color>,
+    diffuse_color: Coords<Color>,
+    specular_color: Coords<Color>,
 ) -> Result<ForeignDataWrapper<TextureWrapper>, EvalError> {
     Ok(ForeignDataWrapper::new(TextureWrapper::new(
         MandelbrotTexture::new(
@@ -158,10 +228,127 @@ pub fn mandelbrot_texture(
     )))
 }
 
+/// Create a heightfield by sampling a lisp function `(lambda (x z) ...)` on a grid.
+/// `(heightfield f resolution extent material)`
+#[register_lisp_function(module = raytrace)]
+pub fn heightfield(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [f, res, ext, mat]: [Expression; 4] = expr.try_into()?;
+
+    let f = eval(env, f)?;
+    let res: i64 = eval(env, res)?.try_into()?;
+    let ext: f64 = eval(env, ext)?.try_into()?;
+    let mat: ForeignDataWrapper<Material> = eval(env, mat)?.try_into()?;
+
+    if res < 1 {
+        return Err(EvalError::ArgumentError(format!(
+            "Heightfield resolution must be positive, got {}",
+            res
+        )));
+    }
+
+    let hf = Heightfield::sample(res as usize, ext, *mat, |x, z| {
+        let call: Expression = [f.clone(), x.into(), z.into()].into();
+        eval(env, call)?.try_into() as Result<f64, EvalError>
+    })?;
+
+    Ok(ForeignDataWrapper::new(RTObjectWrapper::from(hf)).into())
+}
+
+#[native_lisp_function(eval, module = raytrace)]
+pub fn noise(x: f64, z: f64) -> Result<f64, EvalError> {
+    Ok(value_noise(x, z))
+}
+
+/// Tag an object with a group name, which can be used to link lights.
+/// `(object-group obj "name")`
+#[native_lisp_function(eval, module = raytrace)]
+pub fn object_group(
+    mut obj: ForeignDataWrapper<RTObjectWrapper>,
+    group: String,
+) -> Result<ForeignDataWrapper<RTObjectWrapper>, EvalError> {
+    obj.add_group(group);
+    Ok(obj)
+}
+
+/// Restrict a light to a list of objects and/or group names.
+/// `(light-link lgt '(s1 s2 "group"))`
+#[register_lisp_function(module = raytrace)]
+pub fn light_link(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [lgt, targets]: [Expression; 2] = expr.try_into()?;
+
+    let mut lgt: ForeignDataWrapper<Light> = eval(env, lgt)?.try_into()?;
+    let targets: Vec<Expression> = eval(env, targets)?.try_into()?;
+
+    let mut links = LightLinks::default();
+    for t in targets {
+        match resolve_scene_element(env, t)? {
+            Expression::String(group) => links.groups.push(group),
+            x => {
+                let obj: ForeignDataWrapper<RTObjectWrapper> = x.try_into()?;
+                links.objects.push(obj.id());
+            }
+        }
+    }
+    lgt.link(links);
+
+    Ok(lgt.into())
+}
+
+/// Number of samples taken from a lisp light profile function.
+const LIGHT_PROFILE_SAMPLES: usize = 256;
+
+/// Modulate a light's intensity by direction using a lisp function of the cosine between
+/// `axis` (default: straight down) and the direction towards the lit point.
+/// `(light-with-profile lgt (lambda (cos-theta) ...) [axis])`
+///
+/// The function is sampled into a lookup table once, so it is never called during rendering.
+#[register_lisp_function(module = raytrace)]
+pub fn light_with_profile(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let (lgt, f, axis) = match <[Expression; 3]>::try_from(args) {
+        Ok([lgt, f, axis]) => {
+            let axis: Coords<Vector3> = eval(env, axis)?.try_into()?;
+            (lgt, f, *axis)
+        }
+        Err(args) => {
+            let [lgt, f]: [Expression; 2] = Expression::from(args).try_into()?;
+            (lgt, f, Vector3::new(0.0, -1.0, 0.0))
+        }
+    };
+
+    let mut lgt: ForeignDataWrapper<Light> = eval(env, lgt)?.try_into()?;
+    let f = eval(env, f)?;
+
+    let profile = LightProfile::sample(axis, LIGHT_PROFILE_SAMPLES, |cos_theta| {
+        let call: Expression = [f.clone(), cos_theta.into()].into();
+        eval(env, call)?.try_into() as Result<f64, EvalError>
+    })?;
+    lgt.profile = Some(profile);
+
+    Ok(lgt.into())
+}
+
+/// Resolve an element of an already evaluated scene list. Symbols are looked up in `env`
+/// (supporting the quoted `'(s1 s2)` style), any other value is taken as is.
+fn resolve_scene_element(env: &Environment, e: Expression) -> Result<Expression, EvalError> {
+    match e {
+        Expression::Symbol(s) => env.get(&s).ok_or(EvalError::SymbolNotBound(s)),
+        x => Ok(x),
+    }
+}
+
+/// Create a scene with an ambient color and lists of objects and lights.
+///
+/// `(scene ambient objects lights)`
+///
+/// The lists may also hold the quoted names of objects and lights, as in `'(s1 s2)`. Resolving
+/// these names needs the environment, which native lisp functions do not get, and the objects
+/// and lights are two lists rather than the remaining arguments, so this stays a builtin.
+#[register_lisp_function(module = raytrace)]
 pub fn scene(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [amb, objs, lgts]: [Expression; 3] = expr.try_into()?;
 
-    let amb: ForeignDataWrapper<Color> = eval(env, amb)?.try_into()?;
+    let amb: Coords<Color> = eval(env, amb)?.try_into()?;
     let objs: Vec<Expression> = eval(env, objs)?.try_into()?;
     let lgts: Vec<Expression> = eval(env, lgts)?.try_into()?;
 
@@ -169,12 +356,12 @@ pub fn scene(env: &Environment, expr: Expression) -> Result<Expression, EvalErro
 
     scene.set_ambient(*amb);
     for o in objs {
-        let o: ForeignDataWrapper<RTObjectWrapper> = eval(env, o)?.try_into()?;
+        let o: ForeignDataWrapper<RTObjectWrapper> = resolve_scene_element(env, o)?.try_into()?;
         scene.add_object(o.clone());
     }
     for l in lgts {
-        let l: ForeignDataWrapper<Light> = eval(env, l)?.try_into()?;
-        scene.add_light(*l);
+        let l: ForeignDataWrapper<Light> = resolve_scene_element(env, l)?.try_into()?;
+        scene.add_light(l.clone());
     }
 
     Ok(ForeignDataWrapper::new(scene).into())
@@ -194,37 +381,142 @@ pub fn scene_add_light(
     mut sce: ForeignDataWrapper<Scene>,
     lgt: ForeignDataWrapper<Light>,
 ) -> Result<ForeignDataWrapper<Scene>, EvalError> {
-    sce.add_light(*lgt);
+    sce.add_light(lgt.clone());
     Ok(sce)
 }
 
 native_lisp_function_proxy!(
-    fname = scene_add,
+    fname = scene_add_element,
     eval,
     dispatch = scene_add_object,
     dispatch = scene_add_light
 );
 
-#[native_lisp_function(eval)]
+/// Edit an evaluated scene, which may be given as the quoted name of a scene. The scene bound
+/// to the name is then replaced with the edited one. Returns the edited scene.
+fn edit_scene(
+    env: &Environment,
+    scn: Expression,
+    edit: impl FnOnce(ForeignDataWrapper<Scene>) -> Result<ForeignDataWrapper<Scene>, EvalError>,
+) -> Result<Expression, EvalError> {
+    match scn {
+        Expression::Symbol(name) => {
+            let scn = env
+                .get(&name)
+                .ok_or_else(|| EvalError::SymbolNotBound(name.clone()))?;
+            let scn: Expression = edit(scn.try_into()?)?.into();
+            env.update(&name, scn.clone());
+            Ok(scn)
+        }
+        scn => Ok(edit(scn.try_into()?)?.into()),
+    }
+}
+
+/// Add an object or a light to a scene, returning the new scene: `(scene-add scn obj)`
+///
+/// Given the quoted name of a scene, `(scene-add 'scn obj)` updates the scene bound to it and
+/// returns the handle of the added object for `scene-remove` and `scene-replace`, or nil for a
+/// light.
+#[register_lisp_function(module = raytrace)]
+pub fn scene_add(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [scn, element]: [Expression; 2] = expr.try_into()?;
+
+    let scn = eval(env, scn)?;
+    let element = eval(env, element)?;
+    let handle = match ForeignDataWrapper::<RTObjectWrapper>::try_from(element.clone()) {
+        Ok(obj) => Expression::Integer(obj.id() as i64),
+        Err(_) => Expression::Nil,
+    };
+    let named = matches!(scn, Expression::Symbol(_));
+    let scn = edit_scene(env, scn, |scn| {
+        let quoted = |e: Expression| Expression::Quote(Arc::new(e));
+        scene_add_element(env, [quoted(scn.into()), quoted(element)].into())?.try_into()
+    })?;
+    Ok(if named { handle } else { scn })
+}
+
+#[native_lisp_function(eval, module = raytrace)]
+pub fn object_id(obj: ForeignDataWrapper<RTObjectWrapper>) -> Result<i64, EvalError> {
+    Ok(obj.id() as i64)
+}
+
+/// Get the id of an evaluated object handle, which is either an object or an id from `object-id`.
+fn object_handle(handle: Expression) -> Result<u64, EvalError> {
+    match handle {
+        Expression::Integer(id) => u64::try_from(id)
+            .map_err(|_| EvalError::TypeError(format!("{} is not a valid object id", id))),
+        x => {
+            let obj: ForeignDataWrapper<RTObjectWrapper> = x.try_into()?;
+            Ok(obj.id())
+        }
+    }
+}
+
+/// Remove an object from a scene, returning the new scene. Like with `scene-add`, a quoted
+/// scene name updates the scene bound to it.
+/// `(scene-remove scn obj-or-id)`
+#[register_lisp_function(module = raytrace)]
+pub fn scene_remove(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [scn, handle]: [Expression; 2] = expr.try_into()?;
+
+    let scn = eval(env, scn)?;
+    let id = object_handle(eval(env, handle)?)?;
+
+    edit_scene(env, scn, |mut scn| {
+        scn.remove_object(id).ok_or(EvalError::RuntimeError(
+            format!("Scene has no object with id {}", id),
+            None,
+        ))?;
+        Ok(scn)
+    })
+}
+
+/// Replace an object of a scene, returning the new scene. The new object takes over the id
+/// of the replaced one. Like with `scene-add`, a quoted scene name updates the scene bound to it.
+/// `(scene-replace scn obj-or-id new-obj)`
+#[register_lisp_function(module = raytrace)]
+pub fn scene_replace(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [scn, handle, obj]: [Expression; 3] = expr.try_into()?;
+
+    let scn = eval(env, scn)?;
+    let id = object_handle(eval(env, handle)?)?;
+    let obj: ForeignDataWrapper<RTObjectWrapper> = eval(env, obj)?.try_into()?;
+
+    edit_scene(env, scn, |mut scn| {
+        scn.replace_object(id, obj.clone())
+            .ok_or(EvalError::RuntimeError(
+                format!("Scene has no object with id {}", id),
+                None,
+            ))?;
+        Ok(scn)
+    })
+}
+
+/// Create a camera at `pos` looking at `cnt`.
+/// `(camera pos cnt [up] [fovy] [width] [height])`
+///
+/// By default, `up` is the y axis, the vertical field of view is 45 degrees and images are
+/// 640x480 pixels.
+#[native_lisp_function(eval, module = raytrace)]
 pub fn camera(
-    pos: ForeignDataWrapper<Point3>,
-    cnt: ForeignDataWrapper<Point3>,
-    up: ForeignDataWrapper<Vector3>,
-    fovy: f64,
-    w: i64,
-    h: i64,
+    pos: Coords<Point3>,
+    cnt: Coords<Point3>,
+    #[default(Coords(Vector3::y()))] up: Coords<Vector3>,
+    #[default(45.0)] fovy: f64,
+    #[default(640)] w: usize,
+    #[default(480)] h: usize,
 ) -> Result<ForeignDataWrapper<Camera>, EvalError> {
     Ok(ForeignDataWrapper::new(Camera::new(
-        *pos, *cnt, *up, fovy, w as usize, h as usize,
+        *pos, *cnt, *up, fovy, w, h,
     )))
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn camera_reposition(
     cam: ForeignDataWrapper<Camera>,
-    pos: ForeignDataWrapper<Point3>,
-    cnt: ForeignDataWrapper<Point3>,
-    up: ForeignDataWrapper<Vector3>,
+    pos: Coords<Point3>,
+    cnt: Coords<Point3>,
+    up: Coords<Vector3>,
     fovy: f64,
 ) -> Result<ForeignDataWrapper<Camera>, EvalError> {
     Ok(ForeignDataWrapper::new(
@@ -232,23 +524,473 @@ pub fn camera_reposition(
     ))
 }
 
-#[native_lisp_function(eval)]
-pub fn render(
+/// Project a world point to pixel coordinates: `(camera-project cam point)` returns the list
+/// `(x y)`, or nil if the point is behind the camera.
+#[native_lisp_function(eval, module = raytrace)]
+pub fn camera_project(
     cam: ForeignDataWrapper<Camera>,
-    sce: ForeignDataWrapper<Scene>,
-    dpt: i64,
-    sbp: i64,
-    out: String,
+    point: Coords<Point3>,
 ) -> Result<Expression, EvalError> {
-    println!("Rendering to {}...", out);
-    let img = cam.render(&sce, dpt as u32, sbp as u32);
+    Ok(match cam.project(*point) {
+        Some((x, y)) => vec![Expression::Float(x), Expression::Float(y)].into(),
+        None => Expression::Nil,
+    })
+}
+
+/// Split evaluated builtin arguments into the leading positional arguments and trailing
+/// `:key value` options. Option names are returned without the leading colon.
+fn split_options(
+    args: Vec<Expression>,
+) -> Result<(Vec<Expression>, HashMap<String, Expression>), EvalError> {
+    let n_positional = args
+        .iter()
+        .position(|a| matches!(a, Expression::Symbol(s) if s.starts_with(':')))
+        .unwrap_or(args.len());
+
+    let mut args = args.into_iter();
+    let positional: Vec<Expression> = args.by_ref().take(n_positional).collect();
+
+    let mut options = HashMap::new();
+    while let Some(key) = args.next() {
+        match key {
+            Expression::Symbol(s) if s.starts_with(':') => {
+                let value = args.next().ok_or(EvalError::ArgumentError(format!(
+                    "Missing value for option {}",
+                    s
+                )))?;
+                options.insert(s[1..].to_string(), value);
+            }
+            x => {
+                return Err(EvalError::ArgumentError(format!(
+                    "Expected an option keyword, got {}",
+                    x
+                )));
+            }
+        }
+    }
+
+    Ok((positional, options))
+}
+
+/// The reflection depth of `render`, if not given.
+const DEFAULT_DEPTH: i64 = 5;
+
+/// The number of samples per pixel and axis of `render`, if not given.
+const DEFAULT_SUBPIXELS: i64 = 4;
+
+/// Minimum time between two progress reports of `render`.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Time between two checkpoints of `render`, if resuming without `:checkpoint`.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Render a scene to an image file.
+/// `(render cam scn [depth subp] "out.png" [:overwrite bool] [:spectral bands] [:crop '(x y w h)]
+///  [:sampler name] [:integrator name] [:checkpoint seconds] [:resume bool])`
+///
+/// Without `depth` and `subp`, rays are traced 5 reflections deep with 4x4 samples per pixel.
+/// Missing parent directories of the output file are created. Unless `:overwrite` is given,
+/// existing files are overwritten, except when `NO-CLOBBER` is bound to a non-nil value. Fails
+/// if the configuration denies file access (see `EnvironmentBuilder::with_io`).
+/// With `:spectral`, each of `bands` wavelength bands is traced separately (see
+/// `material-refraction` and `material-spectrum`). With `:crop`, only the pixels inside the given
+/// window are rendered, the rest of the image stays black. `:sampler` places the `subp * subp`
+/// samples of each pixel with the `grid` (the default), `random`, `stratified` or `halton`
+/// sampler. `:integrator` selects the rendering algorithm: `whitted` ray tracing (the default), or
+/// the `normals` or `depth` of the first intersection for debugging. Returns the absolute path of
+/// the written image.
+/// Long renders print their progress and an estimate of the remaining time.
+///
+/// With `:checkpoint seconds`, the finished rows are saved to `out.png.checkpoint` at most once
+/// per interval. With `:resume true`, the rows of an existing checkpoint are not rendered again.
+/// The checkpoint is removed once the image is written.
+///
+/// The functions added to the `before-render` and `after-render` hooks with `add-hook` are
+/// called with an alist of the output `path`, the image `width` and `height` and, after the
+/// render, the render time in `seconds`.
+#[register_lisp_function(module = raytrace)]
+pub fn render(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (mut args, options) = eval_render_args(env, expr)?;
+
+    if args.len() == 3 {
+        args.splice(2..2, [DEFAULT_DEPTH.into(), DEFAULT_SUBPIXELS.into()]);
+    }
+    let [cam, sce, dpt, sbp, out]: [Expression; 5] = Expression::from(args).try_into()?;
+    let cam: ForeignDataWrapper<Camera> = cam.try_into()?;
+    let sce: ForeignDataWrapper<Scene> = sce.try_into()?;
+    let dpt: i64 = dpt.try_into()?;
+    let sbp: i64 = sbp.try_into()?;
+    let settings = render_settings(env, out.try_into()?, options)?;
+    let cam = settings.camera(&cam);
+    let checkpoint = settings
+        .checkpoint(env, &cam, (&*sce, dpt, sbp))?
+        .map(Mutex::new);
+
+    settings.run_hook(env, "before-render", cam.resolution(), None)?;
+    env.write_output(&format!("Rendering to {}...\n", settings.out.display()))?;
+    let start = Instant::now();
+    let last_report = Mutex::new(Instant::now());
+    let img = cam.render_resumable(
+        &sce,
+        dpt as u32,
+        sbp as u32,
+        settings.bands,
+        checkpoint.as_ref(),
+        |p| report_progress(env, &last_report, p),
+    );
+    let out = settings.save(img)?;
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.into_inner().unwrap().remove()?;
+    }
+    settings.run_hook(env, "after-render", cam.resolution(), Some(start.elapsed()))?;
+    Ok(out)
+}
+
+/// Render a side-by-side stereo image of a scene to an image file.
+/// `(render-stereo cam scn ipd depth subp "out.png" [options])`
+///
+/// The left and right eye are `ipd` apart and centered on the camera position. The options,
+/// the result and the hooks are the same as for `render`, a crop window applies to both eyes.
+/// Checkpoints are not supported.
+#[register_lisp_function(module = raytrace)]
+pub fn render_stereo(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let (args, options) = eval_render_args(env, expr)?;
+
+    let [cam, sce, ipd, dpt, sbp, out]: [Expression; 6] = Expression::from(args).try_into()?;
+    let cam: ForeignDataWrapper<Camera> = cam.try_into()?;
+    let sce: ForeignDataWrapper<Scene> = sce.try_into()?;
+    let ipd: f64 = ipd.try_into()?;
+    let dpt: i64 = dpt.try_into()?;
+    let sbp: i64 = sbp.try_into()?;
+    let settings = render_settings(env, out.try_into()?, options)?;
+    let cam = settings.camera(&cam);
+    if settings.checkpoint_interval.is_some() {
+        return Err(EvalError::ArgumentError(
+            "render-stereo does not support checkpoints".to_string(),
+        ));
+    }
+
+    let (width, height) = cam.resolution();
+    settings.run_hook(env, "before-render", (2 * width, height), None)?;
+    env.write_output(&format!(
+        "Rendering stereo image to {}...\n",
+        settings.out.display()
+    ))?;
+    let start = Instant::now();
+    let last_report = Mutex::new(Instant::now());
+    let img =
+        cam.render_stereo_with_progress(&sce, ipd, dpt as u32, sbp as u32, settings.bands, |p| {
+            report_progress(env, &last_report, p)
+        });
+    let out = settings.save(img)?;
+    settings.run_hook(
+        env,
+        "after-render",
+        (2 * width, height),
+        Some(start.elapsed()),
+    )?;
+    Ok(out)
+}
+
+/// The maximum number of reflections of a `preview`.
+const PREVIEW_DEPTH: u32 = 2;
+
+/// Quickly render a rough preview of a scene, to iterate on the composition before an expensive
+/// `render`: `(preview cam scn ["out.png"])`
+///
+/// The preview is rendered at half the resolution of the camera with a single sample per pixel
+/// and at most `PREVIEW_DEPTH` reflections. It is written to `out.png`, or to
+/// `lispers-preview.png` in the temporary directory, replacing an existing file. Returns the
+/// render time in seconds.
+#[register_lisp_function(module = raytrace)]
+pub fn preview(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let mut args: Vec<Expression> = expr.try_into()?;
+    let out = match args.len() {
+        3 => {
+            let out: String = eval(env, args.pop().unwrap())?.try_into()?;
+            std::path::absolute(PathBuf::from(out))?
+        }
+        _ => std::env::temp_dir().join("lispers-preview.png"),
+    };
+    let [cam, sce]: [Expression; 2] = Expression::from(args).try_into()?;
+    let cam: ForeignDataWrapper<Camera> = eval(env, cam)?.try_into()?;
+    let sce: ForeignDataWrapper<Scene> = eval(env, sce)?.try_into()?;
+
+    let (width, height) = cam.resolution();
+    env.config().check_file_access(&out)?;
+    let cam = cam.with_resolution((width / 2).max(1), (height / 2).max(1));
+    let start = Instant::now();
+    let img = cam.render(&sce, PREVIEW_DEPTH, 1);
+    let elapsed = start.elapsed().as_secs_f64();
+
+    img.save(&out)
+        .map_err(|e| EvalError::with_context(out.display(), e))?;
+    env.write_output(&format!(
+        "Preview written to {} in {:.2}s\n",
+        out.display(),
+        elapsed
+    ))?;
+    Ok(Expression::Float(elapsed))
+}
+
+/// Evaluate the arguments of a render builtin and split off its options.
+fn eval_render_args(
+    env: &Environment,
+    expr: Expression,
+) -> Result<(Vec<Expression>, HashMap<String, Expression>), EvalError> {
+    let args: Vec<Expression> = expr.try_into()?;
+    let args = args
+        .into_iter()
+        .map(|a| eval(env, a))
+        .collect::<Result<Vec<Expression>, EvalError>>()?;
+    split_options(args)
+}
+
+/// The output file and options of a render builtin.
+struct RenderSettings {
+    /// The absolute output path, whose parent directories exist.
+    out: PathBuf,
+    /// The number of spectral bands, if rendering in spectral mode.
+    bands: Option<usize>,
+    /// The part of the image to render.
+    crop: Option<Crop>,
+    /// The sampler of the subpixels, if not the one of the camera.
+    sampler: Option<SamplerWrapper>,
+    /// The rendering algorithm, if not the one of the camera.
+    integrator: Option<IntegratorWrapper>,
+    /// The time between two checkpoints, if checkpoints are enabled.
+    checkpoint_interval: Option<Duration>,
+    /// Whether to continue from an existing checkpoint.
+    resume: bool,
+    /// Whether the image is rendered for a benchmark only and not written.
+    benchmark: bool,
+}
+
+impl RenderSettings {
+    /// Get the camera to render with.
+    fn camera(&self, cam: &Camera) -> Camera {
+        let cam = match &self.sampler {
+            Some(sampler) => cam.with_sampler(sampler.clone()),
+            None => cam.clone(),
+        };
+        let cam = match &self.integrator {
+            Some(integrator) => cam.with_integrator(integrator.clone()),
+            None => cam,
+        };
+        match self.crop {
+            Some(crop) => cam.with_crop(crop),
+            None => cam,
+        }
+    }
+
+    /// Get the checkpoint to render with, which is loaded from the checkpoint file when resuming.
+    /// Together with the camera, `render` identifies the scene and settings of the render, see
+    /// `checkpoint::fingerprint`.
+    fn checkpoint(
+        &self,
+        env: &Environment,
+        cam: &Camera,
+        render: impl Debug,
+    ) -> Result<Option<Checkpoint>, EvalError> {
+        let Some(interval) = self.checkpoint_interval.filter(|_| !self.benchmark) else {
+            return Ok(None);
+        };
+        let mut path = self.out.clone().into_os_string();
+        path.push(".checkpoint");
+        let path = PathBuf::from(path);
+        let (width, height) = cam.resolution();
+        let fingerprint = fingerprint((cam, render, self.bands));
+
+        if self.resume && path.exists() {
+            let checkpoint = Checkpoint::load(&path, interval, width, height, fingerprint)?;
+            env.write_output(&format!(
+                "Resuming from {} with {}/{} rows done\n",
+                path.display(),
+                checkpoint.rows_done(),
+                height
+            ))?;
+            Ok(Some(checkpoint))
+        } else {
+            Ok(Some(Checkpoint::new(
+                &path,
+                interval,
+                width,
+                height,
+                fingerprint,
+            )))
+        }
+    }
+
+    /// Run a render hook (see `add-hook`) with a context alist of the output `path`, the image
+    /// `width` and `height` and, once rendered, the render time in `seconds`.
+    fn run_hook(
+        &self,
+        env: &Environment,
+        hook: &str,
+        (width, height): (usize, usize),
+        elapsed: Option<Duration>,
+    ) -> Result<(), EvalError> {
+        let entry = |key: &str, value: Expression| -> Expression {
+            (Expression::Symbol(key.to_string()), value).into()
+        };
+        let mut context = vec![
+            entry("path", self.out.display().to_string().into()),
+            entry("width", Expression::Integer(width as i64)),
+            entry("height", Expression::Integer(height as i64)),
+        ];
+        if let Some(elapsed) = elapsed {
+            context.push(entry("seconds", Expression::Float(elapsed.as_secs_f64())));
+        }
+        run_hook(env, hook, vec![context.into()])
+    }
+
+    /// Save a rendered image, unless rendering for a benchmark, and return its path.
+    fn save(&self, img: RgbImage) -> Result<Expression, EvalError> {
+        if !self.benchmark {
+            img.save(&self.out)
+                .map_err(|e| EvalError::with_context(self.out.display(), e))?;
+        }
+        Ok(self.out.display().to_string().into())
+    }
+}
 
-    match img.save(out) {
-        Ok(_) => Ok(Expression::Nil),
-        Err(e) => Err(EvalError::RuntimeError(e.to_string())),
+/// Check whether rendering for a benchmark, which renders without writing any file. This is
+/// the case when `BENCHMARK` is bound to a non-nil value.
+fn is_benchmark(env: &Environment) -> bool {
+    env.get("BENCHMARK")
+        .is_some_and(|e| e.is_truthy(env.config()))
+}
+
+/// Apply the render options to the output file `out`. When `BENCHMARK` is bound to a non-nil
+/// value, nothing is written.
+fn render_settings(
+    env: &Environment,
+    out: String,
+    mut options: HashMap<String, Expression>,
+) -> Result<RenderSettings, EvalError> {
+    let benchmark = is_benchmark(env);
+    let overwrite = match options.remove("overwrite") {
+        Some(e) => e.is_truthy(env.config()),
+        None => !env
+            .get("NO-CLOBBER")
+            .is_some_and(|e| e.is_truthy(env.config())),
+    };
+    let bands = match options.remove("spectral") {
+        Some(Expression::Nil) | None => None,
+        Some(e) => {
+            let bands: i64 = e.try_into()?;
+            if bands < 1 {
+                return Err(EvalError::ArgumentError(format!(
+                    "Expected at least one spectral band, got {}",
+                    bands
+                )));
+            }
+            Some(bands as usize)
+        }
+    };
+    let crop = match options.remove("crop") {
+        Some(Expression::Nil) | None => None,
+        Some(e) => {
+            let [x, y, w, h]: [i64; 4] = e.try_into()?;
+            if x < 0 || y < 0 || w < 1 || h < 1 {
+                return Err(EvalError::ArgumentError(format!(
+                    "Invalid crop window ({} {} {} {})",
+                    x, y, w, h
+                )));
+            }
+            Some(Crop::new(x as usize, y as usize, w as usize, h as usize))
+        }
+    };
+    let sampler = match options.remove("sampler") {
+        Some(Expression::Nil) | None => None,
+        Some(Expression::Symbol(name)) => Some(SamplerWrapper::from_name(&name).ok_or(
+            EvalError::ArgumentError(format!(
+                "Unknown sampler {}, expected grid, random, stratified or halton",
+                name
+            )),
+        )?),
+        Some(x) => return Err(EvalError::NotASymbol(x)),
+    };
+    let integrator = match options.remove("integrator") {
+        Some(Expression::Nil) | None => None,
+        Some(Expression::Symbol(name)) => Some(IntegratorWrapper::from_name(&name).ok_or(
+            EvalError::ArgumentError(format!(
+                "Unknown integrator {}, expected whitted, normals or depth",
+                name
+            )),
+        )?),
+        Some(x) => return Err(EvalError::NotASymbol(x)),
+    };
+    let resume = options
+        .remove("resume")
+        .is_some_and(|e| e.is_truthy(env.config()));
+    let checkpoint_interval = match options.remove("checkpoint") {
+        Some(Expression::Nil) | None if resume => Some(CHECKPOINT_INTERVAL),
+        Some(Expression::Nil) | None => None,
+        Some(e) => {
+            let seconds: f64 = e.try_into()?;
+            Some(Duration::try_from_secs_f64(seconds).map_err(|_| {
+                EvalError::ArgumentError(format!("Invalid checkpoint interval {}", seconds))
+            })?)
+        }
+    };
+    if let Some(k) = options.keys().next() {
+        return Err(EvalError::ArgumentError(format!(
+            "Unknown render option :{}",
+            k
+        )));
+    }
+
+    let out = std::path::absolute(PathBuf::from(out))?;
+    if !benchmark {
+        env.config().check_file_access(&out)?;
+    }
+    if !overwrite && !benchmark && out.exists() {
+        return Err(EvalError::RuntimeError(
+            format!("Refusing to overwrite existing file {}", out.display()),
+            None,
+        ));
+    }
+    if let Some(parent) = out.parent().filter(|_| !benchmark) {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| EvalError::with_context(parent.display(), e))?;
+    }
+    Ok(RenderSettings {
+        out,
+        bands,
+        crop,
+        sampler,
+        integrator,
+        checkpoint_interval,
+        resume,
+        benchmark,
+    })
+}
+
+/// Print the progress of a render, at most once per `PROGRESS_INTERVAL` so quick renders stay
+/// silent. Failing to print the progress does not abort the render.
+fn report_progress(env: &Environment, last_report: &Mutex<Instant>, p: RenderProgress) {
+    let mut last_report = last_report.lock().unwrap();
+    if last_report.elapsed() < PROGRESS_INTERVAL {
+        return;
+    }
+    *last_report = Instant::now();
+    if let Some(eta) = p.eta() {
+        let _ = env.write_output(&format!(
+            "  {:.0}% after {:.1}s, ETA {:.1}s\n",
+            100.0 * p.rows_done as f64 / p.rows as f64,
+            p.elapsed.as_secs_f64(),
+            eta.as_secs_f64()
+        ));
     }
 }
 
+/// Render an animation to a video file.
+/// `(render-animation cam "out.mp4" scene-fn camera-fn frames fps depth subp)`
+///
+/// Frame `t` shows the scene `(scene-fn t)` from the camera `(camera-fn t cam)`, where `cam` is
+/// the camera of the previous frame. When rendering for a benchmark, no video is written.
+#[register_lisp_function(module = raytrace)]
 pub fn render_animation(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
     let [cam, path, scene_fn, update_cam, frames, fps, depth, subp]: [Expression; 8] =
         expr.try_into()?;
@@ -274,9 +1016,13 @@ pub fn render_animation(env: &Environment, expr: Expression) -> Result<Expressio
     };
 
     let path: PathBuf = path.into();
+    let benchmark = is_benchmark(env);
+    if !benchmark {
+        env.config().check_file_access(&path)?;
+    }
 
     match cam.render_animation(
-        &path,
+        (!benchmark).then_some(path.as_path()),
         sfn,
         ucm,
         frames as u32,
@@ -286,30 +1032,20 @@ pub fn render_animation(env: &Environment, expr: Expression) -> Result<Expressio
     ) {
         Ok(()) => Ok(Expression::Nil),
         Err(RTError::EvalError(e)) => Err(e),
-        Err(RTError::FFMpegError(e)) => Err(EvalError::RuntimeError(e.to_string())),
+        Err(RTError::FFMpegError(e)) => Err(EvalError::from_error(e)),
     }
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn sin(x: f64) -> Result<f64, EvalError> {
     Ok(x.sin())
 }
 
-#[native_lisp_function(eval)]
+#[native_lisp_function(eval, module = raytrace)]
 pub fn cos(x: f64) -> Result<f64, EvalError> {
     Ok(x.cos())
 }
 
-#[native_lisp_function(eval)]
-pub fn add_i(x: i64, y: i64) -> Result<i64, EvalError> {
-    Ok(x + y)
-}
-
-#[native_lisp_function(eval)]
-pub fn add_f(x: f64, y: f64) -> Result<f64, EvalError> {
-    Ok(x + y)
-}
-
 #[native_lisp_function]
 pub fn vadd_vv(
     a: ForeignDataWrapper<Vector3>,
@@ -335,25 +1071,14 @@ pub fn vadd_pv(
 }
 
 native_lisp_function_proxy!(
-    fname = add,
+    fname = add_overloaded,
     eval,
-    dispatch = add_i,
-    dispatch = add_f,
     dispatch = vadd_vv,
     dispatch = vadd_vp,
-    dispatch = vadd_pv
+    dispatch = vadd_pv,
+    dispatch = add_dual
 );
 
-#[native_lisp_function(eval)]
-pub fn sub_i(x: i64, y: i64) -> Result<i64, EvalError> {
-    Ok(x - y)
-}
-
-#[native_lisp_function(eval)]
-pub fn sub_f(x: f64, y: f64) -> Result<f64, EvalError> {
-    Ok(x - y)
-}
-
 #[native_lisp_function]
 pub fn sub_vv(
     a: ForeignDataWrapper<Vector3>,
@@ -387,26 +1112,15 @@ pub fn sub_pp(
 }
 
 native_lisp_function_proxy!(
-    fname = sub,
+    fname = sub_overloaded,
     eval,
-    dispatch = sub_i,
-    dispatch = sub_f,
     dispatch = sub_vv,
     dispatch = sub_vp,
     dispatch = sub_pv,
-    dispatch = sub_pp
+    dispatch = sub_pp,
+    dispatch = sub_dual
 );
 
-#[native_lisp_function(eval)]
-pub fn mul_i(x: i64, y: i64) -> Result<i64, EvalError> {
-    Ok(x * y)
-}
-
-#[native_lisp_function(eval)]
-pub fn mul_f(x: f64, y: f64) -> Result<f64, EvalError> {
-    Ok(x * y)
-}
-
 #[native_lisp_function]
 pub fn mul_vs(
     a: ForeignDataWrapper<Vector3>,
@@ -440,26 +1154,15 @@ pub fn mul_sp(
 }
 
 native_lisp_function_proxy!(
-    fname = mul,
+    fname = mul_overloaded,
     eval,
-    dispatch = mul_i,
-    dispatch = mul_f,
     dispatch = mul_vs,
     dispatch = mul_sv,
     dispatch = mul_ps,
-    dispatch = mul_sp
+    dispatch = mul_sp,
+    dispatch = mul_dual
 );
 
-#[native_lisp_function(eval)]
-pub fn div_i(x: i64, y: i64) -> Result<f64, EvalError> {
-    Ok(x as f64 / y as f64)
-}
-
-#[native_lisp_function(eval)]
-pub fn div_f(x: f64, y: f64) -> Result<f64, EvalError> {
-    Ok(x / y)
-}
-
 #[native_lisp_function]
 pub fn div_vs(
     a: ForeignDataWrapper<Vector3>,
@@ -493,25 +1196,76 @@ pub fn div_sp(
 }
 
 native_lisp_function_proxy!(
-    fname = div,
+    fname = div_overloaded,
     eval,
-    dispatch = div_i,
-    dispatch = div_f,
     dispatch = div_vs,
     dispatch = div_sv,
     dispatch = div_ps,
-    dispatch = div_sp
+    dispatch = div_sp,
+    dispatch = div_dual
 );
 
-#[native_lisp_function(eval)]
-pub fn dot(
-    a: ForeignDataWrapper<Vector3>,
-    b: ForeignDataWrapper<Vector3>,
-) -> Result<f64, EvalError> {
+/// Evaluate the operands of an arithmetic operator once. Plain numbers take the fast path
+/// `numbers` of the prelude, other operands are passed on quoted to the `overloaded`
+/// implementations for vectors, points and dual numbers.
+fn arithmetic_operator(
+    env: &Environment,
+    expr: Expression,
+    numbers: impl Fn(Expression, Expression) -> Result<Expression, EvalError>,
+    overloaded: fn(&Environment, Expression) -> Result<Expression, EvalError>,
+) -> Result<Expression, EvalError> {
+    let is_number = |e: &Expression| {
+        matches!(
+            e,
+            Expression::Integer(_) | Expression::BigInt(_) | Expression::Float(_)
+        )
+    };
+
+    match binary_operands(env, expr)? {
+        (a, b) if is_number(&a) && is_number(&b) => numbers(a, b),
+        (a, b) => overloaded(
+            env,
+            [
+                Expression::Quote(Arc::new(a)),
+                Expression::Quote(Arc::new(b)),
+            ]
+            .into(),
+        ),
+    }
+}
+
+#[register_lisp_function(module = raytrace, name = "+")]
+pub fn add(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    arithmetic_operator(env, expr, add_numbers, add_overloaded)
+}
+
+#[register_lisp_function(module = raytrace, name = "-")]
+pub fn sub(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    arithmetic_operator(env, expr, sub_numbers, sub_overloaded)
+}
+
+#[register_lisp_function(module = raytrace, name = "*")]
+pub fn mul(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    arithmetic_operator(env, expr, mul_numbers, mul_overloaded)
+}
+
+/// Numbers are divided like the prelude `/`. Scripts declaring a dialect version before 0.2 get
+/// a float quotient of integers, which the raytracer `/` gave then.
+#[register_lisp_function(module = raytrace, name = "/")]
+pub fn div(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let numbers = |a: Expression, b: Expression| match language_version(env) < (0, 2) {
+        true => Ok(Expression::Float(f64::try_from(a)? / f64::try_from(b)?)),
+        false => div_numbers(a, b),
+    };
+    arithmetic_operator(env, expr, numbers, div_overloaded)
+}
+
+#[native_lisp_function(eval, module = raytrace)]
+pub fn dot(a: Coords<Vector3>, b: Coords<Vector3>) -> Result<f64, EvalError> {
     Ok(a.dot(&b))
 }
 
-#[native_lisp_function]
+#[native_lisp_function(strict)]
 pub fn abs_i(a: i64) -> Result<i64, EvalError> {
     Ok(a.abs())
 }
@@ -526,58 +1280,320 @@ pub fn abs_v(a: ForeignDataWrapper<Vector3>) -> Result<f64, EvalError> {
     Ok(a.dot(&a).sqrt())
 }
 
+/// Get a component of a point, vector or color: `(vector-ref v i)`
+#[register_lisp_function(module = raytrace)]
+pub fn vector_ref(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [v, i]: [Expression; 2] = expr.try_into()?;
+    let v = eval(env, v)?;
+    let i: i64 = eval(env, i)?.try_into()?;
+
+    let v: Vector3 = match ForeignDataWrapper::<Point3>::try_from(v.clone()) {
+        Ok(p) => p.coords,
+        Err(_) => *Coords::<Vector3>::try_from(v)?,
+    };
+    Ok(v[env.config().index(i, 3)?].into())
+}
+
 native_lisp_function_proxy!(
     fname = abs,
     eval,
+    module = raytrace,
     dispatch = abs_i,
     dispatch = abs_f,
     dispatch = abs_v
 );
 
-/// Adds the raytracing functions to the given environment layer.
-pub fn mk_raytrace(layer: &mut EnvironmentLayer) {
-    layer.set("point".to_string(), Expression::Function(point));
-    layer.set("point2".to_string(), Expression::Function(point2));
-    layer.set("vector".to_string(), Expression::Function(vector));
-    layer.set("color".to_string(), Expression::Function(color));
-    layer.set("light".to_string(), Expression::Function(light));
-    layer.set("material".to_string(), Expression::Function(material));
-    layer.set("plane".to_string(), Expression::Function(plane));
+/// Adds the constant vectors and points to the given environment layer.
+fn mk_raytrace_constants(layer: &mut EnvironmentLayer) {
     layer.set(
-        "checkerboard".to_string(),
-        Expression::Function(checkerboard),
+        "+x+".to_string(),
+        ForeignDataWrapper::new(Vector3::x()).into(),
     );
     layer.set(
-        "texture-plane".to_string(),
-        Expression::Function(texture_plane),
+        "+y+".to_string(),
+        ForeignDataWrapper::new(Vector3::y()).into(),
     );
     layer.set(
-        "mandelbrot-texture".to_string(),
-        Expression::Function(mandelbrot_texture),
+        "+z+".to_string(),
+        ForeignDataWrapper::new(Vector3::z()).into(),
     );
-    layer.set("sphere".to_string(), Expression::Function(sphere));
     layer.set(
-        "texture-sphere".to_string(),
-        Expression::Function(texture_sphere),
+        "origin".to_string(),
+        ForeignDataWrapper::new(Point3::origin()).into(),
     );
-    layer.set("scene".to_string(), Expression::Function(scene));
-    layer.set("scene-add".to_string(), Expression::Function(scene_add));
-    layer.set("camera".to_string(), Expression::Function(camera));
-    layer.set(
-        "camera-reposition".to_string(),
-        Expression::Function(camera_reposition),
-    );
-    layer.set("render".to_string(), Expression::Function(render));
-    layer.set(
-        "render-animation".to_string(),
-        Expression::Function(render_animation),
+}
+
+collect_module!(raytrace, mk_raytrace_constants);
+
+/// Adds `with_raytracer` to `EnvironmentBuilder`, for `use lispers::prelude::*`.
+pub trait RaytracerBuilderExt {
+    /// Add the raytracing functions, which overload the arithmetic of the prelude for vectors.
+    fn with_raytracer(self) -> Self;
+}
+
+impl RaytracerBuilderExt for EnvironmentBuilder {
+    fn with_raytracer(self) -> Self {
+        self.with(mk_raytrace)
+    }
+}
+
+#[test]
+fn test_scene_inline_lists() {
+    use lispers_core::lisp::prelude::mk_prelude;
+    use lispers_core::parser::ExpressionStream;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let program = r#"
+        (set 'm (material (color 1 0 0) (color 1 0 0) (color 1 1 1) 10 0))
+        (set 's1 (sphere (point 0 0 0) 1 m))
+        (set 'l1 (light (point 0 5 0) (color 1 1 1)))
+        (scene (color 0 0 0) '(s1) '(l1))
+        (scene (color 0 0 0) (list s1 (sphere (point 1 0 0) 1 m)) (list l1))
+    "#;
+
+    let results = ExpressionStream::from_char_stream(program.chars())
+        .map(|e| eval(&env, e.unwrap()))
+        .collect::<Result<Vec<Expression>, EvalError>>()
+        .unwrap();
+
+    let quoted: ForeignDataWrapper<Scene> = results[3].clone().try_into().unwrap();
+    let inline: ForeignDataWrapper<Scene> = results[4].clone().try_into().unwrap();
+    assert!(quoted.to_string().ends_with("#objects: 1, #lights: 1)"));
+    assert!(inline.to_string().ends_with("#objects: 2, #lights: 1)"));
+}
+
+#[test]
+fn test_scene_edit() {
+    use lispers_core::lisp::prelude::mk_prelude;
+    use lispers_core::parser::ExpressionStream;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let program = r#"
+        (set 'm (material (color 1 0 0) (color 1 0 0) (color 1 1 1) 10 0))
+        (set 's1 (sphere (point 0 0 0) 1 m))
+        (set 's2 (sphere (point 2 0 0) 1 m))
+        (set 'scn (scene-add (scene-add (scene (color 0 0 0) nil nil) s1) s2))
+        (set 'scn (scene-replace scn s1 (sphere (point 0 1 0) 2 m)))
+        (set 'scn (scene-remove scn (object-id s2)))
+        (scene-remove scn s2)
+        (set 'handle (scene-add 'scn s2))
+        (scene-remove 'scn handle)
+        (scene-remove scn -1)
+    "#;
+
+    let results = ExpressionStream::from_char_stream(program.chars())
+        .map(|e| eval(&env, e.unwrap()))
+        .collect::<Vec<_>>();
+
+    let scn: ForeignDataWrapper<Scene> = results[5].clone().unwrap().try_into().unwrap();
+    assert!(format!("{}", *scn).contains("#objects: 1"));
+    assert!(matches!(results[6], Err(EvalError::RuntimeError(..))));
+    // Adding to a quoted scene name updates the binding and returns the handle
+    let s2: ForeignDataWrapper<RTObjectWrapper> = env.get("s2").unwrap().try_into().unwrap();
+    assert_eq!(results[7], Ok(Expression::Integer(s2.id() as i64)));
+    assert_eq!(results[8].as_ref().ok(), env.get("scn").as_ref());
+    assert!(matches!(results[9], Err(EvalError::TypeError(..))));
+
+    // The replacement keeps the handle of the replaced object
+    let s1: ForeignDataWrapper<RTObjectWrapper> = env.get("s1").unwrap().try_into().unwrap();
+    let mut scn = scn;
+    assert!(scn.remove_object(s1.id()).is_some());
+}
+
+#[test]
+fn test_coordinate_lists() {
+    use lispers_core::lisp::prelude::mk_prelude;
+    use lispers_core::parser::ExpressionStream;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let program = r#"
+        (dot '(1 2 3) (vector 1 1 1))
+        (light '(0 5 0) '(1 1 1))
+        (light (point 0 5 0) (color 1 1 1))
+        (dot '(1 2) (vector 1 1 1))
+        (dot '(1 a 2) (vector 1 1 1))
+        (vector-ref (point 1 2 3) 2)
+        (vector-ref '(1 2 3) 3)
+    "#;
+
+    let results = ExpressionStream::from_char_stream(program.chars())
+        .map(|e| eval(&env, e.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(results[0], Ok(Expression::Float(6.0)));
+    assert_eq!(results[1], results[2]);
+    assert!(matches!(results[3], Err(EvalError::TypeError(_))));
+    assert!(matches!(results[4], Err(EvalError::TypeError(_))));
+    assert_eq!(results[5], Ok(Expression::Float(3.0)));
+    assert_eq!(results[6], Err(EvalError::IndexOutOfRange(3, 3)));
+}
+
+#[test]
+fn test_arithmetic_operators() {
+    use lispers_core::lisp::eval_source;
+    use lispers_core::lisp::prelude::mk_prelude;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    let env = Environment::from_layer(layer);
+
+    let results = eval_source(
+        &env,
+        r#"
+        (+ 1 2)
+        (* 9223372036854775807 2)
+        (/ 7 2)
+        (- 1.5 1)
+        (vector-ref (+ (vector 1 2 3) (vector 1 1 1)) 2)
+        (vector-ref (* 2 (point 1 2 3)) 0)
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(results[0], Expression::Integer(3));
+    assert_eq!(results[1].to_string(), "18446744073709551614");
+    assert_eq!(results[2], Expression::Float(3.5));
+    assert_eq!(results[3], Expression::Float(0.5));
+    assert_eq!(results[4], Expression::Float(4.0));
+    assert_eq!(results[5], Expression::Float(2.0));
+    assert!(eval_source(&env, "(+ 1 'a)").is_err());
+}
+
+#[test]
+fn test_preview() {
+    use lispers_core::lisp::eval_source;
+    use lispers_core::lisp::prelude::mk_prelude;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    let out = std::env::temp_dir().join(format!("lispers-test-preview-{}.png", std::process::id()));
+    layer.set("OUT".to_string(), out.display().to_string().into());
+    let env = Environment::from_layer(layer);
+
+    let results = eval_source(
+        &env,
+        r#"
+        (set 'm (material (color 1 0 0) (color 1 0 0) (color 1 1 1) 10 0))
+        (set 'scn (scene (color 0 0 0) (list (sphere (point 0 0 0) 1 m)) nil))
+        (set 'cam (camera (point 0 0 5) (point 0 0 0) (vector 0 1 0) 40 20 11))
+        (set 'captured (with-captured-output (preview cam scn OUT)))
+        (car captured)
+        (cdr captured)
+        "#,
+    )
+    .unwrap();
+
+    assert!(matches!(results[4], Expression::Float(t) if t >= 0.0));
+    assert!(matches!(&results[5], Expression::String(s) if s.starts_with("Preview written to")));
+    let img = image::open(&out).unwrap();
+    assert_eq!((img.width(), img.height()), (10, 5));
+    std::fs::remove_file(out).unwrap();
+}
+
+#[test]
+fn test_render_hooks() {
+    use lispers_core::lisp::eval_source;
+    use lispers_core::lisp::prelude::mk_prelude;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    mk_raytrace(&mut layer);
+    layer.set("BENCHMARK".to_string(), Expression::True);
+    let env = Environment::from_layer(layer);
+
+    let results = eval_source(
+        &env,
+        r#"
+        (set 'contexts nil)
+        (add-hook 'before-render (lambda (ctx) (set 'contexts (cons ctx contexts))))
+        (add-hook 'after-render (lambda (ctx) (set 'contexts (cons ctx contexts))))
+        (set 'cam (camera (point 0 0 5) (point 0 0 0) (vector 0 1 0) 40 4 2))
+        (render cam (scene (color 0 0 0) nil nil) 1 1 "hooks.png")
+        contexts
+        (render-animation cam "hooks.mp4" (lambda (t) (scene (color 0 0 0) nil nil)) (lambda (t c) c) 2 30 1 1)
+        "#,
+    )
+    .unwrap();
+
+    let contexts: Vec<Expression> = results[5].clone().try_into().unwrap();
+    let [after, before] = contexts.try_into().unwrap();
+    let before = before.to_string();
+    assert!(before.contains("hooks.png\") (width . 4) (height . 2))"));
+    assert!(after.to_string().starts_with(&before[..before.len() - 1]));
+    assert!(after.to_string().contains("(seconds . "));
+    assert_eq!(results[6], Expression::Nil);
+    assert!(!std::path::Path::new("hooks.mp4").exists());
+}
+
+#[test]
+fn test_registered_functions() {
+    use lispers_core::lisp::registry::registered_functions;
+
+    let names: Vec<_> = registered_functions("raytrace")
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "*",
+            "+",
+            "-",
+            "/",
+            "abs",
+            "camera",
+            "camera-project",
+            "camera-reposition",
+            "checkerboard",
+            "color",
+            "cos",
+            "dot",
+            "heightfield",
+            "light",
+            "light-link",
+            "light-with-profile",
+            "mandelbrot-texture",
+            "material",
+            "material-refraction",
+            "material-spectrum",
+            "noise",
+            "object-group",
+            "object-id",
+            "plane",
+            "point",
+            "point2",
+            "preview",
+            "render",
+            "render-animation",
+            "render-stereo",
+            "scene",
+            "scene-add",
+            "scene-remove",
+            "scene-replace",
+            "sin",
+            "sphere",
+            "texture-plane",
+            "texture-sphere",
+            "vector",
+            "vector-ref",
+        ]
     );
-    layer.set("sin".to_string(), Expression::Function(sin));
-    layer.set("cos".to_string(), Expression::Function(cos));
-    layer.set("+".to_string(), Expression::Function(add));
-    layer.set("-".to_string(), Expression::Function(sub));
-    layer.set("*".to_string(), Expression::Function(mul));
-    layer.set("/".to_string(), Expression::Function(div));
-    layer.set("dot".to_string(), Expression::Function(dot));
-    layer.set("abs".to_string(), Expression::Function(abs));
+
+    let mut layer = EnvironmentLayer::new();
+    mk_raytrace(&mut layer);
+    assert!(layer.get("origin").is_some());
 }
@@ -1,18 +1,29 @@
 pub mod camera;
+pub mod checkpoint;
+pub mod framebuffer;
+pub mod heightfield;
+pub mod integrator;
+#[cfg(feature = "lisp-bindings")]
 pub mod lisp;
 pub mod plane;
+pub mod sampler;
 pub mod scene;
+pub mod spectral;
 pub mod sphere;
-mod texture;
+pub mod texture;
 pub mod types;
 mod vec;
 
 #[derive(Debug, Clone)]
+#[non_exhaustive]
+/// Errors of rendering scenes and videos.
 pub enum RTError {
+    #[cfg(feature = "lisp-bindings")]
     EvalError(lispers_core::lisp::eval::EvalError),
     FFMpegError(video_rs::Error),
 }
 
+#[cfg(feature = "lisp-bindings")]
 impl From<lispers_core::lisp::eval::EvalError> for RTError {
     fn from(value: lispers_core::lisp::eval::EvalError) -> Self {
         RTError::EvalError(value)
@@ -24,3 +35,23 @@ impl From<video_rs::Error> for RTError {
         RTError::FFMpegError(value)
     }
 }
+
+impl std::fmt::Display for RTError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(feature = "lisp-bindings")]
+            RTError::EvalError(e) => write!(f, "{}", e),
+            RTError::FFMpegError(e) => write!(f, "FFMpeg error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RTError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "lisp-bindings")]
+            RTError::EvalError(e) => e.source(),
+            RTError::FFMpegError(e) => Some(e),
+        }
+    }
+}
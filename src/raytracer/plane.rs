@@ -113,13 +113,14 @@ fn plane_intersect(
     position: Point3,
     normal: Vector3,
     ray: &super::types::Ray,
+    tmax: super::types::Scalar,
 ) -> Option<(Point3, Vector3, super::types::Scalar)> {
     let denom = normal.dot(&ray.direction);
     if denom != 0.0 {
         let d = normal.dot(&position.coords);
         let t = (d - normal.dot(&ray.origin.coords)) / denom;
 
-        if t > 1e-5 {
+        if t > 1e-5 && t < tmax {
             let point = ray.origin + ray.direction * t;
             return Some((point, normal, t));
         }
@@ -131,14 +132,15 @@ impl Intersect for Plane {
     fn intersect(
         &self,
         ray: &super::types::Ray,
+        tmax: super::types::Scalar,
     ) -> Option<(
         Point3,
         Vector3,
         super::types::Scalar,
         super::types::Material,
     )> {
-        if let Some((point, normal, t)) = plane_intersect(self.position, self.normal, ray) {
-            Some((point, normal, t, self.material.clone()))
+        if let Some((point, normal, t)) = plane_intersect(self.position, self.normal, ray, tmax) {
+            Some((point, normal, t, self.material))
         } else {
             None
         }
@@ -149,22 +151,23 @@ impl Intersect for Checkerboard {
     fn intersect(
         &self,
         ray: &super::types::Ray,
+        tmax: super::types::Scalar,
     ) -> Option<(
         Point3,
         Vector3,
         super::types::Scalar,
         super::types::Material,
     )> {
-        if let Some((point, normal, t, material)) = self.base.intersect(ray) {
+        if let Some((point, normal, t, material)) = self.base.intersect(ray, tmax) {
             let v3 = point - self.base.position;
             let v2 = self.projection_matrix * v3;
 
             if ((v2.x / self.scale).round() % 2.0 == 0.0)
                 == ((v2.y / self.scale).round() % 2.0 == 0.0)
             {
-                Some((point, normal, t, material.clone()))
+                Some((point, normal, t, material))
             } else {
-                Some((point, normal, t, self.material_alt.clone()))
+                Some((point, normal, t, self.material_alt))
             }
         } else {
             None
@@ -176,13 +179,14 @@ impl Intersect for TexturePlane {
     fn intersect(
         &self,
         ray: &super::types::Ray,
+        tmax: super::types::Scalar,
     ) -> Option<(
         Point3,
         Vector3,
         super::types::Scalar,
         super::types::Material,
     )> {
-        if let Some((point, normal, t)) = plane_intersect(self.position, self.normal, ray) {
+        if let Some((point, normal, t)) = plane_intersect(self.position, self.normal, ray, tmax) {
             let v3 = point - self.position;
             let v2 = self.projection_matrix * v3;
             let material = self
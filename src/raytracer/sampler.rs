@@ -0,0 +1,170 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::types::Scalar;
+
+/// Generates the positions of the subpixel samples of a pixel, which are averaged for
+/// antialiasing.
+pub trait Sampler: Debug + Sync + Send {
+    /// Get the position of the `index`-th of `count` samples of the pixel at `x`, `y`, as the
+    /// offset in `[0, 1)` from the corner of the pixel. `count` is the square of the number of
+    /// subpixels per axis.
+    fn sample(&self, x: usize, y: usize, index: usize, count: usize) -> (Scalar, Scalar);
+}
+
+/// Samples at the corners of a regular grid of subpixels. Deterministic, but prone to aliasing
+/// patterns. This is the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GridSampler;
+
+impl Sampler for GridSampler {
+    fn sample(&self, _x: usize, _y: usize, index: usize, count: usize) -> (Scalar, Scalar) {
+        let n = grid_size(count);
+        let cell = 1.0 / n as Scalar;
+        ((index / n) as Scalar * cell, (index % n) as Scalar * cell)
+    }
+}
+
+/// Uniformly distributed random samples, which turn aliasing into noise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn sample(&self, x: usize, y: usize, index: usize, _count: usize) -> (Scalar, Scalar) {
+        (random(x, y, index, 0), random(x, y, index, 1))
+    }
+}
+
+/// One random sample in each cell of a regular grid of subpixels (jittered sampling), which has
+/// less noise than `RandomSampler`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StratifiedSampler;
+
+impl Sampler for StratifiedSampler {
+    fn sample(&self, x: usize, y: usize, index: usize, count: usize) -> (Scalar, Scalar) {
+        let n = grid_size(count);
+        let cell = 1.0 / n as Scalar;
+        (
+            ((index / n) as Scalar + random(x, y, index, 0)) * cell,
+            ((index % n) as Scalar + random(x, y, index, 1)) * cell,
+        )
+    }
+}
+
+/// The low-discrepancy Halton sequence in the bases 2 and 3, which covers the pixel evenly for
+/// any number of samples. Each pixel shifts the sequence randomly, so neighbouring pixels do not
+/// sample the same positions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HaltonSampler;
+
+impl Sampler for HaltonSampler {
+    fn sample(&self, x: usize, y: usize, index: usize, _count: usize) -> (Scalar, Scalar) {
+        let shift = |base: usize, dimension: u64| {
+            (radical_inverse(index + 1, base) + random(x, y, 0, dimension)).fract()
+        };
+        (shift(2, 0), shift(3, 1))
+    }
+}
+
+/// Get the number of subpixels per axis of `count` samples.
+fn grid_size(count: usize) -> usize {
+    ((count as Scalar).sqrt().round() as usize).max(1)
+}
+
+/// Mirror the digits of `i` in `base` at the decimal point, which gives the `i`-th element of
+/// the van der Corput sequence.
+fn radical_inverse(mut i: usize, base: usize) -> Scalar {
+    let mut inverse = 0.0;
+    let mut digit_value = 1.0 / base as Scalar;
+    while i > 0 {
+        inverse += (i % base) as Scalar * digit_value;
+        i /= base;
+        digit_value /= base as Scalar;
+    }
+    inverse
+}
+
+/// Get a random number in `[0, 1)`, which only depends on the pixel, the sample index and the
+/// dimension. Renders are reproducible and need no shared state between threads.
+fn random(x: usize, y: usize, index: usize, dimension: u64) -> Scalar {
+    // The SplitMix64 finalizer
+    let mut z = (x as u64)
+        .wrapping_mul(0x9e3779b97f4a7c15)
+        .wrapping_add((y as u64).wrapping_mul(0xc2b2ae3d27d4eb4f))
+        .wrapping_add((index as u64).wrapping_mul(0x165667b19e3779f9))
+        .wrapping_add(dimension.wrapping_mul(0x27d4eb2f165667c5));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    (z >> 11) as Scalar / (1u64 << 53) as Scalar
+}
+
+/// A shared sampler, which is compared by identity.
+#[derive(Clone, Debug)]
+pub struct SamplerWrapper(Arc<dyn Sampler>);
+
+impl SamplerWrapper {
+    pub fn new<S: Sampler + 'static>(sampler: S) -> Self {
+        Self(Arc::new(sampler))
+    }
+
+    /// Get a builtin sampler by its name: `grid`, `random`, `stratified` or `halton`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grid" => Some(Self::new(GridSampler)),
+            "random" => Some(Self::new(RandomSampler)),
+            "stratified" => Some(Self::new(StratifiedSampler)),
+            "halton" => Some(Self::new(HaltonSampler)),
+            _ => None,
+        }
+    }
+
+    pub fn sample(&self, x: usize, y: usize, index: usize, count: usize) -> (Scalar, Scalar) {
+        self.0.sample(x, y, index, count)
+    }
+}
+
+impl Default for SamplerWrapper {
+    fn default() -> Self {
+        Self::new(GridSampler)
+    }
+}
+
+impl PartialEq for SamplerWrapper {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[test]
+fn test_samplers() {
+    for name in ["grid", "random", "stratified", "halton"] {
+        let sampler = SamplerWrapper::from_name(name).unwrap();
+        let samples: Vec<_> = (0..16).map(|i| sampler.sample(3, 5, i, 16)).collect();
+        assert!(
+            samples
+                .iter()
+                .all(|(x, y)| (0.0..1.0).contains(x) && (0.0..1.0).contains(y)),
+            "{} samples outside of the pixel",
+            name
+        );
+        // Every quarter of the pixel gets samples
+        for (qx, qy) in [(0.0, 0.0), (0.5, 0.0), (0.0, 0.5), (0.5, 0.5)] {
+            assert!(
+                samples
+                    .iter()
+                    .any(|(x, y)| (qx..qx + 0.5).contains(x) && (qy..qy + 0.5).contains(y)),
+                "{} leaves a quarter of the pixel empty",
+                name
+            );
+        }
+    }
+
+    assert_eq!(GridSampler.sample(0, 0, 5, 9), (1.0 / 3.0, 2.0 / 3.0));
+    assert_eq!(radical_inverse(6, 2), 0.375);
+    assert_ne!(
+        RandomSampler.sample(0, 0, 0, 1),
+        RandomSampler.sample(1, 0, 0, 1)
+    );
+    assert!(SamplerWrapper::from_name("sobol").is_none());
+}
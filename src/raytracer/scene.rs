@@ -1,15 +1,19 @@
 use std::fmt::Display;
 
+use super::integrator::Integrator;
+use super::integrator::WhittedIntegrator;
+use super::spectral::Channel;
 use super::types::Color;
 use super::types::Intersect;
+use super::types::Intersection;
 use super::types::Light;
 use super::types::Material;
 use super::types::Point3;
 use super::types::RTObjectWrapper;
 use super::types::Ray;
+use super::types::Scalar;
 use super::types::Vector3;
 use super::vec::mirror;
-use super::vec::reflect;
 extern crate nalgebra as na;
 
 /// A scene is a collection of objects and lights, and provides a method to trace a ray through the scene.
@@ -43,59 +47,79 @@ impl Scene {
         self.objects.push(obj);
     }
 
+    /// Remove the object with the given id from the scene, returning it if present
+    pub fn remove_object(&mut self, id: u64) -> Option<RTObjectWrapper> {
+        let idx = self.objects.iter().position(|o| o.id() == id)?;
+        Some(self.objects.remove(idx))
+    }
+
+    /// Replace the object with the given id, returning the old object if present.
+    /// The new object takes over the id, so existing handles and light links stay valid.
+    pub fn replace_object(&mut self, id: u64, obj: RTObjectWrapper) -> Option<RTObjectWrapper> {
+        let slot = self.objects.iter_mut().find(|o| o.id() == id)?;
+        Some(std::mem::replace(slot, obj.with_id(id)))
+    }
+
     /// Add a light to the scene
     pub fn add_light(&mut self, light: Light) {
         self.lights.push(light);
     }
 
-    /// Trace a ray through the scene and return the color of the ray.
+    /// Trace a ray through the scene with `WhittedIntegrator` and return the color of the ray.
     /// - `ray` is the ray to be traced
     /// - `depth` is the maximum recursion depth aka the number of reflections
     pub fn trace(&self, ray: &Ray, depth: u32) -> Color {
-        if depth == 0 {
-            return na::Vector3::new(0.0, 0.0, 0.0);
-        }
+        WhittedIntegrator.radiance(self, ray, depth, Channel::Rgb)
+    }
 
-        match self
-            .objects
-            .iter()
-            .filter_map(|obj| obj.intersect(ray))
-            .min_by(|(_, _, t1, _), (_, _, t2, _)| t1.partial_cmp(t2).unwrap())
-        {
-            Some((isect_pt, isect_norm, _, material)) => {
-                // Lighting of material at the intersection point
-                let color = self.lighting(-&ray.direction, &material, isect_pt, isect_norm);
-
-                // Calculate reflections, if the material has mirror properties
-                if material.mirror > 0.0 {
-                    let new_ray = Ray {
-                        origin: isect_pt,
-                        direction: reflect(ray.direction, isect_norm),
-                    };
-                    return (1.0 - material.mirror) * color
-                        + material.mirror * self.trace(&new_ray, depth - 1);
-                } else {
-                    return color;
-                }
-            }
-            _ => {
-                return na::Vector3::new(0.0, 0.0, 0.0);
+    /// Trace a ray with `WhittedIntegrator` separately for each wavelength band and convert the
+    /// result to RGB.
+    /// - `ray` is the ray to be traced
+    /// - `depth` is the maximum recursion depth aka the number of reflections
+    /// - `wavelengths` are the center wavelengths [nm] of the bands
+    pub fn trace_spectral(&self, ray: &Ray, depth: u32, wavelengths: &[Scalar]) -> Color {
+        WhittedIntegrator.radiance_spectral(self, ray, depth, wavelengths)
+    }
+
+    /// Find the closest intersection of a ray with the objects of the scene. Returns the
+    /// intersected object and its intersection (see `Intersect::intersect`).
+    ///
+    /// The distance of the closest hit so far is passed on as `tmax`, so farther objects are
+    /// rejected without computing their intersection.
+    pub fn intersect(&self, ray: &Ray) -> Option<(&RTObjectWrapper, Intersection)> {
+        let mut closest = None;
+        let mut tmax = Scalar::MAX;
+        for obj in &self.objects {
+            if let Some(isect) = obj.intersect(ray, tmax) {
+                tmax = isect.2;
+                closest = Some((obj, isect));
             }
         }
+        closest
     }
 
-    /// Calculate Phong lighting from a `view` on a `material` at an intersection point `isect_pt` with a normal `isect_norm`.
-    fn lighting(
+    /// Calculate Phong lighting from a `view` on a `material` at an intersection point `isect_pt`
+    /// with a normal `isect_norm`.
+    /// Only lights linked to the intersected object `obj` contribute. All colors are projected
+    /// onto `channel`.
+    pub fn lighting(
         &self,
+        obj: &RTObjectWrapper,
         view: Vector3,
         material: &Material,
         isect_pt: Point3,
         isect_norm: Vector3,
+        channel: Channel,
     ) -> Color {
+        let diffuse_color = channel.reflectance(&material.diffuse_color, material);
+        let specular_color = channel.project(&material.specular_color);
+
         // Start with ambient lighting
-        let mut color = material.ambient_color.component_mul(&self.ambient);
+        let mut color = channel
+            .reflectance(&material.ambient_color, material)
+            .component_mul(&channel.project(&self.ambient));
 
-        for light in &self.lights {
+        for light in self.lights.iter().filter(|l| l.illuminates(obj)) {
             // Cast Shadow-Ray
             let direction = light.position - isect_pt;
             let distance = direction.norm();
@@ -104,25 +128,26 @@ impl Scene {
                 origin: isect_pt,
                 direction,
             };
-            if self.objects.iter().any(|obj| {
-                obj.intersect(&shadow_ray)
-                    .and_then(|(_, _, t, _)| Some(t < distance))
-                    .unwrap_or(false)
-            }) {
+            if self
+                .objects
+                .iter()
+                .any(|obj| obj.intersect(&shadow_ray, distance).is_some())
+            {
                 continue;
             }
 
             // Diffuse
             let l = (light.position - isect_pt).normalize();
+            let light_color = channel.project(&light.color) * light.intensity(&-l);
             let cos_theta = l.dot(&isect_norm);
             if cos_theta > 0.0 {
-                color += material.diffuse_color.component_mul(&light.color) * cos_theta;
+                color += diffuse_color.component_mul(&light_color) * cos_theta;
 
                 // Specular
                 let r = mirror(l, isect_norm);
                 let cos_alpha = r.dot(&view);
                 if cos_alpha > 0.0 {
-                    color += material.specular_color.component_mul(&light.color)
+                    color += specular_color.component_mul(&light_color)
                         * cos_alpha.powf(material.shininess);
                 }
             }
@@ -132,6 +157,12 @@ impl Scene {
     }
 }
 
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PartialOrd for Scene {
     fn partial_cmp(&self, _other: &Self) -> Option<std::cmp::Ordering> {
         None
@@ -149,3 +180,99 @@ impl Display for Scene {
         )
     }
 }
+
+#[test]
+fn test_light_linking() {
+    use super::sphere::Sphere;
+    use super::types::LightLinks;
+
+    let material = Material::new(
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(1.0, 1.0, 1.0),
+        Color::new(0.0, 0.0, 0.0),
+        1.0,
+        0.0,
+    );
+    let lit = RTObjectWrapper::from(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material));
+    let mut unlit = RTObjectWrapper::from(Sphere::new(Point3::new(5.0, 0.0, 0.0), 1.0, material));
+    unlit.add_group("background".to_string());
+
+    let mut light = Light::new(Point3::new(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
+    light.link(LightLinks {
+        objects: vec![lit.id()],
+        groups: vec![],
+    });
+    assert!(light.illuminates(&lit));
+    assert!(!light.illuminates(&unlit));
+
+    let mut scene = Scene::new();
+    scene.add_object(lit);
+    scene.add_object(unlit);
+    scene.add_light(light);
+
+    let to_lit = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    let to_unlit = Ray::new(Point3::new(5.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(scene.trace(&to_lit, 1).norm() > 0.0);
+    assert_eq!(scene.trace(&to_unlit, 1), Color::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn test_closest_intersection() {
+    use super::sphere::Sphere;
+
+    let material = Material::new(
+        Color::new(0.0, 0.0, 0.0),
+        Color::new(1.0, 1.0, 1.0),
+        Color::new(0.0, 0.0, 0.0),
+        1.0,
+        0.0,
+    );
+    let near = RTObjectWrapper::from(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material));
+    let far = RTObjectWrapper::from(Sphere::new(Point3::new(0.0, 0.0, -1.5), 1.0, material));
+    let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+
+    // The overlapping farther sphere is skipped, whichever is added first
+    for objects in [[&near, &far], [&far, &near]] {
+        let mut scene = Scene::new();
+        for obj in objects {
+            scene.add_object(obj.clone());
+        }
+        let (obj, isect) = scene.intersect(&ray).unwrap();
+        assert_eq!(obj.id(), near.id());
+        assert!((isect.2 - 4.0).abs() < 1e-9);
+    }
+    // Once the near sphere is hit, the far one is rejected by `tmax`
+    assert!(far.intersect(&ray, Scalar::MAX).is_some());
+    assert!(far.intersect(&ray, 4.0).is_none());
+
+    // Shadow rays only reach up to the light, occluders right behind it cast no shadow
+    let lit = |occluder_z: Scalar| {
+        let mut scene = Scene::new();
+        scene.add_object(near.clone());
+        scene.add_object(RTObjectWrapper::from(Sphere::new(
+            Point3::new(0.0, 0.0, occluder_z),
+            1.0,
+            material,
+        )));
+        scene.add_light(Light::new(
+            Point3::new(0.0, 0.0, 10.0),
+            Color::new(1.0, 1.0, 1.0),
+        ));
+        let view = Vector3::new(0.0, 0.0, 1.0);
+        let color = scene.lighting(
+            &scene.objects[0],
+            view,
+            &material,
+            Point3::new(0.0, 0.0, 1.0),
+            view,
+            Channel::Rgb,
+        );
+        color.norm() > 0.0
+    };
+    assert!(!lit(5.0));
+    // Hit at a distance of 8.5, before the light at 9
+    assert!(!lit(10.5));
+    // Hit at a distance of 9.5, beyond the light
+    assert!(lit(11.5));
+    assert!(lit(15.0));
+}
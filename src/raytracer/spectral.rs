@@ -0,0 +1,136 @@
+use super::types::{Color, Material, Scalar};
+
+/// Shortest wavelength of the rendered spectrum [nm]
+pub const MIN_WAVELENGTH: Scalar = 400.0;
+/// Longest wavelength of the rendered spectrum [nm]
+pub const MAX_WAVELENGTH: Scalar = 700.0;
+/// Number of samples stored in a `Spectrum`
+pub const SPECTRUM_SAMPLES: usize = 16;
+/// Wavelength at which a material's base index of refraction is specified (sodium D line) [nm]
+const REFERENCE_WAVELENGTH: Scalar = 589.3;
+
+/// A reflectance curve over the visible spectrum, stored as evenly spaced samples
+/// between `MIN_WAVELENGTH` and `MAX_WAVELENGTH`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spectrum([Scalar; SPECTRUM_SAMPLES]);
+
+impl Spectrum {
+    /// Create a spectrum by sampling `f(wavelength)` with wavelengths in nm.
+    pub fn sample<E, F: FnMut(Scalar) -> Result<Scalar, E>>(mut f: F) -> Result<Spectrum, E> {
+        let mut samples = [0.0; SPECTRUM_SAMPLES];
+        for (i, s) in samples.iter_mut().enumerate() {
+            let t = i as Scalar / (SPECTRUM_SAMPLES - 1) as Scalar;
+            *s = f(MIN_WAVELENGTH + t * (MAX_WAVELENGTH - MIN_WAVELENGTH))?;
+        }
+        Ok(Spectrum(samples))
+    }
+
+    /// Get the linearly interpolated value at `wavelength` [nm], clamped to the sampled range.
+    pub fn at(&self, wavelength: Scalar) -> Scalar {
+        let t = ((wavelength - MIN_WAVELENGTH) / (MAX_WAVELENGTH - MIN_WAVELENGTH)).clamp(0.0, 1.0)
+            * (SPECTRUM_SAMPLES - 1) as Scalar;
+        let i = (t.floor() as usize).min(SPECTRUM_SAMPLES - 2);
+        let f = t - i as Scalar;
+        self.0[i] * (1.0 - f) + self.0[i + 1] * f
+    }
+}
+
+/// Get the center wavelengths [nm] of `n` evenly spaced bands covering the visible spectrum.
+pub fn band_wavelengths(n: usize) -> Vec<Scalar> {
+    let width = (MAX_WAVELENGTH - MIN_WAVELENGTH) / n as Scalar;
+    (0..n)
+        .map(|i| MIN_WAVELENGTH + (i as Scalar + 0.5) * width)
+        .collect()
+}
+
+/// Sensitivity of the red, green and blue channel at `wavelength` [nm].
+fn rgb_sensitivity(wavelength: Scalar) -> Color {
+    let gauss = |center: Scalar, width: Scalar| {
+        let x = (wavelength - center) / width;
+        (-0.5 * x * x).exp()
+    };
+    Color::new(gauss(610.0, 40.0), gauss(550.0, 35.0), gauss(465.0, 30.0))
+}
+
+/// Upsample an RGB color to its value at `wavelength` [nm]. White maps to 1 at all wavelengths.
+pub fn rgb_to_band(color: &Color, wavelength: Scalar) -> Scalar {
+    let s = rgb_sensitivity(wavelength);
+    color.dot(&s) / s.sum()
+}
+
+/// Convert band radiances at the given `wavelengths` [nm] back to RGB.
+/// A constant spectrum of 1 maps to white.
+pub fn bands_to_rgb(wavelengths: &[Scalar], radiances: &[Scalar]) -> Color {
+    let mut color = Color::new(0.0, 0.0, 0.0);
+    let mut norm = Color::new(0.0, 0.0, 0.0);
+    for (w, r) in wavelengths.iter().zip(radiances) {
+        let s = rgb_sensitivity(*w);
+        color += s * *r;
+        norm += s;
+    }
+    color.component_div(&norm)
+}
+
+/// The channel a ray is traced in: either plain RGB or a single wavelength band.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Rgb,
+    /// A single band with its center wavelength [nm]. Colors are projected onto the band and
+    /// broadcast to all three components.
+    Band(Scalar),
+}
+
+impl Channel {
+    /// Project a light color onto the channel.
+    pub fn project(&self, color: &Color) -> Color {
+        match self {
+            Channel::Rgb => *color,
+            Channel::Band(w) => Color::repeat(rgb_to_band(color, *w)),
+        }
+    }
+
+    /// Project a surface color of `material` onto the channel, using the material's
+    /// reflectance spectrum if it has one.
+    pub fn reflectance(&self, color: &Color, material: &Material) -> Color {
+        match (self, &material.reflectance) {
+            (Channel::Band(w), Some(s)) => Color::repeat(s.at(*w)),
+            _ => self.project(color),
+        }
+    }
+
+    /// Get the index of refraction of `material` in the channel, following Cauchy's equation
+    /// `n(λ) = ior + dispersion * (1/λ² - 1/λ_D²)` with λ in µm. RGB ignores dispersion.
+    pub fn ior(&self, material: &Material) -> Scalar {
+        match self {
+            Channel::Rgb => material.ior,
+            Channel::Band(w) => {
+                let inv_sq = |w: Scalar| 1.0 / (w / 1000.0).powi(2);
+                material.ior + material.dispersion * (inv_sq(*w) - inv_sq(REFERENCE_WAVELENGTH))
+            }
+        }
+    }
+}
+
+#[test]
+fn test_spectral_roundtrip() {
+    let wavelengths = band_wavelengths(8);
+    assert_eq!(wavelengths.len(), 8);
+    assert!(wavelengths[0] > MIN_WAVELENGTH && wavelengths[7] < MAX_WAVELENGTH);
+
+    let white = Color::new(1.0, 1.0, 1.0);
+    let radiances: Vec<Scalar> = wavelengths
+        .iter()
+        .map(|w| rgb_to_band(&white, *w))
+        .collect();
+    assert!((bands_to_rgb(&wavelengths, &radiances) - white).norm() < 1e-9);
+
+    // Red stays predominantly red
+    let red = Color::new(1.0, 0.0, 0.0);
+    let radiances: Vec<Scalar> = wavelengths.iter().map(|w| rgb_to_band(&red, *w)).collect();
+    let back = bands_to_rgb(&wavelengths, &radiances);
+    assert!(back.x > back.y && back.y > back.z);
+
+    let ramp = Spectrum::sample(|w| Ok::<_, ()>(w / 1000.0)).unwrap();
+    assert!((ramp.at(550.0) - 0.55).abs() < 1e-9);
+    assert!((ramp.at(900.0) - 0.7).abs() < 1e-9);
+}
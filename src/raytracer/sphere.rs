@@ -41,7 +41,12 @@ impl Sphere {
 /// Numerical error tolerance
 const EPSILON: Scalar = 1e-5;
 
-fn intersect(ray: &Ray, center: &Point3, radius: Scalar) -> Option<(Point3, Vector3, Scalar)> {
+fn intersect(
+    ray: &Ray,
+    center: &Point3,
+    radius: Scalar,
+    tmax: Scalar,
+) -> Option<(Point3, Vector3, Scalar)> {
     let co = ray.origin - center;
 
     let a = ray.direction.dot(&ray.direction);
@@ -53,7 +58,7 @@ fn intersect(ray: &Ray, center: &Point3, radius: Scalar) -> Option<(Point3, Vect
         let e = d.sqrt();
         let t1 = (-b - e) / (2.0 * a);
         let t2 = (-b + e) / (2.0 * a);
-        let mut t = Scalar::MAX;
+        let mut t = tmax;
 
         if t1 > EPSILON && t1 < t {
             t = t1;
@@ -62,7 +67,7 @@ fn intersect(ray: &Ray, center: &Point3, radius: Scalar) -> Option<(Point3, Vect
             t = t2;
         }
 
-        if t < Scalar::MAX {
+        if t < tmax {
             let isect_pt: Point3 = ray.origin + ray.direction * t;
 
             if c >= 0.0 {
@@ -77,11 +82,9 @@ fn intersect(ray: &Ray, center: &Point3, radius: Scalar) -> Option<(Point3, Vect
 }
 
 impl Intersect for Sphere {
-    fn intersect(&self, ray: &Ray) -> Option<(Point3, Vector3, Scalar, Material)> {
-        match intersect(ray, &self.center, self.radius) {
-            Some((isect_pt, normal, t)) => Some((isect_pt, normal, t, self.material.clone())),
-            None => None,
-        }
+    fn intersect(&self, ray: &Ray, tmax: Scalar) -> Option<(Point3, Vector3, Scalar, Material)> {
+        intersect(ray, &self.center, self.radius, tmax)
+            .map(|(isect_pt, normal, t)| (isect_pt, normal, t, self.material))
     }
 }
 
@@ -113,8 +116,8 @@ impl TextureSphere {
 }
 
 impl Intersect for TextureSphere {
-    fn intersect(&self, ray: &Ray) -> Option<(Point3, Vector3, Scalar, Material)> {
-        match intersect(ray, &self.center, self.radius) {
+    fn intersect(&self, ray: &Ray, tmax: Scalar) -> Option<(Point3, Vector3, Scalar, Material)> {
+        match intersect(ray, &self.center, self.radius, tmax) {
             Some((isect_pt, normal, t)) => {
                 let n_isect_pt = (isect_pt - self.center) / self.radius;
                 let uv: Point2 = Point2::new(
@@ -96,13 +96,13 @@ impl Texture for MandelbrotTexture {
         }
         let c = n as f64 / self.max_iter as f64;
 
-        Material {
-            ambient_color: self.ambient_color * c,
-            diffuse_color: self.diffuse_color * c,
-            specular_color: self.specular_color * c,
-            shininess: (1.0 - c) * 10.0,
-            mirror: 1.0 - c,
-        }
+        Material::new(
+            self.ambient_color * c,
+            self.diffuse_color * c,
+            self.specular_color * c,
+            (1.0 - c) * 10.0,
+            1.0 - c,
+        )
     }
 }
 
@@ -1,7 +1,10 @@
 use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use as_any::AsAny;
 
+use super::spectral::Spectrum;
+
 extern crate nalgebra as na;
 
 /// The Scalar type to use for raytracing (f32 may result in acne effects)
@@ -15,29 +18,119 @@ pub type Point2 = na::Point2<Scalar>;
 /// The Color type to use for raytracing
 pub type Color = Vector3;
 
+/// An intersection of a ray with an object: the intersection point, the normal vector at the
+/// point, the distance from the ray origin and the material of the object.
+pub type Intersection = (Point3, Vector3, Scalar, Material);
+
 /// A trait indicating, that an object can be intersected by a ray
 pub trait Intersect {
     /// Intersect the object with a ray.
-    /// Returns None if the ray does not intersect the object.
+    /// Returns None if the ray does not intersect the object before the distance `tmax`.
     /// Otherwise the intersection point, a normal vector at the intersection point,
     /// the distance from the ray origin to the intersection point and
     /// the material of the object are returned.
-    fn intersect(&self, ray: &Ray) -> Option<(Point3, Vector3, Scalar, Material)>;
+    ///
+    /// Pass `Scalar::MAX` to find any intersection, or the distance of the closest hit so far to
+    /// let objects reject farther hits early.
+    fn intersect(&self, ray: &Ray, tmax: Scalar) -> Option<(Point3, Vector3, Scalar, Material)>;
+}
+
+/// The set of objects a light is restricted to.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct LightLinks {
+    /// Ids of linked objects (see `RTObjectWrapper::id`)
+    pub objects: Vec<u64>,
+    /// Names of linked object groups
+    pub groups: Vec<String>,
+}
+
+impl LightLinks {
+    /// Check if an object is part of the links, either by id or by one of its groups.
+    pub fn contains(&self, obj: &RTObjectWrapper) -> bool {
+        self.objects.contains(&obj.id()) || obj.groups().iter().any(|g| self.groups.contains(g))
+    }
+}
+
+/// A directional intensity profile of a light, stored as a lookup table over the cosine of the
+/// angle between the profile axis and the direction from the light to the lit point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightProfile {
+    /// The main emission direction of the light
+    axis: Vector3,
+    /// Intensity factors for cosines evenly spaced in `[-1, 1]`
+    samples: Vec<Scalar>,
+}
+
+impl LightProfile {
+    /// Create a new profile around `axis` by sampling `f` at `n` (at least 2) evenly spaced
+    /// cosines in `[-1, 1]`.
+    pub fn sample<E, F: FnMut(Scalar) -> Result<Scalar, E>>(
+        axis: Vector3,
+        n: usize,
+        mut f: F,
+    ) -> Result<LightProfile, E> {
+        let n = n.max(2);
+        let samples = (0..n)
+            .map(|i| f(-1.0 + 2.0 * i as Scalar / (n - 1) as Scalar))
+            .collect::<Result<Vec<Scalar>, E>>()?;
+
+        Ok(LightProfile {
+            axis: axis.normalize(),
+            samples,
+        })
+    }
+
+    /// Get the linearly interpolated intensity factor for a direction from the light.
+    pub fn intensity(&self, direction: &Vector3) -> Scalar {
+        let cos_theta = self.axis.dot(direction).clamp(-1.0, 1.0);
+        let x = (cos_theta + 1.0) / 2.0 * (self.samples.len() - 1) as Scalar;
+        let i = (x.floor() as usize).min(self.samples.len() - 2);
+        let frac = x - i as Scalar;
+        self.samples[i] * (1.0 - frac) + self.samples[i + 1] * frac
+    }
 }
 
 /// A point light source
-#[derive(Clone, Debug, PartialEq, Copy)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Light {
     /// Position of the light source
     pub position: Point3,
     /// Light color
     pub color: Color,
+    /// Restrict the light to these objects. `None` lights every object.
+    pub links: Option<LightLinks>,
+    /// Directional intensity profile. `None` emits uniformly in all directions.
+    pub profile: Option<LightProfile>,
 }
 
 impl Light {
     /// Create a new light source at position with color
     pub fn new(position: Point3, color: Color) -> Light {
-        Light { position, color }
+        Light {
+            position,
+            color,
+            links: None,
+            profile: None,
+        }
+    }
+
+    /// Get the intensity factor of the light towards the normalized `direction`.
+    pub fn intensity(&self, direction: &Vector3) -> Scalar {
+        self.profile
+            .as_ref()
+            .map_or(1.0, |p| p.intensity(direction))
+    }
+
+    /// Restrict the light to the objects in `links`. Repeated calls extend the links.
+    pub fn link(&mut self, links: LightLinks) {
+        let current = self.links.get_or_insert_with(LightLinks::default);
+        current.objects.extend(links.objects);
+        current.groups.extend(links.groups);
+    }
+
+    /// Check if the light illuminates `obj`.
+    pub fn illuminates(&self, obj: &RTObjectWrapper) -> bool {
+        self.links.as_ref().is_none_or(|l| l.contains(obj))
     }
 }
 
@@ -75,6 +168,14 @@ pub struct Material {
     pub shininess: Scalar,
     /// A mirror factor, used to calculate the reflection of the object. `self_color * reflected_color = final_color`
     pub mirror: Scalar,
+    /// A transparency factor, used to calculate the refraction through the object
+    pub transparency: Scalar,
+    /// Index of refraction at the sodium D line (589.3nm)
+    pub ior: Scalar,
+    /// Cauchy dispersion coefficient [µm²], only used in spectral rendering
+    pub dispersion: Scalar,
+    /// Reflectance spectrum replacing the ambient and diffuse color in spectral rendering
+    pub reflectance: Option<Spectrum>,
 }
 
 impl Material {
@@ -97,8 +198,34 @@ impl Material {
             specular_color,
             shininess,
             mirror,
+            transparency: 0.0,
+            ior: 1.0,
+            dispersion: 0.0,
+            reflectance: None,
         }
     }
+
+    /// Make the material refractive.
+    /// - `transparency` is the fraction of light transmitted through the surface
+    /// - `ior` is the index of refraction
+    /// - `dispersion` is the Cauchy coefficient controlling how much `ior` varies with wavelength
+    pub fn with_refraction(
+        mut self,
+        transparency: Scalar,
+        ior: Scalar,
+        dispersion: Scalar,
+    ) -> Material {
+        self.transparency = transparency;
+        self.ior = ior;
+        self.dispersion = dispersion;
+        self
+    }
+
+    /// Set the reflectance spectrum used in spectral rendering.
+    pub fn with_reflectance(mut self, reflectance: Spectrum) -> Material {
+        self.reflectance = Some(reflectance);
+        self
+    }
 }
 
 impl PartialOrd for Material {
@@ -113,9 +240,20 @@ impl Display for Light {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "(light position: {}, color: {})",
+            "(light position: {}, color: {}",
             self.position, self.color
-        )
+        )?;
+        if let Some(l) = &self.links {
+            write!(
+                f,
+                ", linked objects: {:?}, linked groups: {:?}",
+                l.objects, l.groups
+            )?;
+        }
+        if let Some(p) = &self.profile {
+            write!(f, ", profile axis: {}", p.axis)?;
+        }
+        write!(f, ")")
     }
 }
 
@@ -123,9 +261,20 @@ impl Display for Material {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "(material ambient_color: {}, diffuse_color: {}, specular_color: {}, shininess: {}, mirror: {})",
+            "(material ambient_color: {}, diffuse_color: {}, specular_color: {}, shininess: {}, mirror: {}",
             self.ambient_color, self.diffuse_color, self.specular_color, self.shininess, self.mirror
-        )
+        )?;
+        if self.transparency > 0.0 {
+            write!(
+                f,
+                ", transparency: {}, ior: {}, dispersion: {}",
+                self.transparency, self.ior, self.dispersion
+            )?;
+        }
+        if self.reflectance.is_some() {
+            write!(f, ", spectral")?;
+        }
+        write!(f, ")")
     }
 }
 
@@ -163,14 +312,28 @@ impl PartialEq for dyn RTObject {
     }
 }
 
+/// Source of unique object ids
+static NEXT_OBJECT_ID: AtomicU64 = AtomicU64::new(0);
+
 /// The RTObjectWrapper is a wrapper around a Box<dyn RTObject> to make it ForeignData compatible
 /// (not depending on the concrete type of the object).
-pub struct RTObjectWrapper(Box<dyn RTObject>);
+pub struct RTObjectWrapper {
+    /// The wrapped object
+    object: Box<dyn RTObject>,
+    /// A unique id, preserved by clones, used to link lights to this object
+    id: u64,
+    /// Group tags of this object, used to link lights to groups of objects
+    groups: Vec<String>,
+}
 
 impl RTObjectWrapper {
     /// Create a new RTObjectWrapper from a Box<dyn RTObject>
     pub fn new<T: RTObject>(value: Box<T>) -> RTObjectWrapper {
-        RTObjectWrapper(value)
+        RTObjectWrapper {
+            object: value,
+            id: NEXT_OBJECT_ID.fetch_add(1, Ordering::Relaxed),
+            groups: Vec::new(),
+        }
     }
     /// Create a new RTObjectWrapper from a RTObject
     pub fn from<T: RTObject>(value: T) -> RTObjectWrapper {
@@ -178,37 +341,65 @@ impl RTObjectWrapper {
     }
     /// Get the inner box as Box<dyn Any> allowing downcasts to the concrete type
     pub fn as_any_box(self) -> Box<dyn std::any::Any> {
-        self.0.as_any_box()
+        self.object.as_any_box()
+    }
+    /// Get the unique id of the object
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// Take over the id of another object, e.g. when replacing it in a scene
+    pub(crate) fn with_id(mut self, id: u64) -> RTObjectWrapper {
+        self.id = id;
+        self
+    }
+    /// Get the group tags of the object
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+    /// Tag the object with a group
+    pub fn add_group(&mut self, group: String) {
+        if !self.groups.contains(&group) {
+            self.groups.push(group);
+        }
     }
 }
 
 impl Clone for RTObjectWrapper {
     fn clone(&self) -> Self {
-        RTObjectWrapper(self.0.clone_impl())
+        RTObjectWrapper {
+            object: self.object.clone_impl(),
+            id: self.id,
+            groups: self.groups.clone(),
+        }
     }
 }
 
 impl PartialEq for RTObjectWrapper {
     fn eq(&self, other: &Self) -> bool {
-        *self.0 == *other.0
+        *self.object == *other.object && self.groups == other.groups
     }
 }
 
 impl Display for RTObjectWrapper {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "RTObjectWrapper({})", self.0)
+        write!(f, "RTObjectWrapper({})", self.object)
     }
 }
 
 impl Debug for RTObjectWrapper {
+    /// Includes the id and groups, as light links refer to them (see `checkpoint::fingerprint`).
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "RTObjectWrapper({:?})", self.0)
+        f.debug_struct("RTObjectWrapper")
+            .field("object", &self.object)
+            .field("id", &self.id)
+            .field("groups", &self.groups)
+            .finish()
     }
 }
 
 impl Intersect for RTObjectWrapper {
-    fn intersect(&self, ray: &Ray) -> Option<(Point3, Vector3, Scalar, Material)> {
-        self.0.intersect(ray)
+    fn intersect(&self, ray: &Ray, tmax: Scalar) -> Option<(Point3, Vector3, Scalar, Material)> {
+        self.object.intersect(ray, tmax)
     }
 }
 
@@ -218,6 +409,7 @@ impl PartialOrd for RTObjectWrapper {
     }
 }
 
+#[cfg(feature = "lisp-bindings")]
 #[test]
 fn test_rt_wrapper_expr_conversion() {
     use super::sphere::Sphere;
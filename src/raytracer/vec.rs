@@ -1,4 +1,4 @@
-use super::types::Vector3;
+use super::types::{Scalar, Vector3};
 
 extern crate nalgebra as na;
 
@@ -7,6 +7,18 @@ pub fn reflect(v: Vector3, n: Vector3) -> Vector3 {
     v - 2.0 * v.dot(&n) * n
 }
 
+/// Refracts a normalized vector `v` at a surface with normal `n` facing against `v`, where
+/// `eta` is the ratio of the refractive indices. Returns `None` on total internal reflection.
+pub fn refract(v: Vector3, n: Vector3, eta: Scalar) -> Option<Vector3> {
+    let cos_i = -v.dot(&n);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        None
+    } else {
+        Some(eta * v + (eta * cos_i - k.sqrt()) * n)
+    }
+}
+
 /// Mirrors a vector `v` around a normal `n`.
 pub fn mirror(v: Vector3, n: Vector3) -> Vector3 {
     2.0 * v.dot(&n) * n - v
@@ -0,0 +1,155 @@
+use std::fmt::Display;
+use std::ops::Deref;
+
+use ::regex::Regex;
+use lispers_core::lisp::{
+    Environment, Expression,
+    environment::EnvironmentLayer,
+    eval::{EvalError, eval},
+    expression::ForeignDataWrapper,
+};
+use lispers_macro::native_lisp_function;
+
+/// A compiled regular expression. Regular expressions are equal if their patterns are.
+#[derive(Clone, Debug)]
+pub struct LispRegex(pub Regex);
+
+impl LispRegex {
+    /// Compile a pattern.
+    pub fn new(pattern: &str) -> Result<Self, EvalError> {
+        Regex::new(pattern)
+            .map(LispRegex)
+            .map_err(|e| EvalError::ArgumentError(format!("Invalid regex {:?}: {}", pattern, e)))
+    }
+}
+
+impl Display for LispRegex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Regex({:?})", self.0.as_str())
+    }
+}
+
+impl PartialEq for LispRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl PartialOrd for LispRegex {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.0.as_str().partial_cmp(other.0.as_str())
+    }
+}
+
+/// A regular expression argument. Besides a compiled `regex`, a pattern string is accepted and
+/// compiled on each call.
+pub struct RegexArg(pub LispRegex);
+
+impl Deref for RegexArg {
+    type Target = Regex;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.0
+    }
+}
+
+impl TryFrom<Expression> for RegexArg {
+    type Error = EvalError;
+
+    fn try_from(value: Expression) -> Result<Self, Self::Error> {
+        match value {
+            Expression::String(pattern) => Ok(RegexArg(LispRegex::new(&pattern)?)),
+            x => {
+                let r: ForeignDataWrapper<LispRegex> = x.try_into()?;
+                Ok(RegexArg((*r).clone()))
+            }
+        }
+    }
+}
+
+/// Compile a regular expression: `(regex "ab+")`
+#[native_lisp_function(eval)]
+pub fn regex(pattern: String) -> Result<ForeignDataWrapper<LispRegex>, EvalError> {
+    Ok(ForeignDataWrapper::new(LispRegex::new(&pattern)?))
+}
+
+/// Match a regular expression anywhere in a string: `(regex-match re s)`. Returns the list of
+/// the matched text and its capture groups, where groups which did not participate are `nil`,
+/// or false if the string does not match.
+pub fn regex_match(env: &Environment, expr: Expression) -> Result<Expression, EvalError> {
+    let [re, s] = expr.try_into()?;
+    let re: RegexArg = eval(env, re)?.try_into()?;
+    let s: String = eval(env, s)?.try_into()?;
+
+    match re.captures(&s) {
+        Some(captures) => Ok(captures
+            .iter()
+            .map(|c| c.map_or(Expression::Nil, |c| c.as_str().to_string().into()))
+            .collect::<Vec<_>>()
+            .into()),
+        None => Ok(env.config().boolean(false)),
+    }
+}
+
+/// Get all non-overlapping matches of a regular expression in a string:
+/// `(regex-find-all re s)`
+#[native_lisp_function(eval)]
+pub fn regex_find_all(re: RegexArg, s: String) -> Vec<Expression> {
+    re.find_iter(&s)
+        .map(|m| m.as_str().to_string().into())
+        .collect()
+}
+
+/// Replace all matches of a regular expression in a string: `(regex-replace re s replacement)`.
+/// The replacement can refer to capture groups like `$1` or `${name}`.
+#[native_lisp_function(eval)]
+pub fn regex_replace(re: RegexArg, s: String, replacement: String) -> String {
+    re.replace_all(&s, replacement.as_str()).into_owned()
+}
+
+/// Adds the regular expression functions to the given environment layer.
+pub fn mk_regex(layer: &mut EnvironmentLayer) {
+    layer.set("regex".to_string(), Expression::Function(regex));
+    layer.set("regex-match".to_string(), Expression::Function(regex_match));
+    layer.set(
+        "regex-find-all".to_string(),
+        Expression::Function(regex_find_all),
+    );
+    layer.set(
+        "regex-replace".to_string(),
+        Expression::Function(regex_replace),
+    );
+}
+
+#[test]
+fn test_regex() {
+    use lispers_core::lisp::eval_source;
+
+    let mut layer = EnvironmentLayer::new();
+    mk_regex(&mut layer);
+    let env = Environment::from_layer(layer);
+    let string = |s: &str| Expression::String(s.to_string());
+
+    let values = eval_source(
+        &env,
+        r#"
+        (regex-match (regex "(\w+)@(\w+)?") "mail: joe@ and more")
+        (regex-match "x+" "abc")
+        (regex-find-all (regex "ab+") "ab abbb b abb")
+        (regex-replace "(\d+)-(\d+)" "1-2 and 30-40" "$2:$1")
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        values[0],
+        vec![string("joe@"), string("joe"), Expression::Nil].into()
+    );
+    assert_eq!(values[1], Expression::Nil);
+    assert_eq!(
+        values[2],
+        vec![string("ab"), string("abbb"), string("abb")].into()
+    );
+    assert_eq!(values[3], string("2:1 and 40:30"));
+    assert!(eval_source(&env, "(regex \"(\")").is_err());
+}
@@ -0,0 +1,161 @@
+use std::path::Path;
+
+use crate::autodiff::mk_autodiff;
+use crate::raytracer::lisp::mk_raytrace;
+use crate::regex::mk_regex;
+use lispers_core::lisp::cache::{COMPILED_EXTENSION, read_compiled};
+use lispers_core::lisp::environment::EnvironmentLayer;
+use lispers_core::lisp::eval::{EvalError, locate_error};
+use lispers_core::lisp::io::mk_io;
+use lispers_core::lisp::prelude::mk_prelude;
+use lispers_core::lisp::{Environment, Expression, InterpreterConfig, Warning, eval};
+use lispers_core::parser::ExpressionStream;
+
+/// An interpreter session shared by the command line tools. It owns the global environment and
+/// evaluates whole sources expression by expression.
+pub struct EvalSession {
+    environment: Environment<'static>,
+    /// Receives the warnings reported during evaluation.
+    on_warning: Box<dyn Fn(&Warning)>,
+}
+
+/// Print a warning to stderr, separate from results and errors.
+fn print_warning(warning: &Warning) {
+    eprintln!("Warning: {}", warning);
+}
+
+impl EvalSession {
+    /// Create a session with the lisp prelude, file I/O, the raytracer builtins, automatic
+    /// differentiation and regular expressions.
+    pub fn new() -> Self {
+        let mut layer = EnvironmentLayer::new();
+        mk_prelude(&mut layer);
+        mk_io(&mut layer);
+        mk_raytrace(&mut layer);
+        mk_autodiff(&mut layer);
+        mk_regex(&mut layer);
+        EvalSession {
+            environment: Environment::from_layer(layer),
+            on_warning: Box::new(print_warning),
+        }
+    }
+
+    /// Create a session with only the lisp prelude and file I/O.
+    pub fn lisp_only() -> Self {
+        let mut layer = EnvironmentLayer::new();
+        mk_prelude(&mut layer);
+        mk_io(&mut layer);
+        EvalSession {
+            environment: Environment::from_layer(layer),
+            on_warning: Box::new(print_warning),
+        }
+    }
+
+    /// Create a session like `new` for benchmarks of the interpreter. `BENCHMARK` is bound so
+    /// `render` skips writing images, and printed output and warnings are discarded.
+    pub fn benchmark() -> Self {
+        let mut session = EvalSession::new();
+        session.set("BENCHMARK", Expression::True);
+        session.environment.set_output(Box::new(std::io::sink()));
+        session.set_warning_handler(|_| {});
+        session
+    }
+
+    /// Get the global environment of the session.
+    pub fn environment(&self) -> &Environment<'static> {
+        &self.environment
+    }
+
+    /// Bind a global value, e.g. host configuration like `NO-CLOBBER`.
+    pub fn set(&mut self, key: &str, value: Expression) {
+        self.environment.set(key.to_string(), value);
+    }
+
+    /// Replace the evaluation settings of the session.
+    pub fn set_config(&mut self, config: InterpreterConfig) {
+        self.environment.set_config(config);
+    }
+
+    /// Replace the warning handler of the session, which prints to stderr by default.
+    pub fn set_warning_handler<F>(&mut self, on_warning: F)
+    where
+        F: Fn(&Warning) + 'static,
+    {
+        self.on_warning = Box::new(on_warning);
+    }
+
+    /// Pass the warnings reported since the last call to the warning handler.
+    pub fn report_warnings(&self) {
+        self.environment
+            .take_warnings()
+            .iter()
+            .for_each(|w| (self.on_warning)(w));
+    }
+
+    /// Evaluate all expressions of `source` in order. Each expression is passed to `on_result`
+    /// together with its value or its evaluation error, located at the innermost failing call
+    /// and carrying the backtrace of the failing calls (see `locate_error`). Evaluation then
+    /// continues with the next expression. Warnings are reported before the result, located at
+    /// the expression.
+    /// Parsing stops at the first parser error, which is returned.
+    pub fn eval_source<F>(&self, source: &str, mut on_result: F) -> Result<(), EvalError>
+    where
+        F: FnMut(&Expression, Result<Expression, EvalError>),
+    {
+        let mut stream = ExpressionStream::from_char_stream(source.chars());
+        // The parsed expressions are kept for the spans of the stream to stay valid
+        let mut parsed = Vec::new();
+        while let Some((pos, expr)) = stream.next_positioned() {
+            let expr = expr?;
+            let result = eval(&self.environment, expr.clone())
+                .map_err(|e| locate_error(&self.environment, e, pos, stream.spans()));
+            for warning in self.environment.take_warnings() {
+                (self.on_warning)(&warning.at(pos));
+            }
+            on_result(&expr, result);
+            parsed.push(expr);
+        }
+        Ok(())
+    }
+
+    /// Evaluate already parsed expressions like `eval_source`, e.g. of a compiled file. Their
+    /// errors are not located, as there are no source positions.
+    pub fn eval_expressions<F>(&self, expressions: Vec<Expression>, mut on_result: F)
+    where
+        F: FnMut(&Expression, Result<Expression, EvalError>),
+    {
+        for expr in expressions {
+            let result = eval(&self.environment, expr.clone())
+                .map_err(|e| e.traced(self.environment.take_backtrace()));
+            for warning in self.environment.take_warnings() {
+                (self.on_warning)(&warning);
+            }
+            on_result(&expr, result);
+        }
+    }
+
+    /// Evaluate a lisp file like `eval_source`, binding `FILE` to its path so relative includes
+    /// are resolved next to it. Compiled files (`.lispb`, see `lispers compile`) are evaluated
+    /// without parsing.
+    pub fn eval_file<F>(&mut self, path: &Path, on_result: F) -> Result<(), EvalError>
+    where
+        F: FnMut(&Expression, Result<Expression, EvalError>),
+    {
+        if path.extension().is_some_and(|e| e == COMPILED_EXTENSION) {
+            let expressions = read_compiled(path)?;
+            self.set("FILE", path.display().to_string().into());
+            self.eval_expressions(expressions, on_result);
+            return Ok(());
+        }
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| EvalError::with_context(path.display(), e))?;
+        self.set("FILE", path.display().to_string().into());
+        self.eval_source(&source, on_result)
+    }
+}
+
+impl Default for EvalSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
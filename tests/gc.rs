@@ -0,0 +1,49 @@
+//! Memory use of long running scripts. Counts the bytes held by the interpreter with a global
+//! allocator, so it lives in its own test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use lispers::prelude::*;
+
+/// The system allocator, counting the bytes currently allocated.
+struct Counting;
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn test_closures_do_not_grow_memory() {
+    let env = Environment::default();
+    let source = "(set 'make (lambda (i) (list 'lambda '(x) (list '+ 'x i))))
+                  (set 'f (eval (make 1)))
+                  (set 'table (list f (eval (make 2)) (lambda () f)))
+                  (f 1)
+                  (gc)";
+    let run = || {
+        let values = eval_source(&env, source).unwrap();
+        assert_eq!(values.last(), Some(&Expression::Integer(0)));
+    };
+
+    run();
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    for _ in 0..1000 {
+        run();
+    }
+    let growth = ALLOCATED.load(Ordering::SeqCst) - before;
+    assert!(growth < 1024, "Memory grew by {} bytes", growth);
+}
@@ -0,0 +1,624 @@
+//! End to end tests of native functions generated by `lispers-macro`, registered in a
+//! `lispers-core` environment, and of the overloaded raytracer builtins of a full session.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use lispers::prelude::*;
+
+/// A foreign value passed between native functions.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+struct Celsius(f64);
+
+impl Display for Celsius {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}°C", self.0)
+    }
+}
+
+#[native_lisp_function(eval)]
+fn repeat(s: String, n: i64) -> String {
+    s.repeat(n as usize)
+}
+
+/// Without `eval`, arguments are converted as written.
+#[native_lisp_function]
+fn half(x: f64) -> Result<f64, EvalError> {
+    Ok(x / 2.0)
+}
+
+/// Trailing arguments with a default or of `Option` types can be left out.
+#[native_lisp_function(eval)]
+fn pad(s: String, #[default(6)] width: i64, fill: Option<String>) -> String {
+    let fill = fill.unwrap_or(" ".to_string());
+    let n = (width as usize).saturating_sub(s.chars().count());
+    s + &fill.repeat(n)
+}
+
+/// A final `Vec` collects the remaining arguments.
+#[native_lisp_function(eval)]
+fn warmest(first: ForeignDataWrapper<Celsius>, rest: Vec<ForeignDataWrapper<Celsius>>) -> f64 {
+    rest.iter().map(|c| (**c).0).fold((*first).0, f64::max)
+}
+
+/// Triples and quadruples are converted from lists of their length.
+#[native_lisp_function(eval)]
+fn volume(size: (usize, usize, usize), scale: u32) -> i64 {
+    (size.0 * size.1 * size.2 * scale as usize) as i64
+}
+
+/// Pairs convert from and into the car and cdr of a Cell.
+#[native_lisp_function(eval)]
+fn swap(pair: (f64, String)) -> (String, f64) {
+    (pair.1, pair.0)
+}
+
+/// Maps convert from and into a `HashMap` with string keys.
+#[native_lisp_function(eval)]
+fn word_lengths(words: Vec<String>) -> HashMap<String, i64> {
+    words
+        .into_iter()
+        .map(|w| {
+            let n = w.chars().count() as i64;
+            (w, n)
+        })
+        .collect()
+}
+
+/// An `Option` result of `None` is nil.
+#[native_lisp_function(eval)]
+fn longer_than(lengths: HashMap<String, i64>, n: i64) -> Option<Vec<String>> {
+    let mut words: Vec<String> = lengths
+        .into_iter()
+        .filter(|(_, len)| *len > n)
+        .map(|(w, _)| w)
+        .collect();
+    words.sort();
+    (!words.is_empty()).then_some(words)
+}
+
+#[native_lisp_function(eval, fname = celsius_from_fahrenheit)]
+fn celsius(fahrenheit: f64) -> Result<ForeignDataWrapper<Celsius>, EvalError> {
+    Ok(ForeignDataWrapper::new(Celsius((fahrenheit - 32.0) / 1.8)))
+}
+
+#[native_lisp_function(eval)]
+fn degrees(c: ForeignDataWrapper<Celsius>) -> f64 {
+    (*c).0
+}
+
+/// Booleans are `false` or nil, depending on `distinct_false`.
+#[native_lisp_function(eval, fname = freezing)]
+fn is_freezing(c: ForeignDataWrapper<Celsius>) -> bool {
+    (*c).0 <= 0.0
+}
+
+#[native_lisp_function(eval, fname = warmer)]
+fn is_warmer(
+    a: ForeignDataWrapper<Celsius>,
+    b: ForeignDataWrapper<Celsius>,
+) -> Result<bool, EvalError> {
+    Ok(*a > *b)
+}
+
+#[native_lisp_function(eval, strict)]
+fn describe_int(i: i64) -> Result<String, EvalError> {
+    match i {
+        i if i < 0 => Err(EvalError::RuntimeError("negative".to_string(), None)),
+        _ => Ok("int".to_string()),
+    }
+}
+
+#[native_lisp_function(eval)]
+fn describe_float(_f: f64) -> Result<String, EvalError> {
+    Ok("number".to_string())
+}
+
+#[native_lisp_function(eval)]
+fn describe_celsius(_c: ForeignDataWrapper<Celsius>) -> Result<String, EvalError> {
+    Ok("celsius".to_string())
+}
+
+/// A record with a foreign field and a field hidden from lisp.
+#[derive(Clone, Debug, PartialEq, PartialOrd, LispRecord)]
+#[lisp_record(name = "probe")]
+struct TemperatureProbe {
+    label: String,
+    samples: i64,
+    #[lisp_record(foreign)]
+    last: Celsius,
+    #[lisp_record(skip)]
+    calibrated: bool,
+}
+
+impl Display for TemperatureProbe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<probe {} {}>", self.label, self.last)
+    }
+}
+
+native_lisp_function_proxy!(
+    fname = describe,
+    eval,
+    dispatch = describe_int,
+    dispatch = describe_float,
+    dispatch = describe_celsius
+);
+
+native_lisp_function_proxy!(
+    fname = describe_strict,
+    eval,
+    propagate_argument_errors,
+    dispatch = describe_int,
+    dispatch = describe_float
+);
+
+fn environment() -> Environment<'static> {
+    let mut layer = EnvironmentLayer::new();
+    mk_prelude(&mut layer);
+    layer.set("repeat".to_string(), Expression::Function(repeat));
+    layer.set("half".to_string(), Expression::Function(half));
+    layer.set("pad".to_string(), Expression::Function(pad));
+    layer.set("warmest".to_string(), Expression::Function(warmest));
+    layer.set("volume".to_string(), Expression::Function(volume));
+    layer.set("swap".to_string(), Expression::Function(swap));
+    layer.set(
+        "word-lengths".to_string(),
+        Expression::Function(word_lengths),
+    );
+    layer.set("longer-than".to_string(), Expression::Function(longer_than));
+    layer.set(
+        "celsius".to_string(),
+        Expression::Function(celsius_from_fahrenheit),
+    );
+    layer.set("degrees".to_string(), Expression::Function(degrees));
+    layer.set("freezing?".to_string(), Expression::Function(freezing));
+    layer.set("warmer?".to_string(), Expression::Function(warmer));
+    layer.set("describe".to_string(), Expression::Function(describe));
+    layer.set(
+        "describe-strict".to_string(),
+        Expression::Function(describe_strict),
+    );
+    TemperatureProbe::mk_record(&mut layer);
+    Environment::from_layer(layer)
+}
+
+/// Parse a single expression at runtime.
+fn parse(source: &str) -> Expression {
+    ExpressionStream::from_char_stream(source.chars())
+        .next()
+        .unwrap()
+        .unwrap()
+}
+
+/// Evaluate a single expression, without the source position of errors.
+fn run(env: &Environment, source: &str) -> Result<Expression, EvalError> {
+    match eval_source(env, source) {
+        Ok(mut values) => Ok(values.pop().unwrap()),
+        Err(LispError::Eval(e)) => Err(e.root().clone()),
+        Err(e) => panic!("Unexpected error {}", e),
+    }
+}
+
+#[test]
+fn test_argument_conversion() {
+    let env = environment();
+
+    assert_eq!(
+        run(&env, "(repeat (concat \"a\" \"b\") (+ 1 1))"),
+        Ok("abab".to_string().into())
+    );
+    assert_eq!(run(&env, "(half 3)"), Ok(Expression::Float(1.5)));
+    assert_eq!(run(&env, "(pad \"ab\")"), Ok("ab    ".to_string().into()));
+    assert_eq!(run(&env, "(pad \"ab\" 3)"), Ok("ab ".to_string().into()));
+    // Numeric arguments accept Integers and integral Floats alike
+    assert_eq!(
+        run(&env, "(repeat \"a\" (* 1.5 2))"),
+        Ok("aaa".to_string().into())
+    );
+    assert_eq!(
+        run(&env, "(pad \"ab\" 4.0 \"-\")"),
+        Ok("ab--".to_string().into())
+    );
+    assert_eq!(
+        run(&env, "(pad \"ab\" (+ 2 2) \"-\")"),
+        Ok("ab--".to_string().into())
+    );
+    assert_eq!(
+        run(&env, "(pad \"ab\" 3 nil)"),
+        Ok("ab ".to_string().into())
+    );
+    assert_eq!(
+        run(&env, "(volume '(3 4 1) 2)"),
+        Ok(Expression::Integer(24))
+    );
+    assert_eq!(
+        run(&env, "(swap (cons 1.5 \"a\"))"),
+        Ok(("a".to_string(), 1.5).into())
+    );
+    assert_eq!(
+        run(&env, "(swap '(2 . \"b\"))"),
+        Ok(Expression::Cell(
+            Expression::String("b".to_string()).into(),
+            Expression::Float(2.0).into()
+        ))
+    );
+    assert_eq!(
+        run(&env, "(map-keys (word-lengths \"sun\" \"moon\" \"abc\"))"),
+        Ok(vec!["abc".to_string(), "moon".to_string(), "sun".to_string()].into())
+    );
+    assert_eq!(
+        run(
+            &env,
+            "(longer-than (word-lengths \"sun\" \"moon\" \"stars\") 3)"
+        ),
+        Ok(vec!["moon".to_string(), "stars".to_string()].into())
+    );
+    assert_eq!(
+        run(&env, "(longer-than (word-lengths \"sun\") 3)"),
+        Ok(Expression::Nil)
+    );
+    assert_eq!(
+        run(&env, "(degrees (celsius 212))"),
+        Ok(Expression::Float(100.0))
+    );
+    assert_eq!(
+        run(&env, "(warmest (celsius 50))"),
+        Ok(Expression::Float(10.0))
+    );
+    assert_eq!(
+        run(&env, "(warmest (celsius 50) (celsius 212) (celsius 32))"),
+        Ok(Expression::Float(100.0))
+    );
+    // Foreign data is compared with its `PartialEq`, but has no identity
+    assert_eq!(
+        run(&env, "(equal? (celsius 212) (celsius 212))"),
+        Ok(Expression::True)
+    );
+    assert_eq!(
+        run(&env, "(eq? (celsius 212) (celsius 212))"),
+        Ok(Expression::Nil)
+    );
+    // Foreign data prints with its `Display`
+    assert_eq!(
+        run(&env, "(cdr (with-captured-output (println (celsius 212))))"),
+        Ok("100°C\n".to_string().into())
+    );
+}
+
+#[test]
+fn test_boolean_results() {
+    let mut env = environment();
+
+    assert_eq!(run(&env, "(freezing? (celsius 0))"), Ok(Expression::True));
+    assert_eq!(run(&env, "(freezing? (celsius 50))"), Ok(Expression::Nil));
+    assert_eq!(
+        run(&env, "(warmer? (celsius 32) (celsius 50))"),
+        Ok(Expression::Nil)
+    );
+
+    env.set_config(InterpreterConfig::default().with_distinct_false(true));
+    assert_eq!(run(&env, "(freezing? (celsius 0))"), Ok(Expression::True));
+    assert_eq!(run(&env, "(freezing? (celsius 50))"), Ok(Expression::False));
+    assert_eq!(
+        run(&env, "(warmer? (celsius 32) (celsius 50))"),
+        Ok(Expression::False)
+    );
+    assert_eq!(
+        run(&env, "(if (freezing? (celsius 50)) 'ice 'water)"),
+        Ok(Expression::Symbol("water".to_string()))
+    );
+    assert_eq!(
+        run(&env, "(eq? (freezing? (celsius 50)) nil)"),
+        Ok(Expression::False)
+    );
+}
+
+#[test]
+fn test_argument_errors() {
+    let env = environment();
+
+    assert_eq!(
+        run(&env, "(repeat \"a\")"),
+        Err(EvalError::ArgumentError("Missing Argument n".to_string()))
+    );
+    assert_eq!(
+        run(&env, "(repeat \"a\" 1.5)"),
+        Err(EvalError::TypeError("1.5 is not a valid i64".to_string()))
+    );
+    assert_eq!(
+        run(&env, "(volume '(3 -4 1) 1)"),
+        Err(EvalError::TypeError("-4 is not a valid usize".to_string()))
+    );
+    assert_eq!(
+        run(&env, "(volume '(3 4) 1)"),
+        Err(EvalError::TypeError(
+            "Expected a list of 3 elements, got 2".to_string()
+        ))
+    );
+    // A pair is a Cell, not a list of two elements
+    assert!(matches!(
+        run(&env, "(swap (list 1.5 \"a\"))"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(swap (cons \"a\" 1.5))"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(volume '(3 4 1) -1)"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(pad \"ab\" 3 1)"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(warmest (celsius 50) 20)"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(degrees 20)"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(let ((x 3)) (half x))"),
+        Err(EvalError::TypeError(_))
+    ));
+    assert_eq!(
+        run(&env, "(repeat undefined-symbol 1)"),
+        Err(EvalError::SymbolNotBound("undefined-symbol".to_string()))
+    );
+}
+
+#[test]
+fn test_proxy_dispatch() {
+    let env = environment();
+
+    assert_eq!(run(&env, "(describe 1)"), Ok("int".to_string().into()));
+    assert_eq!(run(&env, "(describe 1.5)"), Ok("number".to_string().into()));
+    // A strict Integer overload does not take integral Floats
+    assert_eq!(run(&env, "(describe 2.0)"), Ok("number".to_string().into()));
+    assert_eq!(
+        run(&env, "(describe (celsius 50))"),
+        Ok("celsius".to_string().into())
+    );
+    // Only argument and type errors fall through to the next implementation
+    assert_eq!(
+        run(&env, "(describe -1)"),
+        Err(EvalError::RuntimeError("negative".to_string(), None))
+    );
+    assert_eq!(
+        run(&env, "(describe \"text\")"),
+        Err(EvalError::TypeError(
+            "Could not call describe with arguments (\"text\"), tried:\n  \
+             (describe i): Type error: Expression is not an Integer\n  \
+             (describe f): Type error: Expression is not a Float\n  \
+             (describe c): Type error: Expression is not a ForeignDataWrapper"
+                .to_string()
+        ))
+    );
+    // Argument errors can end the dispatch
+    assert_eq!(
+        run(&env, "(describe-strict)"),
+        Err(EvalError::ArgumentError("Missing Argument i".to_string()))
+    );
+    assert_eq!(
+        run(&env, "(describe-strict 1.5)"),
+        Ok("number".to_string().into())
+    );
+}
+
+#[test]
+fn test_record() {
+    let mut env = environment();
+
+    assert_eq!(TemperatureProbe::FIELDS, ["label", "samples", "last"]);
+    env.set(
+        "p".to_string(),
+        TemperatureProbe {
+            label: "outside".to_string(),
+            samples: 3,
+            last: Celsius(12.0),
+            calibrated: true,
+        }
+        .into(),
+    );
+    assert_eq!(run(&env, "(probe-samples p)"), Ok(Expression::Integer(3)));
+    assert_eq!(
+        run(&env, "(degrees (probe-last p))"),
+        Ok(Expression::Float(12.0))
+    );
+    // Setters return a copy with the field replaced
+    assert_eq!(
+        run(&env, "(probe-label (set-probe-label p \"inside\"))"),
+        Ok("inside".to_string().into())
+    );
+    assert_eq!(
+        run(&env, "(probe-label p)"),
+        Ok("outside".to_string().into())
+    );
+
+    let probe: TemperatureProbe = run(&env, "(make-probe \"roof\" 0 (celsius 212))")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(probe.last, Celsius(100.0));
+    assert!(!probe.calibrated);
+
+    assert_eq!(
+        run(&env, "(probe-label (celsius 0))"),
+        Err(EvalError::TypeError(
+            "Expression is not a probe".to_string()
+        ))
+    );
+    assert!(matches!(
+        run(&env, "(make-probe \"roof\" 0)"),
+        Err(EvalError::ArgumentError(_))
+    ));
+    assert!(matches!(
+        run(&env, "(set-probe-last p 20)"),
+        Err(EvalError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_doc() {
+    let env = environment();
+
+    assert_eq!(
+        run(&env, "(doc 'pad)"),
+        Ok("(pad s [width] [fill])\n\nTrailing arguments with a default or of `Option` types can be left out."
+            .to_string()
+            .into())
+    );
+    assert_eq!(
+        run(&env, "(arity 'pad)"),
+        Ok(vec![Expression::Integer(1), Expression::Integer(3)].into())
+    );
+    assert_eq!(
+        run(&env, "(doc 'warmest)"),
+        Ok(
+            "(warmest first rest...)\n\nA final `Vec` collects the remaining arguments."
+                .to_string()
+                .into()
+        )
+    );
+    assert_eq!(
+        run(&env, "(arity 'warmest)"),
+        Ok(vec![Expression::Integer(1), Expression::Nil].into())
+    );
+    // Proxies accept the arguments of any implementation
+    assert_eq!(
+        run(&env, "(doc 'describe)"),
+        Ok("(describe i)\n(describe f)\n(describe c)"
+            .to_string()
+            .into())
+    );
+    assert_eq!(
+        run(&env, "(arity 'describe)"),
+        Ok(vec![Expression::Integer(1), Expression::Integer(1)].into())
+    );
+}
+
+#[test]
+fn test_embedded_lisp() {
+    let env = environment();
+
+    let expr = lisp! { (repeat (concat "a" "b") 2) };
+    assert_eq!(expr, parse("(repeat (concat \"a\" \"b\") 2)"));
+    assert_eq!(eval(&env, expr), Ok("abab".to_string().into()));
+    assert_eq!(lisp! { 'do-n-times }, parse("'do-n-times"));
+    assert_eq!(
+        lisp! { (list 1.5 -2 100000000000000000000 true nil) },
+        parse("(list 1.5 -2 100000000000000000000 true nil)")
+    );
+
+    let program = lisp_str!("(set 'xs '(1 2 3)) ; a comment\n(length xs)");
+    assert_eq!(program.len(), 2);
+    assert_eq!(
+        program
+            .into_iter()
+            .map(|e| eval(&env, e))
+            .collect::<Result<Vec<_>, _>>(),
+        Ok(vec![
+            vec![
+                Expression::Integer(1),
+                Expression::Integer(2),
+                Expression::Integer(3)
+            ]
+            .into(),
+            Expression::Integer(3)
+        ])
+    );
+}
+
+#[test]
+fn test_raytracer_session() {
+    let session = EvalSession::new();
+    let mut results = Vec::new();
+
+    session
+        .eval_source(
+            r#"
+            (vector-ref (+ (vector 1 2 3) (vector 1 1 1)) 2)
+            (vector-ref (* 2 (vector 1 2 3)) 1)
+            (vector-ref (- (point 3 3 3) (vector 1 2 3)) 0)
+            ((grad (lambda (x) (* x x))) 3)
+            (+ (vector 1 2 3) "text")
+            "#,
+            |_, r| results.push(r),
+        )
+        .unwrap();
+
+    assert_eq!(results[0], Ok(Expression::Float(4.0)));
+    assert_eq!(results[1], Ok(Expression::Float(4.0)));
+    assert_eq!(results[2], Ok(Expression::Float(2.0)));
+    assert_eq!(results[3], Ok(Expression::Float(6.0)));
+    assert!(results[4].is_err());
+}
+
+#[test]
+fn test_environment_builder() {
+    let env = Environment::builder()
+        .with_prelude()
+        .with_math()
+        .with_raytracer()
+        .with(mk_autodiff)
+        .build();
+
+    assert_eq!(
+        run(&env, "(vector-ref (+ (vector 1 2 3) (vector 1 1 1)) 2)"),
+        Ok(Expression::Float(4.0))
+    );
+    assert_eq!(
+        run(&env, "((grad (lambda (x) (* x x))) 3)"),
+        Ok(Expression::Float(6.0))
+    );
+    // The camera defaults to 640x480 pixels
+    assert_eq!(
+        run(
+            &env,
+            "(camera-project (camera (point 0 0 5) (point 0 0 0)) (point 0 0 0))"
+        ),
+        Ok(vec![Expression::Float(320.0), Expression::Float(240.0)].into())
+    );
+    assert_eq!(
+        run(
+            &env,
+            "(camera-project (camera (point 0 0 5) (point 0 0 0) (vector 0 1 0) 40 1920.0 1080) \
+             (point 0 0 0))"
+        ),
+        Ok(vec![Expression::Float(960.0), Expression::Float(540.0)].into())
+    );
+    // Overloads keep the kind of number
+    assert_eq!(run(&env, "(abs -2)"), Ok(Expression::Integer(2)));
+    assert_eq!(run(&env, "(abs -2.0)"), Ok(Expression::Float(2.0)));
+    assert!(
+        matches!(run(&env, "(doc 'render)"), Ok(Expression::String(doc)) if doc.starts_with("Render a scene"))
+    );
+    assert_eq!(run(&env, "(arity 'render)"), Ok(Expression::Nil));
+    assert!(env.get("include").is_none());
+
+    // Without file I/O, the raytracer does not write images either
+    let dir = std::env::temp_dir().join(format!("lispers-test-no-io-{}", std::process::id()));
+    env.shared_set(
+        "OUT".to_string(),
+        dir.join("sub/out.png").display().to_string().into(),
+    );
+    assert!(matches!(
+        run(
+            &env,
+            "(render (camera (point 0 0 5) (point 0 0 0) (vector 0 1 0) 40 4 2) \
+             (scene (color 0 0 0) nil nil) 1 1 OUT)"
+        ),
+        Err(EvalError::RuntimeError(_, None))
+    ));
+    assert!(!dir.exists());
+
+    // Scripts for lispers 0.1 keep the float quotient of the raytracer `/`
+    assert_eq!(run(&env, "(/ 7 7)"), Ok(Expression::Integer(1)));
+    run(&env, "(lispers-version \"0.1\")").unwrap();
+    assert_eq!(run(&env, "(/ 7 7)"), Ok(Expression::Float(1.0)));
+    assert_eq!(run(&env, "(/ 7 2)"), Ok(Expression::Float(3.5)));
+}